@@ -0,0 +1,297 @@
+//! Indexes archived with rkyv and memory mapped, so they're searched in place.
+//!
+//! Every install also writes the index of the doc to `archives/<slug>.rkyv`.
+//! [`MappedIndex`] maps that file and hands out entries borrowing their name, path and
+//! type from the mapping; nothing is deserialized and only the pages of the file that
+//! are touched become resident. [`DevDocsManager::search_mapped`] ranks entries that
+//! way and only allocates for the results it returns.
+//!
+//! This is an extra search path, not a replacement for the cache: [`init`] still loads
+//! every installed index into memory, since listing, browsing, updates and
+//! [`search`] all work on it. Startup time and resident memory only shrink for callers
+//! that search through [`MappedIndex`] without keeping the cache around.
+//!
+//! [`init`]: DevDocsManager::init
+//! [`search`]: DevDocsManager::search
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use nucleo::{Config, Matcher, Utf32Str};
+use tokio::fs;
+
+use crate::{
+    ARCHIVES_DIR, DevDocsError, DevDocsManager, DocIndex, Entry, SearchOptions, SearchResult,
+    SearchableEntry, matcher_needle, write_atomic,
+};
+
+/// The archived form of a [`DocIndex`], without its types
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct IndexArchive {
+    entries: Vec<EntryArchive>,
+}
+
+#[derive(rkyv::Archive, rkyv::Serialize)]
+struct EntryArchive {
+    name: String,
+    path: String,
+    entry_type: String,
+}
+
+/// An entry of a [`MappedIndex`], borrowed from the mapped file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MappedEntry<'a> {
+    pub name: &'a str,
+    /// Page path within the doc, with an `#anchor` for sections
+    pub path: &'a str,
+    pub entry_type: &'a str,
+}
+
+impl MappedEntry<'_> {
+    /// An owned copy of the entry
+    pub fn to_entry(&self) -> Entry {
+        Entry {
+            name: self.name.to_string(),
+            path: PathBuf::from(self.path),
+            entry_type: self.entry_type.to_string(),
+        }
+    }
+}
+
+/// The archived index of a doc, mapped into memory
+#[derive(Debug)]
+pub struct MappedIndex {
+    map: memmap2::Mmap,
+}
+
+impl MappedIndex {
+    /// Map the archive at `path`, checking once that it's well-formed
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = std::fs::File::open(path)
+            .with_context(|| format!("Couldn't open {}", path.display()))?;
+        // SAFETY: archives are only ever replaced by renaming a new file over them, never
+        // written in place, so the mapped file doesn't change under the mapping
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        rkyv::access::<ArchivedIndexArchive, rkyv::rancor::Error>(&map)
+            .with_context(|| format!("{} isn't a valid index archive", path.display()))?;
+        Ok(Self { map })
+    }
+
+    fn archive(&self) -> &ArchivedIndexArchive {
+        // SAFETY: the bytes were validated in `open` and the mapping is read-only
+        unsafe { rkyv::access_unchecked::<ArchivedIndexArchive>(&self.map) }
+    }
+
+    /// Number of entries in the index
+    pub fn len(&self) -> usize {
+        self.archive().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The entries of the index, in the order of the doc's index
+    pub fn entries(&self) -> impl Iterator<Item = MappedEntry<'_>> {
+        self.archive().entries.iter().map(mapped_entry)
+    }
+
+    /// The entry at position `i` of the index
+    pub fn get(&self, i: usize) -> Option<MappedEntry<'_>> {
+        self.archive().entries.get(i).map(mapped_entry)
+    }
+}
+
+fn mapped_entry(entry: &ArchivedEntryArchive) -> MappedEntry<'_> {
+    MappedEntry {
+        name: entry.name.as_str(),
+        path: entry.path.as_str(),
+        entry_type: entry.entry_type.as_str(),
+    }
+}
+
+/// The archive of `index`, as written to [`ARCHIVES_DIR`]
+pub(crate) fn archive_index(index: &DocIndex) -> Result<Vec<u8>> {
+    let archive = IndexArchive {
+        entries: index
+            .entries
+            .iter()
+            .map(|entry| EntryArchive {
+                name: entry.name.clone(),
+                path: entry.path.to_string_lossy().into_owned(),
+                entry_type: entry.entry_type.clone(),
+            })
+            .collect(),
+    };
+    Ok(rkyv::to_bytes::<rkyv::rancor::Error>(&archive)?.into_vec())
+}
+
+impl DevDocsManager {
+    /// Write the archived index of an installed doc
+    pub(crate) async fn save_archive(&self, slug: &str, index: &DocIndex) -> Result<()> {
+        let data = archive_index(index)?;
+        let dir = self.write_dir().join(ARCHIVES_DIR);
+        fs::create_dir_all(&dir).await?;
+        write_atomic(&dir.join(format!("{}.rkyv", slug)), &data).await?;
+        Ok(())
+    }
+
+    /// The index of an installed doc, mapped from its archive.
+    ///
+    /// Docs installed before archives were written get theirs now.
+    pub async fn mapped_index(&self, slug: &str) -> Result<MappedIndex> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let relative = Path::new(ARCHIVES_DIR).join(format!("{}.rkyv", slug));
+        let path = match self.resolve_path(&relative) {
+            Some(path) => path,
+            None => {
+                let index = self.get_doc_index(&slug).await?;
+                self.save_archive(&slug, &index).await?;
+                self.write_dir().join(relative)
+            }
+        };
+        tokio::task::spawn_blocking(move || MappedIndex::open(&path)).await?
+    }
+
+    /// Fuzzy search the entry names of installed docs, ranking them straight from
+    /// their mapped archives.
+    ///
+    /// Honors the scope, types, paging and minimum score of `options`; the boosts and
+    /// per-doc settings of [`search`](Self::search) don't apply. The docs in scope must
+    /// be loaded by [`init`](Self::init) like for any search, only their entries are
+    /// read from the archives.
+    pub async fn search_mapped(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        if query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut docs = Vec::new();
+        for slug in self.resolve_scope(&options.docs).await? {
+            let name = self.get_doc_info(&slug).await?.name;
+            let index = self.mapped_index(&slug).await?;
+            docs.push((slug, name, index));
+        }
+
+        let query = query.to_string();
+        let options = options.clone();
+        tokio::task::spawn_blocking(move || rank_mapped(&docs, &query, &options)).await?
+    }
+}
+
+fn rank_mapped(
+    docs: &[(String, String, MappedIndex)],
+    query: &str,
+    options: &SearchOptions,
+) -> Result<Vec<SearchResult>> {
+    let ignore_case = options.case_matching.ignores_case(query);
+    let mut config = Config::DEFAULT;
+    config.prefer_prefix = true;
+    config.ignore_case = ignore_case;
+    config.normalize = options.normalize;
+    let mut matcher = Matcher::new(config);
+    let needle = matcher_needle(query, ignore_case, options.normalize);
+    let mut needle_buf = Vec::new();
+    let needle = Utf32Str::new(&needle, &mut needle_buf);
+
+    // The best entries so far, worst on top: by score, then shorter names first
+    let keep = options.offset.saturating_add(options.limit);
+    let mut best = BinaryHeap::new();
+    let mut buf = Vec::new();
+    for (doc, (_, _, index)) in docs.iter().enumerate() {
+        for (i, entry) in index.entries().enumerate() {
+            if !options.types.is_empty() && !options.types.iter().any(|t| t == entry.entry_type) {
+                continue;
+            }
+            let haystack = Utf32Str::new(entry.name, &mut buf);
+            let Some(score) = matcher.fuzzy_match(haystack, needle) else {
+                continue;
+            };
+            let score = u32::from(score);
+            if score < options.min_score {
+                continue;
+            }
+            best.push(Reverse((
+                score,
+                Reverse(entry.name.len()),
+                Reverse((doc, i)),
+            )));
+            if best.len() > keep {
+                best.pop();
+            }
+        }
+    }
+
+    // Only the results get owned copies of their entries
+    Ok(best
+        .into_sorted_vec()
+        .into_iter()
+        .skip(options.offset)
+        .filter_map(|Reverse((score, _, Reverse((doc, i))))| {
+            let (slug, name, index) = &docs[doc];
+            Some(SearchResult {
+                entry: SearchableEntry {
+                    entry: index.get(i)?.to_entry(),
+                    doc_slug: slug.clone(),
+                    doc_name: name.clone(),
+                },
+                score,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::tests::temp_dir;
+
+    #[tokio::test]
+    async fn test_mapped_indexes_are_searched_in_place() {
+        let dir = temp_dir("mapped");
+        let manager = DevDocsManager::with_transport(&dir, MockTransport::fixtures()).unwrap();
+        manager.init().await.unwrap();
+        manager.add_doc("kubernetes", None).await.unwrap();
+        manager.add_doc("rust", None).await.unwrap();
+        assert!(dir.join("archives/rust.rkyv").exists());
+
+        let index = manager.mapped_index("rust").await.unwrap();
+        assert_eq!(
+            index.len(),
+            manager.get_doc_index("rust").await.unwrap().entries.len()
+        );
+        assert_eq!(index.get(1).unwrap().name, "Vec::push");
+
+        let options = SearchOptions {
+            limit: 2,
+            ..Default::default()
+        };
+        let results = manager.search_mapped("deploy", &options).await.unwrap();
+        assert_eq!(results[0].entry.entry.name, "Deployment");
+        assert_eq!(results[0].entry.doc_name, "Kubernetes");
+        let options = SearchOptions {
+            docs: vec!["rust".to_string()],
+            types: vec!["Macros".to_string()],
+            ..Default::default()
+        };
+        let results = manager.search_mapped("vec", &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry.entry.name, "vec!");
+
+        // Docs installed before archives were written get theirs on first use
+        std::fs::remove_file(dir.join("archives/rust.rkyv")).unwrap();
+        assert_eq!(
+            manager.mapped_index("rust").await.unwrap().len(),
+            index.len()
+        );
+        manager.remove_doc("rust").await.unwrap();
+        assert!(!dir.join("archives/rust.rkyv").exists());
+    }
+}
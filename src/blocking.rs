@@ -0,0 +1,417 @@
+//! Synchronous facade over [`DevDocsManager`](crate::DevDocsManager)
+//!
+//! Every method blocks the calling thread until the operation finishes, driving
+//! the async manager on a runtime owned by the facade. Don't call these from
+//! within an async context; use the async manager there instead.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use tokio::runtime::Runtime;
+
+use crate::{
+    CodeBlock, CodeResult, ContentResult, DevDocsManagerBuilder, DiskUsage, Doc, DocChanges,
+    DocIndex, DownloadReport, Entry, EntryType, Formats, HistoryEntry, ManagerStats, Onboarding,
+    Plan, QueryCacheStats, RecentPage, SearchOptions, SearchResponse, SearchResult, SearchSession,
+    Settings, Signature, UpdateReport, UpdateStatus,
+};
+
+/// A [`crate::DevDocsManager`] with blocking methods
+pub struct DevDocsManager {
+    inner: crate::DevDocsManager,
+    runtime: Runtime,
+}
+
+impl DevDocsManager {
+    /// Create a manager with the default settings and initialize it
+    pub fn new() -> Result<Self> {
+        Self::from_builder(crate::DevDocsManager::builder())
+    }
+
+    /// Build a manager from a configured builder and initialize it
+    pub fn from_builder(builder: DevDocsManagerBuilder) -> Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let inner = builder.build()?;
+        runtime.block_on(inner.init())?;
+        Ok(DevDocsManager { inner, runtime })
+    }
+
+    /// The async manager, for anything the facade doesn't cover
+    pub fn inner(&self) -> &crate::DevDocsManager {
+        &self.inner
+    }
+
+    /// Directory all changes are written to
+    pub fn write_dir(&self) -> &Path {
+        self.inner.write_dir()
+    }
+
+    /// Link to an entry on the DevDocs site
+    pub fn entry_url(&self, slug: &str, path: &str) -> String {
+        self.inner.entry_url(slug, path)
+    }
+
+    /// Whether nothing was ever set up in the data dir
+    pub fn needs_onboarding(&self) -> bool {
+        self.runtime.block_on(self.inner.needs_onboarding())
+    }
+
+    /// List the available docs and suggest those the project `dir` belongs to needs
+    pub fn onboard(&self, dir: &Path) -> Result<Onboarding> {
+        self.runtime.block_on(self.inner.onboard(dir))
+    }
+
+    /// Refresh the list of available documentation
+    pub fn refresh_available_docs(&self) -> Result<Vec<Doc>> {
+        self.runtime.block_on(self.inner.refresh_available_docs())
+    }
+
+    /// Get the list of available documentation (cached or fresh)
+    pub fn get_available_docs(&self) -> Result<Vec<Doc>> {
+        self.runtime.block_on(self.inner.get_available_docs())
+    }
+
+    /// Add a new documentation
+    pub fn add_doc(&self, slug: &str, format: Option<Formats>) -> Result<()> {
+        self.runtime.block_on(self.inner.add_doc(slug, format))
+    }
+
+    /// Add a new documentation, rendering its pages in the given formats
+    pub fn add_doc_with_formats(&self, slug: &str, formats: Formats) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.add_doc_with_formats(slug, formats))
+    }
+
+    /// Installed docs that are no longer available upstream
+    pub fn orphaned_docs(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.orphaned_docs())
+    }
+
+    /// Remove a documentation
+    pub fn remove_doc(&self, slug: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.remove_doc(slug))
+    }
+
+    /// Delete the rendered pages of the given formats, keeping the doc installed
+    pub fn remove_formats(&self, slug: &str, formats: Formats) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.remove_formats(slug, formats))
+    }
+
+    /// Install only the pages of a doc referenced by its entries of the given types
+    pub fn add_doc_filtered(
+        &self,
+        slug: &str,
+        types: &[String],
+        format: Option<Formats>,
+    ) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.add_doc_filtered(slug, types, format))
+    }
+
+    /// Render the pages of an installed doc again in the given formats, without
+    /// downloading it
+    pub fn render_doc(&self, slug: &str, formats: Formats) -> Result<usize> {
+        self.runtime.block_on(self.inner.render_doc(slug, formats))
+    }
+
+    /// Download all available documentation
+    pub fn download_all(&self, format: Formats) -> Result<DownloadReport> {
+        self.runtime.block_on(self.inner.download_all(format))
+    }
+
+    /// Update a specific documentation
+    pub fn update_doc(&self, slug: &str) -> Result<UpdateStatus> {
+        self.runtime.block_on(self.inner.update_doc(slug))
+    }
+
+    /// Update all installed documentation
+    pub fn update_all(&self) -> Result<UpdateReport> {
+        self.runtime.block_on(self.inner.update_all())
+    }
+
+    /// Download a doc again even when it's current
+    pub fn force_update_doc(&self, slug: &str) -> Result<UpdateStatus> {
+        let cancel = tokio_util::sync::CancellationToken::new();
+        self.runtime
+            .block_on(self.inner.force_update_doc(slug, &cancel))
+    }
+
+    /// Resolve which docs [`add_doc`](Self::add_doc) would download
+    pub fn plan_add(&self, slugs: &[String], format: Option<Formats>) -> Result<Plan> {
+        self.runtime.block_on(self.inner.plan_add(slugs, format))
+    }
+
+    /// Resolve which files removing the docs would delete
+    pub fn plan_remove(&self, slugs: &[String]) -> Result<Plan> {
+        self.runtime.block_on(self.inner.plan_remove(slugs))
+    }
+
+    /// Resolve which installed docs have a newer version upstream
+    pub fn plan_update(&self, slugs: &[String]) -> Result<Plan> {
+        self.runtime.block_on(self.inner.plan_update(slugs))
+    }
+
+    /// Disk space used by the writable layer and by each installed doc
+    pub fn disk_usage(&self) -> Result<DiskUsage> {
+        self.runtime.block_on(self.inner.disk_usage())
+    }
+
+    /// Counts of what's installed and of what this manager has done since it was built
+    pub fn stats(&self) -> Result<ManagerStats> {
+        self.runtime.block_on(self.inner.stats())
+    }
+
+    /// List installed documentation
+    pub fn list_installed_docs(&self) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.list_installed_docs())
+    }
+
+    /// The slug of a doc, installing it in Markdown first unless it's installed already
+    pub fn ensure_installed(&self, name: &str) -> Result<String> {
+        self.runtime.block_on(self.inner.ensure_installed(name))
+    }
+
+    /// The docs of a group, user-defined or built in like `web`
+    pub fn expand_group(&self, name: &str) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.expand_group(name))
+    }
+
+    /// Check if a documentation is installed
+    pub fn is_doc_installed(&self, slug: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.is_doc_installed(slug))
+    }
+
+    /// Get information about an installed documentation
+    pub fn get_doc_info(&self, slug: &str) -> Result<Doc> {
+        self.runtime.block_on(self.inner.get_doc_info(slug))
+    }
+
+    /// The index of an installed doc
+    pub fn get_doc_index(&self, slug: &str) -> Result<DocIndex> {
+        self.runtime.block_on(self.inner.get_doc_index(slug))
+    }
+
+    /// Iterate over the entries of an installed doc, in index order
+    pub fn iter_entries(&self, slug: &str) -> Result<impl Iterator<Item = Entry> + use<>> {
+        self.runtime.block_on(self.inner.iter_entries(slug))
+    }
+
+    /// The entry types of an installed doc, with how many entries each has
+    pub fn entry_types(&self, slug: &str) -> Result<Vec<EntryType>> {
+        self.runtime.block_on(self.inner.entry_types(slug))
+    }
+
+    /// Entries that changed with the latest update of an installed doc
+    pub fn doc_changes(&self, slug: &str) -> Result<Option<DocChanges>> {
+        self.runtime.block_on(self.inner.doc_changes(slug))
+    }
+
+    /// Resolve a slug or alias to the slug of a doc
+    pub fn resolve_slug(&self, name: &str) -> Result<String> {
+        self.runtime.block_on(self.inner.resolve_slug(name))
+    }
+
+    /// Define a persistent alias for a slug
+    pub fn set_alias(&self, alias: &str, slug: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.set_alias(alias, slug))
+    }
+
+    /// Remove a user-defined alias, returning whether it existed
+    pub fn remove_alias(&self, alias: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.remove_alias(alias))
+    }
+
+    /// List the user-defined aliases
+    pub fn aliases(&self) -> HashMap<String, String> {
+        self.runtime.block_on(self.inner.aliases())
+    }
+
+    /// Enable or disable an installed doc
+    pub fn set_doc_enabled(&self, slug: &str, enabled: bool) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.set_doc_enabled(slug, enabled))
+    }
+
+    /// Whether an installed doc takes part in searches
+    pub fn is_doc_enabled(&self, slug: &str) -> bool {
+        self.runtime.block_on(self.inner.is_doc_enabled(slug))
+    }
+
+    /// The user settings
+    pub fn settings(&self) -> Settings {
+        self.runtime.block_on(self.inner.settings())
+    }
+
+    /// Change a setting and persist it
+    pub fn set_config(&self, key: &str, value: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.set_config(key, value))
+    }
+
+    /// Reset a setting to its default, returning whether it was set
+    pub fn unset_config(&self, key: &str) -> Result<bool> {
+        self.runtime.block_on(self.inner.unset_config(key))
+    }
+
+    /// Installed docs that are disabled, sorted by slug
+    pub fn disabled_docs(&self) -> Vec<String> {
+        self.runtime.block_on(self.inner.disabled_docs())
+    }
+
+    /// Signatures documented by an entry of an installed doc
+    pub fn signatures(&self, slug: &str, entry: &str) -> Result<Vec<Signature>> {
+        self.runtime.block_on(self.inner.signatures(slug, entry))
+    }
+
+    /// Search through installed documentation with fuzzy matching
+    pub fn search(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchResult>> {
+        self.runtime.block_on(self.inner.search(query, limit))
+    }
+
+    /// Search through installed documentation with explicit options
+    pub fn search_with(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        self.runtime
+            .block_on(self.inner.search_with(query, options))
+    }
+
+    /// Hit and miss counts of the query cache
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        self.inner.query_cache_stats()
+    }
+
+    /// Search, suggesting corrected queries when nothing matches well
+    pub fn search_response(&self, query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+        self.runtime
+            .block_on(self.inner.search_response(query, options))
+    }
+
+    /// Search the indexes of docs on devdocs.io, without installing them
+    pub fn search_remote(
+        &self,
+        query: &str,
+        docs: &[String],
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        self.runtime
+            .block_on(self.inner.search_remote(query, docs, options))
+    }
+
+    /// A single page of a doc on devdocs.io, rendered in `format`, without installing
+    /// the doc
+    pub fn remote_page(&self, slug: &str, path: &str, format: Formats) -> Result<String> {
+        self.runtime
+            .block_on(self.inner.remote_page(slug, path, format))
+    }
+
+    /// The HTML of a single page, downloading just that page when its doc isn't
+    /// installed
+    pub fn fetch_page(&self, slug: &str, path: &str) -> Result<String> {
+        self.runtime.block_on(self.inner.fetch_page(slug, path))
+    }
+
+    /// What's corrupt or missing among the files of an installed doc
+    pub fn verify_doc(&self, slug: &str) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.verify_doc(slug))
+    }
+
+    /// Guess what a misspelled query was meant to be
+    pub fn suggest(&self, query: &str, options: &SearchOptions) -> Result<Vec<String>> {
+        self.runtime.block_on(self.inner.suggest(query, options))
+    }
+
+    /// Run several searches at once, returning the results of each query in order
+    pub fn search_many(
+        &self,
+        queries: &[&str],
+        options: &SearchOptions,
+    ) -> Result<Vec<Vec<SearchResult>>> {
+        self.runtime
+            .block_on(self.inner.search_many(queries, options))
+    }
+
+    /// Search the code examples of the installed docs
+    pub fn search_code(&self, query: &str, options: &SearchOptions) -> Result<Vec<CodeResult>> {
+        self.runtime
+            .block_on(self.inner.search_code(query, options))
+    }
+
+    /// Search the text of the pages of the installed docs
+    pub fn search_content(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<ContentResult>> {
+        self.runtime
+            .block_on(self.inner.search_content(query, options))
+    }
+
+    /// Find the pages and sections closest in meaning to `query`
+    pub fn search_semantic(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        self.runtime
+            .block_on(self.inner.search_semantic(query, options))
+    }
+
+    /// Rank by name and by meaning at once
+    pub fn search_hybrid(&self, query: &str, options: &SearchOptions) -> Result<Vec<SearchResult>> {
+        self.runtime
+            .block_on(self.inner.search_hybrid(query, options))
+    }
+
+    /// Code blocks of a page of an installed doc, in document order
+    pub fn page_code_blocks(&self, slug: &str, path: &str) -> Result<Vec<CodeBlock>> {
+        self.runtime
+            .block_on(self.inner.page_code_blocks(slug, path))
+    }
+
+    /// Start an incremental search session over the installed docs
+    pub fn search_session(&self, options: &SearchOptions) -> Result<SearchSession> {
+        self.runtime.block_on(self.inner.search_session(options))
+    }
+
+    /// Append a query, and optionally the result picked for it, to the search history
+    pub fn record_search(&self, query: &str, chosen: Option<&str>) -> Result<()> {
+        self.runtime
+            .block_on(self.inner.record_search(query, chosen))
+    }
+
+    /// Past queries, oldest first
+    pub fn search_history(&self) -> Vec<HistoryEntry> {
+        self.runtime.block_on(self.inner.search_history())
+    }
+
+    /// Record that a page was opened, moving it to the front of the recent list
+    pub fn record_view(&self, slug: &str, path: &str) -> Result<()> {
+        self.runtime.block_on(self.inner.record_view(slug, path))
+    }
+
+    /// Recently opened pages, most recent first
+    pub fn recent_pages(&self, limit: usize) -> Vec<RecentPage> {
+        self.runtime.block_on(self.inner.recent_pages(limit))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[test]
+    fn test_blocking_facade_runs_without_a_runtime() {
+        let dir = temp_dir("blocking");
+        let manager = DevDocsManager::from_builder(test_builder(&dir)).unwrap();
+
+        manager.set_alias("js", "javascript").unwrap();
+        assert_eq!(
+            manager.aliases().get("js").map(String::as_str),
+            Some("javascript")
+        );
+        assert!(manager.list_installed_docs().unwrap().is_empty());
+    }
+}
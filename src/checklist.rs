@@ -0,0 +1,218 @@
+//! Picking docs to install from a checklist of the available docs, driven a line of
+//! input at a time so it works in any terminal.
+//!
+//! Each line either toggles docs by their number in the list (`3`, `1 4-6`), or
+//! filters the list by slug or name. `*` toggles every listed doc, an empty line
+//! accepts the selection and `q` gives up.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::Doc;
+
+/// Most docs listed at once, the filter narrows down the rest
+const MAX_ROWS: usize = 30;
+
+/// What a line of input left the checklist at
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecklistStep {
+    /// More input is expected
+    Continue,
+    /// The selection was accepted
+    Done,
+    /// The checklist was abandoned
+    Quit,
+}
+
+/// Available docs, the filter narrowing them down and the docs picked so far
+#[derive(Debug, Clone)]
+pub struct Checklist {
+    docs: Vec<Doc>,
+    installed: HashSet<String>,
+    filter: String,
+    selected: HashSet<String>,
+}
+
+impl Checklist {
+    /// A checklist of `docs`, where the `installed` ones are shown but can't be picked
+    pub fn new(docs: Vec<Doc>, installed: &[String]) -> Self {
+        Self {
+            docs,
+            installed: installed.iter().cloned().collect(),
+            filter: String::new(),
+            selected: HashSet::new(),
+        }
+    }
+
+    /// Docs matching the filter, in the order they're numbered
+    pub fn visible(&self) -> Vec<&Doc> {
+        let filter = self.filter.to_lowercase();
+        self.docs
+            .iter()
+            .filter(|doc| {
+                doc.slug.to_lowercase().contains(&filter)
+                    || doc.name.to_lowercase().contains(&filter)
+            })
+            .collect()
+    }
+
+    /// Slugs of the picked docs, in the order of the list
+    pub fn selection(&self) -> Vec<String> {
+        self.docs
+            .iter()
+            .filter(|doc| self.selected.contains(&doc.slug))
+            .map(|doc| doc.slug.clone())
+            .collect()
+    }
+
+    /// Estimated number of bytes the picked docs add up to
+    pub fn selection_bytes(&self) -> usize {
+        self.docs
+            .iter()
+            .filter(|doc| self.selected.contains(&doc.slug))
+            .map(|doc| doc.db_size)
+            .sum()
+    }
+
+    /// Apply a line of input
+    pub fn handle(&mut self, line: &str) -> ChecklistStep {
+        let line = line.trim();
+        match line {
+            "" => return ChecklistStep::Done,
+            "q" => return ChecklistStep::Quit,
+            "*" => {
+                let slugs: Vec<String> =
+                    self.visible().iter().map(|doc| doc.slug.clone()).collect();
+                for slug in slugs {
+                    self.toggle(&slug);
+                }
+                return ChecklistStep::Continue;
+            }
+            _ => {}
+        }
+
+        match numbers(line, self.docs.len()) {
+            Some(numbers) => {
+                let slugs: Vec<String> = {
+                    let visible = self.visible();
+                    numbers
+                        .iter()
+                        .filter_map(|n| visible.get(n.checked_sub(1)?))
+                        .map(|doc| doc.slug.clone())
+                        .collect()
+                };
+                for slug in slugs {
+                    self.toggle(&slug);
+                }
+            }
+            None => self.filter = line.strip_prefix('/').unwrap_or(line).to_string(),
+        }
+        ChecklistStep::Continue
+    }
+
+    fn toggle(&mut self, slug: &str) {
+        if self.installed.contains(slug) {
+            return;
+        }
+        if !self.selected.remove(slug) {
+            self.selected.insert(slug.to_string());
+        }
+    }
+
+    /// The list as shown before each line of input
+    pub fn render(&self) -> String {
+        let visible = self.visible();
+        let mut out = String::new();
+        for (i, doc) in visible.iter().take(MAX_ROWS).enumerate() {
+            let mark = if self.installed.contains(&doc.slug) {
+                "="
+            } else if self.selected.contains(&doc.slug) {
+                "x"
+            } else {
+                " "
+            };
+            let _ = writeln!(
+                out,
+                "[{}] {:>3}  {:<24} {:<32} {:>6.1} MB",
+                mark,
+                i + 1,
+                doc.slug,
+                doc.name,
+                doc.db_size as f64 / 1e6
+            );
+        }
+        if visible.len() > MAX_ROWS {
+            let _ = writeln!(
+                out,
+                "… and {} more, type part of a name to narrow the list down",
+                visible.len() - MAX_ROWS
+            );
+        }
+        if !self.filter.is_empty() {
+            let _ = writeln!(out, "filter: {} (`/` clears it)", self.filter);
+        }
+        let _ = writeln!(
+            out,
+            "{} selected, ~{:.1} MB — numbers toggle docs, `*` all listed, enter installs, q quits",
+            self.selected.len(),
+            self.selection_bytes() as f64 / 1e6
+        );
+        out
+    }
+}
+
+/// The numbers of a line like `1 4-6`, up to `max`; `None` when it's anything else
+fn numbers(line: &str, max: usize) -> Option<Vec<usize>> {
+    let mut numbers = Vec::new();
+    for token in line.split(|c: char| c.is_whitespace() || c == ',') {
+        if token.is_empty() {
+            continue;
+        }
+        match token.split_once('-') {
+            Some((from, to)) => {
+                let (from, to): (usize, usize) = (from.parse().ok()?, to.parse().ok()?);
+                numbers.extend(from..=to.min(max));
+            }
+            None => numbers.push(token.parse().ok()?),
+        }
+    }
+    Some(numbers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::sample_cached_doc;
+
+    #[test]
+    fn test_checklist_filters_and_toggles_docs() {
+
+        let docs = ["rust", "ruby~3.3", "python~3.12", "go"].map(|slug| {
+            let mut doc = sample_cached_doc(slug).doc;
+            doc.db_size = 2_000_000;
+            doc
+        });
+        let mut checklist = Checklist::new(docs.to_vec(), &["go".to_string()]);
+        assert_eq!(checklist.visible().len(), 4);
+
+        assert_eq!(checklist.handle("ru"), ChecklistStep::Continue);
+        let visible: Vec<&str> = checklist
+            .visible()
+            .iter()
+            .map(|d| d.slug.as_str())
+            .collect();
+        assert_eq!(visible, ["rust", "ruby~3.3"]);
+        checklist.handle("2");
+        checklist.handle("/");
+        // Installed docs can't be picked, numbers past the list are ignored
+        checklist.handle("1, 3-9");
+        assert_eq!(checklist.selection(), ["rust", "ruby~3.3", "python~3.12"]);
+        checklist.handle("1");
+        assert_eq!(checklist.selection(), ["ruby~3.3", "python~3.12"]);
+        assert_eq!(checklist.selection_bytes(), 4_000_000);
+        assert!(checklist.render().contains("2 selected, ~4.0 MB"));
+
+        assert_eq!(checklist.handle(""), ChecklistStep::Done);
+        assert_eq!(checklist.handle("q"), ChecklistStep::Quit);
+    }
+}
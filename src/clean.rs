@@ -0,0 +1,310 @@
+//! Stripping pages down to their documentation, without the site chrome around it
+
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use lol_html::errors::RewritingError;
+use lol_html::html_content::Element;
+use lol_html::{
+    EndTagHandler, RewriteStrSettings, doc_comments, doc_text, element, rewrite_str, text,
+};
+use rayon::prelude::*;
+use regex::Regex;
+use tracing::warn;
+
+/// Elements that are navigation or chrome wherever they appear
+const CHROME: &[&str] = &[
+    "nav",
+    "[role=navigation]",
+    "[role=banner]",
+    "[role=contentinfo]",
+    "[role=search]",
+    "script",
+    "noscript",
+    "iframe",
+    ".breadcrumb",
+    ".breadcrumbs",
+    "[aria-label=breadcrumb]",
+    "[class*=edit-page]",
+    "[class*=edit-link]",
+    "[class*=edit-this-page]",
+];
+
+/// Elements marking the main content of a page
+const MAIN: &[&str] = &["main", "[role=main]"];
+
+/// Text of links to edit or report a page, once trimmed of icons and punctuation
+const EDIT_LINKS: &str = r"(?i)^(?:(?:edit|improve|suggest (?:an )?edits? (?:to|for)) (?:this|the) (?:page|doc|document|article|section)|edit (?:on|in) (?:github|gitlab|bitbucket)|(?:view|show) (?:page )?source|report an? (?:issue|problem|bug)(?: with this page)?)$";
+
+/// Prefixes of the attributes analytics scripts hook into
+const TRACKING_ATTRIBUTES: &[&str] = &[
+    "data-ga",
+    "data-gtm",
+    "data-track",
+    "data-analytics",
+    "data-event",
+];
+
+/// Query parameters that only identify a campaign or click
+const TRACKING_PARAMS: &[&str] = &["fbclid", "gclid", "mc_cid", "mc_eid", "_ga"];
+
+/// Clean every page of a doc in place, keeping the pages that fail to parse as they are
+pub(crate) fn clean_pages(content: &mut HashMap<String, String>) {
+    let edit_links = Regex::new(EDIT_LINKS).unwrap();
+    content
+        .par_iter_mut()
+        .for_each(|(page, html)| match clean_page(html, &edit_links) {
+            Ok(cleaned) => *html = cleaned,
+            Err(e) => warn!("Failed to clean {}: {}", page, e),
+        });
+}
+
+/// Drop the navigation, edit links and tracking attributes of a page, and everything
+/// around its main content when it marks one
+fn clean_page(html: &str, edit_links: &Regex) -> Result<String, RewritingError> {
+    let html = strip_chrome(html)?;
+
+    // Which links are edit links and whether there's a main element can only be told
+    // once they've been read, so look before cutting
+    let has_main = Cell::new(false);
+    let links: RefCell<Vec<String>> = RefCell::default();
+    let mut handlers = vec![
+        element!("a", |_| {
+            links.borrow_mut().push(String::new());
+            Ok(())
+        }),
+        text!("a", |chunk| {
+            if let Some(text) = links.borrow_mut().last_mut() {
+                text.push_str(chunk.as_str());
+            }
+            Ok(())
+        }),
+    ];
+    for selector in MAIN {
+        handlers.push(element!(selector, |_| {
+            has_main.set(true);
+            Ok(())
+        }));
+    }
+    let settings = RewriteStrSettings {
+        element_content_handlers: handlers,
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(&html, settings)?;
+
+    let edits: HashSet<usize> = links
+        .into_inner()
+        .iter()
+        .enumerate()
+        .filter(|(_, text)| {
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            edit_links.is_match(text.trim_matches(|c: char| !c.is_alphanumeric()))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    cut(&html, &edits, has_main.get())
+}
+
+/// Remove the chrome elements, event handlers and tracking attributes and parameters
+fn strip_chrome(html: &str) -> Result<String, RewritingError> {
+    let mut handlers = vec![element!("*", |el| {
+        strip_tracking(el);
+        Ok(())
+    })];
+    for selector in CHROME {
+        handlers.push(element!(selector, |el| {
+            el.remove();
+            Ok(())
+        }));
+    }
+    let settings = RewriteStrSettings {
+        element_content_handlers: handlers,
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings)
+}
+
+fn strip_tracking(el: &mut Element) {
+    let names: Vec<String> = el.attributes().iter().map(|a| a.name()).collect();
+    for name in names {
+        let tracking = name.starts_with("on")
+            || name == "ping"
+            || TRACKING_ATTRIBUTES
+                .iter()
+                .any(|prefix| name.starts_with(prefix));
+        if tracking {
+            el.remove_attribute(&name);
+        } else if (name == "href" || name == "src")
+            && let Some(url) = el.get_attribute(&name)
+            && let Some(stripped) = strip_tracking_params(&url)
+        {
+            // The value comes from the page itself, so it's always a valid attribute
+            let _ = el.set_attribute(&name, &stripped);
+        }
+    }
+}
+
+/// A URL attribute without its tracking query parameters, `None` when it has none.
+/// Parameters may be separated by `&` or, as attribute values have them, `&amp;`.
+fn strip_tracking_params(url: &str) -> Option<String> {
+    let (rest, fragment) = match url.split_once('#') {
+        Some((rest, fragment)) => (rest, Some(fragment)),
+        None => (url, None),
+    };
+    let (path, query) = rest.split_once('?')?;
+    let query = query.replace("&amp;", "&");
+    let is_tracking = |param: &str| {
+        let key = param.split_once('=').map_or(param, |(key, _)| key);
+        key.starts_with("utm_") || TRACKING_PARAMS.contains(&key)
+    };
+    if !query.split('&').any(is_tracking) {
+        return None;
+    }
+
+    let kept: Vec<&str> = query
+        .split('&')
+        .filter(|param| !param.is_empty() && !is_tracking(param))
+        .collect();
+    let mut stripped = path.to_string();
+    if !kept.is_empty() {
+        stripped.push('?');
+        stripped.push_str(&kept.join("&amp;"));
+    }
+    if let Some(fragment) = fragment {
+        stripped.push('#');
+        stripped.push_str(fragment);
+    }
+    Some(stripped)
+}
+
+/// Remove the `edits`-th links, and with `has_main` everything outside the first main
+/// element
+fn cut(html: &str, edits: &HashSet<usize>, has_main: bool) -> Result<String, RewritingError> {
+    let links = Cell::new(0);
+    // Whether the parser is within the main element; outside of it when there's none
+    let inside = Rc::new(Cell::new(!has_main));
+    let found = Cell::new(!has_main);
+
+    let mut handlers = Vec::new();
+    for selector in MAIN {
+        handlers.push(element!(selector, |el| {
+            if !found.get() {
+                found.set(true);
+                inside.set(true);
+                el.remove_and_keep_content();
+                let inside = inside.clone();
+                let leave: EndTagHandler<'static> = Box::new(move |_| {
+                    inside.set(false);
+                    Ok(())
+                });
+                el.on_end_tag(leave)?;
+            }
+            Ok(())
+        }));
+    }
+    handlers.push(element!("*", |el| {
+        if !inside.get() {
+            el.remove_and_keep_content();
+        }
+        Ok(())
+    }));
+    handlers.push(element!("a", |el| {
+        if edits.contains(&links.get()) {
+            el.remove();
+        }
+        links.set(links.get() + 1);
+        Ok(())
+    }));
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: handlers,
+        document_content_handlers: vec![
+            doc_text!(|chunk| {
+                if !inside.get() {
+                    chunk.remove();
+                }
+                Ok(())
+            }),
+            doc_comments!(|comment| {
+                if !inside.get() {
+                    comment.remove();
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Formats;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_clean_setting_strips_page_chrome() {
+        let page = r#"<html><body>
+            <nav><a href="/">Home</a></nav>
+            <div class="sidebar">Guides</div>
+            <main>
+              <h1 onclick="track()" data-ga-label="title">Vec</h1>
+              <p>A <a href="https://rust.test/?utm_source=docs&amp;page=2#new">growable</a> array.</p>
+              <a href="https://github.com/x/edit/vec.md">✎ Edit this page</a>
+              <script>analytics()</script>
+            </main>
+            <footer>© Someone</footer>
+        </body></html>"#;
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"Vec","path":"vec","type":"struct"}],
+                    "types":[{"name":"struct","count":1,"slug":"struct"}]}"#,
+            )
+            .respond(
+                "/documents/rust/db.json",
+                serde_json::json!({ "vec": page }).to_string(),
+            );
+        let manager = test_builder(temp_dir("clean"))
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        assert!(manager.set_config("clean", "maybe").await.is_err());
+        manager.set_config("clean", "true").await.unwrap();
+        assert_eq!(
+            manager.settings().await.entries(),
+            [("clean".to_string(), "true".to_string())]
+        );
+
+        manager.add_doc("rust", Some(Formats::HTML)).await.unwrap();
+        let file = manager
+            .page_file("rust", "vec", Formats::HTML)
+            .await
+            .unwrap();
+        let html = std::fs::read_to_string(file).unwrap();
+        assert!(html.contains("<h1>Vec</h1>"));
+        assert!(html.contains(r#"<a href="https://rust.test/?page=2#new">growable</a>"#));
+        for chrome in [
+            "Home",
+            "Guides",
+            "Edit this page",
+            "analytics",
+            "Someone",
+            "<main",
+        ] {
+            assert!(!html.contains(chrome), "{} is left in {}", chrome, html);
+        }
+
+        assert!(manager.unset_config("clean").await.unwrap());
+        assert_eq!(manager.settings().await.get("clean").unwrap(), None);
+    }
+}
@@ -0,0 +1,357 @@
+//! Searching the text of pages rather than the names of entries
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::Result;
+use tracing::warn;
+
+use crate::{
+    ContentResult, DevDocsManager, Entry, SECTION_TYPE, SearchOptions, cap_per_doc, page_path,
+};
+
+/// Characters of context kept on either side of the first match of a scanned page
+const SNIPPET_CONTEXT: usize = 80;
+
+impl DevDocsManager {
+    /// Search the text of the pages of the installed docs.
+    ///
+    /// With the `fulltext` feature, docs are searched through the index built when
+    /// they were installed, ranked by BM25 and supporting the tantivy query syntax
+    /// (`"exact phrase"`, `+required`, `-excluded`). Otherwise, and for docs installed
+    /// without an index, pages are scanned for every word of the query (or for the
+    /// whole query when it's quoted). Honors the scope, limit and per-doc settings of
+    /// `options`.
+    pub async fn search_content(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<ContentResult>> {
+        if query.trim().is_empty() || options.limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let slugs = self.resolve_scope(&options.docs).await?;
+
+        let mut results = Vec::new();
+        for slug in slugs {
+            match self.search_doc_content(&slug, query, options.limit).await {
+                Ok(found) => results.extend(found),
+                Err(e) => warn!("Skipping the pages of {}: {}", slug, e),
+            }
+        }
+
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.doc_slug.cmp(&b.doc_slug))
+                .then_with(|| a.page.cmp(&b.page))
+        });
+        let mut under_cap = cap_per_doc(options.max_per_doc);
+        results.retain(|r| under_cap(&r.doc_slug));
+        results.truncate(options.limit);
+        Ok(results)
+    }
+
+    /// The best `limit` pages of a doc for `query`
+    async fn search_doc_content(
+        &self,
+        slug: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ContentResult>> {
+        #[cfg(feature = "fulltext")]
+        let index = std::path::Path::new(crate::FULLTEXT_DIR).join(slug);
+        #[cfg(feature = "fulltext")]
+        if let Some(dir) = self.resolve_path(&index) {
+            let (slug, query) = (slug.to_string(), query.to_string());
+            return tokio::task::spawn_blocking(move || {
+                fulltext::search(&dir, &slug, &query, limit)
+            })
+            .await?;
+        }
+
+        let titles = {
+            let cache = self.cache.read().await;
+            cache
+                .get(slug)
+                .map(|c| page_titles(&c.index.entries))
+                .unwrap_or_default()
+        };
+        let pages = self.html_pages(slug).await?;
+        let (slug, query) = (slug.to_string(), query.to_string());
+        let mut results: Vec<ContentResult> = tokio::task::spawn_blocking(move || {
+            pages
+                .into_iter()
+                .filter_map(|(page, html)| {
+                    let (score, snippet, highlights) = scan_page(&query, &page_text(&html))?;
+                    Some(ContentResult {
+                        doc_slug: slug.clone(),
+                        title: titles.get(&page).cloned().unwrap_or_else(|| page.clone()),
+                        page,
+                        snippet,
+                        highlights,
+                        score,
+                    })
+                })
+                .collect()
+        })
+        .await?;
+
+        results.sort_by(|a, b| b.score.total_cmp(&a.score).then(a.page.cmp(&b.page)));
+        results.truncate(limit);
+        Ok(results)
+    }
+}
+
+/// Title of each page: the name of the first entry pointing at it
+pub(crate) fn page_titles(entries: &[Entry]) -> HashMap<String, String> {
+    let mut titles = HashMap::new();
+    for entry in entries.iter().filter(|e| e.entry_type != SECTION_TYPE) {
+        titles
+            .entry(page_path(&entry.path).to_string())
+            .or_insert_with(|| entry.name.clone());
+    }
+    titles
+}
+
+/// The readable text of a page, with tags dropped and whitespace collapsed
+pub(crate) fn page_text(html: &str) -> String {
+    use lol_html::{RewriteStrSettings, doc_text, element, rewrite_str};
+    use std::cell::RefCell;
+
+    let text = RefCell::new(String::new());
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!("*", |_| {
+            // Keep the words of neighbouring blocks apart
+            text.borrow_mut().push(' ');
+            Ok(())
+        })],
+        document_content_handlers: vec![doc_text!(|chunk| {
+            text.borrow_mut().push_str(chunk.as_str());
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    if rewrite_str(html, settings).is_err() {
+        return String::new();
+    }
+
+    let text = text.into_inner();
+    crate::decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// Score a page by how often the words of `query` occur in it, if they all do.
+///
+/// Returns the score, a snippet around the first match and the byte ranges of the
+/// matches within the snippet.
+fn scan_page(query: &str, text: &str) -> Option<(f32, String, Vec<Range<usize>>)> {
+    let query = query.to_ascii_lowercase();
+    let terms: Vec<&str> = match query.trim().strip_prefix('"') {
+        Some(phrase) => vec![phrase.strip_suffix('"').unwrap_or(phrase)],
+        None => query.split_whitespace().collect(),
+    };
+    let terms: Vec<&str> = terms.into_iter().filter(|t| !t.is_empty()).collect();
+
+    // ASCII lowercasing keeps byte offsets valid for the original text
+    let lower = text.to_ascii_lowercase();
+    let mut score = 0;
+    for term in &terms {
+        let count = lower.matches(term).count();
+        if count == 0 {
+            return None;
+        }
+        score += count;
+    }
+
+    let first = lower.find(terms.first()?)?;
+    let mut start = first.saturating_sub(SNIPPET_CONTEXT);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    let mut end = (first + SNIPPET_CONTEXT * 2).min(text.len());
+    while !text.is_char_boundary(end) {
+        end += 1;
+    }
+
+    let window = &lower[start..end];
+    let mut highlights: Vec<Range<usize>> = terms
+        .iter()
+        .flat_map(|term| {
+            window
+                .match_indices(term)
+                .map(|(i, m)| i..i + m.len())
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    highlights.sort_by_key(|r| r.start);
+
+    Some((score as f32, text[start..end].to_string(), highlights))
+}
+
+#[cfg(feature = "fulltext")]
+pub(crate) mod fulltext {
+    //! The tantivy index of a doc's pages
+
+    use std::path::Path;
+
+    use anyhow::Result;
+    use tantivy::collector::TopDocs;
+    use tantivy::query::QueryParser;
+    use tantivy::schema::{STORED, STRING, Schema, TEXT, Value};
+    use tantivy::snippet::SnippetGenerator;
+    use tantivy::{Index, IndexWriter, TantivyDocument, doc};
+
+    use crate::ContentResult;
+
+    /// Memory the index writer may use while building an index
+    const WRITER_MEMORY: usize = 50_000_000;
+
+    fn schema() -> Schema {
+        let mut builder = Schema::builder();
+        builder.add_text_field("page", STRING | STORED);
+        builder.add_text_field("title", TEXT | STORED);
+        builder.add_text_field("body", TEXT | STORED);
+        builder.build()
+    }
+
+    /// Build a fresh index in `dir` from `(page, title, text)` triples
+    pub(crate) fn build(dir: &Path, pages: &[(String, String, String)]) -> Result<()> {
+        if dir.exists() {
+            std::fs::remove_dir_all(dir)?;
+        }
+        std::fs::create_dir_all(dir)?;
+
+        let schema = schema();
+        let (page, title, body) = (
+            schema.get_field("page")?,
+            schema.get_field("title")?,
+            schema.get_field("body")?,
+        );
+        let index = Index::create_in_dir(dir, schema)?;
+        let mut writer: IndexWriter = index.writer(WRITER_MEMORY)?;
+        for (path, name, text) in pages {
+            writer.add_document(doc!(
+                page => path.as_str(),
+                title => name.as_str(),
+                body => text.as_str(),
+            ))?;
+        }
+        writer.commit()?;
+        Ok(())
+    }
+
+    /// The best `limit` pages of the index in `dir`, with highlighted snippets
+    pub(crate) fn search(
+        dir: &Path,
+        slug: &str,
+        query: &str,
+        limit: usize,
+    ) -> Result<Vec<ContentResult>> {
+        let index = Index::open_in_dir(dir)?;
+        let schema = index.schema();
+        let (page, title, body) = (
+            schema.get_field("page")?,
+            schema.get_field("title")?,
+            schema.get_field("body")?,
+        );
+
+        let searcher = index.reader()?.searcher();
+        let mut parser = QueryParser::for_index(&index, vec![title, body]);
+        parser.set_field_boost(title, 2.0);
+        parser.set_conjunction_by_default();
+        // Stray syntax shouldn't fail the search, just be matched as text
+        let (query, _) = parser.parse_query_lenient(query);
+
+        let mut snippets = SnippetGenerator::create(&searcher, &*query, body)?;
+        snippets.set_max_num_chars(super::SNIPPET_CONTEXT * 2);
+
+        let mut results = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let doc: TantivyDocument = searcher.doc(address)?;
+            let text = |field| {
+                doc.get_first(field)
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default()
+                    .to_string()
+            };
+            let snippet = snippets.snippet_from_doc(&doc);
+            results.push(ContentResult {
+                doc_slug: slug.to_string(),
+                page: text(page),
+                title: text(title),
+                snippet: snippet.fragment().to_string(),
+                highlights: snippet.highlighted().to_vec(),
+                score,
+            });
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{insert_doc, sample_cached_doc, temp_dir, test_builder};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_search_content_matches_page_text() {
+        let dir = temp_dir("content-search");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![Entry {
+            name: "std::future".to_string(),
+            path: PathBuf::from("std/future/index"),
+            entry_type: "module".to_string(),
+        }];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust/std/future")).unwrap();
+        std::fs::write(
+            dir.join("rust/std/future/index.html"),
+            "<h1>Module std::future</h1><p>Dropping a future cancels it.</p>",
+        )
+        .unwrap();
+        std::fs::write(dir.join("rust/other.html"), "<p>Unrelated.</p>").unwrap();
+
+        let options = SearchOptions::default();
+        let results = manager
+            .search_content("future cancels", &options)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page, "std/future/index");
+        assert_eq!(results[0].title, "std::future");
+        let highlight = results[0].highlights[0].clone();
+        assert_eq!(&results[0].snippet[highlight], "future");
+    }
+
+    #[cfg(feature = "fulltext")]
+    #[test]
+    fn test_fulltext_index_matches_phrases() {
+        let dir = temp_dir("fulltext").join("rust");
+        let pages = [
+            (
+                "std/future/index".to_string(),
+                "std::future".to_string(),
+                "Dropping a future cancels it.".to_string(),
+            ),
+            (
+                "std/task/index".to_string(),
+                "std::task".to_string(),
+                "A future is cancelled by the executor, dropping it.".to_string(),
+            ),
+        ];
+        fulltext::build(&dir, &pages).unwrap();
+
+        let results = fulltext::search(&dir, "rust", "\"future cancels\"", 10).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].title, "std::future");
+        assert!(!results[0].highlights.is_empty());
+
+        let results = fulltext::search(&dir, "rust", "dropping future", 10).unwrap();
+        assert_eq!(results.len(), 2);
+    }
+}
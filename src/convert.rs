@@ -0,0 +1,197 @@
+//! Converting pages through an external command, like pandoc, instead of the built-in
+//! converters
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result, bail};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::{DevDocsManager, Formats};
+
+/// What a converter can be configured for, as in `converter.<target>` config keys
+pub(crate) const CONVERTER_TARGETS: &[&str] = &["markdown", "epub"];
+
+/// How long a converter may take on a page before it's killed
+pub(crate) const CONVERTER_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl DevDocsManager {
+    /// The command configured to convert pages for `target`, if any
+    pub(crate) async fn converter(&self, target: &str) -> Option<String> {
+        self.settings.read().await.converters.get(target).cloned()
+    }
+}
+
+/// Render a page as markdown through `converter`, or the built-in converter when there's
+/// none or it fails
+pub(crate) async fn render_markdown(
+    converter: Option<&str>,
+    slug: &str,
+    page: &str,
+    contents: &str,
+) -> String {
+    if let Some(command) = converter {
+        let html = crate::rewrite_links(contents, slug, page, "md");
+        match run(command, &html, CONVERTER_TIMEOUT).await {
+            Ok(markdown) => return markdown,
+            Err(e) => warn!(
+                "Converting {}/{} with the built-in converter: {}",
+                slug, page, e
+            ),
+        }
+    }
+    crate::render_page(slug, page, contents, Formats::MARKDOWN)
+}
+
+/// Pipe `input` through `command`, returning what it writes to stdout.
+///
+/// The page is written to the command's stdin while its output is read, so commands
+/// that stream don't block on a full pipe. Commands that exit unsuccessfully or take
+/// longer than `timeout` are errors, and killed in the latter case.
+pub(crate) async fn run(command: &str, input: &str, timeout: Duration) -> Result<String> {
+    let args = split_command(command);
+    let Some((program, args)) = args.split_first() else {
+        bail!("Empty converter command");
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Can't run '{}'", program))?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let mut stderr = child.stderr.take().unwrap();
+    let converted = async {
+        let write = async {
+            // Commands may exit without reading all of their input
+            let _ = stdin.write_all(input.as_bytes()).await;
+            drop(stdin);
+        };
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let (_, read, _) = tokio::join!(
+            write,
+            stdout.read_to_end(&mut output),
+            stderr.read_to_end(&mut errors)
+        );
+        read?;
+        let status = child.wait().await?;
+        if !status.success() {
+            let errors = String::from_utf8_lossy(&errors);
+            bail!(
+                "'{}' failed with {}: {}",
+                program,
+                status,
+                errors.lines().next().unwrap_or_default()
+            );
+        }
+        String::from_utf8(output).context("Converter output isn't UTF-8")
+    };
+
+    tokio::time::timeout(timeout, converted)
+        .await
+        .with_context(|| format!("'{}' timed out after {:?}", program, timeout))?
+}
+
+/// Split a command line into its program and arguments, honoring single and double
+/// quotes and backslash escapes
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut arg: Option<String> = None;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    arg.get_or_insert_default().push(next);
+                }
+            }
+            (Some(_), c) => arg.get_or_insert_default().push(c),
+            (None, '"' | '\'') => {
+                quote = Some(c);
+                arg.get_or_insert_default();
+            }
+            (None, c) if c.is_whitespace() => args.extend(arg.take()),
+            (None, c) => arg.get_or_insert_default().push(c),
+        }
+    }
+    args.extend(arg);
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::render_page;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_converter_setting_renders_markdown_externally() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"Vec","path":"vec","type":"struct"}],
+                    "types":[{"name":"struct","count":1,"slug":"struct"}]}"#,
+            )
+            .respond(
+                "/documents/rust/db.json",
+                serde_json::json!({ "vec": "<h1>Vec</h1>" }).to_string(),
+            );
+        let manager = test_builder(temp_dir("converter"))
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        assert!(manager.set_config("converter.pdf", "pandoc").await.is_err());
+        assert!(manager.set_config("converter.markdown", " ").await.is_err());
+        manager
+            .set_config("converter.markdown", r#"sed "s/Vec/Converted vec/""#)
+            .await
+            .unwrap();
+        assert_eq!(
+            manager.settings().await.get("converter.markdown").unwrap(),
+            Some(r#"sed "s/Vec/Converted vec/""#.to_string())
+        );
+
+        manager
+            .add_doc("rust", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        let file = manager
+            .page_file("rust", "vec", Formats::MARKDOWN)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(file).unwrap(),
+            "<h1>Converted vec</h1>"
+        );
+
+        // Failing and hanging converters fall back to the built-in converter
+        let md = render_markdown(Some("false"), "rust", "vec", "<h1>Vec</h1>").await;
+        assert_eq!(
+            md,
+            render_page("rust", "vec", "<h1>Vec</h1>", Formats::MARKDOWN)
+        );
+        let slow = run("sleep 5", "", Duration::from_millis(100)).await;
+        assert!(slow.unwrap_err().to_string().contains("timed out"));
+
+        assert!(manager.unset_config("converter.markdown").await.unwrap());
+        assert!(manager.settings().await.converters.is_empty());
+    }
+}
@@ -0,0 +1,759 @@
+//! Exporting installed docs to formats meant for reading outside of the CLI
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::Path;
+use std::sync::LazyLock;
+
+use anyhow::Result;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tracing::warn;
+
+use crate::convert::{self, CONVERTER_TIMEOUT};
+use crate::paths::disk_page;
+use crate::{DevDocsError, DevDocsManager, Doc, Entry, add_ext, escape_html, page_path};
+
+/// Links to pages of the doc: the page without `.html`, and the fragment
+static PAGE_LINK: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r##"href="([^"#]+)\.html(?:#([^"]*))?""##).unwrap());
+/// Markdown links, code spans, strong and emphasized text, as `roff_inline` turns them
+static MD_LINK: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap());
+static MD_CODE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"`([^`]+)`").unwrap());
+static MD_STRONG: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\*\*([^*]+)\*\*").unwrap());
+static MD_EMPHASIS: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"\*([^*]+)\*").unwrap());
+/// Void elements, which XHTML needs closed
+static VOID_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"<(br|hr|img|input|meta|link|col|area|wbr|source)\b([^>]*?)\s*/?>").unwrap()
+});
+
+impl DevDocsManager {
+    /// Write a self-contained static website for the given docs (all installed docs if empty).
+    ///
+    /// The site has an index page linking every doc, a table of contents per doc
+    /// grouped by entry type next to it as `<slug>.toc.html`, out of the way of the
+    /// doc's pages, and the doc's pages with links made relative, so it
+    /// can be served from any directory without JavaScript. The user's
+    /// [`USER_CSS`](crate::USER_CSS) and [`USER_JS`](crate::USER_JS) are inlined in
+    /// every page.
+    pub async fn export_site(&self, dir: &Path, slugs: &[String]) -> Result<()> {
+        let slugs = self.export_slugs(slugs).await?;
+        fs::create_dir_all(dir).await?;
+
+        let mut docs = Vec::new();
+        for slug in &slugs {
+            let info = self.get_doc_info(slug).await?;
+            let entries = self.export_entries(slug).await?;
+            let pages = self.html_pages(slug).await?;
+            let doc_dir = dir.join(slug);
+
+            for (page, html) in &pages {
+                let file = add_ext(doc_dir.join(&*disk_page(page)), "html");
+                if let Some(parent) = file.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                // Links back up to the site root, relative to the page's depth
+                let depth = Path::new(page).components().count();
+                let root = "../".repeat(depth);
+                let nav = format!(
+                    r#"<a href="{root}index.html">All docs</a> / <a href="{root}{toc}">{name}</a>"#,
+                    toc = escape_html(&toc_file(slug)),
+                    name = escape_html(&info.name),
+                );
+                let page = site_page(&info.name, &nav, html);
+                fs::write(file, self.with_user_assets(&page)).await?;
+            }
+
+            let mut toc = String::new();
+            // Sections are reachable from their pages, listing them would drown the rest
+            let entries: Vec<Entry> = entries
+                .into_iter()
+                .filter(|e| e.entry_type != crate::SECTION_TYPE)
+                .collect();
+            for (entry_type, entries) in group_by_type(&entries) {
+                writeln!(toc, "<h2>{}</h2>\n<ul>", escape_html(entry_type))?;
+                for entry in entries {
+                    writeln!(
+                        toc,
+                        r#"<li><a href="{}/{}">{}</a></li>"#,
+                        escape_html(slug),
+                        escape_html(&entry_href(entry)),
+                        escape_html(&entry.name)
+                    )?;
+                }
+                toc.push_str("</ul>\n");
+            }
+            let nav = r#"<a href="index.html">All docs</a>"#;
+            let toc = site_page(&info.name, nav, &toc);
+            fs::write(dir.join(toc_file(slug)), self.with_user_assets(&toc)).await?;
+
+            docs.push((slug.clone(), info.name));
+        }
+
+        docs.sort_by(|a, b| a.1.cmp(&b.1));
+        let mut index = String::from("<ul>\n");
+        for (slug, name) in docs {
+            writeln!(
+                index,
+                r#"<li><a href="{}">{}</a></li>"#,
+                escape_html(&toc_file(&slug)),
+                escape_html(&name)
+            )?;
+        }
+        index.push_str("</ul>\n");
+        let index = site_page("Docs", "", &index);
+        fs::write(dir.join("index.html"), self.with_user_assets(&index)).await?;
+
+        Ok(())
+    }
+
+    /// Render a whole doc as one HTML document, pages ordered by the index.
+    ///
+    /// Links between pages of the doc point at the pages' sections instead.
+    pub async fn export_single_page(&self, slug: &str) -> Result<String> {
+        let (info, pages) = self.ordered_pages(slug).await?;
+        let ids: HashMap<&str, String> = pages
+            .iter()
+            .map(|(page, _)| (page.as_str(), page_id(page)))
+            .collect();
+
+        let mut toc = String::from("<ul>\n");
+        let mut body = String::new();
+        for (page, html) in &pages {
+            let id = &ids[page.as_str()];
+            writeln!(
+                toc,
+                r##"<li><a href="#{}">{}</a></li>"##,
+                id,
+                escape_html(page)
+            )?;
+            let html = rewrite_page_links(html, page, |target, fragment| {
+                let id = ids.get(target)?;
+                Some(format!("#{}", fragment.unwrap_or(id)))
+            });
+            writeln!(body, "<section id=\"{}\">\n{}\n</section>", id, html)?;
+        }
+        toc.push_str("</ul>\n");
+
+        Ok(self.with_user_assets(&site_page(&info.name, &toc, &body)))
+    }
+
+    /// Write a doc as an EPUB book to `path`, one chapter per page, ordered by the index
+    pub async fn export_epub(&self, slug: &str, path: &Path) -> Result<()> {
+        let (info, pages) = self.ordered_pages(slug).await?;
+        let chapters: HashMap<&str, String> = pages
+            .iter()
+            .enumerate()
+            .map(|(i, (page, _))| (page.as_str(), format!("page-{}.xhtml", i)))
+            .collect();
+
+        let converter = self.converter("epub").await;
+        let mut files = Vec::new();
+        for (page, html) in &pages {
+            let html = rewrite_page_links(html, page, |target, fragment| {
+                let chapter = chapters.get(target)?;
+                Some(match fragment {
+                    Some(fragment) => format!("{}#{}", chapter, fragment),
+                    None => chapter.clone(),
+                })
+            });
+            let html = match &converter {
+                Some(command) => match convert::run(command, &html, CONVERTER_TIMEOUT).await {
+                    Ok(converted) => converted,
+                    Err(e) => {
+                        warn!("Keeping the HTML of {}/{}: {}", slug, page, e);
+                        html
+                    }
+                },
+                None => html,
+            };
+            files.push((
+                chapters[page.as_str()].clone(),
+                xhtml_page(page, &to_xhtml(&html)),
+            ));
+        }
+
+        let mut nav = String::new();
+        let mut manifest = String::new();
+        let mut spine = String::new();
+        for (page, _) in &pages {
+            let chapter = &chapters[page.as_str()];
+            writeln!(
+                nav,
+                r#"<li><a href="{}">{}</a></li>"#,
+                chapter,
+                escape_html(page)
+            )?;
+            writeln!(
+                manifest,
+                r#"<item id="{0}" href="{0}" media-type="application/xhtml+xml"/>"#,
+                chapter
+            )?;
+            writeln!(spine, r#"<itemref idref="{}"/>"#, chapter)?;
+        }
+        let nav = format!(
+            "<nav epub:type=\"toc\" id=\"toc\">\n<h1>{}</h1>\n<ol>\n{}</ol>\n</nav>",
+            escape_html(&info.name),
+            nav
+        );
+        files.push(("nav.xhtml".to_string(), xhtml_page(&info.name, &nav)));
+        let opf = format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<package xmlns="http://www.idpf.org/2007/opf" version="3.0" unique-identifier="id">
+<metadata xmlns:dc="http://purl.org/dc/elements/1.1/">
+<dc:identifier id="id">devdocs:{slug}:{mtime}</dc:identifier>
+<dc:title>{title}</dc:title>
+<dc:language>en</dc:language>
+<meta property="dcterms:modified">{modified}</meta>
+</metadata>
+<manifest>
+<item id="nav" href="nav.xhtml" media-type="application/xhtml+xml" properties="nav"/>
+{manifest}</manifest>
+<spine>
+{spine}</spine>
+</package>
+"#,
+            slug = info.slug,
+            mtime = info.mtime,
+            title = escape_html(&info.name),
+            modified = epub_timestamp(info.mtime),
+        );
+        files.push(("content.opf".to_string(), opf));
+
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || write_epub(&path, &files)).await??;
+        Ok(())
+    }
+
+    /// The HTML pages of a doc, in the order the index lists them by type.
+    ///
+    /// Pages no entry points at come last, sorted by path.
+    async fn ordered_pages(&self, slug: &str) -> Result<(Doc, Vec<(String, String)>)> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let info = self.get_doc_info(&slug).await?;
+        let entries = self.export_entries(&slug).await?;
+        let mut pages = self.html_pages(&slug).await?;
+
+        let mut ordered = Vec::new();
+        for (_, entries) in group_by_type(&entries) {
+            for entry in entries {
+                let page = page_path(&entry.path);
+                if let Some(html) = pages.remove(page) {
+                    ordered.push((page.to_string(), html));
+                }
+            }
+        }
+        let mut rest: Vec<_> = pages.into_iter().collect();
+        rest.sort();
+        ordered.extend(rest);
+
+        Ok((info, ordered))
+    }
+
+    /// Render the page of an entry as a man page (roff), looking the entry up by name
+    pub async fn entry_man_page(&self, slug: &str, name: &str) -> Result<String> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let info = self.get_doc_info(&slug).await?;
+        let entry = self.find_entry(&slug, name).await?;
+
+        let page = page_path(&entry.path);
+        let markdown =
+            match self.resolve_path(&add_ext(Path::new(&slug).join(&*disk_page(page)), "md")) {
+                Some(file) => fs::read_to_string(file).await?,
+                None => {
+                    let file = self.page_file(&slug, page, crate::Formats::HTML).await?;
+                    html2md::parse_html(&fs::read_to_string(file).await?)
+                }
+            };
+
+        Ok(markdown_to_roff(&entry.name, &info.name, &markdown))
+    }
+
+    /// Resolve the docs to export, defaulting to every installed doc in slug order
+    pub async fn export_slugs(&self, slugs: &[String]) -> Result<Vec<String>> {
+        if slugs.is_empty() {
+            let mut installed = self.list_installed_docs().await?;
+            installed.sort();
+            return Ok(installed);
+        }
+
+        let mut resolved = Vec::new();
+        for slug in slugs {
+            let Some(slug) = self.resolve_installed(slug).await else {
+                return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+            };
+            resolved.push(slug);
+        }
+        Ok(resolved)
+    }
+
+    /// Write every searchable entry of the given docs (all installed docs if empty) to
+    /// `out` as JSON Lines, one [`ExportedEntry`] per line, returning how many.
+    ///
+    /// Docs are written in slug order and their entries in index order, sections
+    /// included, so exports of the same docs diff cleanly.
+    pub async fn export_jsonl(
+        &self,
+        out: &mut impl std::io::Write,
+        slugs: &[String],
+    ) -> Result<usize> {
+        let mut count = 0;
+        for slug in self.export_slugs(slugs).await? {
+            for entry in self.export_entries(&slug).await? {
+                let path = entry.path.to_string_lossy();
+                let line = ExportedEntry {
+                    url: self.entry_url(&slug, &path),
+                    doc: slug.clone(),
+                    name: entry.name,
+                    entry_type: entry.entry_type,
+                    path: path.into_owned(),
+                };
+                serde_json::to_writer(&mut *out, &line)?;
+                out.write_all(b"\n")?;
+                count += 1;
+            }
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// The index entries of an installed doc, in index order
+    async fn export_entries(&self, slug: &str) -> Result<Vec<Entry>> {
+        let cache = self.cache.read().await;
+        let cached_doc = cache
+            .get(slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?;
+        Ok(cached_doc.index.entries.clone())
+    }
+}
+
+/// A line of [`export_jsonl`](DevDocsManager::export_jsonl)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedEntry {
+    /// Slug of the doc the entry belongs to
+    pub doc: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    /// Path of the entry within its doc, with its `#fragment` if any
+    pub path: String,
+    /// Link to the entry on the DevDocs site
+    pub url: String,
+}
+
+/// Group entries by their type, keeping the order types first appear in
+fn group_by_type(entries: &[Entry]) -> Vec<(&str, Vec<&Entry>)> {
+    let mut order: Vec<&str> = Vec::new();
+    let mut groups: HashMap<&str, Vec<&Entry>> = HashMap::new();
+    for entry in entries {
+        let group = groups.entry(&entry.entry_type).or_insert_with(|| {
+            order.push(&entry.entry_type);
+            Vec::new()
+        });
+        group.push(entry);
+    }
+    order
+        .into_iter()
+        .map(|entry_type| (entry_type, groups.remove(entry_type).unwrap_or_default()))
+        .collect()
+}
+
+/// Name of the table of contents of a doc in an exported site, next to the doc's
+/// directory, where no page of the doc can be
+fn toc_file(slug: &str) -> String {
+    format!("{}.toc.html", slug)
+}
+
+/// Link to an entry's page, relative to the doc root, keeping its fragment
+fn entry_href(entry: &Entry) -> String {
+    let path = entry.path.to_string_lossy();
+    let (page, fragment) = match path.split_once('#') {
+        Some((page, fragment)) => (page, format!("#{}", fragment)),
+        None => (path.as_ref(), String::new()),
+    };
+    let page = add_ext(disk_page(page).into_owned().into(), "html");
+    format!("{}{}", page.to_string_lossy(), fragment)
+}
+
+fn site_page(title: &str, nav: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n<nav>{}</nav>\n<main>\n{}\n</main>\n</body>\n</html>\n",
+        escape_html(title),
+        nav,
+        body
+    )
+}
+
+/// Point `href`s at other pages of the doc somewhere else.
+///
+/// `target` gets the linked page path, resolved against `page`, and the fragment;
+/// links it returns `None` for are kept.
+fn rewrite_page_links(
+    html: &str,
+    page: &str,
+    target: impl Fn(&str, Option<&str>) -> Option<String>,
+) -> String {
+    PAGE_LINK
+        .replace_all(html, |caps: &Captures| {
+            let fragment = caps.get(2).map(|m| m.as_str());
+            let Some(linked) = resolve_from(page, &caps[1]) else {
+                return caps[0].to_string();
+            };
+            match target(&linked, fragment) {
+                Some(href) => format!(r#"href="{}""#, href),
+                None => caps[0].to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Convert rendered Markdown into a man page.
+///
+/// Covers headings, paragraphs, lists, code blocks and inline emphasis, which is
+/// what rendered doc pages consist of.
+pub fn markdown_to_roff(title: &str, section_name: &str, markdown: &str) -> String {
+    let mut roff = format!(
+        ".TH \"{}\" 3 \"\" \"\" \"{}\"\n",
+        roff_quoted(title),
+        roff_quoted(section_name)
+    );
+    let mut in_code = false;
+    let mut paragraph = false;
+
+    for line in markdown.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with("```") {
+            roff.push_str(if in_code {
+                ".fi\n.RE\n"
+            } else {
+                ".RS 4\n.nf\n"
+            });
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            roff.push_str(&roff_line(&roff_escape(line)));
+            roff.push('\n');
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            paragraph = false;
+        } else if let Some(heading) = trimmed.strip_prefix("# ") {
+            writeln!(
+                roff,
+                ".SH \"{}\"",
+                roff_quoted(&roff_inline(heading).to_uppercase())
+            )
+            .ok();
+        } else if trimmed.starts_with('#') {
+            let heading = trimmed.trim_start_matches('#').trim();
+            writeln!(roff, ".SS \"{}\"", roff_quoted(&roff_inline(heading))).ok();
+        } else if let Some(item) = trimmed
+            .strip_prefix("* ")
+            .or_else(|| trimmed.strip_prefix("- "))
+        {
+            roff.push_str(".IP \\(bu 2\n");
+            roff.push_str(&roff_line(&roff_inline(item)));
+            roff.push('\n');
+            paragraph = true;
+        } else if let Some(code) = line.strip_prefix("    ") {
+            roff.push_str(".RS 4\n.nf\n");
+            roff.push_str(&roff_line(&roff_escape(code)));
+            roff.push_str("\n.fi\n.RE\n");
+        } else {
+            if !paragraph {
+                roff.push_str(".PP\n");
+                paragraph = true;
+            }
+            roff.push_str(&roff_line(&roff_inline(trimmed)));
+            roff.push('\n');
+        }
+    }
+    if in_code {
+        roff.push_str(".fi\n.RE\n");
+    }
+
+    roff
+}
+
+/// Guard a line of escaped text so roff doesn't take it for a request
+fn roff_line(text: &str) -> String {
+    if text.starts_with('.') || text.starts_with('\'') {
+        format!("\\&{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn roff_escape(text: &str) -> String {
+    text.replace('\\', "\\e")
+}
+
+fn roff_quoted(text: &str) -> String {
+    text.replace('"', "\\(dq")
+}
+
+/// Turn inline code and emphasis into fonts, and links into their text
+fn roff_inline(text: &str) -> String {
+    let text = roff_escape(text);
+    let text = MD_LINK.replace_all(&text, "$1");
+    let text = MD_CODE.replace_all(&text, r"\fB$1\fR");
+    let text = MD_STRONG.replace_all(&text, r"\fB$1\fR");
+    MD_EMPHASIS.replace_all(&text, r"\fI$1\fR").into_owned()
+}
+
+/// Resolve a relative link of `page` to a page path from the doc root
+fn resolve_from(page: &str, link: &str) -> Option<String> {
+    let mut path: Vec<&str> = page.split('/').collect();
+    path.pop();
+    for segment in link.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                path.pop()?;
+            }
+            segment => path.push(segment),
+        }
+    }
+    Some(path.join("/"))
+}
+
+/// Anchor id of a page's section in a single-page export
+fn page_id(page: &str) -> String {
+    let id: String = page
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    format!("page-{}", id)
+}
+
+/// Patch up the most common ways HTML isn't well-formed XHTML
+fn to_xhtml(html: &str) -> String {
+    VOID_ELEMENT
+        .replace_all(html, "<$1$2/>")
+        .replace("&nbsp;", "&#160;")
+}
+
+fn xhtml_page(title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n<html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\">\n<head>\n<title>{}</title>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+/// `dcterms:modified` value for a Unix timestamp
+fn epub_timestamp(secs: u64) -> String {
+    let days = secs / 86400;
+    let rem = secs % 86400;
+    // Civil date from days since the epoch (Howard Hinnant's algorithm)
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Package the book's files, which all live next to `content.opf`
+fn write_epub(path: &Path, files: &[(String, String)]) -> Result<()> {
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(path)?);
+    // The mimetype has to come first, uncompressed
+    let stored = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    zip.start_file("mimetype", stored)?;
+    zip.write_all(b"application/epub+zip")?;
+
+    zip.start_file("META-INF/container.xml", SimpleFileOptions::default())?;
+    zip.write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>
+"#,
+    )?;
+
+    for (name, contents) in files {
+        zip.start_file(format!("OEBPS/{}", name), SimpleFileOptions::default())?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::tests::{entry, insert_doc, sample_cached_doc, temp_dir, test_builder};
+    use crate::{CONFIG_FILE, Formats, Theme, USER_CSS, USER_JS};
+    use std::path::PathBuf;
+
+    #[tokio::test]
+    async fn test_export_site_links_pages_from_the_index() {
+        let dir = temp_dir("export-site");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML);
+        doc.index.entries = vec![Entry {
+            path: PathBuf::from("std/vec#method.push"),
+            ..entry("Vec::push", "method")
+        }];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust/std")).unwrap();
+        std::fs::write(dir.join("rust/std/vec.html"), "<h1>Vec</h1>").unwrap();
+        // A page named like the site's index pages
+        std::fs::write(dir.join("rust/index.html"), "<h1>Crate std</h1>").unwrap();
+
+        let site = dir.join("site");
+        manager.export_site(&site, &[]).await.unwrap();
+
+        let index = std::fs::read_to_string(site.join("index.html")).unwrap();
+        assert!(index.contains(r#"href="rust.toc.html""#));
+        let toc = std::fs::read_to_string(site.join("rust.toc.html")).unwrap();
+        assert!(toc.contains(r#"href="rust/std/vec.html#method.push""#));
+        let page = std::fs::read_to_string(site.join("rust/std/vec.html")).unwrap();
+        assert!(page.contains("<h1>Vec</h1>"));
+        assert!(page.contains(r#"href="../../index.html""#));
+        assert!(page.contains(r#"href="../../rust.toc.html""#));
+        let page = std::fs::read_to_string(site.join("rust/index.html")).unwrap();
+        assert!(page.contains("<h1>Crate std</h1>"));
+    }
+
+    #[tokio::test]
+    async fn test_user_css_and_js_are_inlined_into_pages() {
+        let dir = temp_dir("user-assets");
+        let config = temp_dir("user-assets-config");
+        std::fs::create_dir_all(&config).unwrap();
+        std::fs::write(config.join(USER_CSS), "body { font-size: 20px; }").unwrap();
+        std::fs::write(config.join(USER_JS), r#"console.log("</script>");"#).unwrap();
+        let manager = test_builder(&dir)
+            .config_file(config.join(CONFIG_FILE))
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML);
+        doc.index.entries = vec![entry("Vec", "struct")];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust")).unwrap();
+        std::fs::write(dir.join("rust/vec.html"), "<h1>Vec</h1>").unwrap();
+
+        let html = manager.export_single_page("rust").await.unwrap();
+        assert!(html.contains("<style>\nbody { font-size: 20px; }\n</style>\n</head>"));
+        assert!(html.contains("<script>\nconsole.log(\"<\\/script>\");\n</script>\n</body>"));
+        assert!(html.find("<h1>Vec</h1>") < html.find("<script>"));
+
+        let page = Theme::Light.apply("<p>hi</p>", "page", None, None);
+        let given = test_builder(&dir)
+            .config_file(config.join(CONFIG_FILE))
+            .user_css("p { color: red; }")
+            .build()
+            .unwrap();
+        let page = given.with_user_assets(&page);
+        assert!(page.contains("p { color: red; }"));
+        assert!(!page.contains("font-size: 20px"));
+        assert!(page.contains("console.log"));
+    }
+
+    #[tokio::test]
+    async fn test_single_page_and_epub_exports_follow_the_index() {
+        let dir = temp_dir("export-single");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML);
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Option", "enum")];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust")).unwrap();
+        std::fs::write(dir.join("rust/vec.html"), "<p>Vec<br></p>").unwrap();
+        std::fs::write(
+            dir.join("rust/option.html"),
+            r#"<a href="vec.html#len">len</a>"#,
+        )
+        .unwrap();
+
+        let html = manager.export_single_page("rust").await.unwrap();
+        assert!(html.find(r#"id="page-vec""#) < html.find(r#"id="page-option""#));
+        assert!(html.contains(r##"href="#len""##));
+
+        let book = dir.join("rust.epub");
+        manager.export_epub("rust", &book).await.unwrap();
+        let mut archive = zip::ZipArchive::new(std::fs::File::open(book).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().name(), "mimetype");
+        let mut chapter = String::new();
+        std::io::Read::read_to_string(
+            &mut archive.by_name("OEBPS/page-0.xhtml").unwrap(),
+            &mut chapter,
+        )
+        .unwrap();
+        assert!(chapter.contains("<br/>"));
+    }
+
+    #[test]
+    fn test_markdown_to_roff() {
+        let roff = markdown_to_roff(
+            "Vec::push",
+            "Rust",
+            "# Vec::push\n\nAppends `value`, see [len](vec#len).\n\n```\nv.push(1);\n.hidden\n```\n* a \\ b\n",
+        );
+        assert!(roff.starts_with(".TH \"Vec::push\" 3"));
+        assert!(roff.contains(".SH \"VEC::PUSH\""));
+        assert!(roff.contains("Appends \\fBvalue\\fR, see len."));
+        assert!(roff.contains(".nf\nv.push(1);\n\\&.hidden\n.fi"));
+        assert!(roff.contains(".IP \\(bu 2\na \\e b"));
+    }
+
+    #[tokio::test]
+    async fn test_export_jsonl_lists_every_entry() {
+        let manager =
+            DevDocsManager::with_transport(temp_dir("jsonl"), MockTransport::fixtures()).unwrap();
+        manager.init().await.unwrap();
+        manager.add_doc("rust", None).await.unwrap();
+        manager.add_doc("kubernetes", None).await.unwrap();
+
+        let mut out = Vec::new();
+        let count = manager.export_jsonl(&mut out, &[]).await.unwrap();
+        let lines: Vec<ExportedEntry> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), count);
+        assert_eq!(lines[0].doc, "kubernetes");
+        assert_eq!(lines[0].name, "Pod");
+        let push = lines.iter().find(|e| e.name == "Vec::push").unwrap();
+        assert_eq!(push.entry_type, "Structs");
+        assert_eq!(
+            push.url,
+            "https://devdocs.io/rust/std/vec/struct.vec#method.push"
+        );
+
+        let mut out = Vec::new();
+        let rust = ["rust".to_string()];
+        assert_eq!(manager.export_jsonl(&mut out, &rust).await.unwrap(), 3);
+    }
+}
@@ -0,0 +1,271 @@
+//! Drawing the images of pages inline in terminals that support it
+
+use std::path::Path;
+
+use anyhow::{Result, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use regex::{Captures, Regex};
+use tokio::fs;
+use tracing::debug;
+
+use crate::{DevDocsManager, ImageProtocol};
+
+/// A markdown image, with its alt text and source
+const IMAGE: &str = r#"!\[([^\]]*)\]\(<?([^)\s>]+)>?(?:\s+"[^"]*")?\)"#;
+
+/// Images wider than this many pixels are scaled down before they're drawn
+#[cfg(feature = "images")]
+const MAX_WIDTH: u32 = 800;
+
+/// Bytes of base64 per chunk of the kitty protocol
+const KITTY_CHUNK: usize = 4096;
+
+impl DevDocsManager {
+    /// Markdown of a page for printing to a terminal, with its images drawn inline
+    /// through `protocol`. Without one, or for images that can't be loaded or drawn,
+    /// images are replaced by their alt text.
+    ///
+    /// Relative image paths resolve against `dir`, the directory of the page; remote
+    /// images are fetched through the manager's transport.
+    pub async fn with_terminal_images(
+        &self,
+        markdown: &str,
+        dir: &Path,
+        protocol: Option<ImageProtocol>,
+    ) -> String {
+        let re = Regex::new(IMAGE).unwrap();
+        let mut out = String::with_capacity(markdown.len());
+        let mut last = 0;
+        for caps in re.captures_iter(markdown) {
+            let image = caps.get(0).unwrap();
+            out.push_str(&markdown[last..image.start()]);
+            last = image.end();
+
+            let drawn = match protocol {
+                Some(protocol) => match self.draw_image(protocol, &caps[2], dir).await {
+                    Ok(drawn) => Some(drawn),
+                    Err(e) => {
+                        debug!("Can't draw image {}: {}", &caps[2], e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            out.push_str(&drawn.unwrap_or_else(|| alt_text(&caps)));
+        }
+        out.push_str(&markdown[last..]);
+        out
+    }
+
+    async fn draw_image(&self, protocol: ImageProtocol, src: &str, dir: &Path) -> Result<String> {
+        let data = self.load_image(src, dir).await?;
+        match protocol {
+            ImageProtocol::Kitty => Ok(kitty(&png(data)?)),
+            ImageProtocol::Iterm2 => {
+                // Neither iTerm2 nor WezTerm draw vector images
+                if data.trim_ascii_start().starts_with(b"<") {
+                    bail!("not a raster image");
+                }
+                Ok(iterm2(&data))
+            }
+            ImageProtocol::Sixel => sixel(data),
+        }
+    }
+
+    /// The bytes of an image from a data URL, the web or a local file
+    async fn load_image(&self, src: &str, dir: &Path) -> Result<Vec<u8>> {
+        if let Some(data) = src.strip_prefix("data:") {
+            let Some((_, encoded)) = data.split_once(";base64,") else {
+                bail!("data URL isn't base64");
+            };
+            return Ok(STANDARD.decode(encoded)?);
+        }
+        if src.starts_with("http://") || src.starts_with("https://") {
+            return self.transport.get(src).await;
+        }
+        if src.starts_with("//") {
+            return self.transport.get(&format!("https:{}", src)).await;
+        }
+
+        let path = src.split(['?', '#']).next().unwrap_or(src);
+        Ok(fs::read(dir.join(path.replace("%20", " "))).await?)
+    }
+}
+
+/// What's printed in place of an image that isn't drawn
+fn alt_text(caps: &Captures) -> String {
+    let alt = caps[1].trim();
+    if alt.is_empty() {
+        format!("[image: {}]", &caps[2])
+    } else {
+        format!("[image: {}]", alt)
+    }
+}
+
+/// Draw a PNG with the kitty graphics protocol, sent in chunks as it requires
+fn kitty(png: &[u8]) -> String {
+    let encoded = STANDARD.encode(png);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let keys = if i == 0 {
+            format!("a=T,f=100,m={}", more)
+        } else {
+            format!("m={}", more)
+        };
+        // Chunks are slices of base64, so always ASCII
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        out.push_str(&format!("\x1b_G{};{}\x1b\\", keys, chunk));
+    }
+    out.push('\n');
+    out
+}
+
+/// Draw an image with iTerm2's inline image escape, which takes any format it decodes
+fn iterm2(data: &[u8]) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};preserveAspectRatio=1:{}\x07\n",
+        data.len(),
+        STANDARD.encode(data)
+    )
+}
+
+/// The image as a PNG, scaled down to fit the terminal
+#[cfg(feature = "images")]
+fn png(data: Vec<u8>) -> Result<Vec<u8>> {
+    let image = decode(&data)?;
+    let mut png = Vec::new();
+    image.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)?;
+    Ok(png)
+}
+
+/// The image as it is when it's a PNG, the only format kitty takes without decoding
+#[cfg(not(feature = "images"))]
+fn png(data: Vec<u8>) -> Result<Vec<u8>> {
+    if !data.starts_with(b"\x89PNG") {
+        bail!("only PNG images can be drawn without the images feature");
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "images")]
+fn decode(data: &[u8]) -> Result<image::DynamicImage> {
+    let image = image::load_from_memory(data)?;
+    if image.width() > MAX_WIDTH {
+        let height = (image.height() as u64 * MAX_WIDTH as u64 / image.width() as u64) as u32;
+        return Ok(image.resize(
+            MAX_WIDTH,
+            height.max(1),
+            image::imageops::FilterType::Triangle,
+        ));
+    }
+    Ok(image)
+}
+
+/// Draw an image as sixels, with its colors reduced to a 6×6×6 cube and its
+/// transparent pixels left undrawn
+#[cfg(feature = "images")]
+fn sixel(data: Vec<u8>) -> Result<String> {
+    use std::collections::BTreeMap;
+    use std::fmt::Write;
+
+    let image = decode(&data)?.to_rgba8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let level = |c: u8| (c as usize * 5 + 127) / 255;
+
+    let mut out = format!("\x1bPq\"1;1;{};{}", width, height);
+    for color in 0..216 {
+        let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+        let _ = write!(out, "#{};2;{};{};{}", color, r * 20, g * 20, b * 20);
+    }
+    for band in (0..height).step_by(6) {
+        // Which of the band's six rows each color is drawn in, per column
+        let mut colors: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for dy in 0..6.min(height - band) {
+            for x in 0..width {
+                let pixel = image.get_pixel(x as u32, (band + dy) as u32);
+                if pixel[3] < 128 {
+                    continue;
+                }
+                let color = level(pixel[0]) * 36 + level(pixel[1]) * 6 + level(pixel[2]);
+                colors.entry(color).or_insert_with(|| vec![0; width])[x] |= 1 << dy;
+            }
+        }
+        for (i, (color, bits)) in colors.iter().enumerate() {
+            if i > 0 {
+                // Back to the start of the band for the next color
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", color);
+            let mut x = 0;
+            while x < width {
+                let run = bits[x..].iter().take_while(|&&b| b == bits[x]).count();
+                let sixel = (63 + bits[x]) as char;
+                if run > 3 {
+                    let _ = write!(out, "!{}{}", run, sixel);
+                } else {
+                    out.extend(std::iter::repeat_n(sixel, run));
+                }
+                x += run;
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    Ok(out)
+}
+
+#[cfg(not(feature = "images"))]
+fn sixel(_data: Vec<u8>) -> Result<String> {
+    bail!("sixel graphics need the images feature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_terminal_images_are_drawn_or_replaced_by_alt_text() {
+        let dir = temp_dir("terminal-images");
+        std::fs::create_dir_all(dir.join("_assets")).unwrap();
+        std::fs::write(dir.join("_assets/plot.png"), b"plot bytes").unwrap();
+        let transport = MockTransport::new().respond("/axis.gif", "axis bytes");
+        let manager = test_builder(dir.join("data"))
+            .transport(transport)
+            .build()
+            .unwrap();
+        let page = "A plot ![Line plot](_assets/plot.png) and ![](https://img.test/axis.gif).\n\
+                    ![Missing](_assets/nope.png)";
+
+        let plain = manager.with_terminal_images(page, &dir, None).await;
+        assert_eq!(
+            plain,
+            "A plot [image: Line plot] and [image: https://img.test/axis.gif].\n[image: Missing]"
+        );
+
+        let drawn = manager
+            .with_terminal_images(page, &dir, Some(ImageProtocol::Iterm2))
+            .await;
+        assert!(drawn.contains(
+            "\x1b]1337;File=inline=1;size=10;preserveAspectRatio=1:cGxvdCBieXRlcw==\x07"
+        ));
+        assert!(drawn.contains(":YXhpcyBieXRlcw==\x07"));
+        assert!(drawn.ends_with("[image: Missing]"));
+
+        #[cfg(feature = "images")]
+        {
+            let red = image::RgbaImage::from_pixel(2, 6, image::Rgba([255, 0, 0, 255]));
+            red.save(dir.join("_assets/red.png")).unwrap();
+            let sixel = manager
+                .with_terminal_images("![Red](_assets/red.png)", &dir, Some(ImageProtocol::Sixel))
+                .await;
+            assert!(sixel.starts_with("\x1bPq\"1;1;2;6"));
+            // Pure red is color 5 × 36 of the cube, drawn in all six rows of both columns
+            assert!(sixel.ends_with("#180~~-\x1b\\\n"), "{:?}", sixel);
+        }
+    }
+}
@@ -2,25 +2,130 @@
 //!
 //! A library for managing DevDocs documentation locally with fuzzy search capabilities.
 
-use bitcode;
-use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result};
-use nucleo::{Config, Matcher, Nucleo, Utf32Str};
-use reqwest::Client;
+use futures::future::BoxFuture;
+use futures::{Stream, StreamExt};
+use nucleo::{Config, Matcher, Nucleo, Utf32Str, pattern};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use thread_local::ThreadLocal;
 use tokio::fs;
-use tokio::sync::RwLock;
-use tracing::{debug, info, warn};
+use tokio::sync::{RwLock, broadcast};
+use tokio_util::sync::CancellationToken;
+use tracing::{Instrument, debug, debug_span, info, instrument, warn};
+
+#[cfg(feature = "mmap")]
+pub mod archive;
+pub mod blocking;
+pub mod checklist;
+mod clean;
+mod content;
+mod convert;
+pub mod export;
+mod images;
+pub mod lsp;
+mod math;
+mod paths;
+#[cfg(feature = "python")]
+mod python;
+mod remote;
+mod semantic;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod service;
+pub mod sources;
+mod terminal;
+#[cfg(any(test, feature = "test-support"))]
+pub mod testing;
 
 const DEVDOCS_BASE_URL: &str = "https://devdocs.io";
 const DOCUMENTS_BASE_URL: &str = "https://documents.devdocs.io";
 const CACHE_DURATION_DAYS: u64 = 7;
+/// Maximum number of queries kept in the search history
+const HISTORY_LIMIT: usize = 1000;
+/// Maximum number of pages kept in the recently viewed list
+const RECENT_LIMIT: usize = 100;
+/// Characters of an anchored element's text that [`anchor_offset`] looks for
+const ANCHOR_MATCH_CHARS: usize = 40;
+/// Maximum number of query results kept by the query cache
+const QUERY_CACHE_LIMIT: usize = 128;
+/// How long [`SearchSession::results`] waits on the matcher
+const SESSION_WAIT: Duration = Duration::from_millis(200);
+/// Results scoring below this per query character count as poor matches
+const LOW_SCORE_PER_CHAR: u32 = 8;
+/// Maximum number of corrected queries suggested for a poorly matching one
+const SUGGESTION_LIMIT: usize = 3;
+/// Number of entries ranked at a time by a streaming search
+const STREAM_CHUNK: usize = 4096;
+/// Directory holding the precomputed matcher haystacks of each doc
+const HAYSTACKS_DIR: &str = "haystacks";
+/// Directory holding the content hashes of each doc's rendered pages
+const PAGE_HASHES_DIR: &str = "pages";
+/// Directory holding the compressed page contents of lazily rendered docs
+const CONTENT_DIR: &str = "content";
+/// Directory holding the code blocks extracted from each doc's pages
+const CODE_DIR: &str = "code";
+/// Directory holding the full-text index of each doc's pages
+const FULLTEXT_DIR: &str = "fulltext";
+/// Directory holding the API signatures extracted from each doc's pages
+const SIGNATURES_DIR: &str = "signatures";
+/// Directory holding the embeddings of each doc's pages and sections
+const EMBEDDINGS_DIR: &str = "embeddings";
+/// Directory of the data dir holding the data dir of each profile, by name
+const PROFILES_DIR: &str = "profiles";
+/// System-wide doc store stacked under the default data dir when it exists
+pub const SHARED_DATA_DIR: &str = "/usr/share/devdocs";
+/// Groups of docs every manager knows, added and removed together as `@<name>`
+const DOC_GROUPS: &[(&str, &[&str])] = &[("web", &["html", "css", "javascript", "dom"])];
+/// Directory holding single pages read from docs that aren't installed, by slug
+const FETCHED_DIR: &str = "fetched";
+/// File listing the docs a project searches, one slug or alias per line
+pub const PROJECT_SCOPE_FILE: &str = ".devdocs-scope";
+/// Configuration file read when building a manager, in the `devdocs` config dir
+pub const CONFIG_FILE: &str = "devdocs.toml";
+/// Stylesheet added to the pages shown in a browser or exported, next to the [`CONFIG_FILE`]
+pub const USER_CSS: &str = "user.css";
+/// Script added to the pages shown in a browser or exported, next to the [`CONFIG_FILE`]
+pub const USER_JS: &str = "user.js";
+/// Entry type of the sub-entries extracted from the headings of pages
+pub const SECTION_TYPE: &str = "Section";
+/// Directory inside a doc's dir holding the images and stylesheets its pages reference
+const ASSETS_DIR: &str = "_assets";
+/// Attribute marking the references pointed at a local copy in [`ASSETS_DIR`]
+const ASSET_ATTR: &str = "data-devdocs-asset";
+/// Directory holding the index changelog of each updated doc
+const CHANGES_DIR: &str = "changes";
+/// Directory holding the checksum of each doc's cache file, recorded when it's written
+const CHECKSUMS_DIR: &str = "checksums";
+/// Directory holding the index of each doc archived for memory mapping
+const ARCHIVES_DIR: &str = "archives";
+/// Directory new doc versions are rendered into before being swapped in
+const STAGING_DIR: &str = ".staging";
+/// Advisory lock file guarding the writable layer against concurrent processes
+const LOCK_FILE: &str = ".lock";
+/// Advisory lock file serializing changes to the state files between processes
+const STATE_LOCK_FILE: &str = ".state.lock";
+/// Extension of the marker files that hide a lower-layer doc in overlay mode
+const WHITEOUT_EXT: &str = "wh";
+/// Directory holding the models of local embedders
+const MODELS_DIR: &str = "models";
+/// Free space below which the disk of the data dir counts as nearly full
+const LOW_SPACE_BYTES: u64 = 200_000_000;
+/// Names listed by a diagnostic before the rest are summed up
+const DIAGNOSTIC_NAMES: usize = 5;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DevDocsError {
+    #[cfg(feature = "reqwest")]
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
     #[error("IO error: {0}")]
@@ -35,6 +140,14 @@ pub enum DevDocsError {
     Cache(String),
     #[error("Invalid slug: {0}")]
     InvalidSlug(String),
+    #[error("Cache at '{0}' is locked by another process")]
+    Locked(String),
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Disk quota exceeded: ~{needed} bytes needed but only {available} bytes left")]
+    QuotaExceeded { needed: u64, available: u64 },
+    #[error("Corrupt download of '{slug}': {reason}")]
+    CorruptDownload { slug: String, reason: String },
 }
 
 use bitflags::bitflags;
@@ -44,11 +157,19 @@ bitflags! {
     #[repr(transparent)]
     #[serde(transparent)]
     pub struct Formats: u8 {
-        const MARKDOWN = 0b01;
-        const HTML     = 0b10;
+        const MARKDOWN = 0b001;
+        const HTML     = 0b010;
+        const TEXT     = 0b100;
     }
 }
 
+/// Each rendered format with the extension of its page files
+const FORMAT_EXTENSIONS: [(Formats, &str); 3] = [
+    (Formats::HTML, "html"),
+    (Formats::MARKDOWN, "md"),
+    (Formats::TEXT, "txt"),
+];
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Doc {
     pub name: String,
@@ -89,519 +210,9085 @@ pub struct EntryType {
     pub slug: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchableEntry {
     pub entry: Entry,
     pub doc_slug: String,
     pub doc_name: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
     pub entry: SearchableEntry,
-    pub score: u16,
+    pub score: u32,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct CachedDoc {
-    doc: Doc,
-    formats: Option<Formats>,
-    index: DocIndex,
-    cached_at: u64,
+/// Results of a [`DevDocsManager::search_response`] call, with what else might have
+/// been meant when they're poor
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchResponse {
+    pub results: Vec<SearchResult>,
+    /// How many results the query has in all, across every page
+    pub total_matches: usize,
+    /// Whether more results follow this page
+    pub truncated: bool,
+    /// Corrected queries, best first; only filled when no result scored well
+    pub suggestions: Vec<String>,
+    /// Results from devdocs.io, in docs that aren't installed; only searched with
+    /// [`SearchOptions::remote`] when nothing installed matches
+    pub remote: Vec<SearchResult>,
 }
 
-#[derive(Debug)]
-pub struct DevDocsManager {
-    client: Client,
-    data_dir: PathBuf,
-    cache: RwLock<HashMap<String, CachedDoc>>,
-    available_docs: RwLock<Option<(Vec<Doc>, u64)>>,
+/// A code example extracted from a page
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CodeBlock {
+    /// Page path within the doc, without extension
+    pub page: String,
+    /// Language the block is tagged with, if any
+    pub language: Option<String>,
+    pub code: String,
 }
 
-impl DevDocsManager {
-    /// Create a new DevDocs manager
-    pub fn new() -> Result<Self> {
-        let data_dir = dirs::data_local_dir()
-            .context("Failed to get local data directory")?
-            .join("devdocs");
+/// A code block matching a [`DevDocsManager::search_code`] query
+#[derive(Debug, Clone)]
+pub struct CodeResult {
+    pub doc_slug: String,
+    pub block: CodeBlock,
+    /// Index of the line of the block that matched best
+    pub line: usize,
+    pub score: u32,
+}
 
-        let client = Client::builder()
-            .timeout(Duration::from_secs(30))
-            .user_agent("devdocs-rs/1.0")
-            .build()?;
+/// A page matching a [`DevDocsManager::search_content`] query
+#[derive(Debug, Clone)]
+pub struct ContentResult {
+    pub doc_slug: String,
+    /// Page path within the doc, without extension
+    pub page: String,
+    /// Name of the first entry pointing at the page
+    pub title: String,
+    /// Excerpt of the page around the matches
+    pub snippet: String,
+    /// Byte ranges of the matched terms within `snippet`
+    pub highlights: Vec<std::ops::Range<usize>>,
+    pub score: f32,
+}
 
-        Ok(Self {
-            client,
-            data_dir,
-            cache: RwLock::new(HashMap::new()),
-            available_docs: RwLock::new(None),
-        })
+/// A function or method signature extracted from a page
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Signature {
+    /// Page path within the doc, without extension
+    pub page: String,
+    /// Anchor of the signature on its page, if it has one
+    pub anchor: Option<String>,
+    /// Name of the function, as written before its parameters (e.g. `time.strftime`)
+    pub name: String,
+    /// The whole signature, on one line
+    pub text: String,
+}
+
+/// How letter case is treated when matching a query
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CaseMatching {
+    /// Always ignore case
+    Ignore,
+    /// Ignore case unless the query contains an uppercase letter
+    #[default]
+    Smart,
+    /// Always match case exactly
+    Respect,
+}
+
+impl CaseMatching {
+    fn to_nucleo(self) -> pattern::CaseMatching {
+        match self {
+            CaseMatching::Ignore => pattern::CaseMatching::Ignore,
+            CaseMatching::Smart => pattern::CaseMatching::Smart,
+            CaseMatching::Respect => pattern::CaseMatching::Respect,
+        }
     }
 
-    /// Initialize the manager (create directories, load cache)
-    pub async fn init(&self) -> Result<()> {
-        fs::create_dir_all(&self.data_dir).await?;
-        self.load_cache().await?;
-        Ok(())
+    fn ignores_case(self, query: &str) -> bool {
+        match self {
+            CaseMatching::Ignore => true,
+            CaseMatching::Smart => !query.chars().any(char::is_uppercase),
+            CaseMatching::Respect => false,
+        }
     }
+}
 
-    /// Refresh the list of available documentation
-    pub async fn refresh_available_docs(&self) -> Result<Vec<Doc>> {
-        info!("Refreshing available documentation list");
+/// Key used to group search results
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GroupBy {
+    /// Group by the slug of the doc an entry belongs to
+    Doc,
+    /// Group by entry type
+    Type,
+}
 
-        let url = format!("{}/docs.json", DEVDOCS_BASE_URL);
-        let response = self.client.get(&url).send().await?;
-        let docs: Vec<Doc> = response.json().await?;
+/// Find the [`PROJECT_SCOPE_FILE`] of the project `dir` belongs to, looking in `dir`
+/// and then in each of its ancestors.
+///
+/// Returns the file and the docs it lists. Blank lines and `#` comments are ignored.
+pub fn find_project_scope(dir: &Path) -> Result<Option<(PathBuf, Vec<String>)>> {
+    let Some(file) = dir
+        .ancestors()
+        .map(|dir| dir.join(PROJECT_SCOPE_FILE))
+        .find(|file| file.is_file())
+    else {
+        return Ok(None);
+    };
 
-        let now = current_timestamp();
-        let mut available = self.available_docs.write().await;
-        *available = Some((docs.clone(), now));
+    let content = std::fs::read_to_string(&file)
+        .with_context(|| format!("Failed to read {}", file.display()))?;
+    let docs = content
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default().trim())
+        .filter(|line| !line.is_empty())
+        .map(str::to_string)
+        .collect();
+    Ok(Some((file, docs)))
+}
 
-        self.save_available_docs(&docs).await?;
+/// Files that mark a project, with the docs it's likely to need
+const PROJECT_MARKERS: &[(&str, &[&str])] = &[
+    ("Cargo.toml", &["rust"]),
+    ("package.json", &["javascript", "node"]),
+    ("tsconfig.json", &["typescript"]),
+    ("pyproject.toml", &["python"]),
+    ("requirements.txt", &["python"]),
+    ("setup.py", &["python"]),
+    ("go.mod", &["go"]),
+    ("Gemfile", &["ruby"]),
+    ("composer.json", &["php"]),
+    ("pom.xml", &["openjdk"]),
+    ("build.gradle", &["openjdk"]),
+    ("CMakeLists.txt", &["cpp", "cmake"]),
+    ("Dockerfile", &["docker"]),
+];
 
-        info!("Refreshed {} available documentation entries", docs.len());
-        Ok(docs)
-    }
+/// npm packages that have docs of their own
+const NPM_DOCS: &[&str] = &[
+    "react",
+    "vue",
+    "svelte",
+    "express",
+    "typescript",
+    "jquery",
+    "lodash",
+    "webpack",
+    "tailwindcss",
+];
 
-    /// Get the list of available documentation (cached or fresh)
-    pub async fn get_available_docs(&self) -> Result<Vec<Doc>> {
-        let available = self.available_docs.read().await;
+/// Docs the project `dir` belongs to likely needs, by name without version.
+///
+/// A [`PROJECT_SCOPE_FILE`] lists them explicitly; otherwise they're guessed from the
+/// files marking the nearest project in `dir` or its ancestors, like `Cargo.toml`, and
+/// from the dependencies of a `package.json`.
+pub fn detect_project_docs(dir: &Path) -> Result<Vec<String>> {
+    if let Some((_, docs)) = find_project_scope(dir)? {
+        return Ok(docs);
+    }
 
-        if let Some((docs, cached_at)) = &*available {
-            if current_timestamp() - cached_at < CACHE_DURATION_DAYS * 24 * 60 * 60 {
-                return Ok(docs.clone());
+    let mut docs: Vec<String> = Vec::new();
+    for dir in dir.ancestors() {
+        for (file, names) in PROJECT_MARKERS {
+            if dir.join(file).is_file() {
+                docs.extend(names.iter().map(|name| name.to_string()));
             }
         }
-
-        drop(available);
-        self.refresh_available_docs().await
+        if let Ok(manifest) = std::fs::read_to_string(dir.join("package.json"))
+            && let Ok(manifest) = serde_json::from_str::<serde_json::Value>(&manifest)
+        {
+            for section in ["dependencies", "devDependencies"] {
+                let Some(deps) = manifest.get(section).and_then(|d| d.as_object()) else {
+                    continue;
+                };
+                docs.extend(
+                    NPM_DOCS
+                        .iter()
+                        .filter(|name| deps.contains_key(**name))
+                        .map(|name| name.to_string()),
+                );
+            }
+        }
+        if !docs.is_empty() {
+            break;
+        }
     }
+    let mut seen = HashSet::new();
+    docs.retain(|doc| seen.insert(doc.clone()));
+    Ok(docs)
+}
 
-    async fn split_into(
-        &self,
-        slug: &str,
-        output_type: &Formats,
-        total_content: &HashMap<String, String>,
-    ) -> Result<()> {
-        total_content.into_iter().for_each(|(name, contents)| {
-            let key = self.data_dir.join(slug).join(name);
-            let parent_dir = key.parent().unwrap();
-            std::fs::create_dir_all(parent_dir).unwrap();
+/// Language of the docs whose slug doesn't name one
+pub const DEFAULT_LANGUAGE: &str = "en";
 
-            use html2md;
-            if output_type.contains(Formats::MARKDOWN) {
-                let contents = ensure_extensions(&contents, "md");
+/// Language of a doc, from the two-letter suffix of translated docs' slugs like
+/// `vue~3_ja`; [`DEFAULT_LANGUAGE`] for the others
+pub fn doc_language(slug: &str) -> &str {
+    match slug.rsplit_once('_') {
+        Some((base, language)) if !base.is_empty() && is_language(language) => language,
+        _ => DEFAULT_LANGUAGE,
+    }
+}
 
-                let contents = html2md::parse_html(&contents);
+/// Slug of a doc without its language suffix, shared by all of its translations
+fn untranslated(slug: &str) -> &str {
+    match slug.rsplit_once('_') {
+        Some((base, language)) if !base.is_empty() && is_language(language) => base,
+        _ => slug,
+    }
+}
 
-                std::fs::write(add_ext(key.clone(), "md"), contents).unwrap();
-            }
+fn is_language(code: &str) -> bool {
+    code.len() == 2 && code.bytes().all(|b| b.is_ascii_lowercase())
+}
 
-            if output_type.contains(Formats::HTML) {
-                std::fs::write(add_ext(key, "html"), ensure_extensions(&contents, "html")).unwrap();
-            }
-        });
+/// Keep a single translation of each doc: the one in `preferred`, else the untranslated
+/// one, else the first by slug
+fn prefer_language(mut slugs: Vec<String>, preferred: Option<&str>) -> Vec<String> {
+    slugs.sort();
+    let rank = |slug: &str| {
+        let language = doc_language(slug);
+        if Some(language) == preferred {
+            0
+        } else if language == DEFAULT_LANGUAGE {
+            1
+        } else {
+            2
+        }
+    };
+    let mut best: HashMap<&str, &str> = HashMap::new();
+    for slug in &slugs {
+        let kept = best.entry(untranslated(slug)).or_insert(slug);
+        if rank(slug) < rank(kept) {
+            *kept = slug;
+        }
+    }
+    let kept: HashSet<&str> = best.into_values().collect();
+    slugs
+        .iter()
+        .filter(|slug| kept.contains(slug.as_str()))
+        .cloned()
+        .collect()
+}
 
-        Ok(())
+/// Split the doc a query is scoped to off it, as in `postgres: jsonb`.
+///
+/// The doc is a slug or alias followed by a colon and whitespace, so that paths like
+/// `std::vec` and URLs aren't taken for one. Returns the doc and the rest of the query.
+pub fn split_doc_scope(query: &str) -> Option<(&str, &str)> {
+    let (doc, rest) = query.trim_start().split_once(':')?;
+    let valid = !doc.is_empty()
+        && doc
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '~' | '+'));
+    if !valid || !(rest.is_empty() || rest.starts_with(char::is_whitespace)) {
+        return None;
     }
+    Some((doc, rest.trim()))
+}
 
-    /// Add a new documentation
-    pub async fn add_doc(&self, slug: &str, format: Option<Formats>) -> Result<()> {
-        if self.is_format_installed(slug, format).await? {
-            warn!("Doc is already installed, skipping.");
-            return Ok(());
+/// Group results by doc or type, keeping their ranked order within each group.
+/// Groups are listed in the order of their best result.
+pub fn group_results(results: Vec<SearchResult>, by: GroupBy) -> Vec<(String, Vec<SearchResult>)> {
+    let mut groups: Vec<(String, Vec<SearchResult>)> = Vec::new();
+    for result in results {
+        let key = match by {
+            GroupBy::Doc => &result.entry.doc_slug,
+            GroupBy::Type => &result.entry.entry.entry_type,
+        };
+        match groups.iter_mut().find(|(k, _)| k == key) {
+            Some((_, group)) => group.push(result),
+            None => groups.push((key.clone(), vec![result])),
         }
+    }
+    groups
+}
 
-        let available_docs = self.get_available_docs().await?;
-        let doc = available_docs
-            .iter()
-            .find(|d| d.slug == slug)
-            .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?
-            .clone();
+/// Options controlling a [`DevDocsManager::search_with`] call
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchOptions {
+    /// Maximum number of results to return
+    pub limit: usize,
+    /// Skip this many of the best results, to page through them
+    pub offset: usize,
+    /// Only search these docs (slugs or aliases); empty means every installed doc
+    pub docs: Vec<String>,
+    /// Only return entries of these types; empty means every type
+    pub types: Vec<String>,
+    /// Multiplier applied to the fuzzy score of the entry name
+    pub name_weight: f32,
+    /// Multiplier applied to the fuzzy score of the entry type
+    pub type_weight: f32,
+    /// Bonus for entries whose name equals the query (case-insensitively)
+    pub exact_boost: u32,
+    /// Bonus for entries whose name starts with the query (case-insensitively)
+    pub prefix_boost: u32,
+    /// Bonus for entries whose word initials contain the query, like `hm` for
+    /// `HashMap` or `gbc` for `getBoundingClientRect`
+    pub acronym_boost: u32,
+    /// Case sensitivity of the match
+    pub case_matching: CaseMatching,
+    /// Match accented letters against their unaccented forms (`é` matches `e`)
+    pub normalize: bool,
+    /// Require the query to appear as a contiguous substring instead of fuzzily
+    pub exact: bool,
+    /// Drop matches scoring below this
+    pub min_score: u32,
+    /// Return at most this many results from any single doc
+    pub max_per_doc: Option<usize>,
+    /// Bonus for entries pointing at recently viewed pages
+    pub recent_boost: u32,
+    /// Search devdocs.io when nothing installed matches, in the named docs that aren't
+    /// installed or else the docs of the `remote` setting
+    pub remote: bool,
+    /// Return entries pointing at the same page and fragment of a doc once, under the
+    /// best-scoring of their names
+    pub collapse_duplicates: bool,
+}
 
-        info!("Adding documentation: {} ({})", doc.name, doc.slug);
+impl Default for SearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: 50,
+            offset: 0,
+            docs: Vec::new(),
+            types: Vec::new(),
+            name_weight: 1.0,
+            type_weight: 0.2,
+            exact_boost: 200,
+            prefix_boost: 100,
+            acronym_boost: 150,
+            case_matching: CaseMatching::default(),
+            normalize: true,
+            exact: false,
+            min_score: 0,
+            max_per_doc: None,
+            recent_boost: 50,
+            remote: false,
+            collapse_duplicates: false,
+        }
+    }
+}
 
-        // Download index and content concurrently
-        let index = self.download_doc_index(&doc.slug).await?;
+/// Progress and outcome notifications emitted by a [`DevDocsManager`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum ManagerEvent {
+    /// Downloading a doc started; `bytes` is the upstream size estimate
+    DownloadStarted { slug: String, bytes: usize },
+    /// A rendered page was written to disk
+    PageWritten { slug: String, path: PathBuf },
+    /// A doc finished installing
+    DocInstalled { slug: String },
+    /// A doc was removed
+    DocRemoved { slug: String },
+    /// An update was skipped because the installed copy is current
+    UpdateSkipped { slug: String },
+    /// An operation on a doc failed
+    Error { slug: String, message: String },
+}
 
-        let content = self.download_doc_content(slug).await?;
-        if let Some(format) = format {
-            self.split_into(&doc.slug, &format, &content).await?;
-        }
+/// How many pages of a doc changed between two installed versions
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct PageChanges {
+    pub added: usize,
+    pub modified: usize,
+    pub removed: usize,
+}
 
-        let cached_doc = CachedDoc {
-            doc,
-            formats: format,
-            index,
-            cached_at: current_timestamp(),
-        };
+/// Outcome of updating a single doc
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UpdateStatus {
+    /// A newer version was downloaded
+    Updated(PageChanges),
+    /// The installed copy already matched upstream
+    Current,
+    /// The doc is no longer available upstream, so the installed copy is kept as it is
+    Orphaned,
+}
 
-        // Update cache and save to disk
-        let mut cache = self.cache.write().await;
-        cache.insert(slug.to_string(), cached_doc.clone());
-        drop(cache);
+/// Summary of an [`update_all`](DevDocsManager::update_all) run
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UpdateReport {
+    /// Docs a newer version was installed for, with their changed pages
+    pub updated: Vec<(String, PageChanges)>,
+    /// Docs that were already current
+    pub current: Vec<String>,
+    /// Docs no longer available upstream, kept as they are
+    pub orphaned: Vec<String>,
+    /// Docs that failed to update, with the reason
+    pub failed: Vec<(String, String)>,
+}
+
+impl UpdateReport {
+    /// How the updates went as a whole
+    pub fn outcome(&self) -> BatchOutcome {
+        BatchOutcome::of(
+            self.updated.len() + self.current.len() + self.orphaned.len(),
+            self.failed.len(),
+        )
+    }
+}
 
-        self.save_doc_cache(slug, &cached_doc).await?;
+/// Summary of installing several docs, e.g. by [`download_all`](DevDocsManager::download_all)
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DownloadReport {
+    /// Docs that were installed
+    pub installed: Vec<String>,
+    /// Docs that failed to install, with the reason
+    pub failed: Vec<(String, String)>,
+}
 
-        info!("Successfully added documentation: {}", slug);
-        Ok(())
+impl DownloadReport {
+    /// How the installs went as a whole
+    pub fn outcome(&self) -> BatchOutcome {
+        BatchOutcome::of(self.installed.len(), self.failed.len())
     }
+}
 
-    /// Remove a documentation
-    pub async fn remove_doc(&self, slug: &str) -> Result<()> {
-        if !self.is_doc_installed(slug).await? {
-            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+/// Whether every doc of a batch of installs or updates succeeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchOutcome {
+    /// No doc failed, including when there was nothing to do
+    Ok,
+    /// Some docs failed and others succeeded
+    Partial,
+    /// Every doc failed
+    Failed,
+}
+
+impl BatchOutcome {
+    fn of(succeeded: usize, failed: usize) -> Self {
+        match (succeeded, failed) {
+            (_, 0) => Self::Ok,
+            (0, _) => Self::Failed,
+            _ => Self::Partial,
         }
+    }
+}
 
-        info!("Removing documentation: {}", slug);
+/// Colors of the pages opened in a browser by `dev preview` and `dev serve`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Theme {
+    /// Light or dark, following the preference of the browser
+    #[default]
+    Auto,
+    Light,
+    Dark,
+}
 
-        // Remove from cache
-        let mut cache = self.cache.write().await;
-        cache.remove(slug);
-        drop(cache);
+/// CSS variables of the light theme
+const LIGHT_PALETTE: &str = ":root { color-scheme: light; --bg: #fff; --fg: #222; --muted: #777; \
+    --link: #2a5db0; --code-bg: #f3f4f6; --border: #ddd; --accent: #4a7bd0; --accent-bg: #eef3ff; }";
+/// CSS variables of the dark theme
+const DARK_PALETTE: &str = ":root { color-scheme: dark; --bg: #1d1f23; --fg: #d8d8d8; --muted: #8e8e8e; \
+    --link: #8ab4f8; --code-bg: #2a2d33; --border: #3b3f46; --accent: #6b9cf0; --accent-bg: #2a3550; }";
 
-        // Remove from disk
-        let doc_path = self.data_dir.join(format!("{}.bin", slug));
-        if doc_path.exists() {
-            fs::remove_file(doc_path).await?;
+impl Theme {
+    /// Name of the theme, as set in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            Theme::Auto => "auto",
+            Theme::Light => "light",
+            Theme::Dark => "dark",
         }
-
-        info!("Successfully removed documentation: {}", slug);
-        Ok(())
     }
 
-    /// Download all available documentation
-    pub async fn download_all(&self, format: Formats) -> Result<()> {
-        let available_docs = self.get_available_docs().await?;
-        let installed_docs = self.list_installed_docs().await?;
-
-        let to_download: Vec<_> = available_docs
+    fn named(name: &str) -> Option<Self> {
+        [Theme::Auto, Theme::Light, Theme::Dark]
             .into_iter()
-            .filter(|doc| !installed_docs.contains(&doc.slug))
-            .collect();
+            .find(|theme| theme.name() == name)
+    }
 
-        info!("Downloading {} documentation entries", to_download.len());
+    /// The colors of the theme, as CSS variables (`--bg`, `--fg`, `--link`, ...) on `:root`
+    pub fn palette(self) -> String {
+        match self {
+            Theme::Light => LIGHT_PALETTE.to_string(),
+            Theme::Dark => DARK_PALETTE.to_string(),
+            Theme::Auto => format!(
+                "{}\n@media (prefers-color-scheme: dark) {{ {} }}",
+                LIGHT_PALETTE, DARK_PALETTE
+            ),
+        }
+    }
 
-        // Download in batches to avoid overwhelming the server
-        const BATCH_SIZE: usize = 5;
-        for batch in to_download.chunks(BATCH_SIZE) {
-            let futures = batch
-                .iter()
-                .map(|doc| self.add_doc(&doc.slug, Some(format)));
-            let results: Vec<_> = futures::future::join_all(futures).await;
+    /// A stylesheet for reading a page on its own with this theme
+    pub fn css(self) -> String {
+        format!("{}\n{}", self.palette(), include_str!("theme.css"))
+    }
 
-            for (doc, result) in batch.iter().zip(results) {
-                if let Err(e) = result {
-                    warn!("Failed to download {}: {}", doc.slug, e);
-                }
+    /// Wrap the HTML of a page in a document styled with this theme. Relative links
+    /// resolve against `base` when given, e.g. the directory the page was rendered to.
+    /// Formulas are typeset by the MathJax script at `mathjax` when given, see
+    /// [`Settings::mathjax`], and approximated in Unicode otherwise.
+    pub fn apply(
+        self,
+        html: &str,
+        title: &str,
+        base: Option<&str>,
+        mathjax: Option<&str>,
+    ) -> String {
+        let mut base = base
+            .map(|base| format!("<base href=\"{}\">\n", base.replace('"', "%22")))
+            .unwrap_or_default();
+        let mut html = Cow::Borrowed(html);
+        if math::has_math(&html) {
+            match mathjax {
+                Some(src) => base.push_str(&math::mathjax(src)),
+                None => html = Cow::Owned(math::to_unicode(&html)),
             }
         }
-
-        Ok(())
+        format!(
+            "<!doctype html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+             <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+             <title>{}</title>\n{}<style>\n{}</style>\n</head>\n<body>\n{}\n</body>\n</html>\n",
+            escape_html(title),
+            base,
+            self.css(),
+            html
+        )
     }
+}
 
-    /// List installed documentation
-    pub async fn list_installed_docs(&self) -> Result<Vec<String>> {
-        let cache = self.cache.read().await;
-        Ok(cache.keys().cloned().collect())
-    }
+/// Escape sequences that terminals draw inline images with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageProtocol {
+    /// The graphics protocol of kitty, also spoken by Ghostty and WezTerm
+    Kitty,
+    /// iTerm2's inline images, also spoken by WezTerm and mintty
+    Iterm2,
+    /// DEC sixel graphics, needing the `images` feature
+    Sixel,
+}
 
-    /// Check if a documentation is installed
-    pub async fn is_doc_installed(&self, slug: &str) -> Result<bool> {
-        let cache = self.cache.read().await;
-        Ok(cache.contains_key(slug))
+impl ImageProtocol {
+    /// The protocol the terminal running this process draws images with, told from its
+    /// environment. `None` for terminals without one, and within tmux or screen, which
+    /// don't pass images through.
+    pub fn detect() -> Option<Self> {
+        let var = |name: &str| std::env::var(name).unwrap_or_default();
+        let term = var("TERM");
+        let program = var("TERM_PROGRAM");
+        if std::env::var_os("TMUX").is_some() || term.starts_with("screen") {
+            return None;
+        }
+        if std::env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || program == "ghostty"
+        {
+            Some(ImageProtocol::Kitty)
+        } else if program == "iTerm.app"
+            || program == "WezTerm"
+            || program == "mintty"
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Some(ImageProtocol::Iterm2)
+        } else if term.contains("sixel")
+            || ["foot", "mlterm", "yaft-256color"].contains(&term.as_str())
+        {
+            Some(ImageProtocol::Sixel)
+        } else {
+            None
+        }
     }
+}
 
-    pub async fn is_format_installed(&self, slug: &str, formats: Option<Formats>) -> Result<bool> {
-        let cache = self.cache.read().await;
+/// User settings persisted in the data dir, changed through
+/// [`DevDocsManager::set_config`]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    /// Multiplier applied to the search scores of each doc's entries (default 1.0), so
+    /// the docs of a primary stack outrank incidental ones
+    pub weights: HashMap<String, f32>,
+    /// Strip navigation, "edit this page" links and tracking attributes from the pages of
+    /// the docs installed or updated from now on, and keep only their main content
+    pub clean: bool,
+    /// Theme of the pages opened in a browser (auto when unset)
+    pub theme: Option<Theme>,
+    /// Commands converting pages instead of the built-in converters, by what they convert
+    /// to: `markdown` or `epub` (XHTML chapters). They read a page's HTML on stdin and
+    /// write the conversion to stdout, e.g. `pandoc -f html -t gfm`.
+    pub converters: HashMap<String, String>,
+    /// Docs searched on devdocs.io by remote searches that don't name any
+    pub remote_docs: Vec<String>,
+    /// Groups of docs added and removed together as `@<name>`, by name; they take
+    /// precedence over the built-in groups of the same name
+    pub groups: HashMap<String, Vec<String>>,
+    /// Language whose translation of a doc is searched when several are installed, as
+    /// a two-letter code like `ja`
+    pub language: Option<String>,
+    /// MathJax script typesetting the formulas of pages opened in a browser, as a URL or
+    /// the path of a local copy of `tex-mml-chtml.js`. Unset, formulas are shown as
+    /// Unicode approximations and nothing is loaded from the network.
+    pub mathjax: Option<String>,
+}
 
-        let found = cache
+impl Settings {
+    /// Every setting as a `key = value` pair, sorted by key
+    pub fn entries(&self) -> Vec<(String, String)> {
+        let mut entries: Vec<_> = self
+            .weights
             .iter()
-            .any(|(key, cached)| key.contains(slug) && cached.formats == formats);
-        Ok(found)
+            .map(|(slug, weight)| (format!("weight.{}", slug), weight.to_string()))
+            .collect();
+        if self.clean {
+            entries.push(("clean".to_string(), true.to_string()));
+        }
+        if let Some(theme) = self.theme {
+            entries.push(("theme".to_string(), theme.name().to_string()));
+        }
+        if !self.remote_docs.is_empty() {
+            entries.push(("remote".to_string(), self.remote_docs.join(",")));
+        }
+        if let Some(language) = &self.language {
+            entries.push(("language".to_string(), language.clone()));
+        }
+        if let Some(mathjax) = &self.mathjax {
+            entries.push(("mathjax".to_string(), mathjax.clone()));
+        }
+        entries.extend(
+            self.converters
+                .iter()
+                .map(|(target, command)| (format!("converter.{}", target), command.clone())),
+        );
+        entries.extend(
+            self.groups
+                .iter()
+                .map(|(name, docs)| (format!("group.{}", name), docs.join(","))),
+        );
+        entries.sort();
+        entries
     }
 
-    /// Get information about an installed documentation
-    pub async fn get_doc_info(&self, slug: &str) -> Result<Doc> {
-        let cache = self.cache.read().await;
-        let cached_doc = cache
-            .get(slug)
-            .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?;
-        Ok(cached_doc.doc.clone())
+    /// The value of a setting, `None` when it isn't set
+    pub fn get(&self, key: &str) -> Result<Option<String>> {
+        match key.split_once('.') {
+            Some(("weight", slug)) => Ok(self.weights.get(slug).map(f32::to_string)),
+            Some(("converter", target)) if convert::CONVERTER_TARGETS.contains(&target) => {
+                Ok(self.converters.get(target).cloned())
+            }
+            Some(("group", name)) => Ok(self.groups.get(name).map(|docs| docs.join(","))),
+            None if key == "clean" => Ok(self.clean.then(|| true.to_string())),
+            None if key == "theme" => Ok(self.theme.map(|theme| theme.name().to_string())),
+            None if key == "remote" => {
+                Ok(Some(self.remote_docs.join(",")).filter(|docs| !docs.is_empty()))
+            }
+            None if key == "language" => Ok(self.language.clone()),
+            None if key == "mathjax" => Ok(self.mathjax.clone()),
+            _ => anyhow::bail!("Unknown config key '{}'", key),
+        }
     }
+}
 
-    /// Search through installed documentation with fuzzy matching
-    pub async fn search(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchResult>> {
-        let cache = self.cache.read().await;
-
-        let limit = limit.unwrap_or(50);
+/// Contents of a [`CONFIG_FILE`]
+#[derive(Debug, Clone, Default, Deserialize, PartialEq)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Local directories of HTML offered as docs, as `[[html-dirs]]` tables
+    pub html_dirs: Vec<HtmlDirConfig>,
+}
 
-        if cache.is_empty() {
-            return Ok(vec![]);
-        }
+/// A directory of HTML pages offered as a doc
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct HtmlDirConfig {
+    /// Slug of the doc
+    pub name: String,
+    /// Directory holding the pages, relative to the config file unless absolute
+    pub path: PathBuf,
+    /// Globs of the pages to index (every `.html` page by default)
+    #[serde(default)]
+    pub entries: Vec<String>,
+}
 
-        // Collect all searchable entries
-        let mut entries = Vec::new();
-        for (slug, cached_doc) in cache.iter() {
-            for entry in &cached_doc.index.entries {
-                entries.push(SearchableEntry {
-                    entry: entry.clone(),
-                    doc_slug: slug.clone(),
-                    doc_name: cached_doc.doc.name.clone(),
-                });
+impl ConfigFile {
+    /// Read a config file, `None` when it doesn't exist
+    pub fn load(path: &Path) -> Result<Option<Self>> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut config: ConfigFile =
+            toml::from_str(&text).with_context(|| format!("Invalid {}", path.display()))?;
+        if let Some(base) = path.parent() {
+            for dir in &mut config.html_dirs {
+                dir.path = base.join(&dir.path);
             }
         }
+        Ok(Some(config))
+    }
+}
 
-        drop(cache);
-
-        use std::cell::RefCell;
-        use thread_local::ThreadLocal;
-        // Perform fuzzy search
-        let mut matcher_config = Config::DEFAULT;
-        matcher_config.prefer_prefix = true;
-        let matcher = Matcher::new(matcher_config);
-        let tls: ThreadLocal<RefCell<Matcher>> = ThreadLocal::new();
+/// A past search query
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct HistoryEntry {
+    pub query: String,
+    /// Unix timestamp of the search
+    pub timestamp: u64,
+    /// Path (`slug/entry path`) of the result that was picked, if any
+    pub chosen: Option<String>,
+}
 
-        let mut pattern_buf: Vec<char> = Vec::new();
+/// How well the query cache of a manager has been doing
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct QueryCacheStats {
+    /// Searches answered from the cache
+    pub hits: u64,
+    /// Searches that had to be run
+    pub misses: u64,
+    /// Queries currently cached
+    pub entries: usize,
+}
 
-        let pattern = Utf32Str::new(query, &mut pattern_buf);
+/// What a first run of [`onboard`](DevDocsManager::onboard) has to offer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Onboarding {
+    /// Available docs the current project likely needs, in their latest version
+    pub suggested: Vec<Doc>,
+    /// Number of docs available in all
+    pub available: usize,
+}
 
-        use rayon::prelude::*;
-        // Pattern match
-        let mut results: Vec<SearchResult> = entries
-            .into_par_iter()
-            .map(|entry| {
-                // each thread/thread-pool task gets its own buffer
-                let mut entry_buf = Vec::new();
+/// What a manager holds, and what it has done since it was built
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ManagerStats {
+    pub docs_installed: usize,
+    /// Entries of the installed docs, sections included
+    pub entries_indexed: usize,
+    /// Bytes used by the writable layer
+    pub bytes_on_disk: u64,
+    /// Searches answered, from the query cache or not
+    pub searches: u64,
+    /// Mean time taken to answer a search, in milliseconds
+    pub avg_search_ms: f64,
+    /// Searches answered from the query cache
+    pub cache_hits: u64,
+    /// Searches that had to be run
+    pub cache_misses: u64,
+    /// Docs downloaded by installs and updates
+    pub downloads: u64,
+    /// Queries whose results are currently cached
+    pub cached_queries: usize,
+    /// Searches answered within each of the [`SEARCH_LATENCY_BUCKETS`], in seconds
+    pub search_latency_buckets: Vec<(f64, u64)>,
+}
 
-                let cell = tls.get_or(|| RefCell::new(matcher.clone()));
-                let mut matcher = cell.borrow_mut();
+/// Upper bounds of the search latency histogram, in seconds
+pub const SEARCH_LATENCY_BUCKETS: [f64; 7] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
 
-                let text = format!("{} {}", entry.entry.name, entry.entry.entry_type);
-                let full = Utf32Str::new(text.as_str(), &mut entry_buf);
+impl ManagerStats {
+    /// The stats in the Prometheus text format, as metrics prefixed with `devdocs_`
+    pub fn to_prometheus(&self) -> String {
+        let metrics = [
+            (
+                "docs_installed",
+                "gauge",
+                "Installed docs",
+                self.docs_installed as f64,
+            ),
+            (
+                "entries_indexed",
+                "gauge",
+                "Searchable entries of the installed docs",
+                self.entries_indexed as f64,
+            ),
+            (
+                "disk_bytes",
+                "gauge",
+                "Bytes used by the data dir",
+                self.bytes_on_disk as f64,
+            ),
+            (
+                "searches_total",
+                "counter",
+                "Searches answered",
+                self.searches as f64,
+            ),
+            (
+                "query_cache_hits_total",
+                "counter",
+                "Searches answered from the query cache",
+                self.cache_hits as f64,
+            ),
+            (
+                "query_cache_misses_total",
+                "counter",
+                "Searches that had to be run",
+                self.cache_misses as f64,
+            ),
+            (
+                "downloads_total",
+                "counter",
+                "Docs downloaded by installs and updates",
+                self.downloads as f64,
+            ),
+            (
+                "cached_queries",
+                "gauge",
+                "Queries whose results are cached",
+                self.cached_queries as f64,
+            ),
+        ];
+        let mut text = String::new();
+        for (name, kind, help, value) in metrics {
+            text.push_str(&format!(
+                "# HELP devdocs_{name} {help}\n# TYPE devdocs_{name} {kind}\ndevdocs_{name} {value}\n"
+            ));
+        }
 
-                let score = matcher.fuzzy_match(full, pattern).unwrap_or(0);
+        let name = "devdocs_search_duration_seconds";
+        text.push_str(&format!(
+            "# HELP {name} Time taken to answer searches\n# TYPE {name} histogram\n"
+        ));
+        for (le, count) in &self.search_latency_buckets {
+            text.push_str(&format!("{name}_bucket{{le=\"{le}\"}} {count}\n"));
+        }
+        let sum = self.avg_search_ms * self.searches as f64 / 1000.0;
+        text.push_str(&format!(
+            "{name}_bucket{{le=\"+Inf\"}} {count}\n{name}_sum {sum}\n{name}_count {count}\n",
+            count = self.searches
+        ));
+        text
+    }
+}
 
-                SearchResult { entry, score }
-            })
-            .collect();
+/// Counters behind the [`ManagerStats`] that aren't kept anywhere else
+#[derive(Debug, Default)]
+struct Metrics {
+    searches: AtomicU64,
+    /// Time spent answering searches, in microseconds
+    search_micros: AtomicU64,
+    /// Searches by the first of the [`SEARCH_LATENCY_BUCKETS`] they were answered within
+    search_buckets: [AtomicU64; SEARCH_LATENCY_BUCKETS.len()],
+    downloads: AtomicU64,
+}
 
-        // Sort by score (higher is better)
-        results.sort_by(|a, b| {
-            b.score
-                .partial_cmp(&a.score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
+impl Metrics {
+    fn record_searches(&self, count: usize, took: Duration) {
+        self.searches.fetch_add(count as u64, Ordering::Relaxed);
+        self.search_micros
+            .fetch_add(took.as_micros() as u64, Ordering::Relaxed);
+        // Batched searches are counted as taking an equal share each
+        let each = took.as_secs_f64() / count.max(1) as f64;
+        if let Some(bucket) = SEARCH_LATENCY_BUCKETS.iter().position(|le| each <= *le) {
+            self.search_buckets[bucket].fetch_add(count as u64, Ordering::Relaxed);
+        }
+    }
 
-        Ok(results.into_iter().take(limit).collect())
+    /// Searches answered within each bucket's bound, counting those of the bounds below
+    fn latency_buckets(&self) -> Vec<(f64, u64)> {
+        let mut within = 0;
+        SEARCH_LATENCY_BUCKETS
+            .iter()
+            .zip(&self.search_buckets)
+            .map(|(le, count)| {
+                within += count.load(Ordering::Relaxed);
+                (*le, within)
+            })
+            .collect()
     }
+}
 
-    // /// Get the content of a specific documentation page
-    // pub async fn get_page_content(&self, slug: &str, path: &str) -> Result<String> {
-    //     let cache = self.cache.read().await;
-    //     let cached_doc = cache
-    //         .get(slug)
-    //         .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?;
+/// Operations running right now by what they do, so identical ones started meanwhile
+/// wait for their result instead of doing the same work again
+#[derive(Debug)]
+struct InFlight<T> {
+    ops: Mutex<HashMap<String, Arc<Outcome<T>>>>,
+}
 
-    //     cached_doc
-    //         .content
-    //         .get(path)
-    //         .cloned()
-    //         .ok_or_else(|| DevDocsError::DocNotFound(format!("{}#{}", slug, path)).into())
-    // }
+/// Result of an operation once it's done, errors kept as messages
+type Outcome<T> = tokio::sync::OnceCell<Result<T, String>>;
 
-    /// Update a specific documentation
-    pub async fn update_doc(&self, slug: &str) -> Result<()> {
-        if !self.is_doc_installed(slug).await? {
-            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+impl<T> Default for InFlight<T> {
+    fn default() -> Self {
+        Self {
+            ops: Mutex::new(HashMap::new()),
         }
-
-        // Remove and re-add
-        self.remove_doc(slug).await?;
-        self.add_doc(slug, None).await?;
-
-        Ok(())
     }
+}
 
-    /// Update all installed documentation
-    pub async fn update_all(&self) -> Result<()> {
-        let installed_docs = self.list_installed_docs().await?;
-
-        info!("Updating {} documentation entries", installed_docs.len());
+impl<T: Clone> InFlight<T> {
+    /// Run `op` unless an operation with the same `key` is running, in which case its
+    /// result is returned instead.
+    ///
+    /// Errors reach the callers that waited as messages. A cancelled operation has no
+    /// result to share, so the next one waiting runs its own `op` instead. A caller
+    /// waiting on someone else's operation stops waiting once its own `cancel` fires.
+    async fn run(
+        &self,
+        key: String,
+        cancel: &CancellationToken,
+        op: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        let cell = self
+            .ops
+            .lock()
+            .expect("in-flight operations poisoned")
+            .entry(key.clone())
+            .or_default()
+            .clone();
 
-        for slug in installed_docs {
-            if let Err(e) = self.update_doc(&slug).await {
-                warn!("Failed to update {}: {}", slug, e);
+        let mut own_error = None;
+        // Whether `op` is the one running, which then handles `cancel` itself
+        let running = AtomicBool::new(false);
+        let shared = {
+            let init = cell.get_or_try_init(|| async {
+                running.store(true, Ordering::Relaxed);
+                match op.await {
+                    Ok(value) => Ok(Ok(value)),
+                    Err(e) => {
+                        let cancelled = matches!(e.downcast_ref(), Some(DevDocsError::Cancelled));
+                        let message = format!("{:#}", e);
+                        own_error = Some(e);
+                        if cancelled { Err(()) } else { Ok(Err(message)) }
+                    }
+                }
+            });
+            tokio::pin!(init);
+            tokio::select! {
+                shared = &mut init => shared,
+                _ = cancel.cancelled() => {
+                    if !running.load(Ordering::Relaxed) {
+                        return Err(DevDocsError::Cancelled.into());
+                    }
+                    // Stopping `op` halfway could leave a doc half written
+                    init.await
+                }
             }
+            .cloned()
+        };
+
+        // Whoever gets here first retires the operation, later calls start afresh
+        let mut ops = self.ops.lock().expect("in-flight operations poisoned");
+        if ops.get(&key).is_some_and(|op| Arc::ptr_eq(op, &cell)) {
+            ops.remove(&key);
         }
+        drop(ops);
 
-        Ok(())
-    }
+        if let Some(e) = own_error {
+            return Err(e);
+        }
+        match shared {
+            Ok(Ok(value)) => Ok(value),
+            Ok(Err(message)) => Err(anyhow::anyhow!(message)),
+            Err(()) => Err(DevDocsError::Cancelled.into()),
+        }
+    }
+}
 
-    // Private helper methods
+/// Results of recent searches, most recently used first
+#[derive(Debug, Default)]
+struct QueryCache {
+    /// Query, options, results and total number of matches
+    entries: std::collections::VecDeque<(String, SearchOptions, Vec<SearchResult>, usize)>,
+    /// Bumped on every invalidation, so searches racing one don't store stale results
+    generation: u64,
+    hits: u64,
+    misses: u64,
+    /// Entries of the installed docs as loaded into search sessions, built on first use
+    corpus: Option<Arc<Corpus>>,
+}
 
-    async fn download_doc_index(&self, slug: &str) -> Result<DocIndex> {
-        let url = format!("{}/{}/index.json", DOCUMENTS_BASE_URL, slug);
-        debug!("Downloading index: {}", url);
+/// The entries of every installed doc, shared by the search sessions started until
+/// what they're built from changes
+#[derive(Debug, Default)]
+struct Corpus {
+    docs: Vec<CorpusDoc>,
+}
 
-        let response = self.client.get(&url).send().await?;
-        let index: DocIndex = response.json().await?;
+#[derive(Debug)]
+struct CorpusDoc {
+    slug: Arc<str>,
+    name: Arc<str>,
+    entries: Vec<Entry>,
+    /// Matcher haystacks parallel to `entries`
+    haystacks: Vec<Haystack>,
+}
+
+/// An entry of a search session, by its position in the [`Corpus`]
+#[derive(Debug, Clone, Copy)]
+struct CorpusEntry {
+    doc: u32,
+    entry: u32,
+}
 
-        Ok(index)
+impl Corpus {
+    fn entry(&self, at: CorpusEntry) -> (&CorpusDoc, &Entry) {
+        let doc = &self.docs[at.doc as usize];
+        (doc, &doc.entries[at.entry as usize])
     }
+}
 
-    async fn download_doc_content(&self, slug: &str) -> Result<HashMap<String, String>> {
-        let url = format!("{}/{}/db.json", DOCUMENTS_BASE_URL, slug);
-        debug!("Downloading content: {}", url);
+/// A page that was recently opened
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecentPage {
+    pub slug: String,
+    /// Page path within the doc, without extension or fragment
+    pub path: String,
+    /// Unix timestamp of the last view
+    pub timestamp: u64,
+}
 
-        let response = self.client.get(&url).send().await?;
-        let content: HashMap<String, String> = response.json().await?;
+/// What an operation would do, as resolved by the `plan_*` methods of [`DevDocsManager`]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Plan {
+    /// Docs that would be downloaded
+    pub downloads: Vec<PlannedDownload>,
+    /// Files and directories that would be deleted
+    pub removals: Vec<PathBuf>,
+    /// Installed docs no longer available upstream, which would be kept as they are
+    pub orphaned: Vec<String>,
+}
 
-        Ok(content)
+impl Plan {
+    /// Estimated number of bytes the downloads add up to
+    pub fn download_bytes(&self) -> usize {
+        self.downloads.iter().map(|d| d.bytes).sum()
     }
+}
 
-    async fn save_doc_cache(&self, slug: &str, cached_doc: &CachedDoc) -> Result<()> {
-        use bitcode;
-        let path = self.data_dir.join(format!("{}.bin", slug));
-        let data = bitcode::serialize::<CachedDoc>(&cached_doc)?;
-        fs::write(path, data).await?;
-        Ok(())
+/// A doc an operation would download
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct PlannedDownload {
+    pub slug: String,
+    pub name: String,
+    /// Upstream `mtime` of the version that would be installed
+    pub mtime: u64,
+    /// Upstream estimate of the download size
+    pub bytes: usize,
+}
+
+impl From<&Doc> for PlannedDownload {
+    fn from(doc: &Doc) -> Self {
+        PlannedDownload {
+            slug: doc.slug.clone(),
+            name: doc.name.clone(),
+            mtime: doc.mtime,
+            bytes: doc.db_size,
+        }
     }
+}
 
-    async fn load_cache(&self) -> Result<()> {
-        let mut entries = fs::read_dir(&self.data_dir).await?;
-        let mut cache = self.cache.write().await;
+/// Disk space taken up by the writable layer
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiskUsage {
+    /// Bytes used in total, including state files
+    pub used: u64,
+    /// Configured maximum, if any
+    pub quota: Option<u64>,
+    /// Bytes used by each installed doc, largest first
+    pub docs: Vec<(String, u64)>,
+}
 
-        while let Some(entry) = entries.next_entry().await? {
-            let path = entry.path();
-            if path.extension().and_then(|s| s.to_str()) == Some("bin") {
-                if let Some(stem) = path.file_stem().and_then(|s| s.to_str()) {
-                    if stem == "available_docs" {
-                        continue; // Skip available docs cache
-                    }
+/// Outcome of a [`Diagnostic`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Ok,
+    /// Works, but something deserves attention
+    Warning,
+    /// Broken
+    Error,
+}
 
-                    match fs::read(&path).await {
-                        Ok(content) => match bitcode::deserialize::<CachedDoc>(&content) {
-                            Ok(cached_doc) => {
-                                cache.insert(stem.to_string(), cached_doc);
-                            }
-                            Err(e) => {
-                                warn!("Failed to parse cached doc {}: {}", stem, e);
-                            }
-                        },
-                        Err(e) => {
-                            warn!("Failed to read cached doc {}: {}", stem, e);
-                        }
-                    }
+/// One check run by [`DevDocsManager::diagnostics`]
+#[derive(Debug, Clone, Serialize)]
+pub struct Diagnostic {
+    /// What was checked, e.g. `data dir`
+    pub check: String,
+    pub status: CheckStatus,
+    /// What the check found
+    pub message: String,
+    /// What to do about it, for checks that didn't pass
+    pub fix: Option<String>,
+}
+
+impl Diagnostic {
+    fn ok(check: &str, message: impl Into<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status: CheckStatus::Ok,
+            message: message.into(),
+            fix: None,
+        }
+    }
+
+    fn failed(
+        status: CheckStatus,
+        check: &str,
+        message: impl Into<String>,
+        fix: impl Into<String>,
+    ) -> Self {
+        Self {
+            check: check.to_string(),
+            status,
+            message: message.into(),
+            fix: Some(fix.into()),
+        }
+    }
+}
+
+/// Entries added, removed or moved by the latest update of a doc
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct DocChanges {
+    /// `mtime` of the version that was replaced
+    pub from_mtime: u64,
+    /// `mtime` of the version that was installed
+    pub to_mtime: u64,
+    /// Unix timestamp of the update
+    pub timestamp: u64,
+    pub added: Vec<Entry>,
+    pub removed: Vec<Entry>,
+    pub moved: Vec<MovedEntry>,
+}
+
+/// An entry that kept its name and type but now lives at another path
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct MovedEntry {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+impl DocChanges {
+    /// Diff the indexes of two versions of a doc
+    fn between(old: &CachedDoc, new: &CachedDoc) -> Self {
+        let key = |e: &Entry| (e.name.clone(), e.path.clone(), e.entry_type.clone());
+        let old_keys: HashSet<_> = old.index.entries.iter().map(key).collect();
+        let new_keys: HashSet<_> = new.index.entries.iter().map(key).collect();
+
+        let mut removed: Vec<Entry> = old
+            .index
+            .entries
+            .iter()
+            .filter(|e| !new_keys.contains(&key(e)))
+            .cloned()
+            .collect();
+
+        let mut added = Vec::new();
+        let mut moved = Vec::new();
+        for entry in new
+            .index
+            .entries
+            .iter()
+            .filter(|e| !old_keys.contains(&key(e)))
+        {
+            // Same name and type at another path is a move, not a new entry
+            let previous = removed
+                .iter()
+                .position(|r| r.name == entry.name && r.entry_type == entry.entry_type);
+            match previous {
+                Some(i) => {
+                    let previous = removed.remove(i);
+                    moved.push(MovedEntry {
+                        name: entry.name.clone(),
+                        entry_type: entry.entry_type.clone(),
+                        from: previous.path,
+                        to: entry.path.clone(),
+                    });
                 }
+                None => added.push(entry.clone()),
             }
         }
 
-        // Load available docs cache
-        if let Ok(content) = fs::read_to_string(self.data_dir.join("available_docs.json")).await {
-            if let Ok((docs, cached_at)) = serde_json::from_str::<(Vec<Doc>, u64)>(&content) {
-                *self.available_docs.write().await = Some((docs, cached_at));
-            }
+        DocChanges {
+            from_mtime: old.doc.mtime,
+            to_mtime: new.doc.mtime,
+            timestamp: current_timestamp(),
+            added,
+            removed,
+            moved,
         }
+    }
+}
 
-        info!("Loaded {} cached documentation entries", cache.len());
-        Ok(())
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedDoc {
+    doc: Doc,
+    formats: Option<Formats>,
+    index: DocIndex,
+    cached_at: u64,
+    /// Matcher haystacks parallel to `index.entries`, persisted in a sidecar file
+    #[serde(skip)]
+    haystacks: Vec<Haystack>,
+    /// [`generation`](Self::generation) of the doc the haystacks were computed for
+    #[serde(skip)]
+    haystacks_generation: Option<(u64, u64)>,
+}
+
+/// Sidecar file of a doc's haystacks, tagged with the generation they belong to
+#[derive(Serialize, Deserialize)]
+struct HaystackFile {
+    generation: (u64, u64),
+    haystacks: Vec<Haystack>,
+}
+
+/// An entry name pre-converted into the form the matcher consumes, so searches
+/// don't redo the UTF-32 conversion for every entry on every call
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+struct Haystack {
+    /// Grapheme chars of the name, or `None` when the raw bytes can be matched directly
+    chars: Option<Vec<char>>,
+    /// Lowercased name, used for exact and prefix boosts
+    lower: String,
+    /// Lowercased first letters of the words of the name, used for the acronym boost
+    initials: String,
+}
+
+impl Haystack {
+    fn new(name: &str) -> Self {
+        // Mirrors `Utf32Str::new`, which treats all-ASCII graphemes as plain bytes
+        let chars = if name.is_ascii() {
+            None
+        } else {
+            let chars: Vec<char> = nucleo::chars::graphemes(name).collect();
+            Some(chars).filter(|chars| !chars.iter().all(char::is_ascii))
+        };
+
+        Self {
+            chars,
+            lower: name.to_lowercase(),
+            initials: initials(name),
+        }
     }
 
-    async fn save_available_docs(&self, docs: &[Doc]) -> Result<()> {
-        let path = self.data_dir.join("available_docs.json");
-        let data = (docs, current_timestamp());
-        let json = serde_json::to_string_pretty(&data)?;
-        fs::write(path, json).await?;
-        Ok(())
+    fn as_utf32<'a>(&'a self, name: &'a str) -> Utf32Str<'a> {
+        match &self.chars {
+            Some(chars) => Utf32Str::Unicode(chars),
+            None => Utf32Str::Ascii(name.as_bytes()),
+        }
     }
 }
 
-impl Default for DevDocsManager {
-    fn default() -> Self {
-        Self::new().expect("Failed to create DevDocsManager")
+/// The lowercased first letter of each word of a name, where words are split at
+/// punctuation and at camel-case humps (`getBoundingClientRect` → `gbcr`,
+/// `HTMLElement` → `he`)
+fn initials(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut initials = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let starts_word = match prev {
+            None => true,
+            Some(prev) if !prev.is_alphanumeric() => true,
+            Some(prev) => {
+                c.is_uppercase()
+                    && (!prev.is_uppercase() || next.is_some_and(|next| next.is_lowercase()))
+            }
+        };
+        if starts_word {
+            initials.extend(c.to_lowercase());
+        }
     }
+    initials
 }
 
-// Helper functions
+impl CachedDoc {
+    /// Which version of the index this is: the doc's mtime and when it was cached
+    fn generation(&self) -> (u64, u64) {
+        (self.doc.mtime, self.cached_at)
+    }
 
-fn current_timestamp() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time went backwards")
-        .as_secs()
-}
+    /// Recompute the haystacks if they are missing or were computed for another generation
+    fn ensure_haystacks(&mut self) -> bool {
+        if self.haystacks_generation == Some(self.generation()) {
+            return false;
+        }
 
-fn add_ext(mut path: PathBuf, ext: &str) -> PathBuf {
-    if let Some(cur_ext) = path.extension() {
-        // If we find an extension, like in the sub-trait thing, extend it with html
-        let mut new_ext = cur_ext.to_os_string();
-        let dotted_ext = format!(".{}", ext);
-        new_ext.push(dotted_ext);
-        path.set_extension(new_ext);
-    } else {
-        // no extension, just html
-        path.set_extension(ext);
+        self.haystacks = self
+            .index
+            .entries
+            .iter()
+            .map(|entry| Haystack::new(&entry.name))
+            .collect();
+        self.haystacks_generation = Some(self.generation());
+        true
     }
-    path
 }
 
-use regex::{Captures, Regex};
-fn ensure_extensions(html: &str, ext: &str) -> String {
-    // match href="..."; group 1 is the URL
-    let re = Regex::new(r#"href="([^"]+)""#).unwrap();
+#[derive(Debug)]
+pub struct DevDocsManager {
+    transport: Arc<dyn HttpTransport>,
+    /// Site serving `docs.json`
+    base_url: String,
+    /// Host serving each doc's pages, `index.json` and `db.json`
+    documents_url: String,
+    data_dir: PathBuf,
+    /// Writable layer stacked on top of `data_dir`, which is then treated as read-only
+    overlay_dir: Option<PathBuf>,
+    /// Maximum size of the writable layer in bytes
+    quota: Option<u64>,
+    /// Whether installs over the quota evict the least recently used docs
+    evict_lru: bool,
+    /// Whether installs store page contents compressed instead of rendering them
+    lazy_render: bool,
+    /// Whether installs fetch the assets pages reference for offline use
+    offline_assets: bool,
+    /// Theme overriding the one in the settings
+    theme: Option<Theme>,
+    /// The user's [`USER_CSS`], if any
+    user_css: Option<String>,
+    /// The user's [`USER_JS`], if any
+    user_js: Option<String>,
+    /// Model embedding pages at install time and queries for semantic search
+    embedder: Option<Arc<dyn Embedder>>,
+    /// Threads searches rank entries on, rayon's global pool when `None`
+    search_pool: Option<Arc<rayon::ThreadPool>>,
+    /// Where docs are installed from, DevDocs first
+    sources: Vec<Arc<dyn DocSource>>,
+    /// Source of each available doc not provided by DevDocs, by slug
+    doc_sources: RwLock<HashMap<String, String>>,
+    cache: RwLock<HashMap<String, CachedDoc>>,
+    /// Indexes of docs searched on devdocs.io without being installed, by slug
+    remote_indexes: RwLock<HashMap<String, CachedDoc>>,
+    /// Results of recent searches, dropped whenever what they were ranked from changes
+    query_cache: Mutex<QueryCache>,
+    metrics: Metrics,
+    /// Installs running right now, by slug and formats
+    adding: InFlight<()>,
+    /// Updates running right now, by slug and whether they're forced
+    updating: InFlight<UpdateStatus>,
+    available_docs: RwLock<Option<(Vec<Doc>, u64)>>,
+    /// User-defined aliases, mapping alias to slug
+    aliases: RwLock<HashMap<String, String>>,
+    /// Installed docs left out of searches unless asked for by name
+    disabled: RwLock<HashSet<String>>,
+    /// Installed docs whose pages are rendered on demand, which updates keep them at
+    lazy_docs: RwLock<HashSet<String>>,
+    /// Entry types the pages of partially installed docs are limited to, by slug
+    type_filters: RwLock<HashMap<String, Vec<String>>>,
+    settings: RwLock<Settings>,
+    /// Exclusive cache lock shared by every in-flight mutation of this manager
+    write_lock: Mutex<Weak<CacheLock>>,
+    events: broadcast::Sender<ManagerEvent>,
+}
+
+/// An advisory lock on the writable layer, released when dropped
+#[derive(Debug)]
+struct CacheLock {
+    _file: std::fs::File,
+}
 
-    let dotted_ext = format!(".{}", ext);
+/// Builder for a [`DevDocsManager`] with non-default storage locations
+#[derive(Debug, Default)]
+pub struct DevDocsManagerBuilder {
+    data_dir: Option<PathBuf>,
+    overlay_dir: Option<PathBuf>,
+    shared_dir: Option<PathBuf>,
+    profile: Option<String>,
+    base_url: Option<String>,
+    documents_url: Option<String>,
+    quota: Option<u64>,
+    evict_lru: bool,
+    lazy_render: bool,
+    offline_assets: bool,
+    theme: Option<Theme>,
+    #[cfg(feature = "reqwest")]
+    proxy: Option<String>,
+    #[cfg(feature = "reqwest")]
+    proxy_auth: Option<(String, String)>,
+    #[cfg(feature = "reqwest")]
+    root_certificates: Vec<PathBuf>,
+    transport: Option<Arc<dyn HttpTransport>>,
+    embedder: Option<Arc<dyn Embedder>>,
+    sources: Vec<Box<dyn DocSource>>,
+    config_file: Option<PathBuf>,
+    user_css: Option<String>,
+    user_js: Option<String>,
+    #[cfg(feature = "semantic")]
+    local_embeddings: bool,
+    search_threads: Option<usize>,
+    search_pool: Option<Arc<rayon::ThreadPool>>,
+    isolated: bool,
+}
 
-    re.replace_all(html, |caps: &Captures| {
-        let url = &caps[1];
+impl DevDocsManagerBuilder {
+    /// Site to fetch the list of available docs from (defaults to devdocs.io)
+    pub fn base_url(mut self, url: impl Into<String>) -> Self {
+        self.base_url = Some(url.into());
+        self
+    }
 
-        // leave absolute URLs alone
-        if url.starts_with("http://") || url.starts_with("https://") {
-            format!(r#"href="{}""#, url)
-        } else {
-            // split off a fragment if any
-            let (path, fragment) = match url.split_once('#') {
-                Some((p, f)) => (p, format!("#{}", f)),
-                None => (url, String::new()),
-            };
-            // only add `.html` if it's not already there
-            let path = if path.ends_with(&dotted_ext) {
-                path.to_string()
-            } else {
-                format!("{}.{}", path, ext)
+    /// Host to download doc indexes and contents from (defaults to documents.devdocs.io)
+    pub fn documents_url(mut self, url: impl Into<String>) -> Self {
+        self.documents_url = Some(url.into());
+        self
+    }
+
+    /// Directory holding the installed docs (defaults to the local data dir), or the
+    /// data dirs of the profiles with [`profile`](Self::profile)
+    pub fn data_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.data_dir = Some(dir.into());
+        self
+    }
+
+    /// Use the docs and settings of a profile, like `work` or `personal`, kept apart
+    /// from those of every other profile and of the data dir itself
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// Enable overlay mode: `data_dir` becomes a read-only lower layer and every
+    /// mutation is written to `dir` instead, then merged back in at read time.
+    pub fn overlay_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.overlay_dir = Some(dir.into());
+        self
+    }
+
+    /// Use only the settings given to the builder: ignore the `DEVDOCS_*` environment
+    /// variables, the config file of the user's config dir and the shared doc store
+    /// under [`SHARED_DATA_DIR`].
+    pub fn isolated(mut self) -> Self {
+        self.isolated = true;
+        self
+    }
+
+    /// Stack the data dir on a read-only doc store shared by every user, like one
+    /// provisioned under `/usr/share`: searches read both, installs and removals only
+    /// touch the data dir. Can't be combined with an [`overlay_dir`](Self::overlay_dir).
+    ///
+    /// Without one, the default data dir is stacked on [`SHARED_DATA_DIR`] if it exists.
+    pub fn shared_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.shared_dir = Some(dir.into());
+        self
+    }
+
+    /// Cap the total size of the writable layer, in bytes.
+    ///
+    /// Installs that would exceed it fail with [`DevDocsError::QuotaExceeded`].
+    pub fn quota(mut self, bytes: u64) -> Self {
+        self.quota = Some(bytes);
+        self
+    }
+
+    /// Make room for installs over the quota by removing the least recently used docs
+    pub fn evict_lru(mut self, evict: bool) -> Self {
+        self.evict_lru = evict;
+        self
+    }
+
+    /// Keep each doc's pages in a single compressed store instead of rendering them at
+    /// install time; pages are then rendered one at a time by [`DevDocsManager::page_file`].
+    ///
+    /// Only applies to docs that aren't installed yet: updates keep each doc the way it
+    /// was installed.
+    pub fn lazy_render(mut self, lazy: bool) -> Self {
+        self.lazy_render = lazy;
+        self
+    }
+
+    /// Download the images and stylesheets pages reference while installing, and point
+    /// the pages at the local copies so they render without a network connection
+    pub fn offline_assets(mut self, offline: bool) -> Self {
+        self.offline_assets = offline;
+        self
+    }
+
+    /// Show pages in a browser with this theme, whatever the `theme` setting says
+    pub fn theme(mut self, theme: Theme) -> Self {
+        self.theme = Some(theme);
+        self
+    }
+
+    /// Fetch everything through a custom transport instead of the built-in reqwest client.
+    ///
+    /// Proxy and certificate settings only apply to the built-in client.
+    pub fn transport(mut self, transport: impl HttpTransport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Read the [`CONFIG_FILE`] at `path` instead of the one in the config dir. The
+    /// [`USER_CSS`] and [`USER_JS`] are then looked for next to it.
+    pub fn config_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config_file = Some(path.into());
+        self
+    }
+
+    /// Add this stylesheet to the pages shown in a browser or exported, instead of the
+    /// [`USER_CSS`] in the config dir
+    pub fn user_css(mut self, css: impl Into<String>) -> Self {
+        self.user_css = Some(css.into());
+        self
+    }
+
+    /// Add this script to the pages shown in a browser or exported, instead of the
+    /// [`USER_JS`] in the config dir
+    pub fn user_js(mut self, js: impl Into<String>) -> Self {
+        self.user_js = Some(js.into());
+        self
+    }
+
+    /// Offer the docs of another source next to those of DevDocs.
+    ///
+    /// When several sources list the same slug, DevDocs wins, then the source
+    /// registered first.
+    pub fn source(mut self, source: impl DocSource + 'static) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    /// Embed pages with a custom model while installing, enabling
+    /// [`DevDocsManager::search_semantic`]
+    pub fn embedder(mut self, embedder: impl Embedder + 'static) -> Self {
+        self.embedder = Some(Arc::new(embedder));
+        self
+    }
+
+    /// Embed pages with a small local model while installing, enabling
+    /// [`DevDocsManager::search_semantic`]. The model is downloaded into the data dir
+    /// the first time it's needed.
+    #[cfg(feature = "semantic")]
+    pub fn local_embeddings(mut self, enable: bool) -> Self {
+        self.local_embeddings = enable;
+        self
+    }
+
+    /// Rank search results on a pool of `threads` threads of the manager's own, instead
+    /// of rayon's global pool that the host application may be using too.
+    ///
+    /// A single thread ranks without any parallelism, for constrained environments.
+    pub fn search_threads(mut self, threads: usize) -> Self {
+        self.search_threads = Some(threads.max(1));
+        self
+    }
+
+    /// Rank search results on `pool`, e.g. one the host application already manages
+    pub fn search_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.search_pool = Some(pool);
+        self
+    }
+
+    /// Send every request through a proxy, e.g. `http://proxy:3128` or `socks5://proxy:1080`.
+    ///
+    /// Without one, the usual `HTTP_PROXY`/`HTTPS_PROXY` variables are honored.
+    #[cfg(feature = "reqwest")]
+    pub fn proxy(mut self, url: impl Into<String>) -> Self {
+        self.proxy = Some(url.into());
+        self
+    }
+
+    /// Credentials to authenticate against the [`proxy`](Self::proxy) with.
+    ///
+    /// Without them, `DEVDOCS_PROXY_AUTH` is read as `user:password`.
+    #[cfg(feature = "reqwest")]
+    pub fn proxy_auth(mut self, username: impl Into<String>, password: impl Into<String>) -> Self {
+        self.proxy_auth = Some((username.into(), password.into()));
+        self
+    }
+
+    /// Trust an additional root certificate (PEM or DER), e.g. of an intercepting proxy
+    #[cfg(feature = "reqwest")]
+    pub fn add_root_certificate(mut self, path: impl Into<PathBuf>) -> Self {
+        self.root_certificates.push(path.into());
+        self
+    }
+
+    /// Build the manager.
+    ///
+    /// Settings that weren't given explicitly fall back to the `DEVDOCS_DATA_DIR`,
+    /// `DEVDOCS_PROFILE`, `DEVDOCS_OVERLAY_DIR`, `DEVDOCS_SHARED_DIR`, `DEVDOCS_BASE_URL`, `DEVDOCS_DOCUMENTS_URL`,
+    /// `DEVDOCS_QUOTA`, `DEVDOCS_PROXY`, `DEVDOCS_PROXY_AUTH`, `DEVDOCS_CA_CERT`,
+    /// `DEVDOCS_CONFIG` and `DEVDOCS_SEARCH_THREADS` environment variables, then to the
+    /// defaults. An [`isolated`](Self::isolated) builder skips the environment.
+    pub fn build(mut self) -> Result<DevDocsManager> {
+        let transport = match self.transport.take() {
+            Some(transport) => transport,
+            None => self.default_transport()?,
+        };
+        let isolated = self.isolated;
+
+        let given_data_dir = self
+            .data_dir
+            .or_else(|| env_var(isolated, "DEVDOCS_DATA_DIR").map(PathBuf::from));
+        let default_data_dir = given_data_dir.is_none();
+        let mut data_dir = match given_data_dir {
+            Some(dir) => dir,
+            None => dirs::data_local_dir()
+                .context("Failed to get local data directory")?
+                .join("devdocs"),
+        };
+        if let Some(profile) = self
+            .profile
+            .or_else(|| env_string(isolated, "DEVDOCS_PROFILE"))
+            .filter(|name| !name.is_empty())
+        {
+            let valid = profile
+                .chars()
+                .all(|c| c.is_alphanumeric() || matches!(c, '_' | '-' | '.'))
+                && !profile.starts_with('.');
+            if !valid {
+                anyhow::bail!("Invalid profile name '{}'", profile);
+            }
+            data_dir = data_dir.join(PROFILES_DIR).join(profile);
+        }
+
+        let base_url = endpoint(
+            self.base_url
+                .or_else(|| env_string(isolated, "DEVDOCS_BASE_URL")),
+            DEVDOCS_BASE_URL,
+        );
+        let documents_url = endpoint(
+            self.documents_url
+                .or_else(|| env_string(isolated, "DEVDOCS_DOCUMENTS_URL")),
+            DOCUMENTS_BASE_URL,
+        );
+
+        let overlay_dir = self
+            .overlay_dir
+            .or_else(|| env_var(isolated, "DEVDOCS_OVERLAY_DIR").map(PathBuf::from));
+
+        // The shared store is the read-only lower layer, the data dir the writable one
+        let mut shared_dir = self
+            .shared_dir
+            .or_else(|| env_var(isolated, "DEVDOCS_SHARED_DIR").map(PathBuf::from));
+        if shared_dir.is_none() && default_data_dir && overlay_dir.is_none() && !isolated {
+            shared_dir = Some(PathBuf::from(SHARED_DATA_DIR)).filter(|dir| dir.is_dir());
+        }
+        let (data_dir, overlay_dir) = match (shared_dir, overlay_dir) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("A shared doc store can't be combined with an overlay dir")
+            }
+            (Some(shared), None) => (shared, Some(data_dir)),
+            (None, overlay_dir) => (data_dir, overlay_dir),
+        };
+
+        let quota = self
+            .quota
+            .or_else(|| env_string(isolated, "DEVDOCS_QUOTA").and_then(|bytes| bytes.parse().ok()));
+
+        #[cfg(feature = "semantic")]
+        if self.local_embeddings && self.embedder.is_none() {
+            let models = overlay_dir.as_ref().unwrap_or(&data_dir).join(MODELS_DIR);
+            self.embedder = Some(Arc::new(semantic::LocalEmbedder::new(models)));
+        }
+
+        let config_file = self
+            .config_file
+            .or_else(|| env_var(isolated, "DEVDOCS_CONFIG").map(PathBuf::from))
+            .or_else(|| {
+                let dir = dirs::config_dir().filter(|_| !isolated)?;
+                Some(dir.join("devdocs").join(CONFIG_FILE))
+            });
+        if let Some(config) = config_file
+            .as_deref()
+            .map(ConfigFile::load)
+            .transpose()?
+            .flatten()
+        {
+            for dir in config.html_dirs {
+                let source = sources::HtmlDirSource::new(dir.name, dir.path).entries(dir.entries);
+                self.sources.push(Box::new(source));
+            }
+        }
+        let config_dir = config_file.as_deref().and_then(Path::parent);
+        let user_file = |name: &str| -> Result<Option<String>> {
+            let Some(path) = config_dir.map(|dir| dir.join(name)) else {
+                return Ok(None);
             };
-            // reassemble
-            format!(r#"href="{}{}""#, path, fragment)
+            match std::fs::read_to_string(&path) {
+                Ok(text) => Ok(Some(text)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+            }
+        };
+        let user_css = match self.user_css {
+            Some(css) => Some(css),
+            None => user_file(USER_CSS)?,
+        };
+        let user_js = match self.user_js {
+            Some(js) => Some(js),
+            None => user_file(USER_JS)?,
+        };
+
+        let search_threads = self.search_threads.or_else(|| {
+            env_string(isolated, "DEVDOCS_SEARCH_THREADS")
+                .and_then(|threads| threads.parse().ok())
+                .filter(|threads| *threads > 0)
+        });
+        let search_pool = match (self.search_pool, search_threads) {
+            (Some(pool), _) => Some(pool),
+            (None, Some(threads)) => Some(Arc::new(
+                rayon::ThreadPoolBuilder::new()
+                    .num_threads(threads)
+                    .thread_name(|i| format!("devdocs-search-{}", i))
+                    .build()?,
+            )),
+            (None, None) => None,
+        };
+
+        let devdocs = DevDocsSource::new(transport.clone(), &base_url, &documents_url);
+        let mut sources: Vec<Arc<dyn DocSource>> = vec![Arc::new(devdocs)];
+        for mut source in self.sources {
+            if sources.iter().any(|s| s.name() == source.name()) {
+                anyhow::bail!("Two doc sources are named '{}'", source.name());
+            }
+            source.set_transport(transport.clone());
+            sources.push(Arc::from(source));
         }
-    })
-    .into_owned()
+
+        Ok(DevDocsManager {
+            transport,
+            base_url,
+            documents_url,
+            data_dir,
+            overlay_dir,
+            quota,
+            evict_lru: self.evict_lru,
+            lazy_render: self.lazy_render,
+            offline_assets: self.offline_assets,
+            theme: self.theme,
+            user_css,
+            user_js,
+            embedder: self.embedder,
+            search_pool,
+            sources,
+            doc_sources: RwLock::new(HashMap::new()),
+            cache: RwLock::new(HashMap::new()),
+            remote_indexes: RwLock::new(HashMap::new()),
+            query_cache: Mutex::new(QueryCache::default()),
+            metrics: Metrics::default(),
+            adding: InFlight::default(),
+            updating: InFlight::default(),
+            available_docs: RwLock::new(None),
+            aliases: RwLock::new(HashMap::new()),
+            disabled: RwLock::new(HashSet::new()),
+            lazy_docs: RwLock::new(HashSet::new()),
+            type_filters: RwLock::new(HashMap::new()),
+            settings: RwLock::new(Settings::default()),
+            write_lock: Mutex::new(Weak::new()),
+            events: broadcast::channel(1024).0,
+        })
+    }
+
+    /// The reqwest client, configured with the proxy and certificate settings
+    #[cfg(feature = "reqwest")]
+    fn default_transport(&self) -> Result<Arc<dyn HttpTransport>> {
+        let mut client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(30))
+            .user_agent("devdocs-rs/1.0");
+
+        let proxy = self
+            .proxy
+            .clone()
+            .or_else(|| env_string(self.isolated, "DEVDOCS_PROXY"));
+        if let Some(url) = proxy {
+            let mut proxy = reqwest::Proxy::all(&url)
+                .with_context(|| format!("Invalid proxy URL '{}'", url))?;
+            let auth = self.proxy_auth.clone().or_else(|| {
+                env_string(self.isolated, "DEVDOCS_PROXY_AUTH").map(|auth| split_credentials(&auth))
+            });
+            if let Some((username, password)) = auth {
+                proxy = proxy.basic_auth(&username, &password);
+            }
+            client = client.proxy(proxy);
+        }
+
+        let mut root_certificates = self.root_certificates.clone();
+        if let Some(path) = env_var(self.isolated, "DEVDOCS_CA_CERT") {
+            root_certificates.push(PathBuf::from(path));
+        }
+        for path in root_certificates {
+            let bytes = std::fs::read(&path)
+                .with_context(|| format!("Failed to read certificate {}", path.display()))?;
+            let certificate = reqwest::Certificate::from_pem(&bytes)
+                .or_else(|_| reqwest::Certificate::from_der(&bytes))
+                .with_context(|| format!("Invalid certificate {}", path.display()))?;
+            client = client.add_root_certificate(certificate);
+        }
+
+        Ok(Arc::new(client.build()?))
+    }
+
+    #[cfg(not(feature = "reqwest"))]
+    fn default_transport(&self) -> Result<Arc<dyn HttpTransport>> {
+        anyhow::bail!(
+            "No HTTP transport: enable the `reqwest` feature or supply one with `transport`"
+        )
+    }
 }
 
-// Re-exports for convenience
-pub use nucleo;
-pub use reqwest;
+/// Fetches documents over HTTP; implement it to plug in another client
+pub trait HttpTransport: Send + Sync {
+    /// GET `url` and return the response body, failing on non-success statuses
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>>;
+}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tokio;
+impl std::fmt::Debug for dyn HttpTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("HttpTransport")
+    }
+}
 
-    #[tokio::test]
-    async fn test_manager_creation() {
-        let manager = DevDocsManager::new().unwrap();
-        assert!(manager.data_dir.to_string_lossy().contains("devdocs"));
+/// A catalog of docs to install; implement it to search docs from elsewhere alongside
+/// DevDocs, e.g. generated API docs or an internal mirror
+pub trait DocSource: Send + Sync {
+    /// Short unique name of the source, e.g. `devdocs`
+    fn name(&self) -> &str;
+
+    /// Hands over the manager's transport before the source is first used, so sources
+    /// downloading anything go through the same proxy and certificates as DevDocs
+    fn set_transport(&mut self, _transport: Arc<dyn HttpTransport>) {}
+
+    /// Every doc the source can provide
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>>;
+
+    /// The index of a doc listed by [`list`](Self::list)
+    fn fetch_index<'a>(&'a self, slug: &'a str) -> BoxFuture<'a, Result<DocIndex>>;
+
+    /// The pages of a doc as HTML, keyed by page path without extension
+    fn fetch_content<'a>(&'a self, slug: &'a str)
+    -> BoxFuture<'a, Result<HashMap<String, String>>>;
+
+    /// Look up a doc by slug or name, for sources whose catalog is too large to
+    /// [`list`](Self::list). Searches the listed docs by default.
+    fn find<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<Doc>>> {
+        Box::pin(async move { Ok(self.list().await?.into_iter().find(|doc| doc.slug == name)) })
     }
 
-    #[tokio::test]
-    async fn test_get_available_docs() {
-        let manager = DevDocsManager::new().unwrap();
-        manager.init().await.unwrap();
+    /// A single page as HTML, for sources that leave pages out of
+    /// [`fetch_content`](Self::fetch_content) to produce them when they're first opened.
+    /// `None` when there is no such page.
+    fn fetch_page<'a>(
+        &'a self,
+        _slug: &'a str,
+        _path: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>>> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+impl std::fmt::Debug for dyn DocSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DocSource({})", self.name())
+    }
+}
+
+/// The docs published by DevDocs, the source every manager starts with
+#[derive(Debug)]
+pub struct DevDocsSource {
+    transport: Arc<dyn HttpTransport>,
+    /// Site serving `docs.json`
+    base_url: String,
+    /// Host serving each doc's `index.json` and `db.json`
+    documents_url: String,
+}
 
-        // This test requires network access
-        if std::env::var("SKIP_NETWORK_TESTS").is_err() {
-            let docs = manager.get_available_docs().await.unwrap();
-            assert!(!docs.is_empty());
+impl DevDocsSource {
+    /// Name the DevDocs source is registered under
+    pub const NAME: &str = "devdocs";
+
+    pub fn new(
+        transport: Arc<dyn HttpTransport>,
+        base_url: impl Into<String>,
+        documents_url: impl Into<String>,
+    ) -> Self {
+        Self {
+            transport,
+            base_url: base_url.into(),
+            documents_url: documents_url.into(),
         }
     }
+
+    async fn fetch_json<T: DeserializeOwned>(&self, url: &str) -> Result<T> {
+        let body = self.transport.get(url).await?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+}
+
+impl DocSource for DevDocsSource {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        Box::pin(async move {
+            let url = format!("{}/docs.json", self.base_url);
+            self.fetch_json(&url).await
+        })
+    }
+
+    fn fetch_index<'a>(&'a self, slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        let url = format!("{}/{}/index.json", self.documents_url, slug);
+        debug!("Downloading index: {}", url);
+        Box::pin(async move { self.fetch_json(&url).await })
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        let url = format!("{}/{}/db.json", self.documents_url, slug);
+        debug!("Downloading content: {}", url);
+        Box::pin(async move { self.fetch_json(&url).await })
+    }
+}
+
+/// Turns text into vectors close to each other for texts of similar meaning; implement
+/// it to plug in an embedding model
+pub trait Embedder: Send + Sync {
+    /// Embed each of `texts`, all into vectors of the same length
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+}
+
+impl std::fmt::Debug for dyn Embedder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Embedder")
+    }
+}
+
+#[cfg(feature = "reqwest")]
+impl HttpTransport for reqwest::Client {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        Box::pin(async move {
+            let response = reqwest::Client::get(self, url).send().await?;
+            let body = response.error_for_status()?.bytes().await?;
+            Ok(body.to_vec())
+        })
+    }
+}
+
+impl DevDocsManager {
+    /// Create a new DevDocs manager
+    pub fn new() -> Result<Self> {
+        Self::builder().build()
+    }
+
+    /// Start configuring a manager
+    pub fn builder() -> DevDocsManagerBuilder {
+        DevDocsManagerBuilder::default()
+    }
+
+    /// Create a manager keeping everything in `data_dir` and fetching through
+    /// `transport`, e.g. the `testing::MockTransport` of the `test-support` feature,
+    /// serving fixtures.
+    ///
+    /// The builder is [`isolated`](DevDocsManagerBuilder::isolated) and reads its config
+    /// file from `data_dir`, so tests built on it don't depend on the machine they run on.
+    pub fn with_transport(
+        data_dir: impl Into<PathBuf>,
+        transport: impl HttpTransport + 'static,
+    ) -> Result<Self> {
+        let data_dir = data_dir.into();
+        Self::builder()
+            .isolated()
+            .config_file(data_dir.join(CONFIG_FILE))
+            .data_dir(data_dir)
+            .transport(transport)
+            .build()
+    }
+
+    /// Initialize the manager (create directories, load cache)
+    pub async fn init(&self) -> Result<()> {
+        fs::create_dir_all(self.write_dir()).await?;
+        self.load_cache().await?;
+        Ok(())
+    }
+
+    /// Whether nothing was ever set up in the data dir: no docs are installed and the
+    /// available docs were never listed
+    pub async fn needs_onboarding(&self) -> bool {
+        self.cache.read().await.is_empty()
+            && self
+                .resolve_path(Path::new("available_docs.json"))
+                .is_none()
+    }
+
+    /// Get a first run going: list the available docs and suggest those the project
+    /// `dir` belongs to likely needs, as found by [`detect_project_docs`].
+    ///
+    /// Nothing is installed, the suggestions are for the user to pick from.
+    pub async fn onboard(&self, dir: &Path) -> Result<Onboarding> {
+        let available = self.refresh_available_docs().await?;
+        let mut suggested: Vec<Doc> = Vec::new();
+        for name in detect_project_docs(dir)? {
+            // Versioned docs are listed newest first
+            let found = available.iter().find(|doc| {
+                doc.slug == name
+                    || doc.alias.as_deref() == Some(name.as_str())
+                    || doc
+                        .slug
+                        .split_once('~')
+                        .is_some_and(|(base, _)| base == name)
+            });
+            if let Some(doc) = found
+                && suggested.iter().all(|d| d.slug != doc.slug)
+            {
+                suggested.push(doc.clone());
+            }
+        }
+        Ok(Onboarding {
+            suggested,
+            available: available.len(),
+        })
+    }
+
+    /// Subscribe to the events of every later operation.
+    ///
+    /// Slow subscribers skip events they fell too far behind on.
+    pub fn subscribe(&self) -> impl Stream<Item = ManagerEvent> + Send + 'static {
+        futures::stream::unfold(self.events.subscribe(), |mut rx| async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) => return Some((event, rx)),
+                    Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        })
+    }
+
+    fn emit(&self, event: ManagerEvent) {
+        // Nobody listening is fine
+        let _ = self.events.send(event);
+    }
+
+    /// Directory that all mutations are written to
+    pub fn write_dir(&self) -> &Path {
+        self.overlay_dir.as_deref().unwrap_or(&self.data_dir)
+    }
+
+    /// Take the exclusive cache lock, failing if another process holds it.
+    ///
+    /// Concurrent operations of the same manager share one lock.
+    fn lock_exclusive(&self) -> Result<Arc<CacheLock>> {
+        let mut held = self.write_lock.lock().expect("cache lock poisoned");
+        if let Some(lock) = held.upgrade() {
+            return Ok(lock);
+        }
+
+        let path = self.write_dir().join(LOCK_FILE);
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(std::fs::TryLockError::WouldBlock) => {
+                return Err(DevDocsError::Locked(self.write_dir().display().to_string()).into());
+            }
+            Err(std::fs::TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        let lock = Arc::new(CacheLock { _file: file });
+        *held = Arc::downgrade(&lock);
+        Ok(lock)
+    }
+
+    /// Take a shared cache lock, waiting for any writing process to finish
+    async fn lock_shared(&self) -> Result<CacheLock> {
+        let path = self.write_dir().join(LOCK_FILE);
+        let file = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+            file.lock_shared()?;
+            Ok(file)
+        })
+        .await??;
+
+        Ok(CacheLock { _file: file })
+    }
+
+    /// Storage layers from bottom to top; later layers shadow earlier ones
+    fn layers(&self) -> Vec<&Path> {
+        let mut layers = vec![self.data_dir.as_path()];
+        if let Some(overlay) = &self.overlay_dir {
+            layers.push(overlay);
+        }
+        layers
+    }
+
+    /// Resolve a path relative to the data dir, preferring the topmost layer that has it
+    pub fn resolve_path(&self, relative: &Path) -> Option<PathBuf> {
+        self.layers()
+            .into_iter()
+            .rev()
+            .map(|layer| layer.join(relative))
+            .find(|path| path.exists())
+    }
+
+    /// Refresh the list of available documentation
+    pub async fn refresh_available_docs(&self) -> Result<Vec<Doc>> {
+        info!("Refreshing available documentation list");
+
+        // DevDocs failing fails the refresh, other sources are skipped with a warning
+        let mut docs: Vec<Doc> = self.sources[0].list().await?;
+        // Imported docs have no source to list them again
+        let previous = self.doc_sources.read().await.clone();
+        let mut doc_sources = previous.clone();
+        doc_sources.retain(|_, name| !self.sources.iter().any(|s| s.name() == name));
+        for source in &self.sources[1..] {
+            let listed = match source.list().await {
+                Ok(listed) => listed,
+                Err(e) => {
+                    warn!("Failed to list the docs of {}: {}", source.name(), e);
+                    continue;
+                }
+            };
+            for doc in listed {
+                if docs.iter().all(|d| d.slug != doc.slug) {
+                    doc_sources.insert(doc.slug.clone(), source.name().to_string());
+                    docs.push(doc);
+                }
+            }
+        }
+        // Installed docs of sources that can't list them are looked up one by one
+        let installed: HashSet<String> = self.cache.read().await.keys().cloned().collect();
+        for (slug, name) in previous {
+            let Some(source) = self.sources[1..].iter().find(|s| s.name() == name) else {
+                continue;
+            };
+            if !installed.contains(&slug) || docs.iter().any(|d| d.slug == slug) {
+                continue;
+            }
+            match source.find(&slug).await {
+                Ok(Some(doc)) if doc.slug == slug => docs.push(doc),
+                Ok(_) => warn!("{} no longer provides {}", name, slug),
+                Err(e) => warn!("Failed to look up {} in {}: {}", slug, name, e),
+            }
+            doc_sources.insert(slug, name);
+        }
+        if !doc_sources.is_empty() || self.sources.len() > 1 {
+            let _lock = self.lock_state().await?;
+            self.write_state("sources.json", &doc_sources).await?;
+        }
+        *self.doc_sources.write().await = doc_sources;
+
+        let now = current_timestamp();
+        let mut available = self.available_docs.write().await;
+        *available = Some((docs.clone(), now));
+
+        self.save_available_docs(&docs).await?;
+
+        info!("Refreshed {} available documentation entries", docs.len());
+        Ok(docs)
+    }
+
+    /// Get the list of available documentation (cached or fresh)
+    pub async fn get_available_docs(&self) -> Result<Vec<Doc>> {
+        let available = self.available_docs.read().await;
+
+        if let Some((docs, cached_at)) = &*available
+            && current_timestamp() - cached_at < CACHE_DURATION_DAYS * 24 * 60 * 60
+        {
+            return Ok(docs.clone());
+        }
+
+        drop(available);
+        self.refresh_available_docs().await
+    }
+
+    /// Resolve a slug or alias to the slug of a doc.
+    ///
+    /// User-defined aliases win, then installed docs (by slug or upstream alias),
+    /// then the available docs list. Unknown names are returned unchanged.
+    pub async fn resolve_slug(&self, name: &str) -> Result<String> {
+        if let Some(slug) = self.resolve_installed(name).await {
+            return Ok(slug);
+        }
+
+        let available_docs = self.get_available_docs().await?;
+        let found = available_docs.iter().find(|d| d.slug == name).or_else(|| {
+            available_docs
+                .iter()
+                .find(|d| d.alias.as_deref() == Some(name))
+        });
+
+        Ok(found.map_or_else(|| name.to_string(), |d| d.slug.clone()))
+    }
+
+    /// The docs of a group: one of the `group.<name>` settings, or a built-in group like
+    /// `web`. The name may be given with its `@`.
+    pub async fn expand_group(&self, name: &str) -> Result<Vec<String>> {
+        let name = name.strip_prefix('@').unwrap_or(name);
+        if let Some(docs) = self.settings.read().await.groups.get(name) {
+            return Ok(docs.clone());
+        }
+        match DOC_GROUPS.iter().find(|(group, _)| *group == name) {
+            Some((_, docs)) => Ok(docs.iter().map(|doc| doc.to_string()).collect()),
+            None => anyhow::bail!("Unknown doc group '@{}'", name),
+        }
+    }
+
+    /// Slugs or aliases with each `@group` among them replaced by its docs, keeping the
+    /// first of any duplicates
+    pub async fn expand_groups(&self, names: &[String]) -> Result<Vec<String>> {
+        let mut docs: Vec<String> = Vec::with_capacity(names.len());
+        for name in names {
+            let expanded = if name.starts_with('@') {
+                self.expand_group(name).await?
+            } else {
+                vec![name.clone()]
+            };
+            for doc in expanded {
+                if !docs.contains(&doc) {
+                    docs.push(doc);
+                }
+            }
+        }
+        Ok(docs)
+    }
+
+    /// The slug of a doc, installing it in Markdown first unless it's installed already.
+    ///
+    /// `name` is a slug or alias of an installed or available doc.
+    pub async fn ensure_installed(&self, name: &str) -> Result<String> {
+        if let Some(slug) = self.resolve_installed(name).await {
+            return Ok(slug);
+        }
+        let slug = self.resolve_slug(name).await?;
+        if !self
+            .get_available_docs()
+            .await?
+            .iter()
+            .any(|d| d.slug == slug)
+        {
+            return Err(DevDocsError::DocNotFound(name.to_string()).into());
+        }
+        self.add_doc(&slug, Some(Formats::MARKDOWN)).await?;
+        Ok(slug)
+    }
+
+    /// Resolve a slug or alias against the installed docs only, without touching the network
+    pub async fn resolve_installed(&self, name: &str) -> Option<String> {
+        if let Some(slug) = self.aliases.read().await.get(name) {
+            return Some(slug.clone());
+        }
+
+        let cache = self.cache.read().await;
+        if cache.contains_key(name) {
+            return Some(name.to_string());
+        }
+
+        cache
+            .iter()
+            .find(|(_, cached)| cached.doc.alias.as_deref() == Some(name))
+            .map(|(slug, _)| slug.clone())
+    }
+
+    /// Define a persistent alias for a slug
+    pub async fn set_alias(&self, alias: &str, slug: &str) -> Result<()> {
+        if alias.is_empty() || alias.contains(['/', '\\']) {
+            return Err(DevDocsError::InvalidSlug(alias.to_string()).into());
+        }
+
+        self.update_state("aliases.json", &self.aliases, |aliases| {
+            aliases.insert(alias.to_string(), slug.to_string());
+            Ok(())
+        })
+        .await?;
+        self.invalidate_queries();
+        Ok(())
+    }
+
+    /// Remove a user-defined alias, returning whether it existed
+    pub async fn remove_alias(&self, alias: &str) -> Result<bool> {
+        let existed = self
+            .update_state("aliases.json", &self.aliases, |aliases| {
+                Ok(aliases.remove(alias).is_some())
+            })
+            .await?;
+        if existed {
+            self.invalidate_queries();
+        }
+        Ok(existed)
+    }
+
+    /// List the user-defined aliases
+    pub async fn aliases(&self) -> HashMap<String, String> {
+        self.aliases.read().await.clone()
+    }
+
+    /// Enable or disable an installed doc.
+    ///
+    /// Disabled docs stay installed but are left out of searches, unless a search is
+    /// scoped to them by name.
+    pub async fn set_doc_enabled(&self, slug: &str, enabled: bool) -> Result<()> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+
+        let changed = self
+            .update_state("disabled.json", &self.disabled, |disabled| {
+                Ok(if enabled {
+                    disabled.remove(&slug)
+                } else {
+                    disabled.insert(slug)
+                })
+            })
+            .await?;
+        if changed {
+            self.invalidate_queries();
+        }
+        Ok(())
+    }
+
+    /// Whether an installed doc takes part in searches
+    pub async fn is_doc_enabled(&self, slug: &str) -> bool {
+        let slug = self
+            .resolve_installed(slug)
+            .await
+            .unwrap_or_else(|| slug.to_string());
+        !self.disabled.read().await.contains(&slug)
+    }
+
+    /// The user settings
+    pub async fn settings(&self) -> Settings {
+        self.settings.read().await.clone()
+    }
+
+    /// Theme to show pages in a browser with: the one given to the builder, else the
+    /// `theme` setting
+    pub async fn theme(&self) -> Theme {
+        self.theme
+            .or(self.settings.read().await.theme)
+            .unwrap_or_default()
+    }
+
+    /// The MathJax script pages opened in a browser load, see [`Settings::mathjax`]
+    pub async fn mathjax(&self) -> Option<String> {
+        self.settings.read().await.mathjax.clone()
+    }
+
+    /// The user's stylesheet, see [`DevDocsManagerBuilder::user_css`]
+    pub fn user_css(&self) -> Option<&str> {
+        self.user_css.as_deref()
+    }
+
+    /// The user's script, see [`DevDocsManagerBuilder::user_js`]
+    pub fn user_js(&self) -> Option<&str> {
+        self.user_js.as_deref()
+    }
+
+    /// Inline the user's stylesheet at the end of the head of an HTML document, and
+    /// their script at the end of its body
+    pub fn with_user_assets(&self, document: &str) -> String {
+        let mut document = document.to_string();
+        if let Some(css) = &self.user_css {
+            let style = format!(
+                "<style>\n{}\n</style>\n",
+                css.replace("</style", "<\\/style")
+            );
+            let at = document.rfind("</head>").unwrap_or(0);
+            document.insert_str(at, &style);
+        }
+        if let Some(js) = &self.user_js {
+            let script = format!(
+                "<script>\n{}\n</script>\n",
+                js.replace("</script", "<\\/script")
+            );
+            let at = document.rfind("</body>").unwrap_or(document.len());
+            document.insert_str(at, &script);
+        }
+        document
+    }
+
+    /// Change a setting and persist it. The keys are:
+    ///
+    /// - `weight.<slug>`: the multiplier of a doc's search scores
+    /// - `converter.<target>`: a command converting pages for `markdown` or `epub`
+    /// - `group.<name>`: comma-separated docs added and removed together as `@<name>`
+    /// - `clean`: `true` to strip site chrome from the pages of docs installed from now on
+    /// - `theme`: `auto`, `light` or `dark`
+    /// - `remote`: comma-separated docs that remote searches fall back to
+    /// - `language`: the translation searched when several are installed, like `ja`
+    /// - `mathjax`: the URL or local path of the MathJax script typesetting formulas
+    pub async fn set_config(&self, key: &str, value: &str) -> Result<()> {
+        let weighted = self.weighted_slug(key).await;
+        let change = |settings: &mut Settings| {
+            match key.split_once('.') {
+                Some(("weight", name)) if !name.is_empty() => {
+                    let weight: f32 = value
+                        .parse()
+                        .ok()
+                        .filter(|w: &f32| w.is_finite() && *w >= 0.0)
+                        .with_context(|| format!("Invalid weight '{}'", value))?;
+                    settings
+                        .weights
+                        .insert(weighted.unwrap_or_default(), weight);
+                }
+                Some(("converter", target)) if convert::CONVERTER_TARGETS.contains(&target) => {
+                    if value.trim().is_empty() {
+                        anyhow::bail!("Empty converter command");
+                    }
+                    settings
+                        .converters
+                        .insert(target.to_string(), value.to_string());
+                }
+                Some(("group", name)) if !name.is_empty() => {
+                    let docs = split_list(value);
+                    if docs.is_empty() {
+                        anyhow::bail!("Empty group, list its docs separated by commas");
+                    }
+                    settings.groups.insert(name.to_string(), docs);
+                }
+                None if key == "clean" => {
+                    settings.clean = value
+                        .parse()
+                        .with_context(|| format!("Invalid value '{}', use true or false", value))?;
+                }
+                None if key == "theme" => {
+                    let theme = Theme::named(value).with_context(|| {
+                        format!("Unknown theme '{}', use auto, light or dark", value)
+                    })?;
+                    settings.theme = Some(theme);
+                }
+                None if key == "remote" => settings.remote_docs = split_list(value),
+                None if key == "language" => {
+                    let language = value.trim().to_lowercase();
+                    if !is_language(&language) {
+                        anyhow::bail!(
+                            "Invalid language '{}', use a two-letter code like ja",
+                            value
+                        );
+                    }
+                    settings.language = Some(language);
+                }
+                None if key == "mathjax" => {
+                    let src = value.trim();
+                    let local = (!src.contains("://")).then(|| std::path::absolute(src));
+                    settings.mathjax = Some(match local {
+                        Some(Ok(path)) if path.is_file() => path.to_string_lossy().into_owned(),
+                        None => src.to_string(),
+                        Some(_) => anyhow::bail!(
+                            "No MathJax script at '{}', use a URL or the path of a local copy",
+                            value
+                        ),
+                    });
+                }
+                _ => anyhow::bail!("Unknown config key '{}'", key),
+            }
+            Ok(())
+        };
+        self.update_state("config.json", &self.settings, change)
+            .await?;
+        self.invalidate_queries();
+        Ok(())
+    }
+
+    /// Reset a setting to its default, returning whether it was set
+    pub async fn unset_config(&self, key: &str) -> Result<bool> {
+        let weighted = self.weighted_slug(key).await;
+        let change = |settings: &mut Settings| {
+            Ok(match key.split_once('.') {
+                Some(("weight", _)) => settings
+                    .weights
+                    .remove(&weighted.unwrap_or_default())
+                    .is_some(),
+                Some(("converter", target)) if convert::CONVERTER_TARGETS.contains(&target) => {
+                    settings.converters.remove(target).is_some()
+                }
+                Some(("group", name)) => settings.groups.remove(name).is_some(),
+                None if key == "clean" => std::mem::take(&mut settings.clean),
+                None if key == "theme" => settings.theme.take().is_some(),
+                None if key == "remote" => !std::mem::take(&mut settings.remote_docs).is_empty(),
+                None if key == "language" => settings.language.take().is_some(),
+                None if key == "mathjax" => settings.mathjax.take().is_some(),
+                _ => anyhow::bail!("Unknown config key '{}'", key),
+            })
+        };
+        let existed = self
+            .update_state("config.json", &self.settings, change)
+            .await?;
+        if existed {
+            self.invalidate_queries();
+        }
+        Ok(existed)
+    }
+
+    /// The installed doc a `weight.<name>` config key is about, or `name` itself
+    async fn weighted_slug(&self, key: &str) -> Option<String> {
+        let name = key.strip_prefix("weight.")?;
+        Some(
+            self.resolve_installed(name)
+                .await
+                .unwrap_or_else(|| name.to_string()),
+        )
+    }
+
+    /// Installed docs that are disabled, sorted by slug
+    pub async fn disabled_docs(&self) -> Vec<String> {
+        let mut disabled: Vec<String> = self.disabled.read().await.iter().cloned().collect();
+        disabled.sort();
+        disabled
+    }
+
+    async fn split_into(
+        &self,
+        dest: &Path,
+        slug: &str,
+        output_type: &Formats,
+        pages: impl IntoIterator<Item = (&String, &String)>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let converter = self.converter("markdown").await;
+        for (name, contents) in pages {
+            if cancel.is_cancelled() {
+                return Err(DevDocsError::Cancelled.into());
+            }
+
+            let key = dest.join(&*paths::disk_page(name));
+            if let Some(parent_dir) = key.parent() {
+                fs::create_dir_all(parent_dir).await?;
+            }
+
+            if output_type.contains(Formats::MARKDOWN) {
+                let contents =
+                    convert::render_markdown(converter.as_deref(), slug, name, contents).await;
+                fs::write(add_ext(key.clone(), "md"), contents).await?;
+            }
+
+            if output_type.contains(Formats::HTML) {
+                let contents = render_page(slug, name, contents, Formats::HTML);
+                fs::write(add_ext(key.clone(), "html"), contents).await?;
+            }
+
+            if output_type.contains(Formats::TEXT) {
+                let contents = render_page(slug, name, contents, Formats::TEXT);
+                fs::write(add_ext(key, "txt"), contents).await?;
+            }
+
+            self.emit(ManagerEvent::PageWritten {
+                slug: slug.to_string(),
+                path: PathBuf::from(name),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Add a new documentation
+    pub async fn add_doc(&self, slug: &str, format: Option<Formats>) -> Result<()> {
+        self.add_doc_with_cancel(slug, format, &CancellationToken::new())
+            .await
+    }
+
+    /// Add a new documentation, rendering its pages in the given formats.
+    ///
+    /// Formats that are already rendered for an installed copy are kept.
+    pub async fn add_doc_with_formats(&self, slug: &str, formats: Formats) -> Result<()> {
+        self.add_doc(slug, Some(formats)).await
+    }
+
+    /// Add a new documentation, aborting as soon as `cancel` fires.
+    ///
+    /// Pages written before the cancellation are cleaned up again. Adding a doc in the
+    /// same formats while it's being added waits for that install instead of starting
+    /// another.
+    pub async fn add_doc_with_cancel(
+        &self,
+        slug: &str,
+        format: Option<Formats>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        let _lock = self.lock_exclusive()?;
+        let slug = self.resolve_slug(slug).await?;
+        // A second identical request waits for the first instead of downloading again
+        let key = format!("{} {:?}", slug, format.map(|f| f.bits()));
+        self.adding
+            .run(key, cancel, self.add_resolved(&slug, format, cancel))
+            .await
+    }
+
+    async fn add_resolved(
+        &self,
+        slug: &str,
+        format: Option<Formats>,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        if self.is_format_installed(slug, format).await? {
+            warn!("Doc is already installed, skipping.");
+            return Ok(());
+        }
+
+        let available_docs = self.get_available_docs().await?;
+        let doc = available_docs
+            .iter()
+            .find(|d| d.slug == slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?
+            .clone();
+
+        // Keep the formats that are already rendered alongside the new ones
+        let installed = self.cache.read().await.get(slug).and_then(|c| c.formats);
+        let formats = match (installed, format) {
+            (Some(installed), Some(format)) => Some(installed | format),
+            (installed, format) => installed.or(format),
+        };
+
+        info!("Adding documentation: {} ({})", doc.name, doc.slug);
+        let source = self.source_of(slug).await;
+        self.install_doc(doc, formats, &*source, false, cancel)
+            .await?;
+
+        info!("Successfully added documentation: {}", slug);
+        Ok(())
+    }
+
+    /// Install only the pages of a doc referenced by its entries of the given types,
+    /// with an index pruned to those entries.
+    ///
+    /// Types match entry types by name or slug, case-insensitively. An installed copy is
+    /// replaced, and updates keep the doc limited to the types until it's removed.
+    pub async fn add_doc_filtered(
+        &self,
+        slug: &str,
+        types: &[String],
+        format: Option<Formats>,
+    ) -> Result<()> {
+        if types.is_empty() {
+            anyhow::bail!("No entry types to install");
+        }
+        let _lock = self.lock_exclusive()?;
+        let slug = self.resolve_slug(slug).await?;
+
+        let available_docs = self.get_available_docs().await?;
+        let doc = available_docs
+            .iter()
+            .find(|d| d.slug == slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.clone()))?
+            .clone();
+        let installed = self.cache.read().await.get(&slug).and_then(|c| c.formats);
+        let formats = match (installed, format) {
+            (Some(installed), Some(format)) => Some(installed | format),
+            (installed, format) => installed.or(format),
+        };
+
+        let previous = self
+            .type_filters
+            .write()
+            .await
+            .insert(slug.clone(), types.to_vec());
+        info!(
+            "Adding documentation: {} ({}), only {}",
+            doc.name,
+            slug,
+            types.join(", ")
+        );
+        let source = self.source_of(&slug).await;
+        let installed = self
+            .install_doc(doc, formats, &*source, false, &CancellationToken::new())
+            .await;
+
+        if installed.is_err() {
+            let mut type_filters = self.type_filters.write().await;
+            match previous {
+                Some(previous) => type_filters.insert(slug, previous),
+                None => type_filters.remove(&slug),
+            };
+            return installed.map(drop);
+        }
+        self.update_state("filters.json", &self.type_filters, |type_filters| {
+            type_filters.insert(slug, types.to_vec());
+            Ok(())
+        })
+        .await
+    }
+
+    /// Add a doc found by name in the registered source `source`, e.g. one whose
+    /// catalog is too large to list. Returns the slug it was installed as.
+    ///
+    /// The doc is looked up again on every refresh, so it's updated like any other.
+    pub async fn add_doc_from(
+        &self,
+        source: &str,
+        name: &str,
+        formats: Option<Formats>,
+        cancel: &CancellationToken,
+    ) -> Result<String> {
+        let _lock = self.lock_exclusive()?;
+        let source = self
+            .sources
+            .iter()
+            .find(|s| s.name() == source)
+            .cloned()
+            .with_context(|| format!("No doc source is named '{}'", source))?;
+        let doc = source
+            .find(name)
+            .await?
+            .ok_or_else(|| DevDocsError::DocNotFound(name.to_string()))?;
+        let slug = doc.slug.clone();
+
+        info!(
+            "Adding documentation: {} ({}) from {}",
+            doc.name,
+            slug,
+            source.name()
+        );
+        self.install_doc(doc.clone(), formats, &*source, false, cancel)
+            .await?;
+
+        self.update_state("sources.json", &self.doc_sources, |doc_sources| {
+            doc_sources.insert(slug.clone(), source.name().to_string());
+            Ok(())
+        })
+        .await?;
+        // Until the next refresh looks it up again
+        self.get_available_docs().await?;
+        if let Some((docs, _)) = &mut *self.available_docs.write().await {
+            docs.retain(|d| d.slug != slug);
+            docs.push(doc);
+            self.save_available_docs(docs).await?;
+        }
+        Ok(slug)
+    }
+
+    /// Install every doc listed by `source`, without registering it as a source.
+    ///
+    /// Updates leave imported docs alone; import them again to refresh them.
+    /// Returns the slugs of the imported docs.
+    pub async fn import(
+        &self,
+        source: &dyn DocSource,
+        formats: Option<Formats>,
+    ) -> Result<Vec<String>> {
+        let _lock = self.lock_exclusive()?;
+        let docs = source.list().await?;
+        let cancel = CancellationToken::new();
+
+        let mut slugs = Vec::with_capacity(docs.len());
+        for doc in docs {
+            let slug = doc.slug.clone();
+            info!("Importing {} from {}", slug, source.name());
+            self.install_doc(doc, formats, source, false, &cancel)
+                .await?;
+
+            self.update_state("sources.json", &self.doc_sources, |doc_sources| {
+                doc_sources.insert(slug.clone(), source.name().to_string());
+                Ok(())
+            })
+            .await?;
+            slugs.push(slug);
+        }
+        Ok(slugs)
+    }
+
+    /// Import the HTML pages below `path` as the doc `name`, with an entry per page
+    /// matching one of `entry_globs` (every `.html` page when empty), titled after it.
+    /// The pages are copied into the data dir as HTML.
+    ///
+    /// To keep the doc current instead, list the directory in the [`CONFIG_FILE`].
+    pub async fn import_html_dir(
+        &self,
+        name: &str,
+        path: impl Into<PathBuf>,
+        entry_globs: &[&str],
+    ) -> Result<String> {
+        let source = sources::HtmlDirSource::new(name, path).entries(entry_globs.iter().copied());
+        self.import(&source, Some(Formats::HTML)).await?;
+        Ok(name.to_string())
+    }
+
+    /// Download and render a doc into a staging area, then swap it in.
+    ///
+    /// Until the swap, an installed copy stays untouched, so failures and
+    /// cancellations leave the previous version in place. When the installed
+    /// copy was rendered in the same formats, only pages whose content
+    /// changed are rendered and the others are carried over, unless `force`
+    /// asks for all of them.
+    #[instrument(name = "install", skip_all, fields(slug = %doc.slug))]
+    async fn install_doc(
+        &self,
+        doc: Doc,
+        formats: Option<Formats>,
+        source: &dyn DocSource,
+        force: bool,
+        cancel: &CancellationToken,
+    ) -> Result<PageChanges> {
+        let slug = doc.slug.clone();
+        self.emit(ManagerEvent::DownloadStarted {
+            slug: slug.clone(),
+            bytes: doc.db_size,
+        });
+
+        self.make_room(&slug, doc.db_size as u64).await?;
+
+        // Download index and content concurrently; every step below shares the one
+        // download of the content
+        let (mut index, mut content) = tokio::try_join!(
+            cancellable(cancel, source.fetch_index(&slug))
+                .instrument(debug_span!("download_index")),
+            cancellable(cancel, source.fetch_content(&slug))
+                .instrument(debug_span!("download_content")),
+        )?;
+        self.metrics.downloads.fetch_add(1, Ordering::Relaxed);
+        check_download(&slug, &index, &content)?;
+        if let Some(types) = self.type_filters.read().await.get(&slug) {
+            retain_types(&mut index, &mut content, types);
+            if index.entries.is_empty() {
+                anyhow::bail!("{} has no entries of type {}", slug, types.join(", "));
+            }
+        }
+        if self.settings.read().await.clean {
+            content = tokio::task::spawn_blocking(move || {
+                clean::clean_pages(&mut content);
+                content
+            })
+            .instrument(debug_span!("clean"))
+            .await?;
+        }
+        let assets = if self.offline_assets {
+            cancellable(cancel, self.download_assets(&mut content))
+                .instrument(debug_span!("download_assets"))
+                .await?
+        } else {
+            Vec::new()
+        };
+
+        let hashes: HashMap<String, u64> = content
+            .iter()
+            .map(|(name, contents)| (name.clone(), page_hash(contents)))
+            .collect();
+        let previous = Self::load_page_hashes(self.write_dir(), &slug).await;
+        let changes = match &previous {
+            Some(previous) => diff_pages(previous, &hashes),
+            None => PageChanges {
+                added: hashes.len(),
+                ..Default::default()
+            },
+        };
+
+        let doc_dir = self.write_dir().join(&slug);
+        let store = self.content_store_path(&slug);
+        let installed = self.cache.read().await.get(&slug).map(|c| c.formats);
+        // Installed docs stay rendered the way they were
+        let lazy = match installed {
+            Some(_) => self.lazy_docs.read().await.contains(&slug),
+            None => self.lazy_render,
+        };
+        let installed = installed.flatten();
+        // Lazily rendered docs only get their pages written when they're opened
+        let render = formats.filter(|_| !lazy);
+        // Only changed pages need rendering if the installed copy has the same shape
+        let previous = previous.filter(|_| {
+            !force && render.is_some() && installed == render && doc_dir.exists() && !store.exists()
+        });
+
+        let staging_root = self.write_dir().join(STAGING_DIR);
+        let staged = staging_root.join(&slug);
+        if let Some(formats) = render {
+            if staged.exists() {
+                fs::remove_dir_all(&staged).await?;
+            }
+            fs::create_dir_all(&staged).await?;
+
+            let pages = content.iter().filter(|(name, _)| match &previous {
+                Some(previous) => previous.get(*name) != hashes.get(*name),
+                None => true,
+            });
+            let written = self
+                .split_into(&staged, &slug, &formats, pages, cancel)
+                .instrument(debug_span!("render", ?formats))
+                .await;
+            if written.is_err() {
+                // Don't leave a half-written doc behind
+                fs::remove_dir_all(&staged).await?;
+            }
+            written?;
+        }
+
+        // Sections of long pages become searchable on their own
+        let known: HashSet<PathBuf> = index.entries.iter().map(|e| e.path.clone()).collect();
+        let mut sections: Vec<Entry> = content
+            .iter()
+            .flat_map(|(page, html)| extract_sections(page, html))
+            .filter(|section| !known.contains(&section.path))
+            .collect();
+        sections.sort_by(|a, b| a.path.cmp(&b.path));
+        index.entries.extend(sections);
+
+        let mut code_blocks: Vec<CodeBlock> = content
+            .iter()
+            .flat_map(|(page, html)| extract_code_blocks(page, html))
+            .collect();
+        code_blocks.sort_by(|a, b| a.page.cmp(&b.page));
+
+        let mut signatures: Vec<Signature> = content
+            .iter()
+            .flat_map(|(page, html)| extract_signatures(&doc.doc_type, page, html))
+            .collect();
+        signatures.sort_by(|a, b| a.page.cmp(&b.page));
+
+        #[cfg(feature = "fulltext")]
+        let fulltext_pages: Vec<(String, String, String)> = {
+            let titles = content::page_titles(&index.entries);
+            content
+                .iter()
+                .map(|(page, html)| {
+                    let title = titles.get(page).cloned().unwrap_or_else(|| page.clone());
+                    (page.clone(), title, content::page_text(html))
+                })
+                .collect()
+        };
+
+        let embeddings = match &self.embedder {
+            Some(embedder) => {
+                let titles = content::page_titles(&index.entries);
+                let sections: Vec<(String, String, String)> = content
+                    .iter()
+                    .flat_map(|(page, html)| {
+                        let title = titles.get(page).map_or(page.as_str(), String::as_str);
+                        semantic::page_sections(page, title, html)
+                    })
+                    .collect();
+                let embedder = embedder.clone();
+                Some(
+                    tokio::task::spawn_blocking(move || {
+                        semantic::embed_sections(&*embedder, sections)
+                    })
+                    .instrument(debug_span!("embed"))
+                    .await??,
+                )
+            }
+            None => None,
+        };
+
+        let mut cached_doc = CachedDoc {
+            doc,
+            formats,
+            index,
+            cached_at: current_timestamp(),
+            haystacks: Vec::new(),
+            haystacks_generation: None,
+        };
+        cached_doc.ensure_haystacks();
+
+        let index_changes = self
+            .cache
+            .read()
+            .await
+            .get(&slug)
+            .map(|old| DocChanges::between(old, &cached_doc));
+
+        // Everything is downloaded and rendered, swap the new version in
+        if lazy {
+            let data = tokio::task::spawn_blocking(move || compress_content(&content)).await??;
+            fs::create_dir_all(self.write_dir().join(CONTENT_DIR)).await?;
+            write_atomic(&store, &data).await?;
+            // Pages extracted from the previous version are stale now
+            if doc_dir.exists() {
+                fs::remove_dir_all(&doc_dir).await?;
+            }
+        } else if let Some(formats) = render {
+            if let Some(previous) = &previous {
+                // The unchanged pages join the changed ones, so the patched doc is swapped
+                // in whole like a new one
+                let unchanged: Vec<String> = content
+                    .keys()
+                    .filter(|name| previous.get(*name) == hashes.get(*name))
+                    .cloned()
+                    .collect();
+                let carried = tokio::task::spawn_blocking({
+                    let (doc_dir, staged) = (doc_dir.clone(), staged.clone());
+                    move || carry_over_pages(&doc_dir, &staged, &unchanged, formats)
+                })
+                .await?;
+                if carried.is_err() {
+                    fs::remove_dir_all(&staged).await?;
+                }
+                carried.context(
+                    "Couldn't carry the unchanged pages over, force the update to render them",
+                )?;
+            }
+
+            let retired = staging_root.join(format!("{}.old", slug));
+            if doc_dir.exists() {
+                if retired.exists() {
+                    fs::remove_dir_all(&retired).await?;
+                }
+                fs::rename(&doc_dir, &retired).await?;
+            }
+            if let Err(e) = fs::rename(&staged, &doc_dir).await {
+                // Put the installed version back
+                if retired.exists() {
+                    fs::rename(&retired, &doc_dir).await?;
+                }
+                return Err(e.into());
+            }
+            if retired.exists() {
+                fs::remove_dir_all(&retired).await?;
+            }
+        }
+        if !lazy && store.exists() {
+            fs::remove_file(&store).await?;
+        }
+        if self.lazy_docs.read().await.contains(&slug) != lazy {
+            self.update_state("lazy.json", &self.lazy_docs, |lazy_docs| {
+                if lazy {
+                    lazy_docs.insert(slug.clone());
+                } else {
+                    lazy_docs.remove(&slug);
+                }
+                Ok(())
+            })
+            .await?;
+        }
+        if !assets.is_empty() {
+            let assets_dir = doc_dir.join(ASSETS_DIR);
+            fs::create_dir_all(&assets_dir).await?;
+            for (name, data) in &assets {
+                fs::write(assets_dir.join(name), data).await?;
+            }
+        }
+
+        self.save_doc_cache(&slug, &cached_doc).await?;
+        self.save_page_hashes(&slug, &hashes).await?;
+        self.save_code_blocks(&slug, &code_blocks).await?;
+        self.save_signatures(&slug, &signatures).await?;
+        match &embeddings {
+            Some(embeddings) => self.save_embeddings(&slug, embeddings).await?,
+            None => {
+                // Embeddings of the previous version would point at stale sections
+                let stale = self
+                    .write_dir()
+                    .join(EMBEDDINGS_DIR)
+                    .join(format!("{}.bin", slug));
+                if stale.exists() {
+                    fs::remove_file(stale).await?;
+                }
+            }
+        }
+        #[cfg(feature = "fulltext")]
+        {
+            let dir = self.fulltext_dir(&slug);
+            tokio::task::spawn_blocking(move || content::fulltext::build(&dir, &fulltext_pages))
+                .await??;
+        }
+        if let Some(index_changes) = index_changes {
+            fs::create_dir_all(self.write_dir().join(CHANGES_DIR)).await?;
+            self.write_state(&format!("{}/{}.json", CHANGES_DIR, slug), &index_changes)
+                .await?;
+        }
+
+        // Update cache
+        let mut cache = self.cache.write().await;
+        cache.insert(slug.clone(), cached_doc);
+        drop(cache);
+        self.invalidate_queries();
+
+        let whiteout = self.whiteout_path(&slug);
+        if whiteout.exists() {
+            fs::remove_file(whiteout).await?;
+        }
+
+        self.emit(ManagerEvent::DocInstalled { slug });
+        Ok(changes)
+    }
+
+    /// Remove a documentation, along with its rendered pages
+    pub async fn remove_doc(&self, slug: &str) -> Result<()> {
+        let _lock = self.lock_exclusive()?;
+        let Some(slug) = &self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+
+        info!("Removing documentation: {}", slug);
+
+        // Remove from cache
+        let mut cache = self.cache.write().await;
+        cache.remove(slug);
+        drop(cache);
+        self.invalidate_queries();
+
+        // Docs of registered sources can be added again, imported ones can't
+        if self.is_imported(slug).await {
+            self.update_state("sources.json", &self.doc_sources, |doc_sources| {
+                Ok(doc_sources.remove(slug))
+            })
+            .await?;
+        }
+        if self.disabled.read().await.contains(slug) {
+            self.update_state("disabled.json", &self.disabled, |disabled| {
+                Ok(disabled.remove(slug))
+            })
+            .await?;
+        }
+        if self.lazy_docs.read().await.contains(slug) {
+            self.update_state("lazy.json", &self.lazy_docs, |lazy_docs| {
+                Ok(lazy_docs.remove(slug))
+            })
+            .await?;
+        }
+        if self.type_filters.read().await.contains_key(slug) {
+            self.update_state("filters.json", &self.type_filters, |type_filters| {
+                Ok(type_filters.remove(slug))
+            })
+            .await?;
+        }
+
+        // Remove from disk
+        for path in self.cache_files(slug) {
+            if path.exists() {
+                fs::remove_file(path).await?;
+            }
+        }
+        let fulltext = self.fulltext_dir(slug);
+        if fulltext.exists() {
+            fs::remove_dir_all(fulltext).await?;
+        }
+        let doc_dir = self.write_dir().join(slug);
+        if doc_dir.exists() {
+            fs::remove_dir_all(doc_dir).await?;
+        }
+
+        // A read-only lower layer can't be touched, so hide its copy instead
+        if self.overlay_dir.is_some() && self.data_dir.join(format!("{}.bin", slug)).exists() {
+            fs::write(self.whiteout_path(slug), b"").await?;
+        }
+
+        info!("Successfully removed documentation: {}", slug);
+        self.emit(ManagerEvent::DocRemoved {
+            slug: slug.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Delete the rendered pages of the given formats, keeping the doc installed.
+    ///
+    /// The doc stays searchable; removing every rendered format leaves just the index.
+    pub async fn remove_formats(&self, slug: &str, formats: Formats) -> Result<()> {
+        let _lock = self.lock_exclusive()?;
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+
+        let doc_dir = self.write_dir().join(&slug);
+        let extensions: Vec<&str> = FORMAT_EXTENSIONS
+            .into_iter()
+            .filter(|(f, _)| formats.contains(*f))
+            .map(|(_, ext)| ext)
+            .collect();
+        tokio::task::spawn_blocking({
+            let doc_dir = doc_dir.clone();
+            move || remove_with_extensions(&doc_dir, &extensions)
+        })
+        .await??;
+
+        let mut cache = self.cache.write().await;
+        let Some(cached_doc) = cache.get_mut(&slug) else {
+            return Err(DevDocsError::DocNotFound(slug).into());
+        };
+        self.invalidate_queries();
+        cached_doc.formats = cached_doc
+            .formats
+            .map(|have| have.difference(formats))
+            .filter(|have| !have.is_empty());
+        if cached_doc.formats.is_none() && doc_dir.exists() {
+            fs::remove_dir_all(&doc_dir).await?;
+        }
+        self.save_doc_cache(&slug, cached_doc).await?;
+
+        info!("Removed {:?} pages of {}", formats, slug);
+        Ok(())
+    }
+
+    /// Render the pages of an installed doc in the given formats again, from its stored
+    /// content or its HTML pages, without downloading it. Returns the number of pages
+    /// rendered.
+    ///
+    /// Adds the formats to the ones the doc is installed with, so a doc can be switched
+    /// to other formats, or brought up to date with improvements to the renderers.
+    pub async fn render_doc(&self, slug: &str, formats: Formats) -> Result<usize> {
+        let _lock = self.lock_exclusive()?;
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+
+        let pages = self.html_pages(&slug).await.map_err(|_| {
+            DevDocsError::Cache(format!(
+                "{} has no stored content or HTML pages to render from, reinstall it",
+                slug
+            ))
+        })?;
+        // The pages link to each other's HTML files, rendering points them at the new ones
+        let names = self.page_names(&slug);
+        let pages: HashMap<String, String> = pages
+            .into_iter()
+            .map(|(page, html)| (page, unrender_links(&html, &names)))
+            .collect();
+        let doc_dir = self.write_dir().join(&slug);
+        self.split_into(&doc_dir, &slug, &formats, &pages, &CancellationToken::new())
+            .await?;
+
+        let mut cache = self.cache.write().await;
+        let Some(cached_doc) = cache.get_mut(&slug) else {
+            return Err(DevDocsError::DocNotFound(slug).into());
+        };
+        cached_doc.formats = Some(cached_doc.formats.unwrap_or(Formats::empty()) | formats);
+        self.save_doc_cache(&slug, cached_doc).await?;
+
+        info!(
+            "Rendered {} pages of {} as {:?}",
+            pages.len(),
+            slug,
+            formats
+        );
+        Ok(pages.len())
+    }
+
+    /// Download all available documentation that isn't installed yet, reporting the
+    /// docs that failed instead of stopping at them
+    pub async fn download_all(&self, format: Formats) -> Result<DownloadReport> {
+        self.download_all_with_cancel(format, &CancellationToken::new())
+            .await
+    }
+
+    /// Download all available documentation, stopping when `cancel` fires
+    pub async fn download_all_with_cancel(
+        &self,
+        format: Formats,
+        cancel: &CancellationToken,
+    ) -> Result<DownloadReport> {
+        let _lock = self.lock_exclusive()?;
+        let available_docs = self.get_available_docs().await?;
+        let installed_docs = self.list_installed_docs().await?;
+
+        let to_download: Vec<_> = available_docs
+            .into_iter()
+            .filter(|doc| !installed_docs.contains(&doc.slug))
+            .collect();
+
+        info!("Downloading {} documentation entries", to_download.len());
+
+        // Download in batches to avoid overwhelming the server
+        const BATCH_SIZE: usize = 5;
+        let mut report = DownloadReport::default();
+        for batch in to_download.chunks(BATCH_SIZE) {
+            if cancel.is_cancelled() {
+                return Err(DevDocsError::Cancelled.into());
+            }
+
+            let futures = batch
+                .iter()
+                .map(|doc| self.add_doc_with_cancel(&doc.slug, Some(format), cancel));
+            let results: Vec<_> = futures::future::join_all(futures).await;
+
+            for (doc, result) in batch.iter().zip(results) {
+                match result {
+                    Ok(()) => report.installed.push(doc.slug.clone()),
+                    Err(e) => {
+                        warn!("Failed to download {}: {}", doc.slug, e);
+                        self.emit(ManagerEvent::Error {
+                            slug: doc.slug.clone(),
+                            message: e.to_string(),
+                        });
+                        report.failed.push((doc.slug.clone(), e.to_string()));
+                    }
+                }
+            }
+        }
+
+        if cancel.is_cancelled() {
+            return Err(DevDocsError::Cancelled.into());
+        }
+
+        Ok(report)
+    }
+
+    /// Resolve which docs [`add_doc`](Self::add_doc) would download, without downloading them.
+    ///
+    /// Docs named more than once are downloaded once, see
+    /// [`download_bytes`](Plan::download_bytes) for what that adds up to.
+    pub async fn plan_add(&self, slugs: &[String], format: Option<Formats>) -> Result<Plan> {
+        let available_docs = self.get_available_docs().await?;
+        let mut plan = Plan::default();
+        for slug in slugs {
+            let slug = self.resolve_slug(slug).await?;
+            if self.is_format_installed(&slug, format).await?
+                || plan.downloads.iter().any(|d| d.slug == slug)
+            {
+                continue;
+            }
+            let doc = available_docs
+                .iter()
+                .find(|d| d.slug == slug)
+                .ok_or_else(|| DevDocsError::DocNotFound(slug.clone()))?;
+            plan.downloads.push(PlannedDownload::from(doc));
+        }
+        Ok(plan)
+    }
+
+    /// Resolve which files removing the docs would delete, without deleting them.
+    ///
+    /// Includes the directory of rendered pages, if there is one.
+    pub async fn plan_remove(&self, slugs: &[String]) -> Result<Plan> {
+        let mut plan = Plan::default();
+        for slug in slugs {
+            let Some(slug) = self.resolve_installed(slug).await else {
+                return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+            };
+            let doc_dir = self.write_dir().join(&slug);
+            plan.removals.extend(
+                self.cache_files(&slug)
+                    .into_iter()
+                    .chain([self.fulltext_dir(&slug), doc_dir])
+                    .filter(|path| path.exists()),
+            );
+        }
+        Ok(plan)
+    }
+
+    /// Resolve which installed docs have a newer version upstream, without updating them
+    pub async fn plan_update(&self, slugs: &[String]) -> Result<Plan> {
+        let available_docs = self.get_available_docs().await?;
+        let mut plan = Plan::default();
+        for slug in slugs {
+            let Some(slug) = self.resolve_installed(slug).await else {
+                return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+            };
+            if self.is_imported(&slug).await {
+                continue;
+            }
+            let installed_mtime = self.cache.read().await.get(&slug).map(|c| c.doc.mtime);
+            let Some(upstream) = available_docs.iter().find(|d| d.slug == slug) else {
+                plan.orphaned.push(slug);
+                continue;
+            };
+            if Some(upstream.mtime) != installed_mtime {
+                plan.downloads.push(PlannedDownload::from(upstream));
+            }
+        }
+        Ok(plan)
+    }
+
+    /// Resolve which docs [`download_all`](Self::download_all) would download
+    pub async fn plan_download_all(&self) -> Result<Plan> {
+        let available_docs = self.get_available_docs().await?;
+        let installed_docs = self.list_installed_docs().await?;
+        let downloads = available_docs
+            .iter()
+            .filter(|doc| !installed_docs.contains(&doc.slug))
+            .map(PlannedDownload::from)
+            .collect();
+        Ok(Plan {
+            downloads,
+            ..Default::default()
+        })
+    }
+
+    /// Disk space used by the writable layer and by each installed doc
+    pub async fn disk_usage(&self) -> Result<DiskUsage> {
+        let write_dir = self.write_dir().to_path_buf();
+        let used = tokio::task::spawn_blocking(move || dir_size(&write_dir)).await?;
+
+        let mut docs = Vec::new();
+        for slug in self.list_installed_docs().await? {
+            let size = self.doc_size(&slug).await?;
+            docs.push((slug, size));
+        }
+        docs.sort_by_key(|(_, size)| Reverse(*size));
+
+        Ok(DiskUsage {
+            used,
+            quota: self.quota,
+            docs,
+        })
+    }
+
+    /// Counts of what's installed and of what this manager has done since it was built.
+    ///
+    /// Searches, hits and downloads are only counted in memory, so they start at zero
+    /// with every manager.
+    pub async fn stats(&self) -> Result<ManagerStats> {
+        let (docs_installed, entries_indexed) = {
+            let cache = self.cache.read().await;
+            let entries = cache.values().map(|c| c.index.entries.len()).sum();
+            (cache.len(), entries)
+        };
+        let write_dir = self.write_dir().to_path_buf();
+        let bytes_on_disk = tokio::task::spawn_blocking(move || dir_size(&write_dir)).await?;
+
+        let queries = self.query_cache_stats();
+        let searches = self.metrics.searches.load(Ordering::Relaxed);
+        let search_micros = self.metrics.search_micros.load(Ordering::Relaxed);
+        let avg_search_ms = match searches {
+            0 => 0.0,
+            n => search_micros as f64 / n as f64 / 1000.0,
+        };
+        Ok(ManagerStats {
+            docs_installed,
+            entries_indexed,
+            bytes_on_disk,
+            searches,
+            avg_search_ms,
+            cache_hits: queries.hits,
+            cache_misses: queries.misses,
+            downloads: self.metrics.downloads.load(Ordering::Relaxed),
+            cached_queries: queries.entries,
+            search_latency_buckets: self.metrics.latency_buckets(),
+        })
+    }
+
+    /// Check the files of an installed doc against the checksums recorded when it was
+    /// installed, returning what's corrupt or missing.
+    ///
+    /// The cache file is checked against its checksum and stored pages against their
+    /// page hashes. Rendered pages can only be checked for presence. Nothing is
+    /// returned for an intact doc.
+    pub async fn verify_doc(&self, slug: &str) -> Result<Vec<String>> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let mut problems = Vec::new();
+
+        let cache_file = PathBuf::from(format!("{}.bin", slug));
+        match self.resolve_path(&cache_file) {
+            Some(path) => {
+                let data = fs::read(path).await?;
+                let recorded: Option<u64> = self
+                    .read_state(&format!("{}/{}.json", CHECKSUMS_DIR, slug))
+                    .await;
+                if recorded.is_some_and(|hash| hash != bytes_hash(&data)) {
+                    problems.push("index doesn't match its checksum".to_string());
+                } else if bitcode::deserialize::<CachedDoc>(&data).is_err() {
+                    problems.push("index can't be read".to_string());
+                }
+            }
+            None => problems.push("index is missing".to_string()),
+        }
+
+        // Docs installed before page hashes were kept have nothing to check pages against
+        let hashes_file = Path::new(PAGE_HASHES_DIR).join(format!("{}.bin", slug));
+        let hashes: Option<HashMap<String, u64>> = match self.resolve_path(&hashes_file) {
+            Some(path) => bitcode::deserialize(&fs::read(path).await?).ok(),
+            None => None,
+        };
+        let Some(hashes) = hashes else {
+            return Ok(problems);
+        };
+
+        let store = Path::new(CONTENT_DIR).join(format!("{}.bin", slug));
+        if let Some(store) = self.resolve_path(&store) {
+            let data = fs::read(store).await?;
+            let Ok(content) =
+                tokio::task::spawn_blocking(move || decompress_content(&data)).await?
+            else {
+                problems.push("content store can't be read".to_string());
+                return Ok(problems);
+            };
+            let mut pages: Vec<_> = hashes.iter().collect();
+            pages.sort();
+            for (page, hash) in pages {
+                match content.get(page) {
+                    Some(html) if page_hash(html) == *hash => {}
+                    Some(_) => problems.push(format!("page {} is corrupt", page)),
+                    None => problems.push(format!("page {} is missing", page)),
+                }
+            }
+            return Ok(problems);
+        }
+
+        let formats = self.cache.read().await.get(&slug).and_then(|c| c.formats);
+        if let Some(formats) = formats {
+            let mut pages: Vec<_> = hashes.keys().collect();
+            pages.sort();
+            for page in pages {
+                for (_, ext) in FORMAT_EXTENSIONS
+                    .iter()
+                    .filter(|(f, _)| formats.contains(*f))
+                {
+                    let file = add_ext(Path::new(&slug).join(&*paths::disk_page(page)), ext);
+                    if self.resolve_path(&file).is_none() {
+                        problems.push(format!("page {}.{} is missing", page, ext));
+                    }
+                }
+            }
+        }
+        Ok(problems)
+    }
+
+    /// Check the DevDocs endpoints, the data dir and the consistency of the installed
+    /// docs. Problems are reported as failed checks with a suggested fix, never as errors.
+    pub async fn diagnostics(&self) -> Vec<Diagnostic> {
+        let mut checks = Vec::new();
+
+        let started = std::time::Instant::now();
+        let available = match self.sources[0].list().await {
+            Ok(docs) => {
+                checks.push(Diagnostic::ok(
+                    "DevDocs site",
+                    format!(
+                        "{} lists {} docs ({} ms)",
+                        self.base_url,
+                        docs.len(),
+                        started.elapsed().as_millis()
+                    ),
+                ));
+                docs
+            }
+            Err(e) => {
+                checks.push(Diagnostic::failed(
+                    CheckStatus::Error,
+                    "DevDocs site",
+                    format!("{} can't be reached: {:#}", self.base_url, e),
+                    "Check the network, or set --proxy / DEVDOCS_BASE_URL",
+                ));
+                Vec::new()
+            }
+        };
+
+        // The smallest doc makes for the cheapest request to the documents host
+        checks.push(match available.iter().min_by_key(|doc| doc.db_size) {
+            Some(doc) => {
+                let started = std::time::Instant::now();
+                match self.sources[0].fetch_index(&doc.slug).await {
+                    Ok(_) => Diagnostic::ok(
+                        "documents host",
+                        format!(
+                            "served the index of {} ({} ms)",
+                            doc.slug,
+                            started.elapsed().as_millis()
+                        ),
+                    ),
+                    Err(e) => Diagnostic::failed(
+                        CheckStatus::Error,
+                        "documents host",
+                        format!("failed to serve the index of {}: {:#}", doc.slug, e),
+                        "Check the network, or set --proxy / DEVDOCS_DOCUMENTS_URL",
+                    ),
+                }
+            }
+            None => Diagnostic::failed(
+                CheckStatus::Warning,
+                "documents host",
+                "not checked, no doc is available to fetch",
+                "Fix the DevDocs site first",
+            ),
+        });
+
+        let dir = self.write_dir().to_path_buf();
+        let base = self.overlay_dir.as_ref().map(|_| self.data_dir.clone());
+        let quota = self.quota;
+        let docs: Vec<(String, Option<Formats>)> = self
+            .cache
+            .read()
+            .await
+            .iter()
+            .map(|(slug, cached)| (slug.clone(), cached.formats))
+            .collect();
+        let blocking = tokio::task::spawn_blocking(move || {
+            let mut checks = check_data_dir(&dir, base.as_deref(), quota);
+            checks.extend(check_cache(&dir, &docs));
+            checks
+        });
+        match blocking.await {
+            Ok(found) => checks.extend(found),
+            Err(e) => checks.push(Diagnostic::failed(
+                CheckStatus::Error,
+                "data dir",
+                format!("checking it failed: {}", e),
+                "Report this as a bug",
+            )),
+        }
+        checks
+    }
+
+    /// Bytes a doc takes up in the writable layer, rendered pages included
+    async fn doc_size(&self, slug: &str) -> Result<u64> {
+        let mut paths = self.cache_files(slug).to_vec();
+        paths.push(self.fulltext_dir(slug));
+        paths.push(self.write_dir().join(slug));
+        Ok(tokio::task::spawn_blocking(move || paths.iter().map(|p| dir_size(p)).sum()).await?)
+    }
+
+    /// Ensure installing `needed` bytes for `slug` stays within the quota.
+    ///
+    /// The space of an installed copy of `slug` counts as free, since it gets replaced.
+    /// With LRU eviction enabled, other docs are removed oldest-used first until it fits.
+    async fn make_room(&self, slug: &str, needed: u64) -> Result<()> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+
+        let usage = self.disk_usage().await?;
+        let replaced = usage
+            .docs
+            .iter()
+            .find(|(s, _)| s == slug)
+            .map_or(0, |(_, size)| *size);
+        let mut used = usage.used.saturating_sub(replaced);
+        if used + needed <= quota {
+            return Ok(());
+        }
+
+        if self.evict_lru {
+            // Last time a page of each doc was viewed, falling back to its install time
+            let recent: Vec<RecentPage> = self.read_state("recent.json").await.unwrap_or_default();
+            let mut candidates: Vec<(u64, String, u64)> = {
+                let cache = self.cache.read().await;
+                usage
+                    .docs
+                    .iter()
+                    .filter(|(s, _)| s != slug)
+                    .map(|(s, size)| {
+                        let viewed = recent.iter().filter(|p| p.slug == *s).map(|p| p.timestamp);
+                        let installed = cache.get(s).map_or(0, |c| c.cached_at);
+                        let last_used = viewed.max().unwrap_or(installed);
+                        (last_used, s.clone(), *size)
+                    })
+                    .collect()
+            };
+            candidates.sort();
+
+            for (_, victim, size) in candidates {
+                if used + needed <= quota {
+                    break;
+                }
+                info!("Evicting {} to stay within the disk quota", victim);
+                self.remove_doc(&victim).await?;
+                used = used.saturating_sub(size);
+            }
+            if used + needed <= quota {
+                return Ok(());
+            }
+        }
+
+        Err(DevDocsError::QuotaExceeded {
+            needed,
+            available: quota.saturating_sub(used),
+        }
+        .into())
+    }
+
+    /// List installed documentation
+    pub async fn list_installed_docs(&self) -> Result<Vec<String>> {
+        let cache = self.cache.read().await;
+        Ok(cache.keys().cloned().collect())
+    }
+
+    /// Check if a documentation is installed
+    pub async fn is_doc_installed(&self, slug: &str) -> Result<bool> {
+        Ok(self.resolve_installed(slug).await.is_some())
+    }
+
+    /// Check if a documentation is installed with (at least) the given formats rendered
+    pub async fn is_format_installed(&self, slug: &str, formats: Option<Formats>) -> Result<bool> {
+        let cache = self.cache.read().await;
+
+        let found = cache.get(slug).is_some_and(|cached| match formats {
+            None => true,
+            Some(formats) => cached.formats.is_some_and(|have| have.contains(formats)),
+        });
+        Ok(found)
+    }
+
+    /// Get information about an installed documentation
+    pub async fn get_doc_info(&self, slug: &str) -> Result<Doc> {
+        let slug = self
+            .resolve_installed(slug)
+            .await
+            .unwrap_or_else(|| slug.to_string());
+        let cache = self.cache.read().await;
+        let cached_doc = cache
+            .get(&slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.clone()))?;
+        Ok(cached_doc.doc.clone())
+    }
+
+    /// The index of an installed doc: its entries, including the sections extracted
+    /// from its pages, and its entry types
+    pub async fn get_doc_index(&self, slug: &str) -> Result<DocIndex> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let cache = self.cache.read().await;
+        let cached_doc = cache
+            .get(&slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.clone()))?;
+        Ok(cached_doc.index.clone())
+    }
+
+    /// Iterate over the entries of an installed doc, in index order
+    pub async fn iter_entries(&self, slug: &str) -> Result<impl Iterator<Item = Entry> + use<>> {
+        Ok(self.get_doc_index(slug).await?.entries.into_iter())
+    }
+
+    /// The entry types of an installed doc, with how many entries each has
+    pub async fn entry_types(&self, slug: &str) -> Result<Vec<EntryType>> {
+        Ok(self.get_doc_index(slug).await?.types)
+    }
+
+    /// Entries that changed with the latest update of an installed doc.
+    ///
+    /// `None` if the doc was never updated since it was installed.
+    pub async fn doc_changes(&self, slug: &str) -> Result<Option<DocChanges>> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        Ok(self
+            .read_state(&format!("{}/{}.json", CHANGES_DIR, slug))
+            .await)
+    }
+
+    /// Look up an entry of an installed doc by name, falling back to a case-insensitive match
+    async fn find_entry(&self, slug: &str, name: &str) -> Result<Entry> {
+        let cache = self.cache.read().await;
+        let entries = &cache
+            .get(slug)
+            .ok_or_else(|| DevDocsError::DocNotFound(slug.to_string()))?
+            .index
+            .entries;
+        entries
+            .iter()
+            .find(|e| e.name == name)
+            .or_else(|| entries.iter().find(|e| e.name.eq_ignore_ascii_case(name)))
+            .cloned()
+            .ok_or_else(|| DevDocsError::DocNotFound(format!("{}#{}", slug, name)).into())
+    }
+
+    /// Signatures documented by an entry of an installed doc.
+    ///
+    /// Only docs whose markup is understood (rust, python and node) have signatures;
+    /// the result is empty for other docs and for entries that aren't callable.
+    pub async fn signatures(&self, slug: &str, entry: &str) -> Result<Vec<Signature>> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let entry = self.find_entry(&slug, entry).await?;
+        let signatures = self.load_signatures(&slug).await.unwrap_or_default();
+
+        let path = entry.path.to_string_lossy();
+        let (page, anchor) = split_fragment(&path);
+        let on_page = signatures.into_iter().filter(|s| s.page == page);
+        Ok(match anchor {
+            Some(anchor) => on_page
+                .filter(|s| s.anchor.as_deref() == Some(anchor))
+                .collect(),
+            // Without an anchor, go by the name the entry ends with
+            None => on_page
+                .filter(|s| {
+                    entry.name == s.name
+                        || entry.name.ends_with(&format!(".{}", s.name))
+                        || entry.name.ends_with(&format!("::{}", s.name))
+                })
+                .collect(),
+        })
+    }
+
+    /// Search through installed documentation with fuzzy matching
+    pub async fn search(&self, query: &str, limit: Option<usize>) -> Result<Vec<SearchResult>> {
+        let mut options = SearchOptions::default();
+        if let Some(limit) = limit {
+            options.limit = limit;
+        }
+        self.search_with(query, &options).await
+    }
+
+    /// Search through installed documentation with explicit options
+    pub async fn search_with(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        Ok(self.search_counted(query, options).await?.0)
+    }
+
+    /// Search with the query cache, also returning how many results there are in all
+    async fn search_counted(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<(Vec<SearchResult>, usize)> {
+        let started = Instant::now();
+        let generation = {
+            let mut queries = self.query_cache.lock().expect("query cache poisoned");
+            let hit = queries
+                .entries
+                .iter()
+                .position(|(q, o, ..)| q == query && o == options);
+            if let Some(position) = hit {
+                queries.hits += 1;
+                let cached = queries
+                    .entries
+                    .remove(position)
+                    .expect("position is in bounds");
+                let results = (cached.2.clone(), cached.3);
+                queries.entries.push_front(cached);
+                self.metrics.record_searches(1, started.elapsed());
+                return Ok(results);
+            }
+            queries.misses += 1;
+            queries.generation
+        };
+
+        let (results, total) = self
+            .search_pages(&[query], options)
+            .await?
+            .pop()
+            .unwrap_or_default();
+
+        let mut queries = self.query_cache.lock().expect("query cache poisoned");
+        if queries.generation == generation {
+            queries.entries.push_front((
+                query.to_string(),
+                options.clone(),
+                results.clone(),
+                total,
+            ));
+            queries.entries.truncate(QUERY_CACHE_LIMIT);
+        }
+        self.metrics.record_searches(1, started.elapsed());
+        Ok((results, total))
+    }
+
+    /// Hit and miss counts of the cache answering repeated [`search_with`](Self::search_with)
+    /// calls
+    pub fn query_cache_stats(&self) -> QueryCacheStats {
+        let queries = self.query_cache.lock().expect("query cache poisoned");
+        QueryCacheStats {
+            hits: queries.hits,
+            misses: queries.misses,
+            entries: queries.entries.len(),
+        }
+    }
+
+    /// Drop every cached query result, after a change to what searches rank
+    fn invalidate_queries(&self) {
+        let mut queries = self.query_cache.lock().expect("query cache poisoned");
+        queries.entries.clear();
+        queries.corpus = None;
+        queries.generation += 1;
+    }
+
+    /// Search like [`search_with`](Self::search_with), with how many results there
+    /// are beyond the requested page and corrected queries when nothing matches well.
+    ///
+    /// With [`SearchOptions::remote`], docs that aren't installed are searched on
+    /// devdocs.io when nothing installed matches.
+    pub async fn search_response(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        if options.remote {
+            return self.search_response_remote(query, options).await;
+        }
+        self.local_response(query, options).await
+    }
+
+    async fn local_response(&self, query: &str, options: &SearchOptions) -> Result<SearchResponse> {
+        let (results, total_matches) = self.search_counted(query, options).await?;
+        let truncated = options.offset + results.len() < total_matches;
+        // Later pages can't tell whether the best results were poor
+        let low_score = LOW_SCORE_PER_CHAR * query.chars().count() as u32;
+        let poor = total_matches == 0
+            || options.offset == 0 && results.first().is_none_or(|best| best.score < low_score);
+        let suggestions = if poor && !query.trim().is_empty() {
+            self.suggest(query, options).await?
+        } else {
+            Vec::new()
+        };
+        Ok(SearchResponse {
+            results,
+            total_matches,
+            truncated,
+            suggestions,
+            remote: Vec::new(),
+        })
+    }
+
+    /// Guess what a misspelled query was meant to be, from the words of the entry names
+    /// in scope.
+    ///
+    /// Each word of the query is replaced by the most frequent word within a small edit
+    /// distance of it. A one-word query gets several alternatives, best first.
+    pub async fn suggest(&self, query: &str, options: &SearchOptions) -> Result<Vec<String>> {
+        let scope = self.resolve_scope(&options.docs).await?;
+        let cache = self.cache.read().await;
+
+        let mut frequencies: HashMap<String, usize> = HashMap::new();
+        for slug in &scope {
+            let Some(cached_doc) = cache.get(slug) else {
+                continue;
+            };
+            for haystack in &cached_doc.haystacks {
+                for word in haystack.lower.split(|c: char| !c.is_alphanumeric()) {
+                    if word.chars().count() >= 3 {
+                        *frequencies.entry(word.to_string()).or_default() += 1;
+                    }
+                }
+            }
+        }
+
+        // Known words closest to `word`, the closest and most frequent first
+        let corrections = |word: &str| {
+            let max_distance = if word.chars().count() <= 4 { 1 } else { 2 };
+            let mut candidates: Vec<(usize, usize, &str)> = frequencies
+                .iter()
+                .filter_map(|(known, &count)| {
+                    let distance = edit_distance(word, known, max_distance)?;
+                    Some((distance, count, known.as_str()))
+                })
+                .collect();
+            candidates.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(b.2)));
+            candidates
+        };
+
+        let words: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+        if let [word] = words.as_slice() {
+            return Ok(corrections(word)
+                .into_iter()
+                .filter(|(distance, ..)| *distance > 0)
+                .take(SUGGESTION_LIMIT)
+                .map(|(.., known)| known.to_string())
+                .collect());
+        }
+
+        let corrected: Vec<String> = words
+            .iter()
+            .map(|word| {
+                corrections(word)
+                    .first()
+                    .map_or_else(|| word.clone(), |(.., known)| known.to_string())
+            })
+            .collect();
+        Ok(if corrected == words {
+            Vec::new()
+        } else {
+            vec![corrected.join(" ")]
+        })
+    }
+
+    /// Search incrementally, yielding results as each chunk of the corpus is ranked.
+    ///
+    /// A result is only yielded if it makes the best `limit` found so far, so early
+    /// results can be outranked by later ones and more than `limit` may be yielded in
+    /// all; the final best `limit` are always among them. Offsets are ignored.
+    pub async fn search_stream(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<impl Stream<Item = SearchResult> + '_> {
+        let options = SearchOptions {
+            offset: 0,
+            ..options.clone()
+        };
+        if query.trim().is_empty() {
+            let results = self.search_with(query, &options).await?;
+            return Ok(futures::stream::iter(results).left_stream());
+        }
+
+        let state = StreamState {
+            query: query.to_string(),
+            slugs: self.resolve_scope(&options.docs).await?,
+            recent_pages: self.recent_pages(RECENT_LIMIT).await,
+            weights: self.settings.read().await.weights.clone(),
+            doc: 0,
+            start: 0,
+            matchers: Default::default(),
+            best: BinaryHeap::new(),
+            pending: std::collections::VecDeque::new(),
+            under_cap: Box::new(cap_per_doc(options.max_per_doc)),
+            targets: HashSet::new(),
+            options,
+        };
+        Ok(futures::stream::unfold(state, move |mut state| async move {
+            loop {
+                if let Some(result) = state.pending.pop_front() {
+                    return Some((result, state));
+                }
+                let slug = state.slugs.get(state.doc)?.clone();
+                self.rank_chunk(&slug, &mut state).await;
+            }
+        })
+        .right_stream())
+    }
+
+    /// Rank the next chunk of a streaming search, queueing the results that make the
+    /// best so far
+    async fn rank_chunk(&self, slug: &str, state: &mut StreamState) {
+        let recent: HashMap<(&str, &str), usize> = state
+            .recent_pages
+            .iter()
+            .enumerate()
+            .map(|(rank, page)| ((page.slug.as_str(), page.path.as_str()), rank))
+            .collect();
+        let recent_rank =
+            |slug: &str, entry: &Entry| recent.get(&(slug, page_path(&entry.path))).copied();
+
+        let cache = self.cache.read().await;
+        // Docs removed since the search started are skipped
+        let Some((slug, cached_doc)) = cache.get_key_value(slug) else {
+            state.doc += 1;
+            state.start = 0;
+            return;
+        };
+        // The doc may have been reinstalled with fewer entries since the last chunk
+        let len = cached_doc
+            .index
+            .entries
+            .len()
+            .min(cached_doc.haystacks.len());
+        let start = state.start.min(len);
+        let end = (start + STREAM_CHUNK).min(len);
+        let candidates: Vec<Candidate> = cached_doc.index.entries[start..end]
+            .iter()
+            .zip(&cached_doc.haystacks[start..end])
+            .map(|(entry, haystack)| (slug, cached_doc, entry, haystack))
+            .filter(|(_, _, entry, _)| has_type(&state.options, entry))
+            .collect();
+        if end == len {
+            state.doc += 1;
+            state.start = 0;
+        } else {
+            state.start = end;
+        }
+
+        let (results, _) = self.in_search_pool(|| {
+            rank_candidates(
+                &candidates,
+                &state.query,
+                &state.options,
+                &recent_rank,
+                &state.weights,
+                &state.matchers,
+            )
+        });
+        for result in results {
+            let makes_best = state.best.len() < state.options.limit
+                || state
+                    .best
+                    .peek()
+                    .is_some_and(|Reverse(worst)| result.score > *worst);
+            // Chunks only collapse their own duplicates, so targets are checked across them
+            let target = state.options.collapse_duplicates.then(|| {
+                (
+                    result.entry.doc_slug.clone(),
+                    result.entry.entry.path.clone(),
+                )
+            });
+            let duplicate = target
+                .as_ref()
+                .is_some_and(|target| state.targets.contains(target));
+            if makes_best && !duplicate && (state.under_cap)(&result.entry.doc_slug) {
+                state.targets.extend(target);
+                push_bounded(&mut state.best, state.options.limit, Reverse(result.score));
+                state.pending.push_back(result);
+            }
+        }
+    }
+
+    /// Run several searches at once, returning the results of each query in order.
+    ///
+    /// The corpus is gathered and the matchers are set up once for all of them, which
+    /// is much cheaper than a [`search_with`](Self::search_with) per query when
+    /// resolving many symbols at a time.
+    pub async fn search_many(
+        &self,
+        queries: &[&str],
+        options: &SearchOptions,
+    ) -> Result<Vec<Vec<SearchResult>>> {
+        let started = Instant::now();
+        let pages = self.search_pages(queries, options).await?;
+        self.metrics
+            .record_searches(queries.len(), started.elapsed());
+        Ok(pages.into_iter().map(|(results, _)| results).collect())
+    }
+
+    /// The requested page of results of each query, with how many results it has in all
+    async fn search_pages(
+        &self,
+        queries: &[&str],
+        options: &SearchOptions,
+    ) -> Result<Vec<(Vec<SearchResult>, usize)>> {
+        let scope = self.resolve_scope(&options.docs).await?;
+
+        // Rank of each recently viewed page, most recent first
+        let recent_pages = self.recent_pages(RECENT_LIMIT).await;
+        let recent: HashMap<(&str, &str), usize> = recent_pages
+            .iter()
+            .enumerate()
+            .map(|(rank, page)| ((page.slug.as_str(), page.path.as_str()), rank))
+            .collect();
+        let recent_rank =
+            |slug: &str, entry: &Entry| recent.get(&(slug, page_path(&entry.path))).copied();
+
+        let weights = self.settings.read().await.weights.clone();
+        let cache = self.cache.read().await;
+
+        // Borrow every candidate straight from the cache; only results get cloned
+        let candidates: Vec<Candidate> = cache
+            .iter()
+            .filter(|(slug, _)| scope.contains(slug))
+            .flat_map(|(slug, cached_doc)| {
+                cached_doc
+                    .index
+                    .entries
+                    .iter()
+                    .zip(&cached_doc.haystacks)
+                    .map(move |(entry, haystack)| (slug, cached_doc, entry, haystack))
+            })
+            .filter(|(_, _, entry, _)| has_type(options, entry))
+            .collect();
+
+        if candidates.is_empty() || options.limit == 0 {
+            return Ok(vec![(Vec::new(), 0); queries.len()]);
+        }
+
+        // Matchers of each worker, ignoring case and respecting it, shared by every query
+        let matchers: [ThreadLocal<RefCell<Matcher>>; 2] = Default::default();
+        Ok(self.in_search_pool(|| {
+            queries
+                .iter()
+                .map(|query| {
+                    // Without a query there is nothing to rank by
+                    if query.trim().is_empty() {
+                        browse_candidates(&candidates, options, &recent_rank)
+                    } else {
+                        let weights = &weights;
+                        rank_candidates(
+                            &candidates,
+                            query,
+                            options,
+                            &recent_rank,
+                            weights,
+                            &matchers,
+                        )
+                    }
+                })
+                .collect()
+        }))
+    }
+
+    /// Run `rank` on the pool searches rank entries on
+    pub(crate) fn in_search_pool<R: Send>(&self, rank: impl FnOnce() -> R + Send) -> R {
+        match &self.search_pool {
+            Some(pool) => pool.install(rank),
+            None => rank(),
+        }
+    }
+
+    /// Search and group the results by doc or type
+    pub async fn search_grouped(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+        by: GroupBy,
+    ) -> Result<Vec<(String, Vec<SearchResult>)>> {
+        Ok(group_results(self.search_with(query, options).await?, by))
+    }
+
+    /// Search the code examples of the installed docs, line by line.
+    ///
+    /// Honors the scope, case, exactness, score and per-doc settings of `options`.
+    /// Docs installed before code blocks were extracted have none until they're updated.
+    pub async fn search_code(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<CodeResult>> {
+        if query.trim().is_empty() || options.limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let slugs = self.resolve_scope(&options.docs).await?;
+
+        let ignore_case = options.case_matching.ignores_case(query);
+        let mut config = Config::DEFAULT;
+        config.ignore_case = ignore_case;
+        config.normalize = options.normalize;
+        let mut matcher = Matcher::new(config);
+
+        let needle = matcher_needle(query, ignore_case, options.normalize);
+        let mut pattern_buf = Vec::new();
+        let pattern = Utf32Str::new(&needle, &mut pattern_buf);
+        let mut line_buf = Vec::new();
+
+        let mut results = Vec::new();
+        for slug in slugs {
+            let Some(blocks) = self.load_code_blocks(&slug).await else {
+                continue;
+            };
+            for block in blocks {
+                let mut best: Option<(u16, usize)> = None;
+                for (i, line) in block.code.lines().enumerate() {
+                    let text = Utf32Str::new(line, &mut line_buf);
+                    let score = if options.exact {
+                        matcher.substring_match(text, pattern)
+                    } else {
+                        matcher.fuzzy_match(text, pattern)
+                    };
+                    if let Some(score) = score
+                        && best.is_none_or(|(top, _)| score > top)
+                    {
+                        best = Some((score, i));
+                    }
+                }
+                if let Some((score, line)) = best
+                    && u32::from(score) >= options.min_score
+                {
+                    results.push(CodeResult {
+                        doc_slug: slug.clone(),
+                        block,
+                        line,
+                        score: u32::from(score),
+                    });
+                }
+            }
+        }
+
+        results.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.doc_slug.cmp(&b.doc_slug))
+                .then_with(|| a.block.page.cmp(&b.block.page))
+        });
+        let mut under_cap = cap_per_doc(options.max_per_doc);
+        results.retain(|r| under_cap(&r.doc_slug));
+        results.truncate(options.limit);
+        Ok(results)
+    }
+
+    /// Code blocks of a page of an installed doc, in document order.
+    ///
+    /// `path` is the page path within the doc, without extension or fragment.
+    pub async fn page_code_blocks(&self, slug: &str, path: &str) -> Result<Vec<CodeBlock>> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let blocks = self.load_code_blocks(&slug).await.unwrap_or_default();
+        Ok(blocks.into_iter().filter(|b| b.page == path).collect())
+    }
+
+    /// Start an incremental search session over the installed docs.
+    ///
+    /// The entries are loaded into the session once; docs installed afterwards
+    /// are only picked up by a new session. Entries are matched by name, then ranked
+    /// like [`search_with`](Self::search_with) ranks them, with the weights, boosts and
+    /// recently viewed pages of the time the session started.
+    pub async fn search_session(&self, options: &SearchOptions) -> Result<SearchSession> {
+        let scope = self.resolve_scope(&options.docs).await?;
+        let corpus = self.corpus().await;
+
+        let mut config = Config::DEFAULT;
+        config.prefer_prefix = true;
+
+        let nucleo = Nucleo::new(config, Arc::new(|| {}), None, 1);
+        let injector = nucleo.injector();
+        for (doc, corpus_doc) in corpus.docs.iter().enumerate() {
+            if !scope.iter().any(|slug| **slug == *corpus_doc.slug) {
+                continue;
+            }
+            for (entry, e) in corpus_doc.entries.iter().enumerate() {
+                if !has_type(options, e) {
+                    continue;
+                }
+                let at = CorpusEntry {
+                    doc: doc as u32,
+                    entry: entry as u32,
+                };
+                injector.push(at, |_, columns| columns[0] = e.name.as_str().into());
+            }
+        }
+
+        let recent = self
+            .recent_pages(RECENT_LIMIT)
+            .await
+            .into_iter()
+            .map(|page| (page.slug, page.path))
+            .collect();
+        Ok(SearchSession {
+            nucleo,
+            corpus,
+            options: options.clone(),
+            weights: self.settings.read().await.weights.clone(),
+            recent,
+            query: String::new(),
+            case_matching: options.case_matching.to_nucleo(),
+            normalization: if options.normalize {
+                pattern::Normalization::Smart
+            } else {
+                pattern::Normalization::Never
+            },
+        })
+    }
+
+    /// Resolve the slugs or aliases of a search scope to installed slugs, or to every
+    /// enabled doc when the scope is empty
+    async fn resolve_scope(&self, docs: &[String]) -> Result<Vec<String>> {
+        if docs.is_empty() {
+            let disabled = self.disabled.read().await;
+            let cache = self.cache.read().await;
+            let slugs = cache
+                .keys()
+                .filter(|slug| !disabled.contains(*slug))
+                .cloned()
+                .collect();
+            let language = self.settings.read().await.language.clone();
+            return Ok(prefer_language(slugs, language.as_deref()));
+        }
+
+        let mut scope = Vec::with_capacity(docs.len());
+        for name in docs {
+            match self.resolve_installed(name).await {
+                Some(slug) => scope.push(slug),
+                None => return Err(DevDocsError::DocNotFound(name.clone()).into()),
+            }
+        }
+        Ok(scope)
+    }
+
+    /// The entries of every installed doc, built once and shared until an install,
+    /// removal or other change to what searches rank
+    async fn corpus(&self) -> Arc<Corpus> {
+        let generation = {
+            let queries = self.query_cache.lock().expect("query cache poisoned");
+            if let Some(corpus) = &queries.corpus {
+                return corpus.clone();
+            }
+            queries.generation
+        };
+
+        let corpus = {
+            let cache = self.cache.read().await;
+            let mut docs: Vec<CorpusDoc> = cache
+                .iter()
+                .map(|(slug, cached_doc)| CorpusDoc {
+                    slug: Arc::from(slug.as_str()),
+                    name: Arc::from(cached_doc.doc.name.as_str()),
+                    entries: cached_doc.index.entries.clone(),
+                    haystacks: cached_doc.haystacks.clone(),
+                })
+                .collect();
+            docs.sort_by(|a, b| a.slug.cmp(&b.slug));
+            Arc::new(Corpus { docs })
+        };
+
+        let mut queries = self.query_cache.lock().expect("query cache poisoned");
+        if queries.generation == generation {
+            queries.corpus = Some(corpus.clone());
+        }
+        corpus
+    }
+
+    /// Append a query, and optionally the result picked for it, to the search history
+    pub async fn record_search(&self, query: &str, chosen: Option<&str>) -> Result<()> {
+        let _lock = self.lock_state().await?;
+        let mut history = self.search_history().await;
+        history.push(HistoryEntry {
+            query: query.to_string(),
+            timestamp: current_timestamp(),
+            chosen: chosen.map(str::to_string),
+        });
+
+        if history.len() > HISTORY_LIMIT {
+            history.drain(..history.len() - HISTORY_LIMIT);
+        }
+
+        self.write_state("history.json", &history).await
+    }
+
+    /// Past queries, oldest first
+    pub async fn search_history(&self) -> Vec<HistoryEntry> {
+        self.read_state("history.json").await.unwrap_or_default()
+    }
+
+    /// Forget all past queries
+    pub async fn clear_history(&self) -> Result<()> {
+        let _lock = self.lock_state().await?;
+        self.write_state::<[HistoryEntry]>("history.json", &[])
+            .await
+    }
+
+    /// Record that a page was opened, moving it to the front of the recent list
+    pub async fn record_view(&self, slug: &str, path: &str) -> Result<()> {
+        let path = page_path(Path::new(path));
+        let _lock = self.lock_state().await?;
+        let mut recent = self.recent_pages(RECENT_LIMIT).await;
+        recent.retain(|page| !(page.slug == slug && page.path == path));
+        recent.insert(
+            0,
+            RecentPage {
+                slug: slug.to_string(),
+                path: path.to_string(),
+                timestamp: current_timestamp(),
+            },
+        );
+        recent.truncate(RECENT_LIMIT);
+
+        self.write_state("recent.json", &recent).await?;
+        // Recently viewed pages rank higher
+        self.invalidate_queries();
+        Ok(())
+    }
+
+    /// Recently opened pages, most recent first
+    pub async fn recent_pages(&self, limit: usize) -> Vec<RecentPage> {
+        let mut recent: Vec<RecentPage> = self.read_state("recent.json").await.unwrap_or_default();
+        recent.truncate(limit);
+        recent
+    }
+
+    /// Map a rendered page file back to its doc slug and page path.
+    ///
+    /// Accepts paths relative to the data dir or absolute paths inside any storage layer.
+    pub fn page_for_file(&self, file: &Path) -> Option<(String, String)> {
+        let relative = self
+            .layers()
+            .into_iter()
+            .find_map(|layer| file.strip_prefix(layer).ok())
+            .unwrap_or(file);
+        if relative.is_absolute() {
+            return None;
+        }
+
+        let mut components = relative.components();
+        let slug = components.next()?.as_os_str().to_str()?.to_string();
+        // Rendering appended a format extension to the page path, so drop just that one
+        let page = components.as_path().with_extension("");
+        let page = page.to_str()?.replace('\\', "/");
+        let page = self.page_names(&slug).page(&page).into_owned();
+        Some((slug, page))
+    }
+
+    /// Names of a doc's pages, as listed by its page hashes, to map the files of pages
+    /// back to the pages
+    fn page_names(&self, slug: &str) -> paths::PageNames {
+        if !paths::SAFE_PATHS {
+            return paths::PageNames::default();
+        }
+        let hashes = self
+            .resolve_path(&Path::new(PAGE_HASHES_DIR).join(format!("{}.bin", slug)))
+            .and_then(|file| std::fs::read(file).ok())
+            .and_then(|data| bitcode::deserialize::<HashMap<String, u64>>(&data).ok())
+            .unwrap_or_default();
+        paths::PageNames::new(hashes.keys())
+    }
+
+    /// Link to an entry on the DevDocs site, e.g. `https://devdocs.io/rust/std/vec/struct.vec`.
+    ///
+    /// `path` is the entry path within the doc and may carry a `#fragment`. Links always
+    /// point at devdocs.io, whichever mirror the docs are downloaded from.
+    pub fn entry_url(&self, slug: &str, path: &str) -> String {
+        let (page, fragment) = split_fragment(path.trim_start_matches('/'));
+        let mut url = format!(
+            "{}/{}/{}",
+            DEVDOCS_BASE_URL,
+            percent_encode(slug, ""),
+            percent_encode(page, "/")
+        );
+        if let Some(fragment) = fragment {
+            url.push('#');
+            url.push_str(&percent_encode(fragment, "/?"));
+        }
+        url
+    }
+
+    /// Get the raw HTML content of a page of a lazily rendered doc
+    pub async fn get_page_content(&self, slug: &str, path: &str) -> Result<String> {
+        let Some(slug) = self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let relative = Path::new(CONTENT_DIR).join(format!("{}.bin", slug));
+        let store = self
+            .resolve_path(&relative)
+            .ok_or_else(|| DevDocsError::Cache(format!("{} has no content store", slug)))?;
+
+        let page = tokio::task::spawn_blocking({
+            let path = path.to_string();
+            move || read_content_page(&store, &path)
+        })
+        .await??;
+        page.ok_or_else(|| DevDocsError::DocNotFound(format!("{}#{}", slug, path)).into())
+    }
+
+    /// Every page of an installed doc as HTML, keyed by page path.
+    ///
+    /// Reads the content store of lazily rendered docs, otherwise the rendered HTML files.
+    async fn html_pages(&self, slug: &str) -> Result<HashMap<String, String>> {
+        let store = Path::new(CONTENT_DIR).join(format!("{}.bin", slug));
+        if let Some(store) = self.resolve_path(&store) {
+            let data = fs::read(store).await?;
+            let slug = slug.to_string();
+            return tokio::task::spawn_blocking(move || {
+                let content = decompress_content(&data)?;
+                Ok(content
+                    .into_iter()
+                    .map(|(page, html)| {
+                        let html = render_page(&slug, &page, &html, Formats::HTML);
+                        (page, html)
+                    })
+                    .collect())
+            })
+            .await?;
+        }
+
+        let Some(doc_dir) = self.resolve_path(Path::new(slug)) else {
+            return Err(DevDocsError::Cache(format!("{} has no rendered pages", slug)).into());
+        };
+        let names = self.page_names(slug);
+        tokio::task::spawn_blocking(move || {
+            let mut pages = HashMap::new();
+            collect_html_pages(&doc_dir, &doc_dir, &names, &mut pages)?;
+            if pages.is_empty() {
+                let slug = doc_dir.file_name().unwrap_or_default().to_string_lossy();
+                return Err(DevDocsError::Cache(format!("{} has no HTML pages", slug)).into());
+            }
+            Ok(pages)
+        })
+        .await?
+    }
+
+    /// Path of a rendered page, rendering it first if its doc is stored lazily.
+    ///
+    /// `path` is the page path within the doc, without extension. The `#fragment` of
+    /// an entry path is ignored, the page holds its section.
+    pub async fn page_file(&self, slug: &str, path: &str, format: Formats) -> Result<PathBuf> {
+        let (path, _) = split_fragment(path);
+        let slug = self
+            .resolve_installed(slug)
+            .await
+            .unwrap_or(slug.to_string());
+        let ext = FORMAT_EXTENSIONS
+            .iter()
+            .find(|(f, _)| format.contains(*f))
+            .map_or("md", |(_, ext)| ext);
+        let relative = add_ext(Path::new(&slug).join(&*paths::disk_page(path)), ext);
+        if let Some(file) = self.resolve_path(&relative) {
+            return Ok(file);
+        }
+
+        let contents = match self.get_page_content(&slug, path).await {
+            Ok(contents) => contents,
+            // Some sources only produce pages once they're asked for
+            Err(e) => match self.source_of(&slug).await.fetch_page(&slug, path).await? {
+                Some(contents) => contents,
+                None => return Err(e),
+            },
+        };
+        let file = self.write_dir().join(relative);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let rendered = if ext == "md" {
+            let converter = self.converter("markdown").await;
+            convert::render_markdown(converter.as_deref(), &slug, path, &contents).await
+        } else {
+            render_page(&slug, path, &contents, format)
+        };
+        fs::write(&file, rendered).await?;
+        Ok(file)
+    }
+
+    /// Update a specific documentation
+    pub async fn update_doc(&self, slug: &str) -> Result<UpdateStatus> {
+        self.update_doc_with_cancel(slug, &CancellationToken::new())
+            .await
+    }
+
+    /// Update a specific documentation, aborting when `cancel` fires.
+    ///
+    /// Updating a doc that's being updated waits for that update and returns its status.
+    pub async fn update_doc_with_cancel(
+        &self,
+        slug: &str,
+        cancel: &CancellationToken,
+    ) -> Result<UpdateStatus> {
+        self.update_one(slug, false, cancel).await
+    }
+
+    /// Download a doc again even when its upstream `mtime` says it's current, e.g. to
+    /// repair a damaged install. Docs gone from upstream are kept as they are.
+    pub async fn force_update_doc(
+        &self,
+        slug: &str,
+        cancel: &CancellationToken,
+    ) -> Result<UpdateStatus> {
+        self.update_one(slug, true, cancel).await
+    }
+
+    #[instrument(name = "update", skip(self, cancel))]
+    async fn update_one(
+        &self,
+        slug: &str,
+        force: bool,
+        cancel: &CancellationToken,
+    ) -> Result<UpdateStatus> {
+        let _lock = self.lock_exclusive()?;
+        let Some(slug) = &self.resolve_installed(slug).await else {
+            return Err(DevDocsError::DocNotFound(slug.to_string()).into());
+        };
+        let key = format!("{} {}", slug, force);
+        self.updating
+            .run(key, cancel, self.update_resolved(slug, force, cancel))
+            .await
+    }
+
+    async fn update_resolved(
+        &self,
+        slug: &str,
+        force: bool,
+        cancel: &CancellationToken,
+    ) -> Result<UpdateStatus> {
+        if self.is_imported(slug).await {
+            info!("{} was imported, import it again to update it", slug);
+            self.emit(ManagerEvent::UpdateSkipped {
+                slug: slug.to_string(),
+            });
+            return Ok(UpdateStatus::Current);
+        }
+
+        let (installed_mtime, formats) = {
+            let cache = self.cache.read().await;
+            let cached = cache.get(slug);
+            (cached.map(|c| c.doc.mtime), cached.and_then(|c| c.formats))
+        };
+        let available_docs = self.get_available_docs().await?;
+        let Some(upstream) = available_docs.iter().find(|d| d.slug == *slug) else {
+            warn!("{} is no longer available upstream, keeping it", slug);
+            self.emit(ManagerEvent::UpdateSkipped {
+                slug: slug.to_string(),
+            });
+            return Ok(UpdateStatus::Orphaned);
+        };
+        if !force && Some(upstream.mtime) == installed_mtime {
+            info!("{} is already up to date", slug);
+            self.emit(ManagerEvent::UpdateSkipped {
+                slug: slug.to_string(),
+            });
+            return Ok(UpdateStatus::Current);
+        }
+
+        // The installed copy is only replaced once the new one is complete
+        let source = self.source_of(slug).await;
+        let changes = self
+            .install_doc(upstream.clone(), formats, &*source, force, cancel)
+            .await?;
+
+        Ok(UpdateStatus::Updated(changes))
+    }
+
+    /// Update all installed documentation
+    pub async fn update_all(&self) -> Result<UpdateReport> {
+        self.update_all_with_cancel(&CancellationToken::new()).await
+    }
+
+    /// Update all installed documentation, stopping when `cancel` fires
+    pub async fn update_all_with_cancel(&self, cancel: &CancellationToken) -> Result<UpdateReport> {
+        self.update_many(false, cancel).await
+    }
+
+    /// Download every installed doc again, current or not, stopping when `cancel` fires
+    pub async fn force_update_all(&self, cancel: &CancellationToken) -> Result<UpdateReport> {
+        self.update_many(true, cancel).await
+    }
+
+    async fn update_many(&self, force: bool, cancel: &CancellationToken) -> Result<UpdateReport> {
+        let _lock = self.lock_exclusive()?;
+        let mut installed_docs = self.list_installed_docs().await?;
+        installed_docs.sort();
+
+        info!("Updating {} documentation entries", installed_docs.len());
+
+        // Update a few docs at a time to avoid overwhelming the server
+        const CONCURRENCY: usize = 4;
+        let mut outcomes = futures::stream::iter(installed_docs)
+            .map(|slug| async move {
+                let outcome = self.update_one(&slug, force, cancel).await;
+                (slug, outcome)
+            })
+            .buffer_unordered(CONCURRENCY);
+
+        let mut report = UpdateReport::default();
+        while let Some((slug, outcome)) = outcomes.next().await {
+            match outcome {
+                Ok(UpdateStatus::Updated(changes)) => report.updated.push((slug, changes)),
+                Ok(UpdateStatus::Current) => report.current.push(slug),
+                Ok(UpdateStatus::Orphaned) => report.orphaned.push(slug),
+                Err(e) => {
+                    warn!("Failed to update {}: {}", slug, e);
+                    self.emit(ManagerEvent::Error {
+                        slug: slug.clone(),
+                        message: e.to_string(),
+                    });
+                    report.failed.push((slug, e.to_string()));
+                }
+            }
+        }
+
+        if cancel.is_cancelled() {
+            return Err(DevDocsError::Cancelled.into());
+        }
+
+        Ok(report)
+    }
+
+    /// Installed docs that are no longer available upstream, sorted.
+    ///
+    /// They keep being served from the installed copy, but can't be updated. Only docs
+    /// installed from DevDocs count, docs of other sources may just be out of reach.
+    pub async fn orphaned_docs(&self) -> Result<Vec<String>> {
+        let available_docs = self.get_available_docs().await?;
+        let doc_sources = self.doc_sources.read().await;
+        let mut orphaned: Vec<String> = self
+            .list_installed_docs()
+            .await?
+            .into_iter()
+            .filter(|slug| {
+                doc_sources
+                    .get(slug)
+                    .is_none_or(|name| name == DevDocsSource::NAME)
+                    && !available_docs.iter().any(|d| d.slug == *slug)
+            })
+            .collect();
+        orphaned.sort();
+        Ok(orphaned)
+    }
+
+    // Private helper methods
+
+    /// Whether an installed doc was imported from a source the manager doesn't know
+    async fn is_imported(&self, slug: &str) -> bool {
+        self.doc_sources
+            .read()
+            .await
+            .get(slug)
+            .is_some_and(|name| self.sources.iter().all(|s| s.name() != name))
+    }
+
+    /// The source an available doc is installed from, DevDocs unless another claimed it
+    async fn source_of(&self, slug: &str) -> Arc<dyn DocSource> {
+        let doc_sources = self.doc_sources.read().await;
+        let name = doc_sources
+            .get(slug)
+            .map_or(DevDocsSource::NAME, String::as_str);
+        self.sources
+            .iter()
+            .find(|source| source.name() == name)
+            .unwrap_or(&self.sources[0])
+            .clone()
+    }
+
+    /// Fetch the remote images and stylesheets referenced by the pages, and point the
+    /// pages at where the local copies are stored in the doc's assets dir.
+    ///
+    /// Assets that fail to download keep their remote URL.
+    async fn download_assets(
+        &self,
+        content: &mut HashMap<String, String>,
+    ) -> Result<Vec<(String, Vec<u8>)>> {
+        let re = asset_regex();
+        let urls: HashSet<String> = content
+            .values()
+            .flat_map(|html| re.captures_iter(html).map(|caps| caps[3].to_string()))
+            .collect();
+
+        const CONCURRENCY: usize = 8;
+        let assets: Vec<(String, String, Vec<u8>)> = futures::stream::iter(urls)
+            .map(|url| async move {
+                let absolute = if url.starts_with("//") {
+                    format!("https:{}", url)
+                } else {
+                    url.clone()
+                };
+                match self.transport.get(&absolute).await {
+                    Ok(data) => Some((asset_name(&url), url, data)),
+                    Err(e) => {
+                        warn!("Failed to download asset {}: {}", url, e);
+                        None
+                    }
+                }
+            })
+            .buffer_unordered(CONCURRENCY)
+            .filter_map(|asset| async move { asset })
+            .collect()
+            .await;
+
+        let local: HashMap<&str, &str> = assets
+            .iter()
+            .map(|(name, url, _)| (url.as_str(), name.as_str()))
+            .collect();
+        for (page, html) in content.iter_mut() {
+            // Relative path from the page's directory back up to the doc dir
+            let depth = Path::new(page).components().count().saturating_sub(1);
+            let up = "../".repeat(depth);
+            *html = re
+                .replace_all(html, |caps: &Captures| match local.get(&caps[3]) {
+                    Some(name) => format!(
+                        "{} {}={}{}{}/{}{}",
+                        ASSET_ATTR, &caps[1], &caps[2], up, ASSETS_DIR, name, &caps[2]
+                    ),
+                    None => caps[0].to_string(),
+                })
+                .into_owned();
+        }
+
+        Ok(assets
+            .into_iter()
+            .map(|(name, _, data)| (name, data))
+            .collect())
+    }
+
+    async fn save_doc_cache(&self, slug: &str, cached_doc: &CachedDoc) -> Result<()> {
+        let path = self.write_dir().join(format!("{}.bin", slug));
+        let data = bitcode::serialize::<CachedDoc>(cached_doc)?;
+        write_atomic(&path, &data).await?;
+        fs::create_dir_all(self.write_dir().join(CHECKSUMS_DIR)).await?;
+        self.write_state(
+            &format!("{}/{}.json", CHECKSUMS_DIR, slug),
+            &bytes_hash(&data),
+        )
+        .await?;
+        #[cfg(feature = "mmap")]
+        self.save_archive(slug, &cached_doc.index).await?;
+        self.save_haystacks(slug, cached_doc).await
+    }
+
+    async fn save_haystacks(&self, slug: &str, cached_doc: &CachedDoc) -> Result<()> {
+        let dir = self.write_dir().join(HAYSTACKS_DIR);
+        fs::create_dir_all(&dir).await?;
+        let file = HaystackFile {
+            generation: cached_doc.generation(),
+            haystacks: cached_doc.haystacks.clone(),
+        };
+        let data = bitcode::serialize(&file)?;
+        fs::write(dir.join(format!("{}.bin", slug)), data).await?;
+        Ok(())
+    }
+
+    /// Read the persisted haystacks of a doc from a storage layer
+    async fn load_haystacks(layer: &Path, slug: &str) -> Option<HaystackFile> {
+        let path = layer.join(HAYSTACKS_DIR).join(format!("{}.bin", slug));
+        let content = fs::read(path).await.ok()?;
+        bitcode::deserialize(&content).ok()
+    }
+
+    async fn save_page_hashes(&self, slug: &str, hashes: &HashMap<String, u64>) -> Result<()> {
+        let dir = self.write_dir().join(PAGE_HASHES_DIR);
+        fs::create_dir_all(&dir).await?;
+        let data = bitcode::serialize(hashes)?;
+        fs::write(dir.join(format!("{}.bin", slug)), data).await?;
+        Ok(())
+    }
+
+    /// Read the content hashes of a doc's pages from a storage layer
+    async fn load_page_hashes(layer: &Path, slug: &str) -> Option<HashMap<String, u64>> {
+        let path = layer.join(PAGE_HASHES_DIR).join(format!("{}.bin", slug));
+        let content = fs::read(path).await.ok()?;
+        bitcode::deserialize(&content).ok()
+    }
+
+    async fn save_code_blocks(&self, slug: &str, blocks: &[CodeBlock]) -> Result<()> {
+        let dir = self.write_dir().join(CODE_DIR);
+        fs::create_dir_all(&dir).await?;
+        let data = bitcode::serialize(blocks)?;
+        write_atomic(&dir.join(format!("{}.bin", slug)), &data).await?;
+        Ok(())
+    }
+
+    /// Read the code blocks of a doc from the topmost layer that has them
+    async fn load_code_blocks(&self, slug: &str) -> Option<Vec<CodeBlock>> {
+        let path = self.resolve_path(&Path::new(CODE_DIR).join(format!("{}.bin", slug)))?;
+        let content = fs::read(path).await.ok()?;
+        bitcode::deserialize(&content).ok()
+    }
+
+    async fn save_signatures(&self, slug: &str, signatures: &[Signature]) -> Result<()> {
+        let dir = self.write_dir().join(SIGNATURES_DIR);
+        fs::create_dir_all(&dir).await?;
+        let data = bitcode::serialize(signatures)?;
+        write_atomic(&dir.join(format!("{}.bin", slug)), &data).await?;
+        Ok(())
+    }
+
+    /// Read the signatures of a doc from the topmost layer that has them
+    async fn load_signatures(&self, slug: &str) -> Option<Vec<Signature>> {
+        let path = self.resolve_path(&Path::new(SIGNATURES_DIR).join(format!("{}.bin", slug)))?;
+        let content = fs::read(path).await.ok()?;
+        bitcode::deserialize(&content).ok()
+    }
+
+    /// The cache file of a doc and its sidecars in the writable layer
+    fn cache_files(&self, slug: &str) -> [PathBuf; 10] {
+        let dir = self.write_dir();
+        [
+            dir.join(format!("{}.bin", slug)),
+            dir.join(HAYSTACKS_DIR).join(format!("{}.bin", slug)),
+            dir.join(PAGE_HASHES_DIR).join(format!("{}.bin", slug)),
+            dir.join(CODE_DIR).join(format!("{}.bin", slug)),
+            dir.join(SIGNATURES_DIR).join(format!("{}.bin", slug)),
+            dir.join(EMBEDDINGS_DIR).join(format!("{}.bin", slug)),
+            dir.join(CHANGES_DIR).join(format!("{}.json", slug)),
+            dir.join(CHECKSUMS_DIR).join(format!("{}.json", slug)),
+            dir.join(ARCHIVES_DIR).join(format!("{}.rkyv", slug)),
+            self.content_store_path(slug),
+        ]
+    }
+
+    fn content_store_path(&self, slug: &str) -> PathBuf {
+        self.write_dir()
+            .join(CONTENT_DIR)
+            .join(format!("{}.bin", slug))
+    }
+
+    fn fulltext_dir(&self, slug: &str) -> PathBuf {
+        self.write_dir().join(FULLTEXT_DIR).join(slug)
+    }
+
+    fn whiteout_path(&self, slug: &str) -> PathBuf {
+        self.write_dir().join(format!("{}.{}", slug, WHITEOUT_EXT))
+    }
+
+    async fn load_cache(&self) -> Result<()> {
+        let _lock = self.lock_shared().await?;
+        let mut cache = self.cache.write().await;
+
+        for layer in self.layers() {
+            if !layer.exists() {
+                continue;
+            }
+
+            let mut entries = fs::read_dir(layer).await?;
+            let mut docs = Vec::new();
+
+            while let Some(entry) = entries.next_entry().await? {
+                let path = entry.path();
+                let Some(stem) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+
+                match path.extension().and_then(|s| s.to_str()) {
+                    // Whiteouts hide docs from the layers below this one
+                    Some(WHITEOUT_EXT) => {
+                        cache.remove(stem);
+                    }
+                    Some("bin") if stem != "available_docs" => docs.push(path.clone()),
+                    _ => {}
+                }
+            }
+
+            for path in docs {
+                let stem = path
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or_default();
+                match fs::read(&path).await {
+                    Ok(content) => match bitcode::deserialize::<CachedDoc>(&content) {
+                        Ok(mut cached_doc) => {
+                            if let Some(file) = Self::load_haystacks(layer, stem).await {
+                                cached_doc.haystacks = file.haystacks;
+                                cached_doc.haystacks_generation = Some(file.generation);
+                            }
+                            // Haystacks persisted for another generation of the doc, or
+                            // not at all, are computed again
+                            if cached_doc.ensure_haystacks()
+                                && let Err(e) = self.save_haystacks(stem, &cached_doc).await
+                            {
+                                warn!("Failed to save haystacks for {}: {}", stem, e);
+                            }
+                            cache.insert(stem.to_string(), cached_doc);
+                        }
+                        Err(e) => {
+                            warn!("Failed to parse cached doc {}: {}", stem, e);
+                        }
+                    },
+                    Err(e) => {
+                        warn!("Failed to read cached doc {}: {}", stem, e);
+                    }
+                }
+            }
+        }
+
+        // Load available docs cache
+        if let Some(path) = self.resolve_path(Path::new("available_docs.json"))
+            && let Ok(content) = fs::read_to_string(path).await
+            && let Ok((docs, cached_at)) = serde_json::from_str::<(Vec<Doc>, u64)>(&content)
+        {
+            *self.available_docs.write().await = Some((docs, cached_at));
+        }
+
+        // Load user-defined aliases
+        if let Some(aliases) = self.read_state("aliases.json").await {
+            *self.aliases.write().await = aliases;
+        }
+
+        if let Some(doc_sources) = self.read_state("sources.json").await {
+            *self.doc_sources.write().await = doc_sources;
+        }
+
+        if let Some(disabled) = self.read_state("disabled.json").await {
+            *self.disabled.write().await = disabled;
+        }
+
+        if let Some(lazy_docs) = self.read_state("lazy.json").await {
+            *self.lazy_docs.write().await = lazy_docs;
+        }
+
+        if let Some(type_filters) = self.read_state("filters.json").await {
+            *self.type_filters.write().await = type_filters;
+        }
+
+        if let Some(settings) = self.read_state("config.json").await {
+            *self.settings.write().await = settings;
+        }
+
+        self.invalidate_queries();
+        info!("Loaded {} cached documentation entries", cache.len());
+        Ok(())
+    }
+
+    /// Read a JSON state file from the topmost layer that has it
+    async fn read_state<T: DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let path = self.resolve_path(Path::new(name))?;
+        let content = fs::read_to_string(path).await.ok()?;
+        match serde_json::from_str(&content) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                warn!("Failed to parse {}: {}", name, e);
+                None
+            }
+        }
+    }
+
+    /// Write a JSON state file to the writable layer
+    async fn write_state<T: Serialize + ?Sized>(&self, name: &str, value: &T) -> Result<()> {
+        let json = serde_json::to_string_pretty(value)?;
+        fs::write(self.write_dir().join(name), json).await?;
+        Ok(())
+    }
+
+    /// Take the state lock, waiting for other processes changing state files.
+    ///
+    /// Unlike the cache lock it's only held for a read-modify-write of a state file.
+    async fn lock_state(&self) -> Result<CacheLock> {
+        let path = self.write_dir().join(STATE_LOCK_FILE);
+        let file = tokio::task::spawn_blocking(move || -> std::io::Result<_> {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(path)?;
+            file.lock()?;
+            Ok(file)
+        })
+        .await??;
+
+        Ok(CacheLock { _file: file })
+    }
+
+    /// Change the state file `name` and its loaded copy in `memory` under the state
+    /// lock, starting from the file so the changes of other processes aren't lost.
+    ///
+    /// Nothing is written when `change` fails.
+    async fn update_state<T, R>(
+        &self,
+        name: &str,
+        memory: &RwLock<T>,
+        change: impl FnOnce(&mut T) -> Result<R>,
+    ) -> Result<R>
+    where
+        T: Serialize + DeserializeOwned,
+    {
+        let _lock = self.lock_state().await?;
+        let mut value = memory.write().await;
+        if let Some(current) = self.read_state(name).await {
+            *value = current;
+        }
+        let result = change(&mut value)?;
+        self.write_state(name, &*value).await?;
+        Ok(result)
+    }
+
+    async fn save_available_docs(&self, docs: &[Doc]) -> Result<()> {
+        let path = self.write_dir().join("available_docs.json");
+        let data = (docs, current_timestamp());
+        let json = serde_json::to_string_pretty(&data)?;
+        fs::write(path, json).await?;
+        Ok(())
+    }
+}
+
+/// A persistent, incremental search over a fixed set of entries.
+///
+/// Entries are pushed into nucleo's matcher once; every [`set_query`](Self::set_query)
+/// afterwards only rescores what changed, which makes per-keystroke refinement cheap.
+pub struct SearchSession {
+    nucleo: Nucleo<CorpusEntry>,
+    /// What the entries of `nucleo` point into
+    corpus: Arc<Corpus>,
+    /// What the matches are ranked by
+    options: SearchOptions,
+    weights: HashMap<String, f32>,
+    /// Recently viewed pages, as their slug and page path
+    recent: HashSet<(String, String)>,
+    query: String,
+    case_matching: pattern::CaseMatching,
+    normalization: pattern::Normalization,
+}
+
+impl SearchSession {
+    /// Replace the current query
+    pub fn set_query(&mut self, query: &str) {
+        // Extending the previous query lets nucleo only re-check prior matches
+        let append = !self.query.is_empty() && query.starts_with(&self.query);
+        // Accents in the query would turn normalization off, so it's normalized upfront
+        let pattern = match self.normalization {
+            pattern::Normalization::Smart => Cow::Owned(matcher_needle(query, false, true)),
+            _ => Cow::Borrowed(query),
+        };
+        self.nucleo
+            .pattern
+            .reparse(0, &pattern, self.case_matching, self.normalization, append);
+        self.query = query.to_string();
+    }
+
+    /// The current query
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    /// Let the matcher work for up to `timeout_ms`, returning whether it is still running
+    pub fn tick(&mut self, timeout_ms: u64) -> bool {
+        self.nucleo.tick(timeout_ms).running
+    }
+
+    /// Number of entries loaded into the session
+    pub fn entry_count(&self) -> u32 {
+        self.nucleo.snapshot().item_count()
+    }
+
+    /// Number of entries matching the current query
+    pub fn matched_count(&self) -> u32 {
+        self.nucleo.snapshot().matched_item_count()
+    }
+
+    /// Wait for the matcher to finish, for up to [`SESSION_WAIT`], and return the best
+    /// `limit` of the entries matched so far
+    pub fn results(&mut self, limit: usize) -> Vec<SearchResult> {
+        // Each tick blocks until the matcher is done; it only runs again for entries
+        // pushed in the meantime
+        let deadline = Instant::now() + SESSION_WAIT;
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if !self.tick(left.as_millis() as u64) || left.is_zero() {
+                break;
+            }
+        }
+
+        let query = ScoreQuery::new(&self.query, &self.options);
+        let mut config = Config::DEFAULT;
+        config.prefer_prefix = true;
+        config.ignore_case = query.ignore_case;
+        config.normalize = self.options.normalize;
+        let mut matcher = Matcher::new(config);
+
+        // Like `rank_candidates`, the best of each target is ranked once they're all
+        // known, and results are kept per doc when they're capped
+        let per_group = self.options.max_per_doc.map_or(limit, |cap| cap.min(limit));
+        let group = |doc: u32| {
+            if self.options.max_per_doc.is_some() {
+                doc
+            } else {
+                0
+            }
+        };
+        let mut targets: HashMap<(u32, &Path), _> = HashMap::new();
+        let mut groups: HashMap<u32, BinaryHeap<_>> = HashMap::new();
+
+        let snapshot = self.nucleo.snapshot();
+        for item in snapshot.matched_items(..) {
+            let at = *item.data;
+            let (doc, entry) = self.corpus.entry(at);
+            let haystack = &doc.haystacks[at.entry as usize];
+            let recent = self
+                .recent
+                .contains(&(doc.slug.to_string(), page_path(&entry.path).to_string()));
+            let weight = self.weights.get(&*doc.slug).copied();
+            let Some(score) = score_entry(
+                &mut matcher,
+                &query,
+                entry,
+                haystack,
+                &self.options,
+                recent,
+                weight,
+            ) else {
+                continue;
+            };
+            if score < self.options.min_score {
+                continue;
+            }
+            let ranked = Reverse((score, Reverse((at.doc, at.entry))));
+            if self.options.collapse_duplicates {
+                let best = targets
+                    .entry((at.doc, entry.path.as_path()))
+                    .or_insert(ranked);
+                *best = (*best).min(ranked);
+            } else {
+                push_bounded(groups.entry(group(at.doc)).or_default(), per_group, ranked);
+            }
+        }
+        for ((doc, _), ranked) in targets {
+            push_bounded(groups.entry(group(doc)).or_default(), per_group, ranked);
+        }
+
+        let mut best: Vec<_> = groups.into_values().flatten().collect();
+        best.sort_unstable();
+        best.truncate(limit);
+        best.into_iter()
+            .map(|Reverse((score, Reverse((doc, entry))))| {
+                let (doc, entry) = self.corpus.entry(CorpusEntry { doc, entry });
+                SearchResult {
+                    entry: SearchableEntry {
+                        entry: entry.clone(),
+                        doc_slug: doc.slug.to_string(),
+                        doc_name: doc.name.to_string(),
+                    },
+                    score,
+                }
+            })
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for SearchSession {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SearchSession")
+            .field("query", &self.query)
+            .field("entries", &self.entry_count())
+            .finish()
+    }
+}
+
+impl Default for DevDocsManager {
+    fn default() -> Self {
+        Self::new().expect("Failed to create DevDocsManager")
+    }
+}
+
+// Helper functions
+
+/// An environment variable, unless the builder reading it is isolated
+fn env_var(isolated: bool, name: &str) -> Option<std::ffi::OsString> {
+    std::env::var_os(name).filter(|_| !isolated)
+}
+
+/// An environment variable holding text, unless the builder reading it is isolated
+fn env_string(isolated: bool, name: &str) -> Option<String> {
+    env_var(isolated, name)?.into_string().ok()
+}
+
+/// Pick an endpoint from an explicit setting or the default, without a trailing slash
+fn endpoint(explicit: Option<String>, default: &str) -> String {
+    explicit
+        .filter(|url| !url.is_empty())
+        .unwrap_or_else(|| default.to_string())
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// The items of a comma-separated setting, trimmed, without empty ones
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Keep only the entries of `types`, given by name or slug, and the pages they point at
+fn retain_types(index: &mut DocIndex, content: &mut HashMap<String, String>, types: &[String]) {
+    let wanted = |name: &str| types.iter().any(|t| t.eq_ignore_ascii_case(name));
+    index.types.retain(|t| wanted(&t.name) || wanted(&t.slug));
+    let names: HashSet<String> = index
+        .types
+        .iter()
+        .map(|t| t.name.to_lowercase())
+        .chain(types.iter().map(|t| t.to_lowercase()))
+        .collect();
+    index
+        .entries
+        .retain(|entry| names.contains(&entry.entry_type.to_lowercase()));
+    for entry_type in &mut index.types {
+        entry_type.count = index
+            .entries
+            .iter()
+            .filter(|entry| entry.entry_type == entry_type.name)
+            .count();
+    }
+
+    let pages: HashSet<&str> = index
+        .entries
+        .iter()
+        .map(|entry| page_path(&entry.path))
+        .collect();
+    content.retain(|page, _| pages.contains(page.as_str()));
+}
+
+/// Reject a downloaded index and content that don't agree with each other, as they
+/// would after a truncated or corrupted transfer
+fn check_download(
+    slug: &str,
+    index: &DocIndex,
+    content: &HashMap<String, String>,
+) -> Result<(), DevDocsError> {
+    let corrupt = |reason: String| DevDocsError::CorruptDownload {
+        slug: slug.to_string(),
+        reason,
+    };
+    if index.entries.is_empty() && !content.is_empty() {
+        return Err(corrupt("the index has no entries".to_string()));
+    }
+    for entry_type in &index.types {
+        let count = index
+            .entries
+            .iter()
+            .filter(|entry| entry.entry_type == entry_type.name)
+            .count();
+        if count != entry_type.count {
+            return Err(corrupt(format!(
+                "the index lists {} entries of type {} but has {}",
+                entry_type.count, entry_type.name, count
+            )));
+        }
+    }
+    // Sources that only ship an index have no pages to point at
+    if !content.is_empty()
+        && let Some(entry) = index
+            .entries
+            .iter()
+            .find(|entry| !content.contains_key(page_path(&entry.path)))
+    {
+        return Err(corrupt(format!(
+            "page {} of entry {} is missing",
+            page_path(&entry.path),
+            entry.name
+        )));
+    }
+    Ok(())
+}
+
+/// Page part of an entry path, without its `#fragment`
+fn page_path(path: &Path) -> &str {
+    split_fragment(path.to_str().unwrap_or_default()).0
+}
+
+/// Split `user:password` credentials, a missing password being empty
+#[cfg(feature = "reqwest")]
+fn split_credentials(credentials: &str) -> (String, String) {
+    let (user, password) = credentials.split_once(':').unwrap_or((credentials, ""));
+    (user.to_string(), password.to_string())
+}
+
+/// Percent-encode what can't appear in a URL path segment or fragment as is, keeping
+/// the characters of `keep` too
+fn percent_encode(text: &str, keep: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for c in text.chars() {
+        let plain =
+            c.is_ascii_alphanumeric() || "-._~!$&'()*+,;=:@".contains(c) || keep.contains(c);
+        if plain {
+            encoded.push(c);
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+/// Split an entry path into its page and the `#fragment` of its section, if any
+pub fn split_fragment(path: &str) -> (&str, Option<&str>) {
+    match path.split_once('#') {
+        Some((page, fragment)) => (page, Some(fragment)),
+        None => (path, None),
+    }
+}
+
+/// Where the element of a page with the id `anchor` starts in `text`, the page as
+/// rendered from its `html`.
+///
+/// Rendered pages lose their ids, so the element is found by its text: the first line
+/// of `text` that reads as it does, ignoring markup and punctuation. The offset is that
+/// of the start of the line.
+pub fn anchor_offset(html: &str, text: &str, anchor: &str) -> Option<usize> {
+    use lol_html::{RewriteStrSettings, rewrite_str};
+    use std::cell::RefCell;
+
+    // Escape every character that could end the selector's string
+    let escaped: String = anchor
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c.to_string(),
+            c => format!("\\{:x} ", u32::from(c)),
+        })
+        .collect();
+    let selector = format!("[id=\"{}\"]", escaped);
+    selector.parse::<lol_html::Selector>().ok()?;
+
+    let heading = RefCell::new(None::<String>);
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![lol_html::text!(selector, |chunk| {
+            heading
+                .borrow_mut()
+                .get_or_insert_default()
+                .push_str(chunk.as_str());
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings).ok()?;
+
+    let words = |text: &str| -> String {
+        text.chars()
+            .filter(|c| c.is_alphanumeric())
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    // Long elements wrap over several lines, so their start has to do
+    let heading: String = words(&decode_entities(heading.into_inner()?))
+        .chars()
+        .take(ANCHOR_MATCH_CHARS)
+        .collect();
+    if heading.is_empty() {
+        return None;
+    }
+    let lines: Vec<(usize, String)> = text
+        .split_inclusive('\n')
+        .scan(0, |offset, line| {
+            let start = *offset;
+            *offset += line.len();
+            Some((start, words(line)))
+        })
+        .collect();
+    // Rather a line that's just the element than one mentioning it
+    lines
+        .iter()
+        .find(|(_, line)| {
+            !line.is_empty()
+                && heading.starts_with(line.as_str())
+                && line.len() * 2 >= heading.len()
+        })
+        .or_else(|| lines.iter().find(|(_, line)| line.contains(&heading)))
+        .map(|(offset, _)| *offset)
+}
+
+fn search_result(slug: &str, cached_doc: &CachedDoc, entry: &Entry, score: u32) -> SearchResult {
+    SearchResult {
+        entry: SearchableEntry {
+            entry: entry.clone(),
+            doc_slug: slug.to_string(),
+            doc_name: cached_doc.doc.name.clone(),
+        },
+        score,
+    }
+}
+
+/// Matches the remote URL of an image `src` or stylesheet `href`, query string and
+/// all.
+///
+/// Groups: attribute name, quote, URL.
+fn asset_regex() -> Regex {
+    Regex::new(
+        r#"\b(src|href)=(["'])((?:https?:)?//[^"']+?\.(?:png|jpe?g|gif|svg|webp|css)(?:[?#][^"']*)?)["']"#,
+    )
+    .unwrap()
+}
+
+/// File name to store a downloaded asset under, unique per URL
+fn asset_name(url: &str) -> String {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+    let file = path.rsplit('/').next().unwrap_or_default();
+    let ext = file.rsplit_once('.').map_or("", |(_, ext)| ext);
+    format!("{:016x}.{}", page_hash(url), ext)
+}
+
+/// Sub-entries for the `h2`/`h3` headings of a page that carry an anchor
+fn extract_sections(page: &str, html: &str) -> Vec<Entry> {
+    use lol_html::{RewriteStrSettings, element, rewrite_str, text};
+    use std::cell::RefCell;
+
+    // (anchor, heading text) in document order
+    let headings: RefCell<Vec<(String, String)>> = RefCell::new(Vec::new());
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("h2[id], h3[id]", |el| {
+                let id = el.get_attribute("id").unwrap_or_default();
+                headings.borrow_mut().push((id, String::new()));
+                Ok(())
+            }),
+            text!("h2[id], h3[id]", |chunk| {
+                if let Some((_, text)) = headings.borrow_mut().last_mut() {
+                    text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    if rewrite_str(html, settings).is_err() {
+        return Vec::new();
+    }
+
+    headings
+        .into_inner()
+        .into_iter()
+        .filter_map(|(id, text)| {
+            let name = decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" "));
+            (!id.is_empty() && !name.is_empty()).then(|| Entry {
+                name,
+                path: PathBuf::from(format!("{}#{}", page, id)),
+                entry_type: SECTION_TYPE.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The `<pre>` blocks of a page, tagged with the language their markup names.
+///
+/// The language comes from `data-language`, or from a `language-*`/`lang-*` class
+/// on the block or its `<code>`.
+fn extract_code_blocks(page: &str, html: &str) -> Vec<CodeBlock> {
+    use lol_html::{RewriteStrSettings, element, rewrite_str, text};
+    use std::cell::RefCell;
+
+    fn language_of(el: &lol_html::html_content::Element) -> Option<String> {
+        el.get_attribute("data-language")
+            .filter(|lang| !lang.is_empty())
+            .or_else(|| {
+                let class = el.get_attribute("class")?;
+                class.split_whitespace().find_map(|class| {
+                    class
+                        .strip_prefix("language-")
+                        .or_else(|| class.strip_prefix("lang-"))
+                        .map(str::to_string)
+                })
+            })
+    }
+
+    // (language, code) in document order
+    let blocks: RefCell<Vec<(Option<String>, String)>> = RefCell::new(Vec::new());
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("pre", |el| {
+                blocks.borrow_mut().push((language_of(el), String::new()));
+                Ok(())
+            }),
+            element!("pre code", |el| {
+                if let Some((language @ None, _)) = blocks.borrow_mut().last_mut() {
+                    *language = language_of(el);
+                }
+                Ok(())
+            }),
+            text!("pre", |chunk| {
+                if let Some((_, code)) = blocks.borrow_mut().last_mut() {
+                    code.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    if rewrite_str(html, settings).is_err() {
+        return Vec::new();
+    }
+
+    blocks
+        .into_inner()
+        .into_iter()
+        .filter_map(|(language, code)| {
+            let code = decode_entities(code.trim_matches('\n').trim_end().to_string());
+            (!code.trim().is_empty()).then(|| CodeBlock {
+                page: page.to_string(),
+                language,
+                code,
+            })
+        })
+        .collect()
+}
+
+/// The function and method signatures of a page, for doc types with known markup
+fn extract_signatures(doc_type: &str, page: &str, html: &str) -> Vec<Signature> {
+    use lol_html::{RewriteStrSettings, element, rewrite_str, text};
+    use std::cell::RefCell;
+
+    let selector = match doc_type {
+        "rust" => ".code-header, pre.item-decl",
+        "sphinx" | "python" => "dt[id]",
+        "node" => "h3[id], h4[id], h5[id]",
+        _ => return Vec::new(),
+    };
+
+    // (anchor, signature text) in document order
+    let found: RefCell<Vec<(Option<String>, String)>> = RefCell::new(Vec::new());
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!(selector, |el| {
+                found
+                    .borrow_mut()
+                    .push((el.get_attribute("id"), String::new()));
+                Ok(())
+            }),
+            text!(selector, |chunk| {
+                if let Some((_, text)) = found.borrow_mut().last_mut() {
+                    text.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    if rewrite_str(html, settings).is_err() {
+        return Vec::new();
+    }
+
+    found
+        .into_inner()
+        .into_iter()
+        .filter_map(|(anchor, text)| {
+            let text = decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" "));
+            let (head, _) = text.split_once('(')?;
+            // Rust headers also cover impls and types, only functions have parameters
+            if doc_type == "rust" && !head.contains("fn ") {
+                return None;
+            }
+            let head = head.split_once('<').map_or(head, |(head, _)| head);
+            let name = head.split_whitespace().last()?;
+            let callable = name
+                .chars()
+                .all(|c| c.is_alphanumeric() || "_.:$".contains(c));
+            (callable && !name.is_empty()).then(|| Signature {
+                page: page.to_string(),
+                anchor: anchor.filter(|a| !a.is_empty()),
+                name: name.to_string(),
+                text: text.trim_end_matches('¶').trim_end().to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Escape text for HTML element content and quoted attribute values
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Decode the character references that commonly show up in heading and code text
+pub(crate) fn decode_entities(text: String) -> String {
+    if !text.contains('&') {
+        return text;
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Render a page's HTML in a single format, pointing its links at the rendered files
+fn render_page(slug: &str, page: &str, contents: &str, format: Formats) -> String {
+    if format.contains(Formats::HTML) {
+        math::to_mathjax(&rewrite_links(contents, slug, page, "html"))
+    } else {
+        let html = math::to_unicode(&rewrite_links(contents, slug, page, "md"));
+        let markdown = terminal::render(&html, html2md::parse_html);
+        if format.contains(Formats::TEXT) {
+            terminal::plain_text(&markdown)
+        } else {
+            markdown
+        }
+    }
+}
+
+/// Where each page's compressed HTML lies in a content store, after the header
+type ContentHeader = HashMap<String, (u64, u64)>;
+
+/// Pack pages into a content store: the length of the header, the header, then each
+/// page compressed on its own so it can be read without the others
+fn compress_content(content: &HashMap<String, String>) -> Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut header = ContentHeader::new();
+    let mut pages = Vec::new();
+    for (page, html) in content {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(html.as_bytes())?;
+        let compressed = encoder.finish()?;
+        header.insert(page.clone(), (pages.len() as u64, compressed.len() as u64));
+        pages.extend(compressed);
+    }
+
+    let header = bitcode::serialize(&header)?;
+    let mut data = Vec::with_capacity(8 + header.len() + pages.len());
+    data.extend((header.len() as u64).to_le_bytes());
+    data.extend(header);
+    data.extend(pages);
+    Ok(data)
+}
+
+fn decompress_page(compressed: &[u8]) -> Result<String> {
+    use std::io::Read;
+
+    let mut html = String::new();
+    flate2::read::GzDecoder::new(compressed).read_to_string(&mut html)?;
+    Ok(html)
+}
+
+/// Split a content store into its header and the compressed pages
+fn content_header(data: &[u8]) -> Result<(ContentHeader, &[u8])> {
+    let len = data
+        .first_chunk::<8>()
+        .map(|len| u64::from_le_bytes(*len) as usize)
+        .filter(|len| 8 + len <= data.len())
+        .context("Truncated content store")?;
+    let header = bitcode::deserialize(&data[8..8 + len])?;
+    Ok((header, &data[8 + len..]))
+}
+
+fn decompress_content(data: &[u8]) -> Result<HashMap<String, String>> {
+    let (header, pages) = content_header(data)?;
+    header
+        .into_iter()
+        .map(|(page, (offset, len))| {
+            let compressed = pages
+                .get(offset as usize..(offset + len) as usize)
+                .context("Truncated content store")?;
+            Ok((page, decompress_page(compressed)?))
+        })
+        .collect()
+}
+
+/// Read a single page of the content store at `path`, seeking past the others
+fn read_content_page(path: &Path, page: &str) -> Result<Option<String>> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut file = std::fs::File::open(path)?;
+    let mut header_len = [0; 8];
+    file.read_exact(&mut header_len)?;
+    let header_len = u64::from_le_bytes(header_len);
+    let mut header = Vec::new();
+    (&mut file).take(header_len).read_to_end(&mut header)?;
+    let header: ContentHeader = bitcode::deserialize(&header)?;
+
+    let Some(&(offset, len)) = header.get(page) else {
+        return Ok(None);
+    };
+    file.seek(SeekFrom::Start(8 + header_len + offset))?;
+    let mut compressed = Vec::new();
+    file.take(len).read_to_end(&mut compressed)?;
+    if compressed.len() as u64 != len {
+        anyhow::bail!("Truncated content store");
+    }
+    decompress_page(&compressed).map(Some)
+}
+
+/// Read every rendered `.html` page below `dir`, keyed by its page path relative to `root`
+fn collect_html_pages(
+    root: &Path,
+    dir: &Path,
+    names: &paths::PageNames,
+    pages: &mut HashMap<String, String>,
+) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_html_pages(root, &path, names, pages)?;
+        } else if path.extension().is_some_and(|ext| ext == "html") {
+            let page = path.strip_prefix(root).unwrap_or(&path).with_extension("");
+            if let Some(page) = page.to_str() {
+                let page = names.page(&page.replace('\\', "/")).into_owned();
+                pages.insert(page, std::fs::read_to_string(&path)?);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete every file below `dir` with one of the given extensions
+fn remove_with_extensions(dir: &Path, extensions: &[&str]) -> std::io::Result<()> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Ok(());
+    };
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            remove_with_extensions(&path, extensions)?;
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| extensions.contains(&ext))
+        {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check that the data dir is writable and has room to spare
+fn check_data_dir(dir: &Path, base: Option<&Path>, quota: Option<u64>) -> Vec<Diagnostic> {
+    let mut checks = Vec::new();
+    let probe = dir.join(format!(".doctor-{}", std::process::id()));
+    checks.push(
+        match std::fs::write(&probe, b"").and_then(|()| std::fs::remove_file(&probe)) {
+            Ok(()) => Diagnostic::ok("data dir", format!("{} is writable", dir.display())),
+            Err(e) => Diagnostic::failed(
+                CheckStatus::Error,
+                "data dir",
+                format!("{} can't be written: {}", dir.display(), e),
+                "Fix its permissions, or point DEVDOCS_DATA_DIR (or --overlay) elsewhere",
+            ),
+        },
+    );
+    if let Some(base) = base {
+        checks.push(match std::fs::read_dir(base) {
+            Ok(_) => Diagnostic::ok("base layer", format!("{} is readable", base.display())),
+            Err(e) => Diagnostic::failed(
+                CheckStatus::Error,
+                "base layer",
+                format!("{} can't be read: {}", base.display(), e),
+                "Fix its permissions, or point DEVDOCS_SHARED_DIR at the shared docs",
+            ),
+        });
+    }
+
+    #[cfg(unix)]
+    if let Ok(stats) = rustix::fs::statvfs(dir) {
+        let free = stats.f_bavail.saturating_mul(stats.f_frsize);
+        let message = format!("{:.1} GB free", free as f64 / 1e9);
+        checks.push(if free < LOW_SPACE_BYTES {
+            let fix = match quota {
+                Some(_) => "Free up space, or remove docs with `dev remove`",
+                None => "Free up space, remove docs with `dev remove`, or set a --quota",
+            };
+            Diagnostic::failed(CheckStatus::Warning, "free space", message, fix)
+        } else {
+            Diagnostic::ok("free space", message)
+        });
+    }
+    checks
+}
+
+/// Check that the writable layer holds readable docs and nothing that isn't theirs
+fn check_cache(dir: &Path, docs: &[(String, Option<Formats>)]) -> Vec<Diagnostic> {
+    let installed: HashSet<&str> = docs.iter().map(|(slug, _)| slug.as_str()).collect();
+    let names = |names: &[String]| {
+        let mut listed = names[..names.len().min(DIAGNOSTIC_NAMES)].join(", ");
+        if names.len() > DIAGNOSTIC_NAMES {
+            listed.push_str(&format!(" and {} more", names.len() - DIAGNOSTIC_NAMES));
+        }
+        listed
+    };
+    let read_dir = |dir: &Path| -> Vec<PathBuf> {
+        std::fs::read_dir(dir)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default()
+    };
+    let stem = |path: &Path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+
+    // Docs the cache couldn't load, e.g. written by an incompatible version
+    let mut unreadable = Vec::new();
+    for path in read_dir(dir) {
+        let slug = stem(&path);
+        if path.extension().is_some_and(|ext| ext == "bin")
+            && slug != "available_docs"
+            && !installed.contains(slug.as_str())
+            && std::fs::read(&path)
+                .ok()
+                .and_then(|data| bitcode::deserialize::<CachedDoc>(&data).ok())
+                .is_none()
+        {
+            unreadable.push(slug);
+        }
+    }
+    unreadable.sort();
+    let mut checks = vec![if unreadable.is_empty() {
+        Diagnostic::ok("cache format", format!("{} docs load", installed.len()))
+    } else {
+        Diagnostic::failed(
+            CheckStatus::Error,
+            "cache format",
+            format!("can't load {}", names(&unreadable)),
+            "Install them again with `dev add`",
+        )
+    }];
+
+    // Files of docs that are no longer installed
+    let per_doc = [
+        HAYSTACKS_DIR,
+        PAGE_HASHES_DIR,
+        CONTENT_DIR,
+        CODE_DIR,
+        FULLTEXT_DIR,
+        SIGNATURES_DIR,
+        EMBEDDINGS_DIR,
+        CHANGES_DIR,
+        CHECKSUMS_DIR,
+        ARCHIVES_DIR,
+    ];
+    let owned = |slug: &str| installed.contains(slug) || unreadable.iter().any(|s| s == slug);
+    let mut orphans = Vec::new();
+    for path in read_dir(dir) {
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let known = per_doc.contains(&name.as_ref())
+            || [STAGING_DIR, MODELS_DIR, FETCHED_DIR, PROFILES_DIR].contains(&name.as_ref());
+        if path.is_dir() && !known && !owned(&name) {
+            orphans.push(path);
+        }
+    }
+    for side in per_doc {
+        orphans.extend(
+            read_dir(&dir.join(side))
+                .into_iter()
+                .filter(|path| !owned(&stem(path))),
+        );
+    }
+    let mut orphans: Vec<String> = orphans
+        .iter()
+        .map(|path| path.strip_prefix(dir).unwrap_or(path).display().to_string())
+        .collect();
+    orphans.sort();
+    checks.push(if orphans.is_empty() {
+        Diagnostic::ok("orphaned files", "none")
+    } else {
+        Diagnostic::failed(
+            CheckStatus::Warning,
+            "orphaned files",
+            format!("{} belong to no installed doc", names(&orphans)),
+            format!("Delete them from {}", dir.display()),
+        )
+    });
+
+    // Rendered pages should be in exactly the formats the docs were installed with
+    let mut mismatched = Vec::new();
+    for (slug, formats) in docs {
+        if !dir.join(format!("{}.bin", slug)).exists() {
+            continue;
+        }
+        let Some(formats) = formats else {
+            continue;
+        };
+        let lazy = dir.join(CONTENT_DIR).join(format!("{}.bin", slug)).exists();
+        let (html, md, txt) = count_pages(&dir.join(slug));
+        let stray = (html > 0 && !formats.contains(Formats::HTML))
+            || (md > 0 && !formats.contains(Formats::MARKDOWN))
+            || (txt > 0 && !formats.contains(Formats::TEXT));
+        let missing = !lazy && html + md + txt == 0;
+        if stray || missing {
+            mismatched.push(slug.clone());
+        }
+    }
+    mismatched.sort();
+    checks.push(if mismatched.is_empty() {
+        Diagnostic::ok("rendered pages", "match the installed formats")
+    } else {
+        Diagnostic::failed(
+            CheckStatus::Warning,
+            "rendered pages",
+            format!(
+                "{} don't match the formats they were installed with",
+                names(&mismatched)
+            ),
+            "Reinstall them with `dev remove <slug>` and `dev add <slug>`",
+        )
+    });
+
+    let staged = read_dir(&dir.join(STAGING_DIR));
+    if !staged.is_empty() {
+        let staged: Vec<String> = staged.iter().map(|path| stem(path)).collect();
+        checks.push(Diagnostic::failed(
+            CheckStatus::Warning,
+            "staging",
+            format!(
+                "interrupted installs of {} left files behind",
+                names(&staged)
+            ),
+            format!("Delete {}", dir.join(STAGING_DIR).display()),
+        ));
+    }
+    checks
+}
+
+/// Number of HTML, Markdown and text pages below a doc's dir, its assets aside
+fn count_pages(dir: &Path) -> (usize, usize, usize) {
+    let (mut html, mut md, mut txt) = (0, 0, 0);
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return (0, 0, 0);
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if path.is_dir() {
+            if path.file_name().is_some_and(|name| name != ASSETS_DIR) {
+                let (dir_html, dir_md, dir_txt) = count_pages(&path);
+                html += dir_html;
+                md += dir_md;
+                txt += dir_txt;
+            }
+        } else {
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("html") => html += 1,
+                Some("md") => md += 1,
+                Some("txt") => txt += 1,
+                _ => {}
+            }
+        }
+    }
+    (html, md, txt)
+}
+
+/// Check whether a `dev serve` answers on `addr`
+pub async fn diagnose_server(addr: std::net::SocketAddr) -> Diagnostic {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const CHECK: &str = "server";
+    let request = async {
+        let mut stream = tokio::net::TcpStream::connect(addr).await?;
+        let request = format!(
+            "GET /api/docs HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+            addr
+        );
+        stream.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).await?;
+        anyhow::Ok(response)
+    };
+    match tokio::time::timeout(std::time::Duration::from_secs(2), request).await {
+        Ok(Ok(response)) if response.starts_with(b"HTTP/1.1 200") => {
+            Diagnostic::ok(CHECK, format!("`dev serve` answers on {}", addr))
+        }
+        Ok(Ok(_)) => Diagnostic::failed(
+            CheckStatus::Error,
+            CHECK,
+            format!("something other than `dev serve` answers on {}", addr),
+            "Stop it, or run `dev serve --addr` on another address",
+        ),
+        Ok(Err(_)) | Err(_) => Diagnostic::failed(
+            CheckStatus::Warning,
+            CHECK,
+            format!("nothing answers on {}", addr),
+            "Start it with `dev serve` if editors or the web UI need it",
+        ),
+    }
+}
+
+/// Total size of a file, or of every file below a directory
+fn dir_size(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map(|entries| entries.flatten().map(|e| dir_size(&e.path())).sum())
+        .unwrap_or(0)
+}
+
+/// FNV-1a hash of a page, stable across runs and toolchains
+pub(crate) fn page_hash(contents: &str) -> u64 {
+    bytes_hash(contents.as_bytes())
+}
+
+fn bytes_hash(data: &[u8]) -> u64 {
+    data.iter().copied().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Count the pages added, modified and removed between two sets of page hashes
+fn diff_pages(old: &HashMap<String, u64>, new: &HashMap<String, u64>) -> PageChanges {
+    let mut changes = PageChanges::default();
+    for (name, hash) in new {
+        match old.get(name) {
+            None => changes.added += 1,
+            Some(old_hash) if old_hash != hash => changes.modified += 1,
+            Some(_) => {}
+        }
+    }
+    changes.removed = old.keys().filter(|name| !new.contains_key(*name)).count();
+    changes
+}
+
+/// Write a file via a temporary sibling and a rename, so readers never see it half-written
+async fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    fs::write(&tmp, data).await?;
+    fs::rename(&tmp, path).await
+}
+
+/// Run `fut` unless `cancel` fires first, in which case the future is dropped
+async fn cancellable<T>(
+    cancel: &CancellationToken,
+    fut: impl std::future::Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        _ = cancel.cancelled() => Err(DevDocsError::Cancelled.into()),
+        result = fut => result,
+    }
+}
+
+/// Edit distance between two words, counting a swap of neighbouring letters as a
+/// single edit, or `None` once it exceeds `max`
+fn edit_distance(a: &str, b: &str, max: usize) -> Option<usize> {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.len().abs_diff(b.len()) > max {
+        return None;
+    }
+
+    // Rows of the distance matrix for the prefixes of `a` one and two chars shorter
+    let mut before: Vec<usize> = Vec::new();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 0..a.len() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for j in 0..b.len() {
+            let substitution = previous[j] + usize::from(a[i] != b[j]);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+            if i > 0 && j > 0 && a[i] == b[j - 1] && a[i - 1] == b[j] {
+                current[j + 1] = current[j + 1].min(before[j - 1] + 1);
+            }
+        }
+        // Every later row only grows from the smallest value of the last two
+        let floor = current.iter().chain(&previous).min().copied();
+        if floor.is_some_and(|min| min > max) {
+            return None;
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    Some(previous[b.len()]).filter(|&distance| distance <= max)
+}
+
+/// Whether an entry is of one of the types a search is limited to
+fn has_type(options: &SearchOptions, entry: &Entry) -> bool {
+    options.types.is_empty() || options.types.contains(&entry.entry_type)
+}
+
+/// Progress of a [`DevDocsManager::search_stream`]
+struct StreamState {
+    query: String,
+    options: SearchOptions,
+    /// Docs to search, and the doc and entry the next chunk starts at
+    slugs: Vec<String>,
+    doc: usize,
+    start: usize,
+    recent_pages: Vec<RecentPage>,
+    weights: HashMap<String, f32>,
+    matchers: [ThreadLocal<RefCell<Matcher>>; 2],
+    /// Scores of the best results yielded so far
+    best: BinaryHeap<Reverse<u32>>,
+    /// Results ranked but not yet yielded
+    pending: std::collections::VecDeque<SearchResult>,
+    /// Passes the results yielded of each doc up to `max_per_doc`
+    under_cap: Box<dyn FnMut(&str) -> bool + Send + Sync>,
+    /// Doc and page of the results yielded so far, when collapsing duplicates
+    targets: HashSet<(String, PathBuf)>,
+}
+
+/// A filter passing the first [`SearchOptions::max_per_doc`] results of each doc, for
+/// results in order of rank
+pub(crate) fn cap_per_doc(cap: Option<usize>) -> impl FnMut(&str) -> bool {
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    move |slug| {
+        let Some(cap) = cap else {
+            return true;
+        };
+        let count = match counts.get_mut(slug) {
+            Some(count) => count,
+            None => counts.entry(slug.to_string()).or_default(),
+        };
+        *count += 1;
+        *count <= cap
+    }
+}
+
+/// An entry to search, borrowed from the cache with its doc and haystack
+type Candidate<'a> = (&'a String, &'a CachedDoc, &'a Entry, &'a Haystack);
+
+/// List candidates without a query: recently viewed pages first, everything else
+/// alphabetically
+fn browse_candidates(
+    candidates: &[Candidate],
+    options: &SearchOptions,
+    recent_rank: &(dyn Fn(&str, &Entry) -> Option<usize> + Sync),
+) -> (Vec<SearchResult>, usize) {
+    let mut browse = candidates.to_vec();
+    browse.sort_by_cached_key(|(slug, _, entry, haystack)| {
+        (
+            recent_rank(slug, entry).unwrap_or(usize::MAX),
+            haystack.lower.clone(),
+            slug.as_str(),
+        )
+    });
+
+    if options.collapse_duplicates {
+        // The first entry of each target stands for the others
+        let mut targets = HashSet::new();
+        browse.retain(|(slug, _, entry, _)| targets.insert((slug.as_str(), entry.path.as_path())));
+    }
+    let mut under_cap = cap_per_doc(options.max_per_doc);
+    browse.retain(|(slug, ..)| under_cap(slug));
+    let total = browse.len();
+    let results = browse
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit)
+        .map(|(slug, cached_doc, entry, _)| search_result(slug, cached_doc, entry, 0))
+        .collect();
+    (results, total)
+}
+
+/// A scored candidate by its index, ordered so the worst is the greatest
+type Ranked = Reverse<(u32, Reverse<usize>)>;
+
+/// The best candidates of each group and how many matched, with the best of each
+/// target when collapsing duplicates
+type Ranking<'a> = (
+    HashMap<&'a str, BinaryHeap<Ranked>>,
+    HashMap<&'a str, usize>,
+    HashMap<(&'a str, &'a Path), Ranked>,
+);
+
+/// Fuzzy match `query` against the candidates and return the requested page of the
+/// best ones, with how many matched in all.
+///
+/// Scores are scaled by the `weights` of their docs. `matchers` hold each worker's
+/// matcher ignoring case and respecting it, so they can be reused across queries.
+fn rank_candidates(
+    candidates: &[Candidate],
+    query: &str,
+    options: &SearchOptions,
+    recent_rank: &(dyn Fn(&str, &Entry) -> Option<usize> + Sync),
+    weights: &HashMap<String, f32>,
+    matchers: &[ThreadLocal<RefCell<Matcher>>; 2],
+) -> (Vec<SearchResult>, usize) {
+    use rayon::prelude::*;
+
+    // Every result up to the end of the requested page has to be ranked
+    let limit = options.offset.saturating_add(options.limit);
+    let ignore_case = options.case_matching.ignores_case(query);
+    let mut matcher_config = Config::DEFAULT;
+    matcher_config.prefer_prefix = true;
+    matcher_config.ignore_case = ignore_case;
+    matcher_config.normalize = options.normalize;
+    let tls = &matchers[usize::from(ignore_case)];
+    let query = ScoreQuery::new(query, options);
+
+    // Pattern match
+    // Keep only the best candidates of each group per worker, so the cost of
+    // ranking scales with the limit rather than with the size of the corpus.
+    // Groups are docs when results are capped per doc, otherwise there is one.
+    let per_group = options.max_per_doc.map_or(limit, |cap| cap.min(limit));
+    // Also count every match of each group, to know how many results there are in all.
+    // Collapsed duplicates are only ranked once the best name of each target is known.
+    let (mut top, mut matches, targets) = candidates
+        .par_iter()
+        .enumerate()
+        .fold(
+            Default::default,
+            |(mut heaps, mut matches, mut targets): Ranking, (idx, (slug, _, entry, haystack))| {
+                let cell = tls.get_or(|| RefCell::new(Matcher::new(matcher_config.clone())));
+                let Some(score) = score_entry(
+                    &mut cell.borrow_mut(),
+                    &query,
+                    entry,
+                    haystack,
+                    options,
+                    recent_rank(slug, entry).is_some(),
+                    weights.get(slug.as_str()).copied(),
+                ) else {
+                    return (heaps, matches, targets);
+                };
+
+                let ranked = Reverse((score, Reverse(idx)));
+                if score >= options.min_score {
+                    if options.collapse_duplicates {
+                        let best = targets
+                            .entry((slug.as_str(), entry.path.as_path()))
+                            .or_insert(ranked);
+                        *best = (*best).min(ranked);
+                    } else {
+                        let group = if options.max_per_doc.is_some() {
+                            slug.as_str()
+                        } else {
+                            ""
+                        };
+                        push_bounded(heaps.entry(group).or_default(), per_group, ranked);
+                        *matches.entry(group).or_default() += 1;
+                    }
+                }
+                (heaps, matches, targets)
+            },
+        )
+        .reduce(
+            Default::default,
+            |(mut heaps, mut matches, mut targets), (other, counts, other_targets)| {
+                for (group, other) in other {
+                    let heap = heaps.entry(group).or_default();
+                    for ranked in other {
+                        push_bounded(heap, per_group, ranked);
+                    }
+                }
+                for (group, count) in counts {
+                    *matches.entry(group).or_default() += count;
+                }
+                for (target, ranked) in other_targets {
+                    let best = targets.entry(target).or_insert(ranked);
+                    *best = (*best).min(ranked);
+                }
+                (heaps, matches, targets)
+            },
+        );
+    for ((slug, _), ranked) in targets {
+        let group = if options.max_per_doc.is_some() {
+            slug
+        } else {
+            ""
+        };
+        push_bounded(top.entry(group).or_default(), per_group, ranked);
+        *matches.entry(group).or_default() += 1;
+    }
+    let total = matches
+        .into_values()
+        .map(|count| options.max_per_doc.map_or(count, |cap| count.min(cap)))
+        .sum();
+
+    // Sorting the min-heap entries ascending yields the best results first
+    let mut ranked: Vec<_> = top.into_values().flatten().collect();
+    ranked.sort_unstable();
+    ranked.truncate(limit);
+
+    let results = ranked
+        .into_iter()
+        .skip(options.offset)
+        .map(|Reverse((score, Reverse(idx)))| {
+            let (slug, cached_doc, entry, _) = candidates[idx];
+            search_result(slug, cached_doc, entry, score)
+        })
+        .collect();
+    (results, total)
+}
+
+/// Turn a query into the needle the matcher expects, which it doesn't prepare itself:
+/// lowercased when ignoring case, and stripped of accents when normalizing since the
+/// haystacks are
+pub(crate) fn matcher_needle(query: &str, ignore_case: bool, normalize: bool) -> String {
+    let needle = if ignore_case {
+        query.to_lowercase()
+    } else {
+        query.to_string()
+    };
+    if normalize {
+        needle.chars().map(nucleo::chars::normalize).collect()
+    } else {
+        needle
+    }
+}
+
+/// A query prepared for [`score_entry`]
+struct ScoreQuery {
+    ignore_case: bool,
+    /// What the matcher gets, lowercased when ignoring case as it expects
+    needle: String,
+    /// Graphemes of `needle`, or `None` when its bytes can be matched directly
+    chars: Option<Vec<char>>,
+    lower: String,
+    /// Whether the query reads as an abbreviation, for the acronym boost
+    is_acronym: bool,
+}
+
+impl ScoreQuery {
+    fn new(query: &str, options: &SearchOptions) -> Self {
+        let ignore_case = options.case_matching.ignores_case(query);
+        let lower = query.to_lowercase();
+        let needle = matcher_needle(query, ignore_case, options.normalize);
+        let haystack = Haystack::new(&needle);
+        // Only short runs of letters read as abbreviations
+        let is_acronym = lower.chars().count() >= 2 && lower.chars().all(char::is_alphanumeric);
+        Self {
+            ignore_case,
+            needle,
+            chars: haystack.chars,
+            lower,
+            is_acronym,
+        }
+    }
+
+    fn pattern(&self) -> Utf32Str<'_> {
+        match &self.chars {
+            Some(chars) => Utf32Str::Unicode(chars),
+            None => Utf32Str::Ascii(self.needle.as_bytes()),
+        }
+    }
+}
+
+/// Score an entry for `query` as searches rank it: its name and type matched with the
+/// weights of `options`, plus the boosts that apply, scaled by the `weight` of its doc.
+///
+/// `None` when neither the name nor the type matches; the minimum score isn't applied.
+fn score_entry(
+    matcher: &mut Matcher,
+    query: &ScoreQuery,
+    entry: &Entry,
+    haystack: &Haystack,
+    options: &SearchOptions,
+    recent: bool,
+    weight: Option<f32>,
+) -> Option<u32> {
+    let pattern = query.pattern();
+    let mut type_buf = Vec::new();
+    let mut score_of = |text: Utf32Str| {
+        if options.exact {
+            matcher.substring_match(text, pattern)
+        } else {
+            matcher.fuzzy_match(text, pattern)
+        }
+    };
+
+    let name_score = score_of(haystack.as_utf32(&entry.name));
+    let type_score = score_of(Utf32Str::new(&entry.entry_type, &mut type_buf));
+    if name_score.is_none() && type_score.is_none() {
+        return None;
+    }
+
+    let mut score = (name_score.unwrap_or(0) as f32 * options.name_weight
+        + type_score.unwrap_or(0) as f32 * options.type_weight) as u32;
+
+    if haystack.lower == query.lower {
+        score += options.exact_boost;
+    } else if !query.lower.is_empty() && haystack.lower.starts_with(&query.lower) {
+        score += options.prefix_boost;
+    } else if query.is_acronym && haystack.initials.contains(&query.lower) {
+        score += options.acronym_boost;
+    }
+
+    if recent {
+        score += options.recent_boost;
+    }
+
+    if let Some(weight) = weight {
+        score = (score as f32 * weight) as u32;
+    }
+    Some(score)
+}
+
+/// Push onto a min-heap holding at most `limit` items, evicting the smallest
+fn push_bounded<T: Ord>(heap: &mut BinaryHeap<Reverse<T>>, limit: usize, item: Reverse<T>) {
+    if heap.len() < limit {
+        heap.push(item);
+    } else if let Some(smallest) = heap.peek()
+        && item < *smallest
+    {
+        heap.pop();
+        heap.push(item);
+    }
+}
+
+fn current_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
+
+/// Link (or copy, across devices) the rendered files of `pages` from the doc dir `from`
+/// into the staged doc dir `to`
+fn carry_over_pages(
+    from: &Path,
+    to: &Path,
+    pages: &[String],
+    formats: Formats,
+) -> std::io::Result<()> {
+    for page in pages {
+        let disk = paths::disk_page(page);
+        for (_, ext) in FORMAT_EXTENSIONS
+            .iter()
+            .filter(|(f, _)| formats.contains(*f))
+        {
+            let source = add_ext(from.join(&*disk), ext);
+            let target = add_ext(to.join(&*disk), ext);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            if std::fs::hard_link(&source, &target).is_err() {
+                std::fs::copy(&source, &target)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn add_ext(mut path: PathBuf, ext: &str) -> PathBuf {
+    if let Some(cur_ext) = path.extension() {
+        // If we find an extension, like in the sub-trait thing, extend it with html
+        let mut new_ext = cur_ext.to_os_string();
+        let dotted_ext = format!(".{}", ext);
+        new_ext.push(dotted_ext);
+        path.set_extension(new_ext);
+    } else {
+        // no extension, just html
+        path.set_extension(ext);
+    }
+    path
+}
+
+use regex::{Captures, Regex};
+
+/// Point the internal links of a page at the rendered files next to it.
+///
+/// Page links (`<a href>`) get the `ext` of the rendered format; other references
+/// (`src`, stylesheets) are only made relative. Root-relative and relative URLs
+/// are resolved against the page's location, fragments are kept, and external
+/// URLs are left alone.
+fn rewrite_links(html: &str, slug: &str, page: &str, ext: &str) -> String {
+    use lol_html::{RewriteStrSettings, element, rewrite_str};
+
+    let rewrite = |el: &mut lol_html::html_content::Element, attr: &str, ext: Option<&str>| {
+        // Local copies of assets are files, not pages in some format
+        let ext = ext.filter(|_| !el.has_attribute(ASSET_ATTR));
+        if let Some(url) = el.get_attribute(attr)
+            && let Some(url) = resolve_link(&url, slug, page, ext)
+        {
+            el.set_attribute(attr, &url)?;
+        }
+        Ok(())
+    };
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("a[href]", |el| rewrite(el, "href", Some(ext))),
+            element!("link[href]", |el| rewrite(el, "href", None)),
+            element!("[src]", |el| rewrite(el, "src", None)),
+        ],
+        ..RewriteStrSettings::new()
+    };
+
+    rewrite_str(html, settings).unwrap_or_else(|e| {
+        warn!("Failed to rewrite links of {}/{}: {}", slug, page, e);
+        html.to_string()
+    })
+}
+
+/// Drop the `.html` extension that rendering gave the links between pages, and map the
+/// files they point at back to their pages, so rendering the page again points them at
+/// the files of another format
+fn unrender_links(html: &str, names: &paths::PageNames) -> String {
+    let rendered = Regex::new(r#"href="([^":?#]+)\.html(#[^"]*)?""#).unwrap();
+    rendered
+        .replace_all(html, |caps: &Captures| {
+            let fragment = caps.get(2).map_or("", |m| m.as_str());
+            format!(r#"href="{}{}""#, names.page(&caps[1]), fragment)
+        })
+        .into_owned()
+}
+
+/// Rewrite an internal URL relative to the rendered `page` of doc `slug`.
+///
+/// Returns `None` for URLs that should be left as they are.
+fn resolve_link(url: &str, slug: &str, page: &str, ext: Option<&str>) -> Option<String> {
+    let scheme = url
+        .split_once(':')
+        .is_some_and(|(scheme, _)| !scheme.is_empty() && !scheme.contains('/'));
+    if url.is_empty() || url.starts_with('#') || url.starts_with("//") || scheme {
+        return None;
+    }
+
+    let (path, fragment) = match url.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (url, None),
+    };
+    let path = path.split_once('?').map_or(path, |(path, _)| path);
+
+    // Resolve against the doc root, where root-relative links may repeat the slug
+    let mut target: Vec<&str> = Vec::new();
+    let relative = match path.strip_prefix('/') {
+        Some(rooted) => rooted
+            .strip_prefix(slug)
+            .and_then(|rest| rest.strip_prefix('/'))
+            .unwrap_or(rooted),
+        None => {
+            target.extend(page.split('/'));
+            target.pop();
+            path
+        }
+    };
+    for segment in relative.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                // Leaves the doc, nothing local to point at
+                target.pop()?;
+            }
+            segment => target.push(segment),
+        }
+    }
+    if target.is_empty() {
+        target.push("index");
+    }
+
+    let mut resolved = target.join("/");
+    if let Some(ext) = ext
+        && !resolved.ends_with(&format!(".{}", ext))
+    {
+        resolved = add_ext(PathBuf::from(&*paths::disk_page(&resolved)), ext)
+            .to_string_lossy()
+            .into_owned();
+    }
+
+    let depth = page.split('/').count() - 1;
+    let mut link = format!("{}{}", "../".repeat(depth), resolved);
+    if let Some(fragment) = fragment {
+        link.push('#');
+        link.push_str(fragment);
+    }
+    Some(link)
+}
+
+// Re-exports for convenience
+pub use nucleo;
+pub use rayon;
+#[cfg(feature = "reqwest")]
+pub use reqwest;
+pub use tokio_util;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("devdocs-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    /// A builder for a manager in `dir` that ignores the environment and the user's
+    /// config dir
+    pub(crate) fn test_builder(dir: impl Into<PathBuf>) -> DevDocsManagerBuilder {
+        let dir = dir.into();
+        DevDocsManager::builder()
+            .isolated()
+            .config_file(dir.join(CONFIG_FILE))
+            .data_dir(dir)
+    }
+
+    pub(crate) fn sample_cached_doc(slug: &str) -> CachedDoc {
+        CachedDoc {
+            doc: Doc {
+                name: slug.to_string(),
+                slug: slug.to_string(),
+                doc_type: "simple".to_string(),
+                links: None,
+                mtime: 0,
+                db_size: 0,
+                attribution: None,
+                alias: None,
+            },
+            formats: None,
+            index: DocIndex {
+                entries: vec![],
+                types: vec![],
+            },
+            cached_at: 0,
+            haystacks: vec![],
+            haystacks_generation: None,
+        }
+    }
+
+    pub(crate) async fn insert_doc(manager: &DevDocsManager, mut doc: CachedDoc) {
+        doc.ensure_haystacks();
+        let slug = doc.doc.slug.clone();
+        manager.cache.write().await.insert(slug, doc);
+        manager.invalidate_queries();
+    }
+
+    pub(crate) fn entry(name: &str, entry_type: &str) -> Entry {
+        Entry {
+            name: name.to_string(),
+            path: PathBuf::from(name.to_lowercase()),
+            entry_type: entry_type.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_manager_creation() {
+        let dir = temp_dir("creation");
+        let manager = DevDocsManager::with_transport(&dir, MockTransport::new()).unwrap();
+        assert_eq!(manager.data_dir, dir);
+        manager.init().await.unwrap();
+        assert!(dir.is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_get_available_docs() {
+        let transport = MockTransport::fixtures();
+        let manager =
+            DevDocsManager::with_transport(temp_dir("available"), transport.clone()).unwrap();
+        manager.init().await.unwrap();
+
+        let docs = manager.get_available_docs().await.unwrap();
+        assert_eq!(docs.len(), 2);
+        // The list is kept rather than downloaded again
+        manager.get_available_docs().await.unwrap();
+        assert_eq!(transport.requests(), ["https://devdocs.io/docs.json"]);
+    }
+
+    #[tokio::test]
+    async fn test_fixtures_install_and_update_without_network() {
+        let transport = MockTransport::fixtures();
+        let dir = temp_dir("fixtures");
+        let manager = DevDocsManager::with_transport(&dir, transport.clone()).unwrap();
+        manager.init().await.unwrap();
+
+        for slug in ["kubernetes", "rust"] {
+            manager
+                .add_doc(slug, Some(Formats::MARKDOWN))
+                .await
+                .unwrap();
+        }
+        assert!(
+            dir.join("kubernetes/concepts/workloads/pods/pod.md")
+                .exists()
+        );
+        let results = manager.search("push", Some(1)).await.unwrap();
+        assert_eq!(results[0].entry.entry.name, "Vec::push");
+
+        // A new upstream version is picked up by the next update
+        let docs = testing::DOCS_JSON
+            .replace("1700000000,\"db_size\":4096", "1800000000,\"db_size\":4096");
+        transport.set("/docs.json", docs);
+        manager.refresh_available_docs().await.unwrap();
+        let report = manager.update_all().await.unwrap();
+        assert_eq!(report.current, ["kubernetes"]);
+        assert_eq!(report.updated.len(), 1);
+
+        transport.remove("/rust/db.json");
+        let cancel = CancellationToken::new();
+        assert!(manager.force_update_doc("rust", &cancel).await.is_err());
+        assert!(manager.is_doc_installed("rust").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_overlay_hides_removed_base_docs() {
+        let base = temp_dir("overlay-base");
+        let overlay = temp_dir("overlay-upper");
+
+        let seed = test_builder(&base).build().unwrap();
+        seed.init().await.unwrap();
+        seed.save_doc_cache("rust", &sample_cached_doc("rust"))
+            .await
+            .unwrap();
+
+        let manager = test_builder(&base).overlay_dir(&overlay).build().unwrap();
+        manager.init().await.unwrap();
+        assert!(manager.is_doc_installed("rust").await.unwrap());
+
+        manager.remove_doc("rust").await.unwrap();
+        assert!(base.join("rust.bin").exists());
+
+        let reloaded = test_builder(&base).overlay_dir(&overlay).build().unwrap();
+        reloaded.init().await.unwrap();
+        assert!(!reloaded.is_doc_installed("rust").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_profiles_keep_docs_and_settings_apart() {
+        let dir = temp_dir("profiles");
+        let work = test_builder(&dir).profile("work").build().unwrap();
+        work.init().await.unwrap();
+        work.save_doc_cache("rust", &sample_cached_doc("rust"))
+            .await
+            .unwrap();
+        work.set_config("clean", "true").await.unwrap();
+        assert_eq!(work.write_dir(), dir.join("profiles/work"));
+
+        let personal = test_builder(&dir).profile("personal").build().unwrap();
+        personal.init().await.unwrap();
+        assert!(!personal.is_doc_installed("rust").await.unwrap());
+        assert!(!personal.settings().await.clean);
+
+        let reopened = test_builder(&dir).profile("work").build().unwrap();
+        reopened.init().await.unwrap();
+        assert!(reopened.is_doc_installed("rust").await.unwrap());
+        assert!(reopened.settings().await.clean);
+
+        for name in ["../work", "a/b", ".hidden"] {
+            assert!(test_builder(&dir).profile(name).build().is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shared_store_is_read_under_the_data_dir() {
+        let shared = temp_dir("shared-store");
+        let user = temp_dir("shared-user");
+
+        let provisioned = test_builder(&shared).build().unwrap();
+        provisioned.init().await.unwrap();
+        provisioned
+            .save_doc_cache("rust", &sample_cached_doc("rust"))
+            .await
+            .unwrap();
+
+        let manager = test_builder(&user).shared_dir(&shared).build().unwrap();
+        manager.init().await.unwrap();
+        assert_eq!(manager.write_dir(), user);
+        assert!(manager.is_doc_installed("rust").await.unwrap());
+
+        manager
+            .save_doc_cache("go", &sample_cached_doc("go"))
+            .await
+            .unwrap();
+        assert!(user.join("go.bin").exists());
+        assert!(!shared.join("go.bin").exists());
+
+        assert!(
+            test_builder(&user)
+                .shared_dir(&shared)
+                .overlay_dir(temp_dir("shared-overlay"))
+                .build()
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_alias_resolution() {
+        let dir = temp_dir("aliases");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut doc = sample_cached_doc("javascript");
+        doc.doc.alias = Some("js".to_string());
+        insert_doc(&manager, doc).await;
+
+        assert_eq!(
+            manager.resolve_installed("js").await.as_deref(),
+            Some("javascript")
+        );
+
+        manager.set_alias("ecma", "javascript").await.unwrap();
+        assert!(manager.is_doc_installed("ecma").await.unwrap());
+        assert!(manager.remove_alias("ecma").await.unwrap());
+        assert!(!manager.is_doc_installed("ecma").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_search_prefers_name_matches() {
+        let manager = test_builder(temp_dir("ranking")).build().unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![
+            entry("HashMap", "collections"),
+            entry("Map", "Iterator"),
+            entry("Vec", "map"),
+        ];
+        insert_doc(&manager, doc).await;
+
+        let results = manager.search("map", None).await.unwrap();
+        let names: Vec<_> = results
+            .iter()
+            .map(|r| r.entry.entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["Map", "HashMap", "Vec"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_session_refines_incrementally() {
+        let manager = test_builder(temp_dir("session")).build().unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![
+            entry("HashMap", "collections"),
+            entry("HashSet", "collections"),
+        ];
+        insert_doc(&manager, doc).await;
+
+        let mut session = manager
+            .search_session(&SearchOptions::default())
+            .await
+            .unwrap();
+
+        session.set_query("hash");
+        assert_eq!(session.results(10).len(), 2);
+
+        session.set_query("hashm");
+        let results = session.results(10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry.entry.name, "HashMap");
+        assert_eq!(results[0].entry.doc_name, "rust");
+        assert_eq!(session.entry_count(), 2);
+
+        // Sessions share the entries until the installed docs change
+        let corpus = manager.corpus().await;
+        assert!(Arc::ptr_eq(&corpus, &session.corpus));
+        let mut doc = sample_cached_doc("go");
+        doc.index.entries = vec![entry("Hash", "hash")];
+        insert_doc(&manager, doc).await;
+        let options = SearchOptions {
+            docs: vec!["go".to_string()],
+            ..Default::default()
+        };
+        let mut session = manager.search_session(&options).await.unwrap();
+        assert!(!Arc::ptr_eq(&corpus, &session.corpus));
+        session.set_query("hash");
+        let results = session.results(10);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry.doc_slug, "go");
+    }
+
+    #[tokio::test]
+    async fn test_normalized_search_ignores_accents_in_queries() {
+        let manager = test_builder(temp_dir("normalize")).build().unwrap();
+        let mut doc = sample_cached_doc("python");
+        doc.index.entries = vec![entry("café", "module"), entry("resume", "function")];
+        insert_doc(&manager, doc).await;
+
+        let options = SearchOptions::default();
+        for (query, name) in [("cafe", "café"), ("café", "café"), ("résumé", "resume")] {
+            let results = manager.search_with(query, &options).await.unwrap();
+            assert_eq!(results.len(), 1, "{}", query);
+            assert_eq!(results[0].entry.entry.name, name);
+
+            let mut session = manager.search_session(&options).await.unwrap();
+            session.set_query(query);
+            let results = session.results(10);
+            assert_eq!(results.len(), 1, "{}", query);
+            assert_eq!(results[0].entry.entry.name, name);
+        }
+
+        let exact = SearchOptions {
+            normalize: false,
+            ..Default::default()
+        };
+        let results = manager.search_with("résumé", &exact).await.unwrap();
+        assert!(results.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_session_ranks_like_search() {
+        let manager = test_builder(temp_dir("session_rank")).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![
+            entry("HashMap", "collections"),
+            entry("Hash", "traits"),
+            entry("hash_map::Entry", "collections"),
+            // Another name of the HashMap page
+            Entry {
+                path: PathBuf::from("hashmap"),
+                ..entry("Hash Map", "collections")
+            },
+        ];
+        insert_doc(&manager, doc).await;
+        let mut doc = sample_cached_doc("go");
+        doc.index.entries = vec![entry("hash", "packages")];
+        insert_doc(&manager, doc).await;
+        manager.set_config("weight.go", "3").await.unwrap();
+
+        let scores = |results: Vec<SearchResult>| {
+            results
+                .into_iter()
+                .map(|r| (r.entry.doc_slug, r.entry.entry.name, r.score))
+                .collect::<Vec<_>>()
+        };
+        let cases = [
+            SearchOptions::default(),
+            SearchOptions {
+                exact: true,
+                ..Default::default()
+            },
+            SearchOptions {
+                types: vec!["collections".to_string()],
+                ..Default::default()
+            },
+            SearchOptions {
+                collapse_duplicates: true,
+                ..Default::default()
+            },
+            SearchOptions {
+                max_per_doc: Some(2),
+                ..Default::default()
+            },
+            SearchOptions {
+                collapse_duplicates: true,
+                max_per_doc: Some(2),
+                ..Default::default()
+            },
+        ];
+        for options in cases {
+            let mut session = manager.search_session(&options).await.unwrap();
+            session.set_query("hash");
+            let expected = manager.search_with("hash", &options).await.unwrap();
+            assert_eq!(scores(session.results(10)), scores(expected));
+        }
+
+        // The weighted doc comes first, and the minimum score drops what falls short
+        let mut session = manager
+            .search_session(&SearchOptions::default())
+            .await
+            .unwrap();
+        session.set_query("hash");
+        let results = session.results(10);
+        assert_eq!(results[0].entry.doc_slug, "go");
+        let min_score = results[1].score + 1;
+        let options = SearchOptions {
+            min_score,
+            ..Default::default()
+        };
+        let mut session = manager.search_session(&options).await.unwrap();
+        session.set_query("hash");
+        let results = session.results(10);
+        assert_eq!(results.len(), 1);
+        assert!(results[0].score >= min_score);
+    }
+
+    #[tokio::test]
+    async fn test_search_drops_non_matches_and_browses_empty_query() {
+        let manager = test_builder(temp_dir("threshold")).build().unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Option", "enum")];
+        insert_doc(&manager, doc).await;
+
+        let results = manager.search("vec", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+
+        let results = manager.search("", None).await.unwrap();
+        let names: Vec<_> = results
+            .iter()
+            .map(|r| r.entry.entry.name.as_str())
+            .collect();
+        assert_eq!(names, ["Option", "Vec"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_many_answers_each_query() {
+        let manager = test_builder(temp_dir("search-many")).build().unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Option", "enum")];
+        insert_doc(&manager, doc).await;
+
+        let options = SearchOptions::default();
+        let results = manager
+            .search_many(&["Option", "vec", "nothing", ""], &options)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0][0].entry.entry.name, "Option");
+        assert_eq!(results[1][0].entry.entry.name, "Vec");
+        assert!(results[2].is_empty());
+        assert_eq!(results[3].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_searches_hit_the_query_cache() {
+        let manager = test_builder(temp_dir("query-cache")).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct")];
+        insert_doc(&manager, doc).await;
+
+        let options = SearchOptions::default();
+        let first = manager.search_with("vec", &options).await.unwrap();
+        let second = manager.search_with("vec", &options).await.unwrap();
+        assert_eq!(first.len(), second.len());
+        let stats = manager.query_cache_stats();
+        assert_eq!((stats.hits, stats.misses, stats.entries), (1, 1, 1));
+
+        manager.record_view("rust", "vec").await.unwrap();
+        assert_eq!(manager.query_cache_stats().entries, 0);
+        manager.search_with("vec", &options).await.unwrap();
+        assert_eq!(manager.query_cache_stats().misses, 2);
+    }
+
+    #[tokio::test]
+    async fn test_doc_index_is_exposed() {
+        let manager = test_builder(temp_dir("doc-index")).build().unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Option", "enum")];
+        doc.index.types = vec![EntryType {
+            name: "struct".to_string(),
+            count: 1,
+            slug: "struct".to_string(),
+        }];
+        insert_doc(&manager, doc).await;
+
+        let names: Vec<String> = manager
+            .iter_entries("rust")
+            .await
+            .unwrap()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["Vec", "Option"]);
+        assert_eq!(manager.entry_types("rust").await.unwrap()[0].name, "struct");
+        assert!(manager.get_doc_index("python").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_docs_are_left_out_of_searches() {
+        let dir = temp_dir("disabled");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut rust = sample_cached_doc("rust");
+        rust.index.entries = vec![entry("Array", "primitive")];
+        insert_doc(&manager, rust).await;
+        let mut ruby = sample_cached_doc("ruby");
+        ruby.index.entries = vec![entry("Array", "class")];
+        insert_doc(&manager, ruby).await;
+
+        manager.set_doc_enabled("ruby", false).await.unwrap();
+        let results = manager.search("array", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].entry.doc_slug, "rust");
+
+        // Naming a disabled doc still searches it
+        let options = SearchOptions {
+            docs: vec!["ruby".to_string()],
+            ..Default::default()
+        };
+        let results = manager.search_with("array", &options).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, "ruby");
+
+        // The flag survives a restart
+        let reopened = test_builder(&dir).build().unwrap();
+        reopened.init().await.unwrap();
+        assert!(!reopened.is_doc_enabled("ruby").await);
+        assert_eq!(reopened.disabled_docs().await, ["ruby"]);
+    }
+
+    #[test]
+    fn test_project_scope_is_found_in_ancestors() {
+        let root = temp_dir("project-scope");
+        let nested = root.join("app/models");
+        std::fs::create_dir_all(&nested).unwrap();
+        assert!(find_project_scope(&nested).unwrap().is_none());
+
+        std::fs::write(
+            root.join(PROJECT_SCOPE_FILE),
+            "# Rails app\nruby\n\nrails  # framework\n",
+        )
+        .unwrap();
+        let (file, docs) = find_project_scope(&nested).unwrap().unwrap();
+        assert_eq!(file, root.join(PROJECT_SCOPE_FILE));
+        assert_eq!(docs, ["ruby", "rails"]);
+    }
+
+    #[tokio::test]
+    async fn test_doc_weights_reorder_results() {
+        let dir = temp_dir("weights");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut rust = sample_cached_doc("rust");
+        rust.index.entries = vec![entry("Array", "primitive")];
+        insert_doc(&manager, rust).await;
+        let mut ruby = sample_cached_doc("ruby");
+        ruby.index.entries = vec![entry("Array", "class")];
+        insert_doc(&manager, ruby).await;
+
+        manager.set_config("weight.ruby", "2").await.unwrap();
+        let results = manager.search("array", None).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, "ruby");
+        assert!(results[0].score > results[1].score);
+
+        assert!(manager.set_config("weight.ruby", "-1").await.is_err());
+        assert!(manager.set_config("colour", "blue").await.is_err());
+
+        let reopened = test_builder(&dir).build().unwrap();
+        reopened.init().await.unwrap();
+        let settings = reopened.settings().await;
+        assert_eq!(settings.get("weight.ruby").unwrap().as_deref(), Some("2"));
+        assert!(reopened.unset_config("weight.ruby").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_abbreviations_match_word_initials() {
+        assert_eq!(initials("getBoundingClientRect"), "gbcr");
+        assert_eq!(initials("HTMLElement.click"), "hec");
+        assert_eq!(initials("std::collections::HashMap"), "schm");
+
+        let manager = test_builder(temp_dir("acronym")).build().unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![
+            entry("hmac_sha256", "function"),
+            entry("std::collections::HashMap", "struct"),
+            entry("Element.getBoundingClientRect", "method"),
+        ];
+        insert_doc(&manager, doc).await;
+
+        let results = manager.search("hm", None).await.unwrap();
+        assert_eq!(results[0].entry.entry.name, "std::collections::HashMap");
+        let results = manager.search("gbc", None).await.unwrap();
+        assert_eq!(results[0].entry.entry.name, "Element.getBoundingClientRect");
+    }
+
+    #[tokio::test]
+    async fn test_misspelled_queries_get_suggestions() {
+        assert_eq!(edit_distance("reciever", "receiver", 2), Some(1));
+        assert_eq!(edit_distance("sendr", "sender", 2), Some(1));
+        assert_eq!(edit_distance("vec", "option", 2), None);
+
+        let manager = test_builder(temp_dir("suggest")).build().unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![
+            entry("mpsc::Receiver", "struct"),
+            entry("mpsc::Receiver::recv", "method"),
+            entry("mpsc::Sender", "struct"),
+        ];
+        insert_doc(&manager, doc).await;
+
+        let options = SearchOptions::default();
+        let response = manager.search_response("reciever", &options).await.unwrap();
+        assert_eq!(response.suggestions, ["receiver"]);
+        let response = manager
+            .search_response("mpsc::Receiver", &options)
+            .await
+            .unwrap();
+        assert!(response.suggestions.is_empty());
+        let suggestions = manager.suggest("mspc sendr", &options).await.unwrap();
+        assert_eq!(suggestions, ["mpsc sender"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_response_pages_through_results() {
+        let manager = test_builder(temp_dir("paging")).build().unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = (0..5)
+            .map(|i| entry(&format!("Vec{}", i), "struct"))
+            .collect();
+        insert_doc(&manager, doc).await;
+
+        let mut options = SearchOptions {
+            limit: 2,
+            ..Default::default()
+        };
+        let first = manager.search_response("vec", &options).await.unwrap();
+        assert_eq!(first.results.len(), 2);
+        assert_eq!(first.total_matches, 5);
+        assert!(first.truncated);
+
+        options.offset = 4;
+        let last = manager.search_response("vec", &options).await.unwrap();
+        assert_eq!(last.results.len(), 1);
+        assert!(!last.truncated);
+        let seen: HashSet<String> = first
+            .results
+            .iter()
+            .chain(&last.results)
+            .map(|r| r.entry.entry.name.clone())
+            .collect();
+        assert_eq!(seen.len(), 3);
+
+        options.offset = 0;
+        let browse = manager.search_response("", &options).await.unwrap();
+        assert_eq!((browse.results.len(), browse.total_matches), (2, 5));
+    }
+
+    #[tokio::test]
+    async fn test_search_stream_yields_the_best_results() {
+        let manager = test_builder(temp_dir("stream")).build().unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = (0..STREAM_CHUNK * 2)
+            .map(|i| entry(&format!("item{}", i), "struct"))
+            .chain([entry("Vec", "struct")])
+            .collect();
+        insert_doc(&manager, doc).await;
+
+        let options = SearchOptions {
+            limit: 3,
+            ..Default::default()
+        };
+        let streamed: Vec<SearchResult> = manager
+            .search_stream("vec", &options)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed[0].entry.entry.name, "Vec");
+
+        let streamed: Vec<SearchResult> = manager
+            .search_stream("item", &options)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        let best = manager.search_with("item", &options).await.unwrap();
+        for result in &best {
+            assert!(
+                streamed
+                    .iter()
+                    .any(|s| s.entry.entry.name == result.entry.entry.name)
+            );
+        }
+
+        // The cap per doc holds across chunks
+        let capped = SearchOptions {
+            limit: 10,
+            max_per_doc: Some(2),
+            ..Default::default()
+        };
+        let streamed: Vec<SearchResult> = manager
+            .search_stream("item", &capped)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(streamed.len(), 2);
+
+        // A doc reinstalled with fewer entries mid-stream ends its part of the stream
+        let mut stream = Box::pin(manager.search_stream("item", &options).await.unwrap());
+        assert!(stream.next().await.is_some());
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("item", "struct")];
+        insert_doc(&manager, doc).await;
+        let _rest: Vec<SearchResult> = stream.collect().await;
+
+        // Duplicates are collapsed across chunks, not just within each one
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = [entry("vec", "struct")]
+            .into_iter()
+            .chain((0..STREAM_CHUNK).map(|i| entry(&format!("item{}", i), "struct")))
+            .chain([entry("Vec", "struct")])
+            .collect();
+        insert_doc(&manager, doc).await;
+        let collapsed = SearchOptions {
+            collapse_duplicates: true,
+            ..Default::default()
+        };
+        let streamed: Vec<SearchResult> = manager
+            .search_stream("vec", &collapsed)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(streamed.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_caps_results_per_doc() {
+        let manager = test_builder(temp_dir("per-doc")).build().unwrap();
+
+        let mut rust = sample_cached_doc("rust");
+        rust.index.entries = vec![entry("Array", "primitive"), entry("ArrayVec", "struct")];
+        insert_doc(&manager, rust).await;
+
+        let mut ruby = sample_cached_doc("ruby");
+        ruby.index.entries = vec![entry("Array", "class")];
+        insert_doc(&manager, ruby).await;
+
+        let options = SearchOptions {
+            max_per_doc: Some(1),
+            ..Default::default()
+        };
+        let results = manager.search_with("array", &options).await.unwrap();
+        assert_eq!(results.len(), 2);
+
+        let groups = group_results(results, GroupBy::Doc);
+        let sizes: Vec<(&str, usize)> = groups.iter().map(|(k, g)| (k.as_str(), g.len())).collect();
+        assert_eq!(sizes.len(), 2);
+        assert!(sizes.contains(&("rust", 1)) && sizes.contains(&("ruby", 1)));
+    }
+
+    #[tokio::test]
+    async fn test_recent_pages_lead_browsing() {
+        let dir = temp_dir("recent");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Option", "enum"), entry("Vec", "struct")];
+        insert_doc(&manager, doc).await;
+
+        let (slug, page) = manager.page_for_file(&dir.join("rust/vec.md")).unwrap();
+        assert_eq!((slug.as_str(), page.as_str()), ("rust", "vec"));
+        manager.record_view(&slug, &page).await.unwrap();
+
+        let results = manager.search("", None).await.unwrap();
+        assert_eq!(results[0].entry.entry.name, "Vec");
+        assert_eq!(manager.recent_pages(10).await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_exclusive_lock_blocks_other_managers() {
+        let dir = temp_dir("locking");
+        let first = test_builder(&dir).build().unwrap();
+        first.init().await.unwrap();
+        let second = test_builder(&dir).build().unwrap();
+
+        let lock = first.lock_exclusive().unwrap();
+        // Operations of the same manager share the lock
+        assert!(first.lock_exclusive().is_ok());
+
+        let err = second.lock_exclusive().unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DevDocsError>(),
+            Some(DevDocsError::Locked(_))
+        ));
+
+        drop(lock);
+        assert!(second.lock_exclusive().is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_state_changes_of_other_managers_are_kept() {
+        let dir = temp_dir("state-lock");
+        let first = test_builder(&dir).build().unwrap();
+        first.init().await.unwrap();
+        let second = test_builder(&dir).build().unwrap();
+        second.init().await.unwrap();
+
+        // Each starts from what the other wrote rather than what it loaded
+        first.set_alias("py", "python").await.unwrap();
+        second.set_alias("js", "javascript").await.unwrap();
+        first.set_config("clean", "true").await.unwrap();
+        second.set_config("theme", "dark").await.unwrap();
+        let (a, b) = tokio::join!(
+            first.record_search("vec", None),
+            second.record_search("map", Some("rust/std/collections/hash_map"))
+        );
+        a.unwrap();
+        b.unwrap();
+
+        let reopened = test_builder(&dir).build().unwrap();
+        reopened.init().await.unwrap();
+        let aliases = reopened.aliases().await;
+        assert_eq!(aliases.len(), 2);
+        let settings = reopened.settings().await;
+        assert_eq!(settings.get("clean").unwrap().as_deref(), Some("true"));
+        assert_eq!(settings.get("theme").unwrap().as_deref(), Some("dark"));
+        let history = reopened.search_history().await;
+        assert_eq!(history.len(), 2);
+        let map = history.iter().find(|h| h.query == "map").unwrap();
+        assert_eq!(map.chosen.as_deref(), Some("rust/std/collections/hash_map"));
+
+        // The state lock waits instead of failing like the cache lock
+        let lock = first.lock_state().await.unwrap();
+        let waiting = tokio::spawn(async move {
+            second.set_alias("rs", "rust").await.unwrap();
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(lock);
+        waiting.await.unwrap();
+        assert_eq!(
+            reopened
+                .read_state::<HashMap<String, String>>("aliases.json")
+                .await
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn test_events_are_streamed_to_subscribers() {
+        use futures::StreamExt;
+
+        let manager = test_builder(temp_dir("events")).build().unwrap();
+        manager.init().await.unwrap();
+        insert_doc(&manager, sample_cached_doc("rust")).await;
+
+        let events = manager.subscribe();
+        manager.remove_doc("rust").await.unwrap();
+
+        futures::pin_mut!(events);
+        assert_eq!(
+            events.next().await,
+            Some(ManagerEvent::DocRemoved {
+                slug: "rust".to_string()
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_format_installed_is_a_superset_check() {
+        let manager = test_builder(temp_dir("formats")).build().unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML | Formats::MARKDOWN);
+        insert_doc(&manager, doc).await;
+        insert_doc(&manager, sample_cached_doc("rustc")).await;
+
+        assert!(manager.is_format_installed("rust", None).await.unwrap());
+        assert!(
+            manager
+                .is_format_installed("rust", Some(Formats::MARKDOWN))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !manager
+                .is_format_installed("rustc", Some(Formats::HTML))
+                .await
+                .unwrap()
+        );
+        assert!(!manager.is_format_installed("rus", None).await.unwrap());
+    }
+
+    #[test]
+    fn test_diff_pages_counts_changes() {
+        let old = HashMap::from([
+            ("a".to_string(), page_hash("one")),
+            ("b".to_string(), page_hash("two")),
+            ("c".to_string(), page_hash("three")),
+        ]);
+        let new = HashMap::from([
+            ("a".to_string(), page_hash("one")),
+            ("b".to_string(), page_hash("two, revised")),
+            ("d".to_string(), page_hash("four")),
+        ]);
+
+        assert_eq!(
+            diff_pages(&old, &new),
+            PageChanges {
+                added: 1,
+                modified: 1,
+                removed: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn test_doc_changes_between_versions() {
+        let mut old = sample_cached_doc("rust");
+        old.index.entries = vec![
+            entry("Vec", "struct"),
+            entry("HashMap", "struct"),
+            entry("Rc", "struct"),
+        ];
+        let mut new = sample_cached_doc("rust");
+        new.doc.mtime = 1;
+        new.index.entries = vec![
+            entry("Vec", "struct"),
+            Entry {
+                path: PathBuf::from("collections/hashmap"),
+                ..entry("HashMap", "struct")
+            },
+            entry("Arc", "struct"),
+        ];
+
+        let changes = DocChanges::between(&old, &new);
+        assert_eq!(changes.to_mtime, 1);
+        assert_eq!(changes.added, vec![entry("Arc", "struct")]);
+        assert_eq!(changes.removed, vec![entry("Rc", "struct")]);
+        assert_eq!(changes.moved.len(), 1);
+        assert_eq!(changes.moved[0].name, "HashMap");
+        assert_eq!(changes.moved[0].to, PathBuf::from("collections/hashmap"));
+    }
+
+    #[tokio::test]
+    async fn test_plan_remove_lists_files_without_deleting() {
+        let dir = temp_dir("plan-remove");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let doc = sample_cached_doc("rust");
+        manager.save_doc_cache("rust", &doc).await.unwrap();
+        insert_doc(&manager, doc).await;
+
+        let plan = manager.plan_remove(&["rust".to_string()]).await.unwrap();
+        assert!(plan.downloads.is_empty());
+        assert!(plan.removals.contains(&dir.join("rust.bin")));
+        assert!(plan.removals.iter().all(|path| path.exists()));
+        assert!(manager.is_doc_installed("rust").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_plan_add_totals_download_size() {
+        let manager = test_builder(temp_dir("plan-add")).build().unwrap();
+        insert_doc(&manager, sample_cached_doc("rust")).await;
+        let available = ["rust", "go", "node"].map(|slug| Doc {
+            db_size: 1000,
+            ..sample_cached_doc(slug).doc
+        });
+        *manager.available_docs.write().await = Some((available.to_vec(), current_timestamp()));
+
+        let slugs = ["rust", "go", "node", "go"].map(String::from);
+        let plan = manager.plan_add(&slugs, None).await.unwrap();
+        let planned: Vec<&str> = plan.downloads.iter().map(|d| d.slug.as_str()).collect();
+        assert_eq!(planned, ["go", "node"]);
+        assert_eq!(plan.download_bytes(), 2000);
+    }
+
+    #[tokio::test]
+    async fn test_stale_haystacks_are_recomputed() {
+        let dir = temp_dir("stale_haystacks");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct")];
+        doc.ensure_haystacks();
+        manager.save_doc_cache("rust", &doc).await.unwrap();
+
+        // A newer index with as many entries, next to the haystacks of the old one
+        doc.doc.mtime += 1;
+        doc.index.entries = vec![entry("HashMap", "struct")];
+        let data = bitcode::serialize::<CachedDoc>(&doc).unwrap();
+        std::fs::write(dir.join("rust.bin"), data).unwrap();
+
+        let reopened = test_builder(&dir).build().unwrap();
+        reopened.init().await.unwrap();
+        let results = reopened.search("hashmap", None).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].score, {
+            let fresh = test_builder(temp_dir("stale_haystacks_fresh"))
+                .build()
+                .unwrap();
+            insert_doc(&fresh, doc).await;
+            fresh.search("hashmap", None).await.unwrap()[0].score
+        });
+    }
+
+    #[tokio::test]
+    async fn test_quota_evicts_least_recently_used() {
+        let dir = temp_dir("quota");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        for (slug, cached_at) in [("rust", 2), ("go", 1)] {
+            let doc = CachedDoc {
+                cached_at,
+                ..sample_cached_doc(slug)
+            };
+            manager.save_doc_cache(slug, &doc).await.unwrap();
+            insert_doc(&manager, doc).await;
+        }
+        let usage = manager.disk_usage().await.unwrap();
+        assert_eq!(usage.docs.len(), 2);
+
+        let limited = test_builder(&dir).quota(usage.used + 10).build().unwrap();
+        limited.init().await.unwrap();
+        let err = limited.make_room("node", 100).await.unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<DevDocsError>(),
+            Some(DevDocsError::QuotaExceeded { .. })
+        ));
+
+        let evicting = test_builder(&dir)
+            .quota(usage.used + 10)
+            .evict_lru(true)
+            .build()
+            .unwrap();
+        evicting.init().await.unwrap();
+        evicting.make_room("node", 11).await.unwrap();
+        assert!(!evicting.is_doc_installed("go").await.unwrap());
+        assert!(evicting.is_doc_installed("rust").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_custom_transport_serves_requests() {
+        let transport = MockTransport::new().respond(
+            "/docs.json",
+            r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+        );
+        let manager = test_builder(temp_dir("transport"))
+            .base_url("http://docs.test")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let docs = manager.refresh_available_docs().await.unwrap();
+        assert_eq!(docs.len(), 1);
+        assert_eq!(docs[0].slug, "rust");
+    }
+
+    #[tokio::test]
+    async fn test_stats_count_docs_and_searches() {
+        let dir = temp_dir("stats");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Vec::push", "method")];
+        manager.save_doc_cache("rust", &doc).await.unwrap();
+        insert_doc(&manager, doc).await;
+
+        manager.search("vec", None).await.unwrap();
+        manager.search("vec", None).await.unwrap();
+        manager
+            .search_many(&["push", "vec"], &SearchOptions::default())
+            .await
+            .unwrap();
+
+        let stats = manager.stats().await.unwrap();
+        assert_eq!((stats.docs_installed, stats.entries_indexed), (1, 2));
+        assert!(stats.bytes_on_disk > 0);
+        assert_eq!(stats.searches, 4);
+        assert_eq!((stats.cache_hits, stats.cache_misses), (1, 1));
+        assert_eq!(stats.downloads, 0);
+        assert!(
+            stats
+                .to_prometheus()
+                .contains("# HELP devdocs_entries_indexed Searchable entries of the installed docs\n# TYPE devdocs_entries_indexed gauge\ndevdocs_entries_indexed 2\n")
+        );
+        // Latency is only exposed as a histogram
+        assert!(
+            !stats
+                .to_prometheus()
+                .contains("devdocs_search_latency_seconds")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_download_all_reports_failed_docs() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10},
+                    {"name":"Go","slug":"go","type":"go","mtime":1,"db_size":5}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"Vec","path":"vec","type":"struct"}],
+                    "types":[{"name":"struct","count":1,"slug":"struct"}]}"#,
+            )
+            .respond("/documents/rust/db.json", r#"{"vec":"<h1>Vec</h1>"}"#);
+        let manager = test_builder(temp_dir("download-all"))
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let report = manager.download_all(Formats::MARKDOWN).await.unwrap();
+        assert_eq!(report.installed, ["rust"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "go");
+        assert_eq!(report.outcome(), BatchOutcome::Partial);
+        assert!(manager.is_doc_installed("rust").await.unwrap());
+        assert_eq!(manager.stats().await.unwrap().downloads, 1);
+
+        // Only the failed doc is retried
+        let report = manager.download_all(Formats::MARKDOWN).await.unwrap();
+        assert!(report.installed.is_empty());
+        assert_eq!(report.outcome(), BatchOutcome::Failed);
+        assert_eq!(DownloadReport::default().outcome(), BatchOutcome::Ok);
+    }
+
+    #[tokio::test]
+    async fn test_theme_comes_from_builder_or_settings() {
+        let dir = temp_dir("theme");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        assert_eq!(manager.theme().await, Theme::Auto);
+        assert!(manager.set_config("theme", "sepia").await.is_err());
+        manager.set_config("theme", "dark").await.unwrap();
+        assert_eq!(manager.theme().await, Theme::Dark);
+
+        let forced = test_builder(&dir).theme(Theme::Light).build().unwrap();
+        forced.init().await.unwrap();
+        assert_eq!(
+            forced.settings().await.get("theme").unwrap().unwrap(),
+            "dark"
+        );
+        assert_eq!(forced.theme().await, Theme::Light);
+
+        assert_eq!(manager.mathjax().await, None);
+        assert!(
+            manager
+                .set_config("mathjax", dir.join("missing.js").to_str().unwrap())
+                .await
+                .is_err()
+        );
+        let script = dir.join("tex-mml-chtml.js");
+        std::fs::write(&script, "").unwrap();
+        manager
+            .set_config("mathjax", script.to_str().unwrap())
+            .await
+            .unwrap();
+        assert_eq!(manager.mathjax().await.as_deref(), script.to_str());
+        assert!(manager.unset_config("mathjax").await.unwrap());
+
+        let page = Theme::Dark.apply(
+            "<h1>Vec</h1>",
+            "rust/<vec>",
+            Some("file:///docs/rust/"),
+            None,
+        );
+        assert!(page.contains("<title>rust/&lt;vec&gt;</title>"));
+        assert!(page.contains(r#"<base href="file:///docs/rust/">"#));
+        assert!(page.contains("color-scheme: dark"));
+        assert!(!page.contains("prefers-color-scheme"));
+        assert!(page.contains("<body>\n<h1>Vec</h1>\n</body>"));
+    }
+
+    #[tokio::test]
+    async fn test_render_doc_adds_formats_without_downloading() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"Vec","path":"std/vec","type":"struct"},
+                    {"name":"Box","path":"std/boxed","type":"struct"}],
+                    "types":[{"name":"struct","count":2,"slug":"struct"}]}"#,
+            )
+            .respond(
+                "/documents/rust/db.json",
+                serde_json::json!({
+                    "std/vec": r#"<h1>Vec</h1><p>A <strong>growable</strong> array, see <a href="boxed#new">Box</a>.</p>"#,
+                    "std/boxed": "<h1>Box</h1>",
+                }).to_string(),
+            );
+        let dir = temp_dir("render-doc");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        manager.add_doc("rust", Some(Formats::HTML)).await.unwrap();
+
+        let pages = manager
+            .render_doc("rust", Formats::MARKDOWN | Formats::TEXT)
+            .await
+            .unwrap();
+        assert_eq!(pages, 2);
+        let md = std::fs::read_to_string(dir.join("rust/std/vec.md")).unwrap();
+        assert!(md.contains("[Box](../std/boxed.md#new)"), "{}", md);
+        let text = std::fs::read_to_string(dir.join("rust/std/vec.txt")).unwrap();
+        assert!(text.contains("Vec\n"), "{}", text);
+        assert!(text.contains("A growable array, see Box."), "{}", text);
+        assert!(
+            manager
+                .is_format_installed("rust", Some(Formats::all()))
+                .await
+                .unwrap()
+        );
+
+        // Without HTML there's nothing to render from
+        manager.remove_formats("rust", Formats::HTML).await.unwrap();
+        assert!(manager.render_doc("rust", Formats::HTML).await.is_err());
+        assert!(matches!(
+            manager
+                .render_doc("go", Formats::HTML)
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(DevDocsError::DocNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_markdown_lays_out_tables_and_definition_lists() {
+        let long = "Hint for expected file type in file upload controls, ".repeat(4);
+        let page = format!(
+            r#"<h1>Attributes</h1>
+            <table>
+              <tr><th>Attribute</th><th>Count</th><th>Description</th></tr>
+              <tr><td><code>accept</code></td><td>1,024</td><td>{long}</td></tr>
+              <tr><td>alt</td><td>7</td><td>Alt text <table><tr><td>nested</td></tr></table></td></tr>
+            </table>
+            <dl>
+              <dt>autocomplete</dt><dd><p>Hint for form autofill.</p><p>Applies to forms.</p></dd>
+              <dt>dir</dt><dd>Text direction &amp; layout</dd>
+            </dl>"#
+        );
+        let md = render_page("html", "attributes", &page, Formats::MARKDOWN);
+
+        let table: Vec<&str> = md.lines().filter(|l| l.starts_with('|')).collect();
+        assert!(table.len() > 4, "{}", md);
+        assert!(
+            table
+                .iter()
+                .all(|l| l.chars().count() == table[0].chars().count())
+        );
+        assert!(table.iter().all(|l| l.chars().count() <= 100));
+        assert!(table[0].starts_with("| Attribute | Count | Description"));
+        assert!(table[1].contains("| ----: |"));
+        assert!(table[2].starts_with("| accept    | 1,024 | Hint for expected"));
+        assert!(
+            table
+                .iter()
+                .any(|l| l.starts_with("| alt       |     7 | Alt text nested"))
+        );
+
+        assert!(md.contains(
+            "**autocomplete**\n:   Hint for form autofill.\n\n    Applies to forms.\n\n**dir**\n:   Text direction & layout"
+        ));
+        assert!(md.contains("Attributes"));
+        assert!(!md.contains("DEVDOCSBLOCK"));
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_find_cache_problems() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10},
+                    {"name":"Go","slug":"go","type":"go","mtime":1,"db_size":5}]"#,
+            )
+            .respond("/documents/go/index.json", r#"{"entries":[],"types":[]}"#);
+        let dir = temp_dir("doctor");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let mut rust = sample_cached_doc("rust");
+        rust.formats = Some(Formats::HTML);
+        manager.save_doc_cache("rust", &rust).await.unwrap();
+        insert_doc(&manager, rust).await;
+        std::fs::create_dir_all(dir.join("rust")).unwrap();
+        std::fs::write(dir.join("rust/index.md"), "# Rust").unwrap();
+        std::fs::write(dir.join("broken.bin"), b"not bitcode").unwrap();
+        std::fs::create_dir_all(dir.join("gone")).unwrap();
+        std::fs::create_dir_all(dir.join(HAYSTACKS_DIR)).unwrap();
+        std::fs::write(dir.join(HAYSTACKS_DIR).join("gone.bin"), b"").unwrap();
+        std::fs::create_dir_all(dir.join(STAGING_DIR).join("go")).unwrap();
+
+        let checks = manager.diagnostics().await;
+        let status = |name: &str| {
+            let check = checks.iter().find(|c| c.check == name).unwrap();
+            (check.status, check.message.clone())
+        };
+        assert_eq!(status("DevDocs site").0, CheckStatus::Ok);
+        assert_eq!(
+            status("documents host"),
+            (CheckStatus::Ok, status("documents host").1)
+        );
+        assert!(status("documents host").1.contains("go"));
+        assert_eq!(status("data dir").0, CheckStatus::Ok);
+        assert_eq!(
+            status("cache format"),
+            (CheckStatus::Error, "can't load broken".to_string())
+        );
+        assert_eq!(
+            status("orphaned files"),
+            (
+                CheckStatus::Warning,
+                "gone, haystacks/gone.bin belong to no installed doc".to_string()
+            )
+        );
+        assert_eq!(status("rendered pages").0, CheckStatus::Warning);
+        assert_eq!(status("staging").0, CheckStatus::Warning);
+        assert!(
+            checks
+                .iter()
+                .all(|c| (c.status == CheckStatus::Ok) == c.fix.is_none())
+        );
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        assert_eq!(diagnose_server(addr).await.status, CheckStatus::Warning);
+    }
+
+    #[cfg(feature = "reqwest")]
+    #[test]
+    fn test_builder_rejects_unreadable_certificate() {
+        let dir = temp_dir("certificates");
+        let missing = test_builder(&dir)
+            .add_root_certificate(dir.join("missing.pem"))
+            .build();
+        assert!(missing.is_err());
+
+        let proxied = test_builder(&dir)
+            .proxy("socks5://127.0.0.1:1080")
+            .proxy_auth("user", "secret")
+            .build();
+        assert!(proxied.is_ok());
+        assert_eq!(
+            split_credentials("user:pass:word"),
+            ("user".to_string(), "pass:word".to_string())
+        );
+        assert_eq!(
+            split_credentials("user"),
+            ("user".to_string(), String::new())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_formats_keeps_other_format() {
+        let dir = temp_dir("remove-formats");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML | Formats::MARKDOWN);
+        insert_doc(&manager, doc).await;
+        let pages = dir.join("rust").join("std");
+        std::fs::create_dir_all(&pages).unwrap();
+        std::fs::write(pages.join("vec.md"), "# Vec").unwrap();
+        std::fs::write(pages.join("vec.html"), "<h1>Vec</h1>").unwrap();
+
+        manager.remove_formats("rust", Formats::HTML).await.unwrap();
+        assert!(pages.join("vec.md").exists());
+        assert!(!pages.join("vec.html").exists());
+        assert!(
+            manager
+                .is_format_installed("rust", Some(Formats::MARKDOWN))
+                .await
+                .unwrap()
+        );
+        assert!(
+            !manager
+                .is_format_installed("rust", Some(Formats::HTML))
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lazy_pages_are_rendered_on_demand() {
+        let dir = temp_dir("lazy");
+        let manager = test_builder(&dir).lazy_render(true).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.formats = Some(Formats::HTML);
+        insert_doc(&manager, doc).await;
+
+        let content = HashMap::from([(
+            "std/vec".to_string(),
+            r#"<a href="option">Option</a>"#.to_string(),
+        )]);
+        std::fs::create_dir_all(dir.join(CONTENT_DIR)).unwrap();
+        std::fs::write(
+            manager.content_store_path("rust"),
+            compress_content(&content).unwrap(),
+        )
+        .unwrap();
+
+        let file = manager
+            .page_file("rust", "std/vec", Formats::HTML)
+            .await
+            .unwrap();
+        assert_eq!(file, dir.join("rust/std/vec.html"));
+        assert!(
+            std::fs::read_to_string(file)
+                .unwrap()
+                .contains("../std/option.html")
+        );
+        assert!(manager.get_page_content("rust", "std/nope").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_updates_keep_docs_lazily_rendered() {
+        let dir = temp_dir("lazy-update");
+        let manager = test_builder(&dir)
+            .lazy_render(true)
+            .transport(MockTransport::fixtures())
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("kubernetes", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        let store = manager.content_store_path("kubernetes");
+        assert!(store.exists());
+        assert!(
+            manager
+                .get_page_content("kubernetes", "concepts/workloads/pods/pod")
+                .await
+                .unwrap()
+                .contains("<h1")
+        );
+
+        // Without lazy rendering asked for, the doc is still updated into its store
+        let reopened = DevDocsManager::with_transport(&dir, MockTransport::fixtures()).unwrap();
+        reopened.init().await.unwrap();
+        reopened
+            .force_update_doc("kubernetes", &CancellationToken::new())
+            .await
+            .unwrap();
+        assert!(store.exists());
+        assert!(!dir.join("kubernetes").exists());
+
+        reopened.remove_doc("kubernetes").await.unwrap();
+        reopened
+            .add_doc("kubernetes", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        assert!(!store.exists());
+        assert!(
+            dir.join("kubernetes/concepts/workloads/pods/pod.md")
+                .exists()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_offline_assets_are_downloaded_and_relinked() {
+        let dir = temp_dir("assets");
+        let page = concat!(
+            r#"<img src="https://img.test/logo.png?v=2">"#,
+            r#"<a href="https://img.test/logo.png?v=2">full size</a>"#,
+            r#"<img src='//img.test/missing.gif'>"#,
+        );
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Guide","slug":"guide","type":"simple","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/guide/index.json",
+                r#"{"entries":[{"name":"Intro","path":"guide/intro","type":"guide"}],
+                    "types":[{"name":"guide","count":1,"slug":"guide"}]}"#,
+            )
+            .respond(
+                "/guide/db.json",
+                serde_json::json!({ "guide/intro": page }).to_string(),
+            )
+            .respond("/logo.png?v=2", "PNG");
+        let manager = test_builder(&dir)
+            .offline_assets(true)
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        manager.add_doc("guide", Some(Formats::HTML)).await.unwrap();
+
+        let name = asset_name("https://img.test/logo.png?v=2");
+        assert!(name.ends_with(".png"));
+        assert_eq!(
+            std::fs::read(dir.join("guide").join(ASSETS_DIR).join(&name)).unwrap(),
+            b"PNG"
+        );
+        let html = std::fs::read_to_string(dir.join("guide/guide/intro.html")).unwrap();
+        let local = format!("../_assets/{}", name);
+        assert!(html.contains(&format!(r#"src="{}""#, local)), "{}", html);
+        // Links to a local copy don't get a page extension
+        assert!(html.contains(&format!(r#"href="{}""#, local)), "{}", html);
+        assert!(html.contains("//img.test/missing.gif"));
+    }
+
+    #[test]
+    fn test_rewrite_links_resolves_against_the_page() {
+        let html = concat!(
+            r#"<a href="struct.Vec#method.push">push</a>"#,
+            r#"<a href='../option/enum.Option'>Option</a>"#,
+            r#"<a href="/rust/std/index">std</a>"#,
+            r#"<a href="https://example.com/x">external</a>"#,
+            r##"<a href="#local">local</a>"##,
+            r#"<img src="/static/logo%20big.png">"#,
+        );
+        let out = rewrite_links(html, "rust", "std/vec/index", "html");
+
+        assert!(out.contains(r#"href="../../std/vec/struct.Vec.html#method.push""#));
+        assert!(out.contains(r#"href="../../std/option/enum.Option.html""#));
+        assert!(out.contains(r#"href="../../std/index.html""#));
+        assert!(out.contains(r#"href="https://example.com/x""#));
+        assert!(out.contains(r##"href="#local""##));
+        assert!(out.contains(r#"src="../../static/logo%20big.png""#));
+    }
+
+    #[test]
+    fn test_sections_are_extracted_from_headings() {
+        let html = concat!(
+            "<h1>Ownership</h1>",
+            r#"<h2 id="the-borrow-checker">The <code>borrow</code> checker</h2>"#,
+            "<h2>No anchor</h2>",
+            r#"<h3 id="rules">Rules &amp; limits</h3>"#,
+        );
+        let sections = extract_sections("book/ch04", html);
+
+        assert_eq!(sections.len(), 2);
+        assert_eq!(sections[0].name, "The borrow checker");
+        assert_eq!(
+            sections[0].path,
+            PathBuf::from("book/ch04#the-borrow-checker")
+        );
+        assert_eq!(sections[0].entry_type, SECTION_TYPE);
+        assert_eq!(sections[1].name, "Rules & limits");
+    }
+
+    #[test]
+    fn test_entry_url_points_at_devdocs() {
+        let manager = test_builder(temp_dir("entry-url"))
+            .base_url("http://localhost:9292")
+            .build()
+            .unwrap();
+        assert_eq!(
+            manager.entry_url("rust", "std/vec/struct.vec#method.push"),
+            "https://devdocs.io/rust/std/vec/struct.vec#method.push"
+        );
+        assert_eq!(
+            manager.entry_url("c", "/language/operator precedence#100%"),
+            "https://devdocs.io/c/language/operator%20precedence#100%25"
+        );
+        assert_eq!(
+            manager.entry_url("css", "css/:hover#a\"b"),
+            "https://devdocs.io/css/css/:hover#a%22b"
+        );
+    }
+
+    #[test]
+    fn test_signatures_are_extracted_per_doc_type() {
+        let python = concat!(
+            r#"<dt class="sig sig-object py" id="time.strftime">"#,
+            r#"<span class="sig-prename">time.</span><span class="sig-name">strftime</span>"#,
+            r#"(<em>format</em>[, <em>t</em>])</dt><dd>Convert a tuple.</dd>"#,
+            r#"<dt id="time.altzone">time.altzone</dt>"#,
+        );
+        let signatures = extract_signatures("sphinx", "library/time", python);
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].name, "time.strftime");
+        assert_eq!(signatures[0].text, "time.strftime(format[, t])");
+        assert_eq!(signatures[0].anchor.as_deref(), Some("time.strftime"));
+
+        let rust = concat!(
+            r#"<h3 id="impl-Vec" class="code-header">impl&lt;T&gt; Vec&lt;T&gt;</h3>"#,
+            r#"<h4 id="method.push" class="code-header">pub fn <a>push</a>(&amp;mut self, value: T)</h4>"#,
+        );
+        let signatures = extract_signatures("rust", "std/vec/struct.vec", rust);
+        assert_eq!(signatures.len(), 1);
+        assert_eq!(signatures[0].name, "push");
+        assert_eq!(signatures[0].text, "pub fn push(&mut self, value: T)");
+
+        assert!(extract_signatures("simple", "page", python).is_empty());
+    }
+
+    #[test]
+    fn test_code_blocks_are_extracted_with_languages() {
+        let html = concat!(
+            "<pre data-language=\"rust\">let v = vec![1, 2];\nv.push(3);</pre>",
+            "<p>Inline <code>push</code> isn't a block.</p>",
+            "<pre><code class=\"hljs language-python\">if a &lt; b:\n    pass</code></pre>",
+            "<pre>\n</pre>",
+        );
+        let blocks = extract_code_blocks("std/vec", html);
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].page, "std/vec");
+        assert_eq!(blocks[0].language.as_deref(), Some("rust"));
+        assert_eq!(blocks[0].code, "let v = vec![1, 2];\nv.push(3);");
+        assert_eq!(blocks[1].language.as_deref(), Some("python"));
+        assert_eq!(blocks[1].code, "if a < b:\n    pass");
+    }
+
+    #[tokio::test]
+    async fn test_search_code_finds_matching_lines() {
+        let manager = test_builder(temp_dir("code-search")).build().unwrap();
+        manager.init().await.unwrap();
+        insert_doc(&manager, sample_cached_doc("rust")).await;
+
+        let blocks = extract_code_blocks(
+            "std/vec",
+            "<pre>let mut v = Vec::new();\nv.push(1);</pre><pre>let s = String::new();</pre>",
+        );
+        manager.save_code_blocks("rust", &blocks).await.unwrap();
+
+        let options = SearchOptions {
+            exact: true,
+            ..Default::default()
+        };
+        let results = manager.search_code("push(", &options).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].doc_slug, "rust");
+        assert_eq!(results[0].line, 1);
+    }
+
+    #[tokio::test]
+    async fn test_scoped_search_installs_the_doc() {
+        assert_eq!(
+            split_doc_scope("postgres: jsonb"),
+            Some(("postgres", "jsonb"))
+        );
+        assert_eq!(split_doc_scope("rust~1.80:"), Some(("rust~1.80", "")));
+        assert_eq!(split_doc_scope("std::vec"), None);
+        assert_eq!(split_doc_scope("https://example.com"), None);
+        assert_eq!(split_doc_scope("jsonb"), None);
+
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"PostgreSQL","slug":"postgresql~16","alias":"postgres","type":"postgres","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/postgresql~16/index.json",
+                r#"{"entries":[{"name":"jsonb_set","path":"functions-json","type":"functions"}],
+                    "types":[{"name":"functions","count":1,"slug":"functions"}]}"#,
+            )
+            .respond(
+                "/documents/postgresql~16/db.json",
+                r#"{"functions-json":"<h1>JSON Functions</h1>"}"#,
+            );
+        let dir = temp_dir("ensure-installed");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let slug = manager.ensure_installed("postgres").await.unwrap();
+        assert_eq!(slug, "postgresql~16");
+        assert!(manager.is_doc_installed("postgresql~16").await.unwrap());
+        assert!(dir.join("postgresql~16/functions-json.md").exists());
+
+        // Already installed, so nothing is downloaded again
+        std::fs::remove_file(dir.join("postgresql~16/functions-json.md")).unwrap();
+        assert_eq!(
+            manager.ensure_installed("postgresql~16").await.unwrap(),
+            "postgresql~16"
+        );
+        assert!(!dir.join("postgresql~16/functions-json.md").exists());
+
+        assert!(matches!(
+            manager
+                .ensure_installed("cobol")
+                .await
+                .unwrap_err()
+                .downcast_ref(),
+            Some(DevDocsError::DocNotFound(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_add_doc_filtered_installs_only_pages_of_the_types() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"vec!","path":"std/macro.vec","type":"Macros"},
+                    {"name":"Vec","path":"std/vec/struct.vec","type":"Structs"},
+                    {"name":"match","path":"std/keyword.match#examples","type":"Keywords"}],
+                    "types":[{"name":"Macros","count":1,"slug":"macros"},
+                    {"name":"Structs","count":1,"slug":"structs"},
+                    {"name":"Keywords","count":1,"slug":"keywords"}]}"#,
+            )
+            .respond(
+                "/documents/rust/db.json",
+                serde_json::json!({
+                    "std/macro.vec": "<h1>vec!</h1>",
+                    "std/vec/struct.vec": "<h1>Vec</h1>",
+                    "std/keyword.match": "<h1>match</h1>",
+                })
+                .to_string(),
+            );
+        let dir = temp_dir("add-filtered");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let types = ["macros".to_string(), "Keywords".to_string()];
+        manager
+            .add_doc_filtered("rust", &types, Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        assert!(dir.join("rust/std/macro.vec.md").exists());
+        assert!(dir.join("rust/std/keyword.match.md").exists());
+        assert!(!dir.join("rust/std/vec/struct.vec.md").exists());
+        let names: Vec<String> = manager
+            .search("", None)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|r| r.entry.entry.name)
+            .collect();
+        assert_eq!(names, ["match", "vec!"]);
+
+        // The filter outlives the manager, until the doc is removed
+        let types = ["bogus".to_string()];
+        assert!(
+            manager
+                .add_doc_filtered("rust", &types, None)
+                .await
+                .is_err()
+        );
+        assert!(dir.join("rust/std/macro.vec.md").exists());
+        let state = std::fs::read_to_string(dir.join("filters.json")).unwrap();
+        assert!(state.contains("Keywords"), "{}", state);
+        manager.remove_doc("rust").await.unwrap();
+        assert!(!dir.join("rust").exists());
+        manager
+            .add_doc("rust", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        assert!(dir.join("rust/std/vec/struct.vec.md").exists());
+    }
+
+    #[tokio::test]
+    async fn test_doc_groups_expand_to_their_docs() {
+        let manager = test_builder(temp_dir("groups"))
+            .transport(MockTransport::new())
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        assert_eq!(
+            manager.expand_group("@web").await.unwrap(),
+            ["html", "css", "javascript", "dom"]
+        );
+        assert!(manager.expand_group("@nope").await.is_err());
+
+        manager
+            .set_config("group.backend", "postgresql~16, redis,")
+            .await
+            .unwrap();
+        manager.set_config("group.web", "html,css").await.unwrap();
+        assert!(manager.set_config("group.empty", " , ").await.is_err());
+        let names = ["rust", "@backend", "@web", "css"].map(String::from);
+        assert_eq!(
+            manager.expand_groups(&names).await.unwrap(),
+            ["rust", "postgresql~16", "redis", "html", "css"]
+        );
+        assert_eq!(
+            manager.settings().await.get("group.backend").unwrap(),
+            Some("postgresql~16,redis".to_string())
+        );
+
+        // Back to the built-in group
+        assert!(manager.unset_config("group.web").await.unwrap());
+        assert_eq!(manager.expand_group("web").await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_downloads_are_checked_and_installs_verified() {
+        let docs = r#"[{"name":"Kubernetes","slug":"kubernetes","type":"kubernetes","mtime":1,"db_size":10}]"#;
+        let index = r#"{"entries":[{"name":"Pod","path":"pod","type":"workloads"},
+            {"name":"Job","path":"job#spec","type":"workloads"}],
+            "types":[{"name":"workloads","count":2,"slug":"workloads"}]}"#;
+        let transport = |db: &str| {
+            MockTransport::new()
+                .respond("/docs.json", docs)
+                .respond("/documents/kubernetes/index.json", index)
+                .respond("/documents/kubernetes/db.json", db)
+        };
+        let dir = temp_dir("verify");
+        let manager = |db: &str| {
+            test_builder(&dir)
+                .base_url("http://docs.test")
+                .documents_url("http://docs.test/documents")
+                .transport(transport(db))
+                .build()
+                .unwrap()
+        };
+
+        // A transfer that lost a page is rejected before anything is written
+        let truncated = manager(r#"{"pod":"<h1>Pod</h1>"}"#);
+        truncated.init().await.unwrap();
+        let err = truncated.add_doc("kubernetes", None).await.unwrap_err();
+        assert!(
+            matches!(
+                err.downcast_ref::<DevDocsError>(),
+                Some(DevDocsError::CorruptDownload { .. })
+            ),
+            "{}",
+            err
+        );
+        assert!(!dir.join("kubernetes.bin").exists());
+
+        let manager = manager(r#"{"pod":"<h1>Pod</h1>","job":"<h1>Job</h1>"}"#);
+        manager.init().await.unwrap();
+        manager
+            .add_doc("kubernetes", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        assert!(manager.verify_doc("kubernetes").await.unwrap().is_empty());
+
+        std::fs::remove_file(dir.join("kubernetes/job.md")).unwrap();
+        let mut cache = std::fs::read(dir.join("kubernetes.bin")).unwrap();
+        cache.truncate(cache.len() - 1);
+        std::fs::write(dir.join("kubernetes.bin"), cache).unwrap();
+        assert_eq!(
+            manager.verify_doc("kubernetes").await.unwrap(),
+            ["index doesn't match its checksum", "page job.md is missing"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_update_all_updates_outdated_docs_past_a_failing_one() {
+        let listed = |docs: &str| {
+            docs.replace(
+                "\n]",
+                r#",{"name":"Go","slug":"go","type":"go","mtime":1700000000,"db_size":64}]"#,
+            )
+        };
+        let transport = MockTransport::fixtures()
+            .respond("/docs.json", listed(testing::DOCS_JSON))
+            .respond(
+                "/go/index.json",
+                r#"{"entries":[{"name":"fmt","path":"fmt/index","type":"packages"}],
+                    "types":[{"name":"packages","count":1,"slug":"packages"}]}"#,
+            )
+            .respond("/go/db.json", r#"{"fmt/index":"<h1>fmt</h1>"}"#);
+        let manager =
+            DevDocsManager::with_transport(temp_dir("update-all"), transport.clone()).unwrap();
+        manager.init().await.unwrap();
+        for slug in ["kubernetes", "rust", "go"] {
+            manager.add_doc(slug, None).await.unwrap();
+        }
+
+        // Kubernetes and Rust are outdated, and Rust's new version can't be downloaded
+        transport.set(
+            "/docs.json",
+            listed(&testing::DOCS_JSON.replace("1700000000", "1800000000")),
+        );
+        transport.remove("/rust/db.json");
+        manager.refresh_available_docs().await.unwrap();
+
+        let report = manager.update_all().await.unwrap();
+        let updated: Vec<_> = report
+            .updated
+            .iter()
+            .map(|(slug, _)| slug.as_str())
+            .collect();
+        assert_eq!(updated, ["kubernetes"]);
+        assert_eq!(report.current, ["go"]);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0, "rust");
+        assert_eq!(report.outcome(), BatchOutcome::Partial);
+
+        // The failed doc keeps its installed version and stays outdated
+        let installed = manager.list_installed_docs().await.unwrap();
+        assert_eq!(installed.len(), 3);
+        let results = manager.search("vec", None).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, "rust");
+        let mut report = manager.update_all().await.unwrap();
+        assert!(report.updated.is_empty());
+        // Docs are reported as their updates finish
+        report.current.sort();
+        assert_eq!(report.current, ["go", "kubernetes"]);
+        assert_eq!(report.failed[0].0, "rust");
+    }
+
+    #[tokio::test]
+    async fn test_docs_removed_upstream_are_kept_as_orphans() {
+        let dir = temp_dir("orphaned");
+        let docs = |listed: &str| {
+            MockTransport::new()
+                .respond("/docs.json", listed)
+                .respond(
+                    "/documents/kubernetes/index.json",
+                    r#"{"entries":[{"name":"Pod","path":"pod","type":"workloads"}],
+                        "types":[{"name":"workloads","count":1,"slug":"workloads"}]}"#,
+                )
+                .respond("/documents/kubernetes/db.json", r#"{"pod":"<h1>Pod</h1>"}"#)
+        };
+        let manager = |listed: &str| {
+            test_builder(&dir)
+                .base_url("http://docs.test")
+                .documents_url("http://docs.test/documents")
+                .transport(docs(listed))
+                .build()
+                .unwrap()
+        };
+        let before = manager(
+            r#"[{"name":"Kubernetes","slug":"kubernetes","type":"kubernetes","mtime":1,"db_size":10}]"#,
+        );
+        before.init().await.unwrap();
+        before.add_doc("kubernetes", None).await.unwrap();
+        assert!(before.orphaned_docs().await.unwrap().is_empty());
+
+        let after = manager("[]");
+        after.init().await.unwrap();
+        after.refresh_available_docs().await.unwrap();
+        assert_eq!(after.orphaned_docs().await.unwrap(), ["kubernetes"]);
+        let report = after.update_all().await.unwrap();
+        assert_eq!(report.orphaned, ["kubernetes"]);
+        assert_eq!(report.outcome(), BatchOutcome::Ok);
+        let plan = after
+            .plan_update(&["kubernetes".to_string()])
+            .await
+            .unwrap();
+        assert!(plan.downloads.is_empty());
+        assert_eq!(plan.orphaned, ["kubernetes"]);
+        // Still served from the installed copy
+        let results = after.search("pod", None).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, "kubernetes");
+    }
+
+    #[tokio::test]
+    async fn test_translated_docs_are_searched_in_the_preferred_language() {
+        assert_eq!(doc_language("vue~3_ja"), "ja");
+        assert_eq!(doc_language("vue~3"), "en");
+        assert_eq!(doc_language("node~18_lts"), "en");
+
+        let manager = test_builder(temp_dir("languages")).build().unwrap();
+        manager.init().await.unwrap();
+        for slug in ["vue~3", "vue~3_ja", "react_ja"] {
+            let mut doc = sample_cached_doc(slug);
+            doc.index.entries = vec![entry("computed", "api")];
+            insert_doc(&manager, doc).await;
+        }
+        let slugs = |results: Vec<SearchResult>| -> Vec<String> {
+            let mut slugs: Vec<String> = results.into_iter().map(|r| r.entry.doc_slug).collect();
+            slugs.sort();
+            slugs
+        };
+
+        assert_eq!(
+            slugs(manager.search("computed", None).await.unwrap()),
+            ["react_ja", "vue~3"]
+        );
+        assert!(manager.set_config("language", "japanese").await.is_err());
+        manager.set_config("language", "JA").await.unwrap();
+        assert_eq!(
+            slugs(manager.search("computed", None).await.unwrap()),
+            ["react_ja", "vue~3_ja"]
+        );
+        // Docs named explicitly are searched whatever their language
+        let options = SearchOptions {
+            docs: vec!["vue~3".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            slugs(manager.search_with("computed", &options).await.unwrap()),
+            ["vue~3"]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forced_updates_download_current_docs_again() {
+        let dir = temp_dir("force-update");
+        let manager = DevDocsManager::with_transport(&dir, MockTransport::fixtures()).unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("kubernetes", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        std::fs::remove_file(dir.join("kubernetes/concepts/workloads/pods/pod.md")).unwrap();
+
+        assert_eq!(
+            manager.update_doc("kubernetes").await.unwrap(),
+            UpdateStatus::Current
+        );
+        let cancel = CancellationToken::new();
+        assert!(matches!(
+            manager
+                .force_update_doc("kubernetes", &cancel)
+                .await
+                .unwrap(),
+            UpdateStatus::Updated(_)
+        ));
+        assert!(
+            dir.join("kubernetes/concepts/workloads/pods/pod.md")
+                .exists()
+        );
+        let report = manager.force_update_all(&cancel).await.unwrap();
+        assert_eq!(report.updated.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_onboarding_suggests_the_docs_of_the_project() {
+        let project = temp_dir("onboarding-project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join("Cargo.toml"), "[package]").unwrap();
+        std::fs::write(
+            project.join("package.json"),
+            r#"{"devDependencies":{"react":"^19","left-pad":"1"}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            detect_project_docs(&project).unwrap(),
+            ["rust", "javascript", "node", "react"]
+        );
+
+        let transport = MockTransport::new().respond(
+            "/docs.json",
+            r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10},
+                {"name":"React","slug":"react","type":"simple","mtime":1,"db_size":10},
+                {"name":"Node.js 22","slug":"node~22_lts","type":"node","mtime":1,"db_size":10},
+                {"name":"Node.js 20","slug":"node~20_lts","type":"node","mtime":1,"db_size":10}]"#,
+        );
+        let manager = test_builder(temp_dir("onboarding"))
+            .base_url("http://docs.test")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        assert!(manager.needs_onboarding().await);
+
+        let onboarding = manager.onboard(&project).await.unwrap();
+        assert_eq!(onboarding.available, 4);
+        let slugs: Vec<&str> = onboarding
+            .suggested
+            .iter()
+            .map(|d| d.slug.as_str())
+            .collect();
+        assert_eq!(slugs, ["rust", "node~22_lts", "react"]);
+        // Asked once only
+        assert!(!manager.needs_onboarding().await);
+    }
+
+    #[tokio::test]
+    async fn test_index_and_content_are_downloaded_together() {
+        /// Holds each doc file back until the other one is asked for too
+        struct Together(MockTransport, Arc<tokio::sync::Barrier>);
+
+        impl HttpTransport for Together {
+            fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+                Box::pin(async move {
+                    if !url.ends_with("/docs.json") {
+                        self.1.wait().await;
+                    }
+                    self.0.get(url).await
+                })
+            }
+        }
+
+        let transport = MockTransport::fixtures();
+        let together = Together(transport.clone(), Arc::new(tokio::sync::Barrier::new(2)));
+        let manager = DevDocsManager::with_transport(temp_dir("together"), together).unwrap();
+        manager.init().await.unwrap();
+
+        let added = tokio::time::timeout(Duration::from_secs(10), manager.add_doc("rust", None));
+        added
+            .await
+            .expect("index and content were downloaded one after the other")
+            .unwrap();
+        let downloads = transport
+            .requests()
+            .iter()
+            .filter(|url| url.ends_with("/db.json"))
+            .count();
+        assert_eq!(downloads, 1);
+    }
+
+    #[tokio::test]
+    async fn test_identical_installs_share_one_download() {
+        /// Takes its time with doc files, so installs started together overlap
+        struct Slow(MockTransport);
+
+        impl HttpTransport for Slow {
+            fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+                Box::pin(async move {
+                    if !url.ends_with("/docs.json") {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    self.0.get(url).await
+                })
+            }
+        }
+
+        let transport = MockTransport::fixtures();
+        let manager =
+            DevDocsManager::with_transport(temp_dir("in-flight"), Slow(transport.clone())).unwrap();
+        manager.init().await.unwrap();
+        manager.refresh_available_docs().await.unwrap();
+
+        let (first, second) =
+            tokio::join!(manager.add_doc("rust", None), manager.add_doc("rust", None));
+        first.unwrap();
+        second.unwrap();
+        let (first, second) = tokio::join!(manager.update_doc("rust"), manager.update_doc("rust"));
+        assert_eq!(first.unwrap(), second.unwrap());
+
+        let downloads = |file: &str| {
+            transport
+                .requests()
+                .iter()
+                .filter(|url| url.ends_with(file))
+                .count()
+        };
+        assert_eq!(downloads("/rust/db.json"), 1);
+        assert_eq!(downloads("/rust/index.json"), 1);
+
+        // Once it's done, the same request runs again
+        let cancel = CancellationToken::new();
+        manager.force_update_doc("rust", &cancel).await.unwrap();
+        assert_eq!(downloads("/rust/db.json"), 2);
+
+        // Cancelling a waiter leaves the update it waits for running
+        let (cancel, running) = (CancellationToken::new(), CancellationToken::new());
+        let waiter = async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            let waited = manager.force_update_doc("rust", &cancel);
+            tokio::pin!(waited);
+            tokio::select! {
+                _ = &mut waited => panic!("the waiter finished before its cancellation"),
+                _ = tokio::time::sleep(Duration::from_millis(20)) => cancel.cancel(),
+            }
+            waited.await
+        };
+        let (first, second) = tokio::join!(manager.force_update_doc("rust", &running), waiter);
+        assert!(matches!(first.unwrap(), UpdateStatus::Updated(_)));
+        let e = second.unwrap_err();
+        assert!(matches!(e.downcast_ref(), Some(DevDocsError::Cancelled)));
+    }
+
+    #[tokio::test]
+    async fn test_searches_rank_on_the_configured_pool() {
+        let pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .thread_name(|i| format!("host-{}", i))
+                .build()
+                .unwrap(),
+        );
+        let managers = [
+            test_builder(temp_dir("pool-global")),
+            test_builder(temp_dir("pool-single")).search_threads(1),
+            test_builder(temp_dir("pool-host")).search_pool(pool.clone()),
+        ]
+        .map(|builder| builder.build().unwrap());
+
+        let mut found = Vec::new();
+        for manager in &managers {
+            let mut doc = sample_cached_doc("rust");
+            doc.index.entries = vec![entry("Vec", "struct"), entry("VecDeque", "struct")];
+            insert_doc(manager, doc).await;
+            let results = manager.search("vec", None).await.unwrap();
+            found.push(
+                results
+                    .into_iter()
+                    .map(|r| r.entry.entry.name)
+                    .collect::<Vec<_>>(),
+            );
+        }
+        assert_eq!(found[0], ["Vec", "VecDeque"]);
+        assert!(found.iter().all(|names| *names == found[0]));
+
+        let thread = || std::thread::current().name().map(str::to_string);
+        assert_eq!(
+            managers[0].in_search_pool(rayon::current_num_threads),
+            rayon::current_num_threads()
+        );
+        assert_eq!(managers[1].in_search_pool(rayon::current_num_threads), 1);
+        assert!(
+            managers[1]
+                .in_search_pool(thread)
+                .is_some_and(|name| name.starts_with("devdocs-search-"))
+        );
+        assert!(
+            managers[2]
+                .in_search_pool(thread)
+                .is_some_and(|name| name.starts_with("host-"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_aliased_entries_collapse_into_their_best_name() {
+        let manager = test_builder(temp_dir("collapse")).build().unwrap();
+        let aliased = |name: &str, path: &str| Entry {
+            path: PathBuf::from(path),
+            ..entry(name, "method")
+        };
+        let mut doc = sample_cached_doc("python");
+        doc.index.entries = vec![
+            aliased("str.split", "library/stdtypes#str.split"),
+            aliased("split (str method)", "library/stdtypes#str.split"),
+            aliased("str.rsplit", "library/stdtypes#str.rsplit"),
+            aliased("shlex.split", "library/shlex#shlex.split"),
+        ];
+        insert_doc(&manager, doc).await;
+
+        let names = |results: Vec<SearchResult>| {
+            results
+                .into_iter()
+                .map(|r| r.entry.entry.name)
+                .collect::<Vec<_>>()
+        };
+        let all = manager.search("split", None).await.unwrap();
+        assert_eq!(all.len(), 4);
+        let best_alias = all
+            .iter()
+            .find(|r| r.entry.entry.path == Path::new("library/stdtypes#str.split"))
+            .map(|r| r.entry.entry.name.clone())
+            .unwrap();
+
+        let options = SearchOptions {
+            collapse_duplicates: true,
+            ..Default::default()
+        };
+        let collapsed = names(manager.search_with("split", &options).await.unwrap());
+        assert_eq!(collapsed.len(), 3);
+        let aliases = ["str.split", "split (str method)"];
+        let kept: Vec<_> = collapsed
+            .iter()
+            .filter(|name| aliases.contains(&name.as_str()))
+            .collect();
+        assert_eq!(kept, [&best_alias]);
+
+        // Other sections of the same page are kept apart
+        assert!(collapsed.contains(&"str.rsplit".to_string()));
+        // Browsing without a query collapses them too
+        let browsed = manager.search_response("", &options).await.unwrap();
+        assert_eq!(browsed.total_matches, 3);
+        assert_eq!(
+            names(browsed.results)
+                .iter()
+                .filter(|name| aliases.contains(&name.as_str()))
+                .count(),
+            1
+        );
+        let page = SearchOptions {
+            limit: 1,
+            offset: 2,
+            ..options
+        };
+        let response = manager.search_response("split", &page).await.unwrap();
+        assert_eq!(response.total_matches, 3);
+        assert_eq!(names(response.results), [collapsed[2].clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_entry_fragments_open_their_page_at_the_section() {
+        let dir = temp_dir("fragments");
+        let manager = DevDocsManager::with_transport(&dir, MockTransport::fixtures()).unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("kubernetes", Some(Formats::MARKDOWN | Formats::HTML))
+            .await
+            .unwrap();
+
+        // The fragment names a section, not an extension of the page file
+        let result = &manager.search("rolling back", None).await.unwrap()[0];
+        let path = result.entry.entry.path.to_string_lossy();
+        let markdown = manager
+            .page_file("kubernetes", &path, Formats::MARKDOWN)
+            .await
+            .unwrap();
+        let page = dir.join("kubernetes/concepts/workloads/controllers/deployment");
+        assert_eq!(markdown, page.with_extension("md"));
+        let html = manager
+            .page_file("kubernetes", &path, Formats::HTML)
+            .await
+            .unwrap();
+        assert_eq!(html, page.with_extension("html"));
+        assert!(
+            !dir.join("kubernetes/concepts/workloads/controllers/deployment#rolling-back.md")
+                .exists()
+        );
+
+        let (page, anchor) = split_fragment(&path);
+        assert_eq!(
+            (page, anchor),
+            (
+                "concepts/workloads/controllers/deployment",
+                Some("rolling-back")
+            )
+        );
+        let text = std::fs::read_to_string(markdown).unwrap();
+        let html = std::fs::read_to_string(html).unwrap();
+        let offset = anchor_offset(&html, &text, "rolling-back").unwrap();
+        assert!(
+            text[offset..].starts_with("Rolling back"),
+            "{}",
+            &text[offset..]
+        );
+        assert!(text[..offset].contains("Declarative updates for Pods"));
+        assert_eq!(anchor_offset(&html, &text, "scaling"), None);
+        assert_eq!(anchor_offset(&html, &text, "\"]"), None);
+    }
+
+    #[tokio::test]
+    async fn test_patch_failing_partway_keeps_the_installed_pages() {
+        let transport = MockTransport::fixtures();
+        let dir = temp_dir("patch-partway");
+        let manager = DevDocsManager::with_transport(&dir, transport.clone()).unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("kubernetes", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        let changed = dir.join("kubernetes/concepts/workloads/controllers/deployment.md");
+        let before = std::fs::read_to_string(&changed).unwrap();
+
+        let docs = testing::DOCS_JSON
+            .replace("1700000000,\"db_size\":2048", "1800000000,\"db_size\":2048");
+        transport.set("/docs.json", docs);
+        transport.set(
+            "/kubernetes/db.json",
+            testing::KUBERNETES_DB.replace("Declarative updates", "Rolling updates"),
+        );
+        manager.refresh_available_docs().await.unwrap();
+
+        // The changed page renders, then an unchanged one can't be carried over
+        std::fs::remove_file(dir.join("kubernetes/concepts/workloads/pods/pod.md")).unwrap();
+        assert!(manager.update_doc("kubernetes").await.is_err());
+        assert_eq!(std::fs::read_to_string(&changed).unwrap(), before);
+        assert!(
+            dir.join("kubernetes/concepts/services-networking/service.md")
+                .exists()
+        );
+        assert!(!dir.join(STAGING_DIR).join("kubernetes").exists());
+
+        // Once every unchanged page is there, the patch lands whole
+        std::fs::write(
+            dir.join("kubernetes/concepts/workloads/pods/pod.md"),
+            "# Pod",
+        )
+        .unwrap();
+        manager.update_doc("kubernetes").await.unwrap();
+        assert!(
+            std::fs::read_to_string(&changed)
+                .unwrap()
+                .contains("Rolling updates")
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("kubernetes/concepts/workloads/pods/pod.md")).unwrap(),
+            "# Pod"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_and_cancelled_updates_keep_the_installed_doc() {
+        let transport = MockTransport::fixtures();
+        let dir = temp_dir("update-intact");
+        let manager = DevDocsManager::with_transport(&dir, transport.clone()).unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("rust", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+        let page = dir.join("rust/std/vec/struct.vec.md");
+        let before = std::fs::read_to_string(&page).unwrap();
+
+        let intact = || async {
+            assert_eq!(std::fs::read_to_string(&page).unwrap(), before);
+            assert!(dir.join("rust/std/macro.vec.md").exists());
+            let results = manager.search("push", Some(1)).await.unwrap();
+            assert_eq!(results[0].entry.entry.name, "Vec::push");
+            assert!(!dir.join(STAGING_DIR).join("rust").exists());
+        };
+
+        // A new version whose content can't be downloaded
+        let docs = testing::DOCS_JSON
+            .replace("1700000000,\"db_size\":4096", "1800000000,\"db_size\":4096");
+        transport.set("/docs.json", docs);
+        manager.refresh_available_docs().await.unwrap();
+        transport.remove("/rust/db.json");
+        assert!(manager.update_doc("rust").await.is_err());
+        intact().await;
+
+        transport.set(
+            "/rust/db.json",
+            testing::RUST_DB.replace("growable", "resizable"),
+        );
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(
+            manager
+                .update_doc_with_cancel("rust", &cancel)
+                .await
+                .is_err()
+        );
+        intact().await;
+    }
 }
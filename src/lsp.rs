@@ -0,0 +1,522 @@
+//! A minimal language server answering hovers from the installed docs
+//!
+//! Speaks LSP over any byte stream (stdin/stdout for `dev lsp`). Besides the lifecycle
+//! and text sync messages it understands:
+//!
+//! - `textDocument/hover`: the best entry for the symbol under the cursor, with its
+//!   signature, a summary of its section and a link to the full page
+//! - `devdocs/search`: `{"query", "docs"?, "limit"?}` answered with [`SearchResult`]s
+//!
+//! Searches are scoped by the [`PROJECT_SCOPE_FILE`](crate::PROJECT_SCOPE_FILE) found
+//! above the hovered file, or by the `docs` of the client's `initializationOptions`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use serde::Deserialize;
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tracing::debug;
+
+use crate::semantic::page_sections;
+use crate::{DevDocsManager, Formats, SearchOptions, SearchResult, find_project_scope, page_path};
+
+/// Characters of a section shown in a hover
+const HOVER_SUMMARY_CHARS: usize = 400;
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Serve LSP requests read from `input` until the client sends `exit` or hangs up
+pub async fn run<R, W>(manager: &DevDocsManager, mut input: R, mut output: W) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut server = Server {
+        manager,
+        documents: HashMap::new(),
+        docs: Vec::new(),
+    };
+    while let Some(message) = read_message(&mut input).await? {
+        // A broken message is answered, the ones after it are still served
+        let message = match message {
+            Ok(message) => message,
+            Err(problem) => {
+                debug!("Unreadable LSP message: {}", problem);
+                let response = json!({
+                    "jsonrpc": "2.0",
+                    "id": null,
+                    "error": { "code": PARSE_ERROR, "message": problem },
+                });
+                write_message(&mut output, &response).await?;
+                continue;
+            }
+        };
+        let method = message["method"].as_str().unwrap_or_default().to_string();
+        if method == "exit" {
+            break;
+        }
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        // Messages without an id are notifications, which get no answer
+        let Some(id) = message.get("id").cloned() else {
+            server.notify(&method, params);
+            continue;
+        };
+
+        let response = match server.request(&method, params).await {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err((code, message)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+        };
+        write_message(&mut output, &response).await?;
+    }
+    Ok(())
+}
+
+struct Server<'a> {
+    manager: &'a DevDocsManager,
+    /// Text of the open documents, by URI
+    documents: HashMap<String, String>,
+    /// Docs to search when no project scope applies
+    docs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HoverParams {
+    text_document: TextDocument,
+    position: Position,
+}
+
+#[derive(Deserialize)]
+struct TextDocument {
+    uri: String,
+}
+
+#[derive(Deserialize)]
+struct Position {
+    line: usize,
+    character: usize,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    query: String,
+    #[serde(default)]
+    docs: Vec<String>,
+    limit: Option<usize>,
+}
+
+impl Server<'_> {
+    fn notify(&mut self, method: &str, params: Value) {
+        let uri = params["textDocument"]["uri"].as_str().map(str::to_string);
+        match (method, uri) {
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri, text.to_string());
+            }
+            // Full sync: the last change holds the whole text
+            ("textDocument/didChange", Some(uri)) => {
+                if let Some(text) = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str())
+                {
+                    self.documents.insert(uri, text.to_string());
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(&uri);
+            }
+            _ => debug!("Ignoring LSP notification {}", method),
+        }
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> Result<Value, (i64, String)> {
+        let invalid = |e: serde_json::Error| (INVALID_PARAMS, e.to_string());
+        let failed = |e: anyhow::Error| (INTERNAL_ERROR, e.to_string());
+        match method {
+            "initialize" => {
+                if let Some(docs) = params["initializationOptions"]["docs"].as_array() {
+                    self.docs = docs
+                        .iter()
+                        .filter_map(|doc| doc.as_str().map(str::to_string))
+                        .collect();
+                }
+                Ok(json!({
+                    "capabilities": { "hoverProvider": true, "textDocumentSync": 1 },
+                    "serverInfo": { "name": "dev", "version": env!("CARGO_PKG_VERSION") },
+                }))
+            }
+            "shutdown" => Ok(Value::Null),
+            "textDocument/hover" => {
+                let params: HoverParams = serde_json::from_value(params).map_err(invalid)?;
+                Ok(self
+                    .hover(params)
+                    .await
+                    .map_err(failed)?
+                    .unwrap_or(Value::Null))
+            }
+            "devdocs/search" => {
+                let params: SearchParams = serde_json::from_value(params).map_err(invalid)?;
+                let docs = if params.docs.is_empty() {
+                    self.docs.clone()
+                } else {
+                    params.docs
+                };
+                let options = SearchOptions {
+                    docs,
+                    limit: params.limit.unwrap_or(SearchOptions::default().limit),
+                    ..Default::default()
+                };
+                let results = self
+                    .manager
+                    .search_with(&params.query, &options)
+                    .await
+                    .map_err(failed)?;
+                serde_json::to_value(results).map_err(|e| failed(e.into()))
+            }
+            _ => Err((METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        }
+    }
+
+    async fn hover(&self, params: HoverParams) -> Result<Option<Value>> {
+        let uri = params.text_document.uri;
+        let Some(text) = self.documents.get(&uri) else {
+            return Ok(None);
+        };
+        let Some(symbol) = symbol_at(text, params.position.line, params.position.character) else {
+            return Ok(None);
+        };
+
+        let options = SearchOptions {
+            docs: self.scope(&uri).await?,
+            limit: 1,
+            ..Default::default()
+        };
+        // Qualifiers may be variables rather than types, so fall back to the bare name
+        let mut found = self.manager.search_with(&symbol, &options).await?;
+        if found.is_empty()
+            && let Some((_, name)) = symbol.rsplit_once(['.', ':'])
+        {
+            found = self.manager.search_with(name, &options).await?;
+        }
+        let Some(result) = found.into_iter().next() else {
+            return Ok(None);
+        };
+
+        Ok(Some(json!({
+            "contents": { "kind": "markdown", "value": self.hover_markdown(&result).await? },
+        })))
+    }
+
+    /// The docs to search for a document: its project scope, else the configured docs
+    async fn scope(&self, uri: &str) -> Result<Vec<String>> {
+        let scope = file_path(uri)
+            .as_deref()
+            .and_then(Path::parent)
+            .map(find_project_scope)
+            .transpose()?
+            .flatten();
+        let Some((file, listed)) = scope else {
+            return Ok(self.docs.clone());
+        };
+
+        let mut docs = Vec::new();
+        for doc in listed {
+            if self.manager.is_doc_installed(&doc).await? {
+                docs.push(doc);
+            }
+        }
+        // Searching no docs would search all of them
+        if docs.is_empty() {
+            anyhow::bail!(
+                "None of the docs listed in {} are installed",
+                file.display()
+            );
+        }
+        Ok(docs)
+    }
+
+    async fn hover_markdown(&self, result: &SearchResult) -> Result<String> {
+        let entry = &result.entry;
+        let path = entry.entry.path.to_string_lossy();
+        let mut markdown = format!(
+            "**{}** · {} · {}",
+            entry.entry.name, entry.entry.entry_type, entry.doc_name
+        );
+
+        let signatures = self
+            .manager
+            .signatures(&entry.doc_slug, &entry.entry.name)
+            .await
+            .unwrap_or_default();
+        if !signatures.is_empty() {
+            let language = entry.doc_slug.split('~').next().unwrap_or_default();
+            let lines: Vec<_> = signatures.iter().map(|s| s.text.as_str()).collect();
+            markdown.push_str(&format!("\n\n```{}\n{}\n```", language, lines.join("\n")));
+        }
+
+        let page = page_path(&entry.entry.path);
+        if let Ok(file) = self
+            .manager
+            .page_file(&entry.doc_slug, page, Formats::HTML)
+            .await
+        {
+            let html = tokio::fs::read_to_string(file).await?;
+            let sections = page_sections(page, &entry.entry.name, &html);
+            if let Some((_, _, text)) = sections.into_iter().find(|(p, _, _)| *p == path) {
+                let mut summary: String = text.chars().take(HOVER_SUMMARY_CHARS).collect();
+                if summary.len() < text.len() {
+                    summary.push('…');
+                }
+                markdown.push_str(&format!("\n\n{}", summary));
+            }
+        }
+
+        let url = self.manager.entry_url(&entry.doc_slug, &path);
+        markdown.push_str(&format!("\n\n[Open documentation]({})", url));
+        Ok(markdown)
+    }
+}
+
+/// The possibly qualified symbol (`Vec::push`, `os.path.join`) under a cursor, up to the
+/// end of the word the cursor is on. `character` counts UTF-16 code units, as in LSP.
+fn symbol_at(text: &str, line: usize, character: usize) -> Option<String> {
+    let line: Vec<char> = text.lines().nth(line)?.chars().collect();
+    let mut units = 0;
+    let cursor = line
+        .iter()
+        .position(|c| {
+            units += c.len_utf16();
+            units > character
+        })
+        .unwrap_or(line.len());
+
+    let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+    let is_symbol = |c: &char| is_word(c) || *c == '.' || *c == ':';
+    let end = cursor + line[cursor..].iter().take_while(|c| is_word(c)).count();
+    let start = end
+        - line[..end]
+            .iter()
+            .rev()
+            .take_while(|c| is_symbol(c))
+            .count();
+
+    let symbol: String = line[start..end].iter().collect();
+    let symbol = symbol.trim_matches(|c| c == '.' || c == ':');
+    (symbol.chars().any(|c| is_word(&c))).then(|| symbol.to_string())
+}
+
+/// Local path of a `file://` URI
+fn file_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| std::str::from_utf8(tail.get(..2)?).ok())
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+/// Read one `Content-Length` framed message, or `None` at the end of the input.
+///
+/// Messages that can't be parsed come back as what's wrong with them; only failing to
+/// read the input is an error.
+async fn read_message<R: AsyncBufRead + Unpin>(
+    input: &mut R,
+) -> Result<Option<std::result::Result<Value, String>>> {
+    const CONTENT_LENGTH: &str = "content-length:";
+
+    let mut length = Err("Message without a Content-Length header".to_string());
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header).await? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // The header may trail the body of a message whose length was unreadable
+        if let Some(at) = header.to_ascii_lowercase().rfind(CONTENT_LENGTH) {
+            let value = header[at + CONTENT_LENGTH.len()..].trim();
+            length = value
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid Content-Length '{}'", value));
+        }
+    }
+
+    let length = match length {
+        Ok(length) => length,
+        Err(problem) => return Ok(Some(Err(problem))),
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body).await?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(|e| format!("Invalid JSON: {}", e)),
+    ))
+}
+
+async fn write_message<W: AsyncWrite + Unpin>(output: &mut W, message: &Value) -> Result<()> {
+    let body = serde_json::to_vec(message)?;
+    output
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await?;
+    output.write_all(&body).await?;
+    output.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PROJECT_SCOPE_FILE;
+    use crate::tests::{entry, insert_doc, sample_cached_doc, temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_lsp_hover_documents_the_symbol_under_the_cursor() {
+        use serde_json::json;
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let dir = temp_dir("lsp");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        let mut push = entry("Vec::push", "method");
+        push.path = PathBuf::from("vec#method.push");
+        doc.index.entries = vec![entry("Vec", "struct"), push];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust")).unwrap();
+        std::fs::write(
+            dir.join("rust/vec.html"),
+            "<h1>Vec</h1><h3 id=\"method.push\">push</h3><p>Appends an element.</p>",
+        )
+        .unwrap();
+
+        // A project scoped to docs that aren't installed
+        let project = dir.join("project");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::write(project.join(PROJECT_SCOPE_FILE), "python\n").unwrap();
+        let scoped = format!("file://{}", project.join("main.py").display());
+
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (server_in, server_out) = tokio::io::split(server);
+        let (client_in, mut client_out) = tokio::io::split(client);
+        let mut client_in = BufReader::new(client_in);
+
+        let messages = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {}}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": "untitled:a.rs", "text": "fn main() {\n    v.push(1);\n}"},
+            }}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "textDocument/hover", "params": {
+                "textDocument": {"uri": "untitled:a.rs"}, "position": {"line": 1, "character": 7},
+            }}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "devdocs/search", "params": {"query": "vec"}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "nonsense"}),
+            json!({"jsonrpc": "2.0", "method": "textDocument/didOpen", "params": {
+                "textDocument": {"uri": scoped, "text": "v.push(1)"},
+            }}),
+            json!({"jsonrpc": "2.0", "id": 5, "method": "textDocument/hover", "params": {
+                "textDocument": {"uri": scoped}, "position": {"line": 0, "character": 3},
+            }}),
+            json!({"jsonrpc": "2.0", "method": "exit"}),
+        ];
+        for message in &messages {
+            let body = message.to_string();
+            let framed = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+            client_out.write_all(framed.as_bytes()).await.unwrap();
+        }
+        run(&manager, BufReader::new(server_in), server_out)
+            .await
+            .unwrap();
+
+        let mut responses = Vec::new();
+        for _ in 0..5 {
+            let mut header = String::new();
+            client_in.read_line(&mut header).await.unwrap();
+            let length: usize = header["Content-Length: ".len()..].trim().parse().unwrap();
+            client_in.read_line(&mut String::new()).await.unwrap();
+            let mut body = vec![0; length];
+            client_in.read_exact(&mut body).await.unwrap();
+            responses.push(serde_json::from_slice::<serde_json::Value>(&body).unwrap());
+        }
+
+        assert_eq!(
+            responses[0]["result"]["capabilities"]["hoverProvider"],
+            true
+        );
+        let hover = responses[1]["result"]["contents"]["value"]
+            .as_str()
+            .unwrap();
+        assert!(hover.starts_with("**Vec::push** · method"));
+        assert!(hover.contains("Appends an element."));
+        assert_eq!(responses[2]["result"][0]["entry"]["entry"]["name"], "Vec");
+        assert_eq!(responses[3]["error"]["code"], -32601);
+        // The scope doesn't widen to every doc when none of its docs are installed
+        assert!(
+            responses[4]["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains(".devdocs-scope")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lsp_answers_unreadable_messages_and_keeps_serving() {
+        use tokio::io::BufReader;
+
+        let manager = test_builder(temp_dir("lsp-parse-error")).build().unwrap();
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "nonsense"}).to_string();
+        let input = format!(
+            "Content-Length: 5\r\n\r\n{{oops\
+             Content-Length: many\r\n\r\n{{}}\
+             Content-Length: {}\r\n\r\n{}",
+            request.len(),
+            request
+        );
+
+        let mut output = Vec::new();
+        run(&manager, BufReader::new(input.as_bytes()), &mut output)
+            .await
+            .unwrap();
+
+        let mut output = BufReader::new(output.as_slice());
+        let mut responses = Vec::new();
+        while let Some(message) = read_message(&mut output).await.unwrap() {
+            responses.push(message.unwrap());
+        }
+        assert_eq!(responses.len(), 3);
+        assert_eq!(responses[0]["error"]["code"], PARSE_ERROR);
+        assert!(responses[0]["id"].is_null());
+        assert!(
+            responses[1]["error"]["message"]
+                .as_str()
+                .unwrap()
+                .contains("Content-Length")
+        );
+        assert_eq!(responses[2]["id"], 1);
+        assert_eq!(responses[2]["error"]["code"], METHOD_NOT_FOUND);
+    }
+}
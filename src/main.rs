@@ -1,20 +1,105 @@
-use std::path::PathBuf;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
-use anyhow::Result;
-use clap::{Parser, Subcommand};
-use dev::{DevDocsManager, Formats};
-use dirs;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand, ValueEnum};
+use dev::checklist::{Checklist, ChecklistStep};
+#[cfg(feature = "serve")]
+use dev::service::{Service, ServiceManager};
+#[cfg(feature = "dash")]
+use dev::sources::DashSource;
+use dev::sources::{HtmlDirSource, ManPageSource, RustdocSource, TldrSource};
+use dev::tokio_util::sync::CancellationToken;
+use dev::{
+    BatchOutcome, CaseMatching, CheckStatus, CodeResult, ContentResult, DevDocsManager, DocSource,
+    DownloadReport, Formats, GroupBy, ImageProtocol, ManagerEvent, Plan, SearchOptions,
+    SearchResponse, SearchResult, Theme, UpdateReport, UpdateStatus, anchor_offset, doc_language,
+    find_project_scope, group_results, split_doc_scope, split_fragment,
+};
+use futures::StreamExt;
 use tokio::fs;
-use webbrowser;
 
 #[derive(Parser)]
 #[clap(
     name = "devdocs",
     version = "1.0",
     author = "You <you@example.com>",
-    about = "Manage DevDocs documentation locally"
+    about = "Manage DevDocs documentation locally",
+    after_help = "Installs and updates of several docs exit with 0 when all of them succeed, \
+                  3 when some fail and 4 when all fail."
 )]
 struct Cli {
+    /// Use the docs and settings of this profile, like `work` (default: DEVDOCS_PROFILE)
+    #[clap(long, global = true)]
+    profile: Option<String>,
+
+    /// Write all changes to this directory, treating the data dir as a read-only base
+    #[clap(long, global = true)]
+    overlay: Option<PathBuf>,
+
+    /// Read docs from this read-only store shared by every user too, installing to the
+    /// data dir (defaults to /usr/share/devdocs when it exists)
+    #[clap(long, global = true, conflicts_with = "overlay")]
+    shared: Option<PathBuf>,
+
+    /// Maximum size of the installed docs, in MB
+    #[clap(long, global = true)]
+    quota: Option<u64>,
+
+    /// Remove the least recently used docs when an install would exceed the quota
+    #[clap(long, global = true)]
+    evict: bool,
+
+    /// Store installed docs compressed and render pages only when they're opened
+    #[clap(long, global = true)]
+    lazy_render: bool,
+
+    /// Download the images and stylesheets pages use, so they render offline
+    #[clap(long, global = true)]
+    offline_assets: bool,
+
+    /// Send requests through this proxy (http://, https:// or socks5:// URL)
+    #[clap(long, global = true)]
+    proxy: Option<String>,
+
+    /// Authenticate against the proxy as USER:PASSWORD (or set DEVDOCS_PROXY_AUTH)
+    #[clap(long, global = true, value_name = "USER:PASSWORD", requires = "proxy")]
+    proxy_user: Option<String>,
+
+    /// Trust this additional root certificate (PEM or DER)
+    #[clap(long, global = true)]
+    ca_cert: Vec<PathBuf>,
+
+    /// Language of the tldr pages offered next to the docs (e.g. de, pt_BR)
+    #[clap(long, global = true, default_value = "en")]
+    tldr_language: String,
+
+    /// Embed pages with a local model when installing, and rank searches by meaning too
+    #[cfg(feature = "semantic")]
+    #[clap(long, global = true)]
+    semantic: bool,
+
+    /// Log what's going on: -v for progress, -vv for details and timings, -vvv for everything
+    #[clap(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Only log errors, and don't report download progress
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Theme of the pages opened in a browser, instead of the `theme` setting
+    #[clap(long, global = true, value_enum)]
+    theme: Option<ThemeArg>,
+
+    /// Write the logs to this file instead of stderr
+    #[clap(long, global = true)]
+    log_file: Option<PathBuf>,
+
+    /// Never ask anything, like whether to set up docs on the first run
+    #[clap(long, global = true)]
+    no_input: bool,
+
     #[clap(subcommand)]
     cmd: Commands,
 }
@@ -29,155 +114,2037 @@ enum Commands {
         /// Generate Markdown files
         #[clap(long)]
         md: bool,
-        /// Slugs of docs to install
+        /// Show what would be downloaded without downloading it
+        #[clap(long)]
+        dry_run: bool,
+        /// Don't ask for confirmation before downloading
+        #[clap(short, long)]
+        yes: bool,
+        /// Find the docs by name in this source (e.g. dash) instead of the available docs
+        #[clap(long)]
+        source: Option<String>,
+        /// Install every available doc that isn't installed yet
+        #[clap(long, conflicts_with_all = ["slugs", "source"])]
+        all: bool,
+        /// Print the summary of the installs as JSON
+        #[clap(long)]
+        json: bool,
+        /// Only install the pages of entries of these types, as in `type:macro,keyword`
+        #[clap(long, value_name = "type:TYPES", conflicts_with_all = ["all", "source"])]
+        only: Option<String>,
+        /// Pick the docs to install from a filterable list of the available docs
+        #[clap(short, long, conflicts_with_all = ["all", "source", "slugs", "dry_run"])]
+        interactive: bool,
+        /// Slugs of docs to install, or groups of them like `@web`
         slugs: Vec<String>,
     },
 
     /// Remove one or more docs
     Remove {
-        /// Only remove HTML files
+        /// Only remove HTML files, keeping the doc installed
         #[clap(long)]
         html: bool,
-        /// Only remove Markdown files
+        /// Only remove Markdown files, keeping the doc installed
         #[clap(long)]
         md: bool,
-        /// Slugs of docs to remove
+        /// Show what would be deleted without deleting it
+        #[clap(long)]
+        dry_run: bool,
+        /// Also remove every doc that's no longer available upstream
+        #[clap(long)]
+        orphaned: bool,
+        /// Slugs of docs to remove, or groups of them like `@web`
         slugs: Vec<String>,
     },
 
+    /// List the installed docs, marking those no longer available upstream
+    List {
+        /// List the docs available for download instead
+        #[clap(long)]
+        available: bool,
+        /// Only list the docs in this language, as a two-letter code like `ja`
+        #[clap(long)]
+        lang: Option<String>,
+    },
+
     /// Fuzzy‐search across installed docs
     Search {
-        /// Query string
+        /// Query string, optionally scoped to a doc as in `postgres: jsonb`
         query: String,
         /// Maximum number of results
         #[clap(short, long)]
         limit: Option<usize>,
+        /// Skip this many of the best results, to see the next page
+        #[clap(long, default_value_t = 0)]
+        offset: usize,
         /// Show absolute paths instead of relative
         #[clap(long)]
         full: bool,
+        /// Show devdocs.io links instead of local paths
+        #[clap(long)]
+        url: bool,
+        /// Only search this doc (slug or alias); may be repeated
+        #[clap(short, long = "doc")]
+        docs: Vec<String>,
+        /// Only show entries of this type (e.g. `method`); may be repeated
+        #[clap(short, long = "type")]
+        types: Vec<String>,
+        /// Search every doc, ignoring the docs listed by the project's .devdocs-scope
+        #[clap(long, conflicts_with = "docs")]
+        all: bool,
+        /// Match the query as an exact substring instead of fuzzily
+        #[clap(long)]
+        exact: bool,
+        /// Match case exactly (default: smart case)
+        #[clap(long)]
+        case_sensitive: bool,
+        /// Drop results scoring below this
+        #[clap(long, default_value_t = 0)]
+        min_score: u32,
+        /// Show at most this many results from any single doc
+        #[clap(long)]
+        per_doc: Option<usize>,
+        /// Show each page or section once, under its best-matching name
+        #[clap(long)]
+        collapse: bool,
+        /// Group the results under a heading per doc or entry type
+        #[clap(long, value_enum)]
+        group_by: Option<GroupArg>,
+        /// Search the code examples of the pages instead of the entries
+        #[clap(long)]
+        code: bool,
+        /// Search the text of the pages instead of the entries
+        #[clap(long, conflicts_with = "code")]
+        content: bool,
+        /// Copy the path or link of the best result (or its code, with --code) to the clipboard
+        #[clap(long)]
+        copy: bool,
+        /// Install the doc a query is scoped to without asking, when it isn't installed
+        #[clap(long)]
+        auto_install: bool,
+        /// When nothing installed matches, search devdocs.io in the docs named that aren't
+        /// installed, or else in those of the `remote` setting
+        #[clap(long)]
+        remote: bool,
+        /// Print the page of the best remote result as Markdown
+        #[clap(long, requires = "remote")]
+        fetch: bool,
     },
 
-    /// Update docs by slug, or use "all" to update everything
+    /// Update docs by slug, or every installed doc with --all; without either, list
+    /// the docs that have a newer version
     Update {
-        /// Slugs to update, or the single token "all"
+        /// Slugs (or aliases) of the docs to update
+        slugs: Vec<String>,
+        /// Update every installed doc
+        #[clap(long, conflicts_with = "slugs")]
+        all: bool,
+        /// Download the docs again even when they're current
+        #[clap(long, conflicts_with = "dry_run")]
+        force: bool,
+        /// Print the summary of the updates as JSON
+        #[clap(long)]
+        json: bool,
+        /// Show what would be downloaded without downloading it
+        #[clap(long)]
+        dry_run: bool,
+    },
+
+    /// Show the entries added, removed or moved by the latest update of a doc
+    Changes {
+        /// Slug (or alias) of an installed doc
+        slug: String,
+    },
+
+    /// Export installed docs for reading outside of the CLI
+    Export {
+        /// What to export to
+        #[clap(long, value_enum, default_value_t = ExportFormat::Site)]
+        format: ExportFormat,
+        /// Directory to write the export to, or `-` for stdout with `--format jsonl`
+        dir: PathBuf,
+        /// Docs to export (default: all installed docs)
+        slugs: Vec<String>,
+    },
+
+    /// Print the signature of the best matching function or method
+    Sig {
+        /// Name to look up, e.g. `strftime`
+        query: String,
+        /// Only search this doc (slug or alias); may be repeated
+        #[clap(short, long = "doc")]
+        docs: Vec<String>,
+        /// Copy the signature to the clipboard
+        #[clap(long)]
+        copy: bool,
+    },
+
+    /// Show the page of an entry as a man page
+    Man {
+        /// Slug (or alias) of an installed doc
+        slug: String,
+        /// Name of the entry, e.g. `Vec::push`
+        entry: String,
+    },
+
+    /// Show how much disk space the installed docs take up
+    Usage,
+
+    /// Show counts of the installed docs and entries, and the disk space they take up
+    Stats {
+        /// Print the stats as JSON
+        #[clap(long)]
+        json: bool,
+        /// Print the stats in the Prometheus text format
+        #[clap(long, conflicts_with = "json")]
+        prometheus: bool,
+    },
+
+    /// Show past search queries
+    History {
+        /// Number of most recent queries to show
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Forget all past queries
+        #[clap(long)]
+        clear: bool,
+    },
+
+    /// Show recently viewed pages
+    Recent {
+        /// Number of pages to show
+        #[clap(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+    },
+
+    /// Define an alias for a doc, or list aliases when called without arguments
+    Alias {
+        /// Remove the alias instead of defining it
+        #[clap(long)]
+        remove: bool,
+        /// Alias to define (e.g. `js`)
+        alias: Option<String>,
+        /// Slug the alias points at (e.g. `javascript`)
+        slug: Option<String>,
+    },
+
+    /// Read and change settings
+    Config {
+        #[clap(subcommand)]
+        action: ConfigAction,
+    },
+
+    /// Install docs from somewhere other than DevDocs
+    Import {
+        /// Generate HTML files
+        #[clap(long, global = true)]
+        html: bool,
+        /// Generate Markdown files
+        #[clap(long, global = true)]
+        md: bool,
+        #[clap(subcommand)]
+        source: ImportSource,
+    },
+
+    /// Check connectivity, the data dir and the installed docs, suggesting fixes
+    Doctor {
+        /// Address `dev serve` is expected to answer on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        serve: std::net::SocketAddr,
+    },
+
+    /// Check installed docs against the checksums recorded when they were installed
+    Verify {
+        /// Slugs (or aliases) of the docs to check, all installed docs when omitted
+        slugs: Vec<String>,
+    },
+
+    /// Answer editor hovers from the installed docs, as a language server on stdio
+    Lsp,
+
+    /// Serve the installed docs over a local web UI and JSON API
+    #[cfg(feature = "serve")]
+    Serve {
+        /// Address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+    },
+
+    /// Run the docs server as a systemd or launchd user service, starting with the session
+    #[cfg(feature = "serve")]
+    Service {
+        #[clap(subcommand)]
+        action: ServiceAction,
+    },
+
+    /// Include docs in searches again
+    Enable {
+        /// Slugs or aliases of the docs
+        #[clap(required = true)]
         slugs: Vec<String>,
     },
 
+    /// Leave docs out of searches without removing them, or list disabled docs when
+    /// called without arguments
+    Disable {
+        /// Slugs or aliases of the docs
+        slugs: Vec<String>,
+    },
+
+    /// Render the pages of installed docs again from what's stored, without downloading
+    Render {
+        /// Docs to render
+        #[clap(required = true)]
+        slugs: Vec<String>,
+        /// Formats to render the pages in
+        #[clap(long, value_enum, required = true)]
+        to: Vec<RenderFormat>,
+    },
+
+    /// Read a single page of a doc without installing the doc
+    Fetch {
+        /// Slug (or alias) of the doc
+        slug: String,
+        /// Page path within the doc, e.g. `concepts/workloads/controllers/deployment`
+        path: String,
+        /// Print the page as plain text instead of Markdown
+        #[clap(long)]
+        text: bool,
+    },
+
     /// Preview a doc file (relative to cache or an absolute path)
     Preview {
-        /// Path to the file to preview (.md → stdout, .html → browser)
+        /// Path to the file to preview (.md and .txt → stdout, .html → browser), with a
+        /// `#fragment` to start at a section
         path: String,
+        /// Copy the first code example of the page (or its link, with --url) to the clipboard
+        #[clap(long)]
+        copy: bool,
+        /// Print the page's devdocs.io link instead of opening it
+        #[clap(long)]
+        url: bool,
+        /// How images of markdown pages are drawn in the terminal
+        #[clap(long, value_enum, default_value_t = ImagesArg::Auto)]
+        images: ImagesArg,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+enum ExportFormat {
+    /// Static website with an index and a table of contents per doc
+    Site,
+    /// One EPUB book per doc
+    Epub,
+    /// One self-contained HTML file per doc
+    HtmlSingle,
+    /// Every searchable entry as JSON Lines, in entries.jsonl or on stdout with `-`
+    Jsonl,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RenderFormat {
+    Md,
+    /// Plain text, without markdown markup
+    Text,
+    Html,
+}
+
+impl From<RenderFormat> for Formats {
+    fn from(format: RenderFormat) -> Self {
+        match format {
+            RenderFormat::Md => Formats::MARKDOWN,
+            RenderFormat::Text => Formats::TEXT,
+            RenderFormat::Html => Formats::HTML,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum ConfigAction {
+    /// Set a setting, e.g. `weight.rust 2.0` to rank rust results twice as high, or
+    /// `clean true` to strip site chrome from the pages of docs installed from now on, or
+    /// `converter.markdown "pandoc -f html -t gfm"` to convert pages with pandoc, or
+    /// `remote kubernetes,postgresql~16` for the docs `search --remote` falls back to, or
+    /// `group.backend postgresql~16,redis` to add and remove them together as `@backend`, or
+    /// `mathjax ~/mathjax/tex-mml-chtml.js` to typeset formulas with a copy of MathJax
+    Set { key: String, value: String },
+    /// Print the value of a setting
+    Get { key: String },
+    /// Reset a setting to its default
+    Unset { key: String },
+    /// List every setting
+    List,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Subcommand)]
+enum ServiceAction {
+    /// Install and start the service, running this binary with the global flags given
+    Install {
+        /// What the service runs
+        #[clap(long, value_enum, default_value = "serve")]
+        mode: ServiceMode,
+        /// Address the server listens on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: std::net::SocketAddr,
+    },
+    /// Stop the service and remove it
+    Uninstall,
+}
+
+#[cfg(feature = "serve")]
+#[derive(Clone, Copy, ValueEnum)]
+enum ServiceMode {
+    /// `dev serve`, the web UI and JSON API
+    Serve,
+}
+
+#[derive(Subcommand)]
+enum ImportSource {
+    /// The crates documented by `cargo doc`, one doc per crate
+    Cargo {
+        /// Output directory of `cargo doc`
+        #[clap(default_value = "target/doc")]
+        dir: PathBuf,
+        /// Only import these crates instead of every documented one
+        #[clap(long = "crate")]
+        crates: Vec<String>,
+    },
+    /// A directory of HTML pages, e.g. an exported wiki, as one doc
+    Html {
+        /// Slug to install the doc as
+        name: String,
+        /// Directory holding the pages
+        dir: PathBuf,
+        /// Only index pages matching these globs (default: every .html page)
+        #[clap(long = "entries")]
+        globs: Vec<String>,
     },
 }
 
+#[derive(Clone, Copy, ValueEnum)]
+enum GroupArg {
+    Doc,
+    Type,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ThemeArg {
+    /// Follow the browser's light or dark preference
+    Auto,
+    Light,
+    Dark,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum ImagesArg {
+    /// Whatever the terminal supports, or alt text
+    Auto,
+    Kitty,
+    Iterm2,
+    Sixel,
+    /// Alt text only
+    None,
+}
+
+impl ImagesArg {
+    fn protocol(self) -> Option<ImageProtocol> {
+        match self {
+            ImagesArg::Auto => ImageProtocol::detect(),
+            ImagesArg::Kitty => Some(ImageProtocol::Kitty),
+            ImagesArg::Iterm2 => Some(ImageProtocol::Iterm2),
+            ImagesArg::Sixel => Some(ImageProtocol::Sixel),
+            ImagesArg::None => None,
+        }
+    }
+}
+
+impl From<ThemeArg> for Theme {
+    fn from(arg: ThemeArg) -> Self {
+        match arg {
+            ThemeArg::Auto => Theme::Auto,
+            ThemeArg::Light => Theme::Light,
+            ThemeArg::Dark => Theme::Dark,
+        }
+    }
+}
+
+/// A token cancelled by Ctrl-C, so long downloads stop cleanly instead of being killed
+/// mid-write. Only commands watching the token take over Ctrl-C; it still ends the others.
+fn cancel_on_ctrl_c() -> CancellationToken {
+    let cancel = CancellationToken::new();
+    tokio::spawn({
+        let cancel = cancel.clone();
+        async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
+            }
+        }
+    });
+    cancel
+}
+
+/// `file://` URL of an absolute path
+fn file_url(path: &Path) -> String {
+    let path = path.to_string_lossy().replace('\\', "/");
+    // Windows paths start with the drive letter
+    let path = if path.starts_with('/') {
+        path
+    } else {
+        format!("/{}", path)
+    };
+    format!("file://{}", path.replace('%', "%25").replace(' ', "%20"))
+}
+
+impl From<GroupArg> for GroupBy {
+    fn from(arg: GroupArg) -> Self {
+        match arg {
+            GroupArg::Doc => GroupBy::Doc,
+            GroupArg::Type => GroupBy::Type,
+        }
+    }
+}
+
+fn result_path(r: &SearchResult, full: bool) -> PathBuf {
+    let rel_full = PathBuf::from(&r.entry.doc_slug).join(&r.entry.entry.path);
+
+    let rel = rel_full.parent().unwrap().into();
+
+    if full { rel_full } else { rel }
+}
+
+fn print_result(r: &SearchResult, location: &str) {
+    println!("{}\t{}", location, r.entry.entry.name);
+}
+
+/// Print a page match as its page and title followed by the snippet, matches in bold
+fn print_content_result(r: &ContentResult) {
+    println!("{}/{}\t{}", r.doc_slug, r.page, r.title);
+    let bold = std::io::stdout().is_terminal();
+    let mut snippet = String::new();
+    let mut last = 0;
+    for range in &r.highlights {
+        if range.start < last || range.end > r.snippet.len() {
+            continue;
+        }
+        snippet.push_str(&r.snippet[last..range.start]);
+        if bold {
+            snippet.push_str(&format!("\x1b[1m{}\x1b[0m", &r.snippet[range.clone()]));
+        } else {
+            snippet.push_str(&r.snippet[range.clone()]);
+        }
+        last = range.end;
+    }
+    snippet.push_str(&r.snippet[last..]);
+    println!("    {}", snippet);
+}
+
+/// Put text on the system clipboard
+#[cfg(feature = "clipboard")]
+fn copy_to_clipboard(text: &str) -> Result<()> {
+    #[cfg(target_os = "linux")]
+    hand_to_clipboard_daemon(text)?;
+    #[cfg(not(target_os = "linux"))]
+    arboard::Clipboard::new()
+        .context("clipboard unavailable")?
+        .set_text(text)?;
+    eprintln!("📋 copied to clipboard");
+    Ok(())
+}
+
+/// Set when the process was spawned to hold the clipboard, see [`hand_to_clipboard_daemon`]
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+const CLIPBOARD_DAEMON: &str = "DEV_CLIPBOARD_DAEMON";
+
+/// X11 and Wayland only keep a copy while the program that made it is running, so the
+/// text goes to a detached copy of this binary that serves it until something else is
+/// copied
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+fn hand_to_clipboard_daemon(text: &str) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Command, Stdio};
+
+    let mut daemon = Command::new(std::env::current_exe()?)
+        .env(CLIPBOARD_DAEMON, "1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .context("clipboard unavailable")?;
+    daemon
+        .stdin
+        .take()
+        .context("clipboard unavailable")?
+        .write_all(text.as_bytes())?;
+    Ok(())
+}
+
+/// Hold the text on stdin in the clipboard until it's replaced
+#[cfg(all(feature = "clipboard", target_os = "linux"))]
+fn serve_clipboard() -> Result<()> {
+    use arboard::SetExtLinux;
+    use std::io::Read;
+
+    let mut text = String::new();
+    std::io::stdin().read_to_string(&mut text)?;
+    arboard::Clipboard::new()?.set().wait().text(text)?;
+    Ok(())
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_to_clipboard(_text: &str) -> Result<()> {
+    anyhow::bail!("built without clipboard support (the `clipboard` feature)")
+}
+
+/// Print a code result as its source page followed by the lines around the match
+fn print_code_result(r: &CodeResult) {
+    match &r.block.language {
+        Some(language) => println!("{}/{} [{}]", r.doc_slug, r.block.page, language),
+        None => println!("{}/{}", r.doc_slug, r.block.page),
+    }
+    for line in r.block.code.lines().skip(r.line.saturating_sub(2)).take(5) {
+        println!("    {}", line);
+    }
+}
+
+/// The docs to search: the ones asked for, or else those listed by the `.devdocs-scope`
+/// of the project the working directory belongs to
+async fn project_docs(mgr: &DevDocsManager, docs: Vec<String>) -> Result<Vec<String>> {
+    if !docs.is_empty() {
+        return Ok(docs);
+    }
+    let Some((file, listed)) = find_project_scope(&std::env::current_dir()?)? else {
+        return Ok(docs);
+    };
+
+    let mut scope = Vec::new();
+    for doc in listed {
+        if mgr.is_doc_installed(&doc).await? {
+            scope.push(doc);
+        } else {
+            eprintln!("⚠ `{}` from {} is not installed", doc, file.display());
+        }
+    }
+    // Searching no docs would search all of them
+    if scope.is_empty() {
+        anyhow::bail!(
+            "none of the docs listed in {} are installed",
+            file.display()
+        );
+    }
+    Ok(scope)
+}
+
+/// Let the user check the docs to install off the available docs, returning their slugs
+async fn pick_docs(mgr: &DevDocsManager) -> Result<Vec<String>> {
+    if !std::io::stdin().is_terminal() {
+        anyhow::bail!("--interactive needs a terminal, name the docs to add instead");
+    }
+    let installed = mgr.list_installed_docs().await?;
+    let mut checklist = Checklist::new(mgr.get_available_docs().await?, &installed);
+    loop {
+        eprint!("{}> ", checklist.render());
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(Vec::new());
+        }
+        match checklist.handle(&line) {
+            ChecklistStep::Continue => eprintln!(),
+            ChecklistStep::Done => return Ok(checklist.selection()),
+            ChecklistStep::Quit => return Ok(Vec::new()),
+        }
+    }
+}
+
+/// Offer to install the docs the current project needs, the first time `dev` runs
+async fn onboard(mgr: &DevDocsManager) -> Result<()> {
+    eprintln!("👋 no docs are installed yet, fetching the list of available docs…");
+    let onboarding = mgr.onboard(&std::env::current_dir()?).await?;
+    eprintln!("{} docs are available", onboarding.available);
+    if onboarding.suggested.is_empty() {
+        eprintln!("add some with `dev add <slug>`, or pick them with `dev add --interactive`");
+        return Ok(());
+    }
+
+    eprintln!("this project looks like it could use:");
+    for doc in &onboarding.suggested {
+        eprintln!(
+            "  {}\t{} (~{:.1} MB)",
+            doc.slug,
+            doc.name,
+            doc.db_size as f64 / 1e6
+        );
+    }
+    if !confirm("install them?")? {
+        eprintln!("add docs later with `dev add <slug>`");
+        return Ok(());
+    }
+    for doc in &onboarding.suggested {
+        match mgr.add_doc(&doc.slug, Some(Formats::MARKDOWN)).await {
+            Ok(()) => eprintln!("✅ installed `{}`", doc.slug),
+            Err(e) => eprintln!("❌ `{}` failed: {:#}", doc.slug, e),
+        }
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal; without one, the answer is no
+fn confirm(question: &str) -> Result<bool> {
+    if !std::io::stdin().is_terminal() {
+        return Ok(false);
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// The global flags of `cli` a service has to be started with to use the same docs
+/// and settings, with paths made absolute
+#[cfg(feature = "serve")]
+fn service_flags(cli: &Cli, profile: Option<&str>) -> Result<Vec<String>> {
+    let path = |path: &Path| -> Result<String> {
+        Ok(std::path::absolute(path)?.to_string_lossy().into_owned())
+    };
+    let mut flags = Vec::new();
+    if let Some(profile) = profile {
+        flags.push(format!("--profile={}", profile));
+    }
+    if let Some(overlay) = &cli.overlay {
+        flags.push(format!("--overlay={}", path(overlay)?));
+    }
+    if let Some(shared) = &cli.shared {
+        flags.push(format!("--shared={}", path(shared)?));
+    }
+    if let Some(quota) = cli.quota {
+        flags.push(format!("--quota={}", quota));
+    }
+    if cli.evict {
+        flags.push("--evict".to_string());
+    }
+    if cli.lazy_render {
+        flags.push("--lazy-render".to_string());
+    }
+    if cli.offline_assets {
+        flags.push("--offline-assets".to_string());
+    }
+    if let Some(proxy) = &cli.proxy {
+        flags.push(format!("--proxy={}", proxy));
+    }
+    if let Some(user) = &cli.proxy_user {
+        flags.push(format!("--proxy-user={}", user));
+    }
+    for cert in &cli.ca_cert {
+        flags.push(format!("--ca-cert={}", path(cert)?));
+    }
+    if cli.tldr_language != "en" {
+        flags.push(format!("--tldr-language={}", cli.tldr_language));
+    }
+    #[cfg(feature = "semantic")]
+    if cli.semantic {
+        flags.push("--semantic".to_string());
+    }
+    if let Some(theme) = cli.theme
+        && let Some(value) = theme.to_possible_value()
+    {
+        flags.push(format!("--theme={}", value.get_name()));
+    }
+    if let Some(log_file) = &cli.log_file {
+        flags.push(format!("--log-file={}", path(log_file)?));
+    }
+    if cli.verbose > 0 {
+        flags.push(format!("-{}", "v".repeat(cli.verbose as usize)));
+    }
+    // A service has nobody to answer questions
+    flags.push("--no-input".to_string());
+    Ok(flags)
+}
+
+/// Run the commands (un)loading a service, telling how to run the ones that fail by hand
+#[cfg(feature = "serve")]
+fn run_service_commands(commands: &[Vec<String>]) {
+    for command in commands {
+        let status = std::process::Command::new(&command[0])
+            .args(&command[1..])
+            .status();
+        if !matches!(status, Ok(status) if status.success()) {
+            eprintln!("⚠ couldn't run `{}`, run it yourself", command.join(" "));
+        }
+    }
+}
+
+fn print_plan(plan: &Plan) {
+    for download in &plan.downloads {
+        println!(
+            "would download `{}` ({}, ~{:.1} MB)",
+            download.slug,
+            download.name,
+            download.bytes as f64 / 1e6
+        );
+    }
+    for path in &plan.removals {
+        println!("would remove {}", path.display());
+    }
+    for slug in &plan.orphaned {
+        println!("would keep `{}`, it's no longer available upstream", slug);
+    }
+    if !plan.downloads.is_empty() {
+        println!(
+            "{} docs, ~{:.1} MB in total",
+            plan.downloads.len(),
+            plan.download_bytes() as f64 / 1e6
+        );
+    }
+}
+
+/// Exit code of a batch in which some docs failed
+const EXIT_PARTIAL: u8 = 3;
+/// Exit code of a batch in which every doc failed
+const EXIT_FAILED: u8 = 4;
+
+fn batch_exit_code(outcome: BatchOutcome) -> ExitCode {
+    match outcome {
+        BatchOutcome::Ok => ExitCode::SUCCESS,
+        BatchOutcome::Partial => ExitCode::from(EXIT_PARTIAL),
+        BatchOutcome::Failed => ExitCode::from(EXIT_FAILED),
+    }
+}
+
+/// A line per installed or failed doc, then the totals
+fn print_download_report(report: &DownloadReport) {
+    let width = report
+        .installed
+        .iter()
+        .chain(report.failed.iter().map(|(slug, _)| slug))
+        .map(|slug| slug.len())
+        .max()
+        .unwrap_or(0);
+    for slug in &report.installed {
+        println!("{:width$}  installed", slug);
+    }
+    for (slug, reason) in &report.failed {
+        println!("{:width$}  failed     {}", slug, reason);
+    }
+    println!(
+        "{} installed, {} failed",
+        report.installed.len(),
+        report.failed.len()
+    );
+}
+
+/// A line per updated, current or failed doc, then the totals
+fn print_update_report(report: &UpdateReport) {
+    let width = report
+        .updated
+        .iter()
+        .map(|(slug, _)| slug)
+        .chain(&report.current)
+        .chain(&report.orphaned)
+        .chain(report.failed.iter().map(|(slug, _)| slug))
+        .map(|slug| slug.len())
+        .max()
+        .unwrap_or(0);
+    for (slug, pages) in &report.updated {
+        println!(
+            "{:width$}  updated  +{} ~{} -{} pages",
+            slug, pages.added, pages.modified, pages.removed
+        );
+    }
+    for slug in &report.current {
+        println!("{:width$}  current", slug);
+    }
+    for slug in &report.orphaned {
+        println!("{:width$}  orphaned (removed upstream, kept)", slug);
+    }
+    for (slug, reason) in &report.failed {
+        println!("{:width$}  failed   {}", slug, reason);
+    }
+    println!(
+        "{} updated, {} current, {} orphaned, {} failed",
+        report.updated.len(),
+        report.current.len(),
+        report.orphaned.len(),
+        report.failed.len()
+    );
+    if !report.orphaned.is_empty() {
+        println!("Remove orphaned docs with `dev remove --orphaned`, or keep them as they are");
+    }
+}
+
+/// Log to stderr, or to `log_file`, at the level picked by -v and -q. `RUST_LOG`
+/// overrides the flags.
+fn init_logging(verbose: u8, quiet: bool, log_file: Option<&Path>) -> Result<()> {
+    use tracing_subscriber::EnvFilter;
+    use tracing_subscriber::fmt::format::FmtSpan;
+
+    // Dependencies only get chattier than warnings with -vvv
+    let directives = match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "warn,dev=info",
+        (false, 2) => "warn,dev=debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(directives));
+    // How long downloads and installs took is logged as their spans close
+    let spans = if verbose >= 2 {
+        FmtSpan::CLOSE
+    } else {
+        FmtSpan::NONE
+    };
+    let subscriber = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(spans);
+
+    match log_file {
+        Some(path) => {
+            let file = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("can't open log file {}", path.display()))?;
+            subscriber
+                .with_ansi(false)
+                .with_writer(std::sync::Mutex::new(file))
+                .init();
+        }
+        None => subscriber.with_writer(std::io::stderr).init(),
+    }
+    Ok(())
+}
+
 #[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
+async fn main() -> Result<ExitCode> {
+    #[cfg(all(feature = "clipboard", target_os = "linux"))]
+    if std::env::var_os(CLIPBOARD_DAEMON).is_some() {
+        serve_clipboard()?;
+        return Ok(ExitCode::SUCCESS);
+    }
     let cli = Cli::parse();
+    #[cfg(feature = "serve")]
+    let service_profile = cli
+        .profile
+        .clone()
+        .or_else(|| std::env::var("DEVDOCS_PROFILE").ok());
+    #[cfg(feature = "serve")]
+    let service_flags = service_flags(&cli, service_profile.as_deref())?;
+    init_logging(cli.verbose, cli.quiet, cli.log_file.as_deref())?;
+    #[cfg(feature = "semantic")]
+    let semantic = cli.semantic;
+    #[cfg(not(feature = "semantic"))]
+    let semantic = false;
 
-    // reconstruct the same data directory the library uses
-    let data_dir = dirs::data_local_dir()
-        .expect("couldn’t find local data dir")
-        .join("devdocs");
+    let mut builder = DevDocsManager::builder();
+    if let Some(profile) = cli.profile {
+        builder = builder.profile(profile);
+    }
+    if let Some(overlay) = cli.overlay {
+        builder = builder.overlay_dir(overlay);
+    }
+    if let Some(shared) = cli.shared {
+        builder = builder.shared_dir(shared);
+    }
+    if let Some(quota) = cli.quota {
+        builder = builder.quota(quota * 1_000_000);
+    }
+    if let Some(theme) = cli.theme {
+        builder = builder.theme(theme.into());
+    }
+    builder = builder
+        .evict_lru(cli.evict)
+        .lazy_render(cli.lazy_render)
+        .offline_assets(cli.offline_assets);
+    #[cfg(feature = "semantic")]
+    {
+        builder = builder.local_embeddings(semantic);
+    }
+    if let Some(proxy) = cli.proxy {
+        builder = builder.proxy(proxy);
+    }
+    if let Some(user) = &cli.proxy_user {
+        let (username, password) = user.split_once(':').unwrap_or((user, ""));
+        builder = builder.proxy_auth(username, password);
+    }
+    for cert in cli.ca_cert {
+        builder = builder.add_root_certificate(cert);
+    }
+    #[cfg(unix)]
+    {
+        builder = builder.source(ManPageSource::new());
+    }
+    builder = builder.source(TldrSource::new().language(cli.tldr_language));
+    #[cfg(feature = "dash")]
+    {
+        builder = builder.source(DashSource::new());
+    }
 
-    let mgr = DevDocsManager::new()?;
-    mgr.init().await?;
+    let mgr = builder.build()?;
+    // The doctor reports a data dir that can't be set up instead of failing on it
+    let initialized = mgr.init().await;
+    if !matches!(cli.cmd, Commands::Doctor { .. }) {
+        initialized?;
+    }
 
+    // Report progress of downloads as the library makes it
+    if !cli.quiet {
+        tokio::spawn({
+            let events = mgr.subscribe();
+            async move {
+                futures::pin_mut!(events);
+                while let Some(event) = events.next().await {
+                    match event {
+                        ManagerEvent::DownloadStarted { slug, bytes } => {
+                            eprintln!("⬇ downloading `{}` (~{:.1} MB)", slug, bytes as f64 / 1e6);
+                        }
+                        ManagerEvent::UpdateSkipped { slug } => {
+                            eprintln!("✓ `{}` is already up to date", slug);
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        });
+    }
+
+    // Commands that already say what to install, or talk over stdin, skip the first-run setup
+    let asks = !matches!(
+        cli.cmd,
+        Commands::Add { .. } | Commands::Lsp | Commands::Doctor { .. }
+    );
+    if asks
+        && !cli.no_input
+        && std::io::stdin().is_terminal()
+        && mgr.needs_onboarding().await
+        && let Err(e) = onboard(&mgr).await
+    {
+        eprintln!("⚠ skipping the first-run setup: {:#}", e);
+    }
+
+    let mut exit = ExitCode::SUCCESS;
     match cli.cmd {
-        Commands::Add { html, md, slugs } => {
-            for slug in slugs {
-                // install the binary cache + markdown
-                println!("✅ installed `{}` (markdown)", slug);
+        Commands::Add {
+            dry_run: true,
+            source: Some(source),
+            ..
+        } => anyhow::bail!("docs found in {} are only sized once downloaded", source),
 
-                if !html && !md {
-                    mgr.add_doc(&slug, None).await?;
-                }
+        Commands::Add {
+            html,
+            md,
+            dry_run: true,
+            source: None,
+            all,
+            slugs,
+            ..
+        } => {
+            let slugs = mgr.expand_groups(&slugs).await?;
+            let mut formats = Formats::empty();
+            formats.set(Formats::HTML, html);
+            formats.set(Formats::MARKDOWN, md || !html);
+            if all {
+                print_plan(&mgr.plan_download_all().await?);
+            } else {
+                print_plan(&mgr.plan_add(&slugs, Some(formats)).await?);
+            }
+        }
 
-                if html {
-                    mgr.add_doc(&slug, Some(Formats::HTML)).await?;
+        Commands::Add {
+            html,
+            md,
+            yes,
+            source,
+            all,
+            json,
+            only,
+            interactive,
+            slugs,
+            ..
+        } => {
+            let slugs = if interactive {
+                let picked = pick_docs(&mgr).await?;
+                if picked.is_empty() {
+                    eprintln!("nothing selected");
+                    return Ok(ExitCode::SUCCESS);
+                }
+                picked
+            } else {
+                mgr.expand_groups(&slugs).await?
+            };
+            let only: Option<Vec<String>> = match only.as_deref() {
+                None => None,
+                Some(filter) => match filter.strip_prefix("type:") {
+                    Some(types) => Some(
+                        types
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|t| !t.is_empty())
+                            .map(str::to_string)
+                            .collect(),
+                    ),
+                    None => anyhow::bail!("unknown filter `{}`, use type:<types>", filter),
+                },
+            };
+            // Markdown unless asked otherwise
+            let mut formats = Formats::empty();
+            formats.set(Formats::HTML, html);
+            formats.set(Formats::MARKDOWN, md || !html);
+            // Docs found by name are only sized once downloaded
+            let (bytes, docs) = match (&source, all) {
+                (_, true) => {
+                    let plan = mgr.plan_download_all().await?;
+                    (plan.download_bytes(), plan.downloads.len())
+                }
+                (None, false) => {
+                    let plan = mgr.plan_add(&slugs, Some(formats)).await?;
+                    (plan.download_bytes(), plan.downloads.len())
                 }
+                (Some(_), false) => (0, 0),
+            };
+            if docs > 0 {
+                eprintln!(
+                    "this will download ~{:.1} MB ({} docs)",
+                    bytes as f64 / 1e6,
+                    docs
+                );
+                if !yes && !std::io::stdin().is_terminal() {
+                    anyhow::bail!("no terminal to confirm the download on, pass --yes to go ahead");
+                }
+                if !yes && !confirm("continue?")? {
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+
+            let label = match (
+                formats.contains(Formats::HTML),
+                formats.contains(Formats::MARKDOWN),
+            ) {
+                (true, true) => "html + markdown",
+                (true, false) => "html",
+                _ => "markdown",
+            };
 
-                if md {
-                    mgr.add_doc(&slug, Some(Formats::MARKDOWN)).await?;
+            let batch = all || slugs.len() > 1;
+            let cancel = cancel_on_ctrl_c();
+            let report = if all {
+                mgr.download_all_with_cancel(formats, &cancel).await?
+            } else {
+                let mut report = DownloadReport::default();
+                for slug in slugs {
+                    let installed = match (&source, &only) {
+                        (Some(source), _) => {
+                            mgr.add_doc_from(source, &slug, Some(formats), &cancel)
+                                .await
+                        }
+                        (None, Some(types)) => mgr
+                            .add_doc_filtered(&slug, types, Some(formats))
+                            .await
+                            .map(|()| slug.clone()),
+                        (None, None) => mgr
+                            .add_doc_with_cancel(&slug, Some(formats), &cancel)
+                            .await
+                            .map(|()| slug.clone()),
+                    };
+                    match installed {
+                        Ok(slug) => {
+                            if !json {
+                                println!("✅ installed `{}` ({})", slug, label);
+                            }
+                            report.installed.push(slug);
+                        }
+                        // The rest of the batch would only fail the same way
+                        Err(e) if cancel.is_cancelled() => return Err(e),
+                        Err(e) => {
+                            if !json {
+                                eprintln!("❌ `{}` failed: {:#}", slug, e);
+                            }
+                            report.failed.push((slug, format!("{:#}", e)));
+                        }
+                    }
                 }
+                report
+            };
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else if batch {
+                print_download_report(&report);
             }
+            exit = batch_exit_code(report.outcome());
         }
 
-        Commands::Remove { html, md, slugs } => {
-            let do_html = html || (!html && !md);
-            let do_md = md || (!html && !md);
+        Commands::Remove {
+            dry_run: true,
+            orphaned,
+            slugs,
+            ..
+        } => {
+            let mut slugs = mgr.expand_groups(&slugs).await?;
+            if orphaned {
+                let mut orphans = mgr.orphaned_docs().await?;
+                orphans.retain(|slug| !slugs.contains(slug));
+                slugs.extend(orphans);
+            }
+            print_plan(&mgr.plan_remove(&slugs).await?)
+        }
 
-            for slug in slugs {
-                if !mgr.is_doc_installed(&slug).await? {
-                    eprintln!("⚠ `{}` is not installed", slug);
+        Commands::Remove {
+            html,
+            md,
+            orphaned,
+            slugs,
+            ..
+        } => {
+            let mut slugs = mgr.expand_groups(&slugs).await?;
+            if orphaned {
+                let mut orphans = mgr.orphaned_docs().await?;
+                orphans.retain(|slug| !slugs.contains(slug));
+                slugs.extend(orphans);
+            }
+            for name in slugs {
+                // Aliases name the same doc, and its pages live under the slug
+                let Some(slug) = mgr.resolve_installed(&name).await else {
+                    eprintln!("⚠ `{}` is not installed", name);
+                    continue;
+                };
+
+                // Only drop the requested format, keeping the doc installed
+                if html != md {
+                    let format = if html {
+                        Formats::HTML
+                    } else {
+                        Formats::MARKDOWN
+                    };
+                    mgr.remove_formats(&slug, format).await?;
+                    let label = if html { "html" } else { "markdown" };
+                    println!("🗑 removed {} files for `{}`", label, slug);
                     continue;
                 }
-                // remove from cache
+
                 mgr.remove_doc(&slug).await?;
-                println!("🗑 removed cache for `{}`", slug);
+                println!("🗑 removed `{}`", slug);
+            }
+        }
 
-                let doc_dir = data_dir.join(&slug);
-                if do_md && doc_dir.exists() {
-                    // remove all .md under that dir
-                    let _ = fs::remove_dir_all(&doc_dir).await;
-                    println!("🗑 removed markdown files for `{}`", slug);
+        Commands::List {
+            available: true,
+            lang,
+        } => {
+            let installed = mgr.list_installed_docs().await?;
+            for doc in mgr.get_available_docs().await? {
+                if lang
+                    .as_deref()
+                    .is_some_and(|lang| doc_language(&doc.slug) != lang)
+                {
+                    continue;
                 }
-                if do_html {
-                    // TODO: same for html files once supported
-                    eprintln!("⚠ html‐only removal isn’t yet supported by the library");
+                if installed.contains(&doc.slug) {
+                    println!("{}\t{}\t(installed)", doc.slug, doc.name);
+                } else {
+                    println!("{}\t{}", doc.slug, doc.name);
                 }
             }
         }
 
-        Commands::Search { query, limit, full } => {
-            let results = mgr.search(&query, limit).await?;
-            for r in results {
-                let rel_full = PathBuf::from(&r.entry.doc_slug).join(&r.entry.entry.path);
+        Commands::List { lang, .. } => {
+            let orphaned = mgr.orphaned_docs().await?;
+            let mut slugs = mgr.list_installed_docs().await?;
+            slugs.retain(|slug| {
+                lang.as_deref()
+                    .is_none_or(|lang| doc_language(slug) == lang)
+            });
+            slugs.sort();
+            for slug in slugs {
+                let doc = mgr.get_doc_info(&slug).await?;
+                if orphaned.contains(&slug) {
+                    println!("{}\t{}\t(orphaned upstream)", slug, doc.name);
+                } else {
+                    println!("{}\t{}", slug, doc.name);
+                }
+            }
+            if !orphaned.is_empty() {
+                eprintln!(
+                    "{} doc(s) are no longer available upstream and won't be updated; \
+                     keep them, or remove them with `dev remove --orphaned`",
+                    orphaned.len()
+                );
+            }
+        }
 
-                let rel = rel_full.parent().unwrap().into();
+        Commands::Search {
+            query,
+            limit,
+            offset,
+            full,
+            url,
+            docs,
+            types,
+            all,
+            exact,
+            case_sensitive,
+            min_score,
+            per_doc,
+            collapse,
+            group_by,
+            code,
+            content,
+            copy,
+            auto_install,
+            remote,
+            fetch,
+        } => {
+            let (query, docs) = match split_doc_scope(&query) {
+                Some((doc, rest)) => {
+                    // Remote searches read docs that aren't installed as they are
+                    let missing = !mgr.is_doc_installed(doc).await?;
+                    if missing && (auto_install || !remote) {
+                        let question = format!("`{}` isn't installed, install it now?", doc);
+                        let install = auto_install || confirm(&question)?;
+                        if !install {
+                            anyhow::bail!(
+                                "`{}` is not installed (install it with --auto-install)",
+                                doc
+                            );
+                        }
+                        let slug = mgr.ensure_installed(doc).await?;
+                        eprintln!("✅ installed `{}`", slug);
+                    }
+                    (rest.to_string(), vec![doc.to_string()])
+                }
+                None if all => (query, docs),
+                None => (query.clone(), project_docs(&mgr, docs).await?),
+            };
+            let mut options = SearchOptions {
+                docs,
+                types,
+                offset,
+                exact,
+                min_score,
+                max_per_doc: per_doc,
+                remote,
+                collapse_duplicates: collapse,
+                ..Default::default()
+            };
+            if case_sensitive {
+                options.case_matching = CaseMatching::Respect;
+            }
+            if let Some(limit) = limit {
+                options.limit = limit;
+            }
 
-                let display_path = if full { rel_full } else { rel };
-                println!("{}\t{}", display_path.display(), r.entry.entry.name);
+            if code {
+                let results = mgr.search_code(&query, &options).await?;
+                for (i, r) in results.iter().enumerate() {
+                    if i > 0 {
+                        println!();
+                    }
+                    print_code_result(r);
+                }
+                if copy && let Some(r) = results.first() {
+                    copy_to_clipboard(&r.block.code)?;
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            if content {
+                let results = mgr.search_content(&query, &options).await?;
+                for r in &results {
+                    print_content_result(r);
+                }
+                if copy && let Some(r) = results.first() {
+                    let location = if url {
+                        mgr.entry_url(&r.doc_slug, &r.page)
+                    } else {
+                        format!("{}/{}", r.doc_slug, r.page)
+                    };
+                    copy_to_clipboard(&location)?;
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let response = if semantic {
+                let results = mgr.search_hybrid(&query, &options).await?;
+                SearchResponse {
+                    total_matches: results.len(),
+                    results,
+                    ..Default::default()
+                }
+            } else {
+                mgr.search_response(&query, &options).await?
+            };
+            let SearchResponse {
+                results,
+                total_matches,
+                truncated,
+                suggestions,
+                remote: remote_results,
+            } = response;
+            let shown = results.len();
+            if let Some(suggestion) = suggestions.first() {
+                if results.is_empty() {
+                    eprintln!("no results — did you mean `{}`?", suggestion);
+                } else {
+                    eprintln!("did you mean `{}`?", suggestion);
+                }
+            }
+            // Where a result lives, as a local path or a link to share
+            let location = |r: &SearchResult| {
+                if url {
+                    mgr.entry_url(&r.entry.doc_slug, &r.entry.entry.path.to_string_lossy())
+                } else {
+                    result_path(r, full).display().to_string()
+                }
+            };
+            if copy && let Some(r) = results.first() {
+                copy_to_clipboard(&location(r))?;
+            }
+            // The result copied or fetched is the one that was picked
+            let chosen = if copy {
+                results.first()
+            } else if fetch {
+                remote_results.first()
+            } else {
+                None
+            }
+            .map(|r| {
+                format!(
+                    "{}/{}",
+                    r.entry.doc_slug,
+                    r.entry.entry.path.to_string_lossy()
+                )
+            });
+            if let Err(e) = mgr.record_search(&query, chosen.as_deref()).await {
+                eprintln!("⚠ couldn’t record search history: {}", e);
+            }
+
+            match group_by {
+                None => {
+                    for r in &results {
+                        print_result(r, &location(r));
+                    }
+                }
+                Some(by) => {
+                    for (key, group) in group_results(results, GroupBy::from(by)) {
+                        println!("{}", key);
+                        for r in group {
+                            print!("  ");
+                            print_result(&r, &location(&r));
+                        }
+                    }
+                }
+            }
+            if truncated {
+                eprintln!(
+                    "… {} of {} results shown; see more with --offset {}",
+                    shown,
+                    total_matches,
+                    offset + shown
+                );
+            }
+            for r in &remote_results {
+                let path = r.entry.entry.path.to_string_lossy();
+                println!(
+                    "{}\t{}\t(remote)",
+                    mgr.entry_url(&r.entry.doc_slug, &path),
+                    r.entry.entry.name
+                );
+            }
+            if fetch && let Some(r) = remote_results.first() {
+                let path = r.entry.entry.path.to_string_lossy();
+                println!();
+                print!(
+                    "{}",
+                    mgr.remote_page(&r.entry.doc_slug, &path, Formats::MARKDOWN)
+                        .await?
+                );
+            }
+        }
+
+        Commands::Update {
+            slugs,
+            all,
+            json,
+            dry_run,
+            ..
+        } if dry_run || (slugs.is_empty() && !all) => {
+            let slugs = if slugs.is_empty() {
+                mgr.list_installed_docs().await?
+            } else {
+                slugs
+            };
+            let plan = mgr.plan_update(&slugs).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&plan)?);
+            } else if dry_run {
+                print_plan(&plan);
+            } else {
+                for download in &plan.downloads {
+                    println!("{}\t{}", download.slug, download.name);
+                }
+                for slug in &plan.orphaned {
+                    println!("{}\t(orphaned upstream)", slug);
+                }
+                if plan.downloads.is_empty() {
+                    eprintln!("all docs are current");
+                } else {
+                    eprintln!(
+                        "{} docs can be updated with `dev update --all`",
+                        plan.downloads.len()
+                    );
+                }
             }
         }
 
-        Commands::Update { slugs } => {
-            if slugs.len() == 1 && slugs[0] == "all" {
-                println!("🔄 updating all installed docs…");
-                mgr.update_all().await?;
+        Commands::Update {
+            slugs,
+            all,
+            force,
+            json,
+            ..
+        } => {
+            let cancel = cancel_on_ctrl_c();
+            if all {
+                if !json {
+                    println!("🔄 updating all installed docs…");
+                }
+                let report = if force {
+                    mgr.force_update_all(&cancel).await?
+                } else {
+                    mgr.update_all_with_cancel(&cancel).await?
+                };
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else {
+                    print_update_report(&report);
+                }
+                exit = batch_exit_code(report.outcome());
             } else {
+                if slugs == ["all"] && !mgr.is_doc_installed("all").await? {
+                    anyhow::bail!("no doc named `all` is installed, use `dev update --all`");
+                }
+                let batch = slugs.len() > 1;
+                let mut report = UpdateReport::default();
                 for slug in slugs {
-                    print!("🔄 updating `{}` … ", slug);
-                    if let Err(e) = mgr.update_doc(&slug).await {
-                        eprintln!("failed: {}", e);
+                    if !json {
+                        print!("🔄 updating `{}` … ", slug);
+                        std::io::stdout().flush()?;
+                    }
+                    let updated = if force {
+                        mgr.force_update_doc(&slug, &cancel).await
                     } else {
-                        println!("ok");
+                        mgr.update_doc_with_cancel(&slug, &cancel).await
+                    };
+                    if !json {
+                        match &updated {
+                            Ok(UpdateStatus::Updated(pages)) => println!(
+                                "ok (+{} ~{} -{} pages)",
+                                pages.added, pages.modified, pages.removed
+                            ),
+                            Ok(UpdateStatus::Current) => println!("already current"),
+                            Ok(UpdateStatus::Orphaned) => println!("removed upstream, kept"),
+                            Err(e) => println!("failed: {}", e),
+                        }
                     }
+                    match updated {
+                        Ok(UpdateStatus::Updated(pages)) => report.updated.push((slug, pages)),
+                        Ok(UpdateStatus::Current) => report.current.push(slug),
+                        Ok(UpdateStatus::Orphaned) => report.orphaned.push(slug),
+                        Err(e) if cancel.is_cancelled() => return Err(e),
+                        Err(e) => report.failed.push((slug, e.to_string())),
+                    }
+                }
+
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                } else if batch {
+                    print_update_report(&report);
                 }
+                exit = batch_exit_code(report.outcome());
             }
         }
 
-        Commands::Preview { path } => {
-            // resolve to absolute
-            let mut file = PathBuf::from(&path);
-            if !file.is_absolute() {
-                file = data_dir.join(file);
+        Commands::Changes { slug } => match mgr.doc_changes(&slug).await? {
+            None => println!("`{}` hasn’t changed since it was installed", slug),
+            Some(changes) => {
+                for entry in &changes.added {
+                    println!("+ {}\t{}", entry.name, entry.path.display());
+                }
+                for entry in &changes.removed {
+                    println!("- {}\t{}", entry.name, entry.path.display());
+                }
+                for entry in &changes.moved {
+                    println!(
+                        "→ {}\t{} → {}",
+                        entry.name,
+                        entry.from.display(),
+                        entry.to.display()
+                    );
+                }
+                println!(
+                    "{} added, {} removed, {} moved",
+                    changes.added.len(),
+                    changes.removed.len(),
+                    changes.moved.len()
+                );
+            }
+        },
+
+        Commands::Export { format, dir, slugs } => {
+            if format == ExportFormat::Jsonl {
+                if dir == Path::new("-") {
+                    mgr.export_jsonl(&mut std::io::stdout().lock(), &slugs)
+                        .await?;
+                } else {
+                    fs::create_dir_all(&dir).await?;
+                    let file = dir.join("entries.jsonl");
+                    let mut out = std::io::BufWriter::new(std::fs::File::create(&file)?);
+                    let count = mgr.export_jsonl(&mut out, &slugs).await?;
+                    println!("📦 exported {} entries to {}", count, file.display());
+                }
+                return Ok(ExitCode::SUCCESS);
             }
-            if !file.exists() {
-                anyhow::bail!("file not found: {}", file.display());
+            if format == ExportFormat::Site {
+                mgr.export_site(&dir, &slugs).await?;
+                println!("📦 exported site to {}", dir.display());
+                return Ok(ExitCode::SUCCESS);
+            }
+
+            let slugs = mgr.export_slugs(&slugs).await?;
+            fs::create_dir_all(&dir).await?;
+            for slug in slugs {
+                let file = match format {
+                    ExportFormat::Epub => {
+                        let file = dir.join(format!("{}.epub", slug));
+                        mgr.export_epub(&slug, &file).await?;
+                        file
+                    }
+                    _ => {
+                        let file = dir.join(format!("{}.html", slug));
+                        fs::write(&file, mgr.export_single_page(&slug).await?).await?;
+                        file
+                    }
+                };
+                println!("📦 exported `{}` to {}", slug, file.display());
+            }
+        }
+
+        Commands::Sig { query, docs, copy } => {
+            let options = SearchOptions {
+                docs: project_docs(&mgr, docs).await?,
+                limit: 20,
+                ..Default::default()
+            };
+            // The best match that documents a signature wins
+            let mut printed = false;
+            for r in mgr.search_with(&query, &options).await? {
+                let signatures = mgr
+                    .signatures(&r.entry.doc_slug, &r.entry.entry.name)
+                    .await?;
+                for signature in &signatures {
+                    println!("{}", signature.text);
+                }
+                if !signatures.is_empty() {
+                    if copy {
+                        let text: Vec<_> = signatures.iter().map(|s| s.text.as_str()).collect();
+                        copy_to_clipboard(&text.join("\n"))?;
+                    }
+                    printed = true;
+                    break;
+                }
+            }
+            if !printed {
+                anyhow::bail!("no signature found for `{}`", query);
+            }
+        }
+
+        Commands::Man { slug, entry } => {
+            let roff = mgr.entry_man_page(&slug, &entry).await?;
+            let file = std::env::temp_dir().join(format!("devdocs-{}.3", std::process::id()));
+            fs::write(&file, &roff).await?;
+            let status = tokio::process::Command::new("man")
+                .arg("-l")
+                .arg(&file)
+                .status()
+                .await;
+            let _ = fs::remove_file(&file).await;
+            // Without a `man` to page it, the roff source is the next best thing
+            if !status.is_ok_and(|s| s.success()) {
+                print!("{}", roff);
+            }
+        }
+
+        Commands::Usage => {
+            let usage = mgr.disk_usage().await?;
+            for (slug, size) in &usage.docs {
+                println!("{:>10.1} MB  {}", *size as f64 / 1e6, slug);
+            }
+            match usage.quota {
+                Some(quota) => println!(
+                    "{:.1} MB of {:.1} MB used",
+                    usage.used as f64 / 1e6,
+                    quota as f64 / 1e6
+                ),
+                None => println!("{:.1} MB used", usage.used as f64 / 1e6),
+            }
+        }
+
+        Commands::Stats { json, prometheus } => {
+            let stats = mgr.stats().await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else if prometheus {
+                print!("{}", stats.to_prometheus());
+            } else {
+                println!("docs installed   {}", stats.docs_installed);
+                println!("entries indexed  {}", stats.entries_indexed);
+                println!(
+                    "disk used        {:.1} MB",
+                    stats.bytes_on_disk as f64 / 1e6
+                );
             }
+        }
+
+        Commands::History { limit, clear } => {
+            if clear {
+                mgr.clear_history().await?;
+                println!("🗑 cleared search history");
+            } else {
+                let history = mgr.search_history().await;
+                let skip = history.len().saturating_sub(limit);
+                for entry in history.into_iter().skip(skip) {
+                    match entry.chosen {
+                        Some(chosen) => {
+                            println!("{}\t{}\t{}", entry.timestamp, entry.query, chosen)
+                        }
+                        None => println!("{}\t{}", entry.timestamp, entry.query),
+                    }
+                }
+            }
+        }
+
+        Commands::Recent { limit } => {
+            for page in mgr.recent_pages(limit).await {
+                println!("{}/{}", page.slug, page.path);
+            }
+        }
+
+        Commands::Alias {
+            remove,
+            alias,
+            slug,
+        } => match (alias, slug) {
+            (Some(alias), _) if remove => {
+                if mgr.remove_alias(&alias).await? {
+                    println!("🗑 removed alias `{}`", alias);
+                } else {
+                    eprintln!("⚠ `{}` is not an alias", alias);
+                }
+            }
+            (Some(alias), Some(slug)) => {
+                mgr.set_alias(&alias, &slug).await?;
+                println!("✅ `{}` → `{}`", alias, slug);
+            }
+            (None, None) => {
+                let mut aliases: Vec<_> = mgr.aliases().await.into_iter().collect();
+                aliases.sort();
+                for (alias, slug) in aliases {
+                    println!("{}\t{}", alias, slug);
+                }
+            }
+            _ => anyhow::bail!("usage: alias <ALIAS> <SLUG> | alias --remove <ALIAS>"),
+        },
+
+        Commands::Import { html, md, source } => {
+            let mut formats = Formats::empty();
+            formats.set(Formats::HTML, html);
+            formats.set(Formats::MARKDOWN, md || !html);
+            let source: Box<dyn DocSource> = match source {
+                ImportSource::Cargo { dir, crates } => {
+                    Box::new(RustdocSource::new(dir).crates(crates))
+                }
+                ImportSource::Html { name, dir, globs } => {
+                    Box::new(HtmlDirSource::new(name, dir).entries(globs))
+                }
+            };
+            for slug in mgr.import(&*source, Some(formats)).await? {
+                println!("✅ imported {}", slug);
+            }
+        }
+
+        Commands::Config { action } => match action {
+            ConfigAction::Set { key, value } => {
+                mgr.set_config(&key, &value).await?;
+                println!("✅ {} = {}", key, value);
+            }
+            ConfigAction::Get { key } => match mgr.settings().await.get(&key)? {
+                Some(value) => println!("{}", value),
+                None => eprintln!("⚠ `{}` is not set", key),
+            },
+            ConfigAction::Unset { key } => {
+                if mgr.unset_config(&key).await? {
+                    println!("🗑 unset `{}`", key);
+                } else {
+                    eprintln!("⚠ `{}` is not set", key);
+                }
+            }
+            ConfigAction::List => {
+                for (key, value) in mgr.settings().await.entries() {
+                    println!("{} = {}", key, value);
+                }
+            }
+        },
+
+        Commands::Doctor { serve } => {
+            let mut checks = mgr.diagnostics().await;
+            checks.push(dev::diagnose_server(serve).await);
+            for check in &checks {
+                let icon = match check.status {
+                    CheckStatus::Ok => "✅",
+                    CheckStatus::Warning => "⚠",
+                    CheckStatus::Error => "❌",
+                };
+                println!("{} {}: {}", icon, check.check, check.message);
+                if let Some(fix) = &check.fix {
+                    println!("   → {}", fix);
+                }
+            }
+            let failed = checks
+                .iter()
+                .filter(|check| check.status == CheckStatus::Error)
+                .count();
+            if failed > 0 {
+                anyhow::bail!("{} check(s) failed", failed);
+            }
+        }
+
+        Commands::Verify { slugs } => {
+            let slugs = if slugs.is_empty() {
+                mgr.list_installed_docs().await?
+            } else {
+                slugs
+            };
+            let mut broken = Vec::new();
+            for slug in &slugs {
+                let problems = mgr.verify_doc(slug).await?;
+                if problems.is_empty() {
+                    println!("✅ {}", slug);
+                    continue;
+                }
+                println!("❌ {}", slug);
+                for problem in &problems {
+                    println!("   {}", problem);
+                }
+                broken.push(slug.as_str());
+            }
+            if !broken.is_empty() {
+                anyhow::bail!(
+                    "{} doc(s) failed verification, download them again with `dev update --force {}`",
+                    broken.len(),
+                    broken.join(" ")
+                );
+            }
+        }
+
+        Commands::Lsp => {
+            let stdin = tokio::io::BufReader::new(tokio::io::stdin());
+            dev::lsp::run(&mgr, stdin, tokio::io::stdout()).await?;
+        }
+
+        #[cfg(feature = "serve")]
+        Commands::Serve { addr } => {
+            eprintln!("🌐 serving on http://{} (Ctrl-C to stop)", addr);
+            let cancel = cancel_on_ctrl_c();
+            dev::serve::serve(std::sync::Arc::new(mgr), addr, &cancel).await?;
+        }
+
+        #[cfg(feature = "serve")]
+        Commands::Service { action } => {
+            let Some(manager) = ServiceManager::native() else {
+                anyhow::bail!("Services are only supported with systemd and launchd");
+            };
+            let dir = manager.user_dir()?;
+            match action {
+                ServiceAction::Install { mode, addr } => {
+                    let program = std::env::current_exe()?;
+                    let mut command = vec![program.to_string_lossy().into_owned()];
+                    command.extend(service_flags);
+                    match mode {
+                        ServiceMode::Serve => {
+                            command.extend(["serve".to_string(), format!("--addr={}", addr)])
+                        }
+                    }
+                    let service = Service::new(manager, service_profile.as_deref(), command);
+                    let path = service.install(&dir)?;
+                    println!("📝 wrote {}", path.display());
+                    run_service_commands(&service.start_commands(&path));
+                    println!("🌐 serving on http://{} from now on", addr);
+                }
+                ServiceAction::Uninstall => {
+                    let service = Service::new(manager, service_profile.as_deref(), Vec::new());
+                    let path = dir.join(service.file_name());
+                    if !path.exists() {
+                        println!("No `{}` service is installed", service.name);
+                    } else {
+                        run_service_commands(&service.stop_commands(&path));
+                        service.uninstall(&dir)?;
+                        println!("🗑 removed {}", path.display());
+                    }
+                }
+            }
+        }
+
+        Commands::Enable { slugs } => {
+            for slug in slugs {
+                mgr.set_doc_enabled(&slug, true).await?;
+                println!("✅ enabled `{}`", slug);
+            }
+        }
+
+        Commands::Disable { slugs } => {
+            if slugs.is_empty() {
+                for slug in mgr.disabled_docs().await {
+                    println!("{}", slug);
+                }
+            }
+            for slug in slugs {
+                mgr.set_doc_enabled(&slug, false).await?;
+                println!("⏸ disabled `{}`", slug);
+            }
+        }
+
+        Commands::Render { slugs, to } => {
+            let formats = to
+                .into_iter()
+                .fold(Formats::empty(), |formats, format| formats | format.into());
+            for slug in slugs {
+                let pages = mgr.render_doc(&slug, formats).await?;
+                println!("✅ rendered {} pages of `{}`", pages, slug);
+            }
+        }
+
+        Commands::Fetch { slug, path, text } => {
+            let format = if text {
+                Formats::TEXT
+            } else {
+                Formats::MARKDOWN
+            };
+            print!("{}", mgr.remote_page(&slug, &path, format).await?);
+        }
+
+        Commands::Preview {
+            path,
+            copy,
+            url,
+            images,
+        } => {
+            // Entry paths point at their section with a fragment, which isn't part of the file
+            let (path, anchor) = split_fragment(&path);
+            let fragment = anchor
+                .map(|anchor| format!("#{}", anchor))
+                .unwrap_or_default();
+            // resolve to absolute, looking through every storage layer
+            let file = PathBuf::from(path);
+            if url {
+                let Some((slug, page)) = mgr.page_for_file(&file) else {
+                    anyhow::bail!("not a doc page: {}", path);
+                };
+                let link = mgr.entry_url(&slug, &format!("{}{}", page, fragment));
+                println!("{}", link);
+                if copy {
+                    copy_to_clipboard(&link)?;
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            let found = if file.is_absolute() {
+                Some(file.clone()).filter(|f| f.exists())
+            } else {
+                mgr.resolve_path(&file)
+            };
+            let file = match found {
+                Some(file) => file,
+                // Lazily rendered docs only have their pages written once they're opened
+                None => {
+                    let Some((slug, page)) = mgr.page_for_file(&file) else {
+                        anyhow::bail!("file not found: {}", path);
+                    };
+                    let format = match file.extension().and_then(|s| s.to_str()) {
+                        Some("html") => Formats::HTML,
+                        Some("txt") => Formats::TEXT,
+                        _ => Formats::MARKDOWN,
+                    };
+                    mgr.page_file(&slug, &page, format)
+                        .await
+                        .with_context(|| format!("file not found: {}", path))?
+                }
+            };
+
+            let doc_page = mgr.page_for_file(&file);
+            if let Some((slug, page)) = &doc_page {
+                if let Err(e) = mgr.record_view(slug, page).await {
+                    eprintln!("⚠ couldn’t record recently viewed page: {}", e);
+                }
+                if copy {
+                    match mgr.page_code_blocks(slug, page).await?.first() {
+                        Some(block) => copy_to_clipboard(&block.code)?,
+                        None => eprintln!("⚠ the page has no code examples to copy"),
+                    }
+                }
+            }
+
             match file.extension().and_then(|s| s.to_str()) {
                 Some("html") => {
-                    webbrowser::open(&file.to_string_lossy())?;
+                    // Pages are bare fragments: open a themed copy, linking back to their dir
+                    let file = std::path::absolute(&file)?;
+                    let name = match &doc_page {
+                        Some((slug, page)) => format!("{}/{}", slug, page),
+                        None => file
+                            .file_stem()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .into(),
+                    };
+                    let base = file.parent().map(|dir| format!("{}/", file_url(dir)));
+                    let html = fs::read_to_string(&file).await?;
+                    let mathjax = mgr.mathjax().await;
+                    let themed =
+                        mgr.theme()
+                            .await
+                            .apply(&html, &name, base.as_deref(), mathjax.as_deref());
+                    let themed = mgr.with_user_assets(&themed);
+
+                    let preview = std::env::temp_dir().join("devdocs-preview");
+                    fs::create_dir_all(&preview).await?;
+                    let preview = preview.join(format!("{}.html", name.replace('/', "-")));
+                    fs::write(&preview, themed).await?;
+                    webbrowser::open(&format!("{}{}", file_url(&preview), fragment))?;
                 }
                 _ => {
-                    // default to printing markdown
-                    let txt = fs::read_to_string(&file).await?;
+                    // default to printing markdown, drawing its images in a terminal
+                    let mut txt = fs::read_to_string(&file).await?;
+                    // Start at the section, as far as the page's HTML tells where it is
+                    if let (Some(anchor), Some((slug, page))) = (anchor, &doc_page) {
+                        let html = match mgr.page_file(slug, page, Formats::HTML).await {
+                            Ok(html) => fs::read_to_string(html).await.unwrap_or_default(),
+                            Err(_) => String::new(),
+                        };
+                        match anchor_offset(&html, &txt, anchor) {
+                            Some(offset) => txt = txt.split_off(offset),
+                            None => eprintln!("⚠ couldn’t find section #{} of the page", anchor),
+                        }
+                    }
+                    if std::io::stdout().is_terminal() {
+                        let dir = file.parent().unwrap_or(Path::new("."));
+                        txt = mgr.with_terminal_images(&txt, dir, images.protocol()).await;
+                    }
                     print!("{txt}");
                 }
             }
         }
     }
 
-    Ok(())
+    Ok(exit)
 }
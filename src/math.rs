@@ -0,0 +1,645 @@
+//! Formulas of pages: Unicode approximations of MathML and TeX for reading them as text,
+//! and TeX kept in the delimiters MathJax typesets for reading them in a browser.
+//!
+//! MathJax is only loaded when the `mathjax` setting names a copy of it; browsers get
+//! the Unicode approximations otherwise, so opening a page never touches the network.
+
+use std::iter::Peekable;
+use std::str::Chars;
+use std::sync::LazyLock;
+
+use lol_html::{RewriteStrSettings, element, rewrite_str};
+use regex::{Captures, Regex};
+
+use crate::escape_html;
+
+/// Scripts loading MathJax from `src`, a URL or the path of a local copy, to typeset
+/// TeX within `\(…\)` and `\[…\]` as well as MathML
+pub(crate) fn mathjax(src: &str) -> String {
+    let src = if src.contains("://") {
+        src.to_string()
+    } else {
+        format!("file://{}", src.replace(' ', "%20"))
+    };
+    format!(
+        "<script>window.MathJax = {{ tex: {{ inlineMath: [['\\\\(', '\\\\)']] }} }};</script>\n\
+         <script async src=\"{}\"></script>\n",
+        escape_html(&src)
+    )
+}
+
+/// A MathML formula
+static MATHML: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?s)<math\b[^>]*>(.*?)</math>").unwrap());
+/// TeX in a script, as MathJax 2 left it in pages
+static TEX_SCRIPT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r#"(?s)<script type="math/tex(; ?mode=display)?"[^>]*>(.*?)</script>"#).unwrap()
+});
+/// TeX in an element with the `math` class, as Sphinx and most generators write it
+static TEX_ELEMENT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r#"<(span|div)\b([^>]*\bclass="(?:[^"]*\s)?math(?:\s[^"]*)?"[^>]*)>([^<]*)</(?:span|div)>"#,
+    )
+    .unwrap()
+});
+/// A tag or the text between tags, within MathML
+static MATHML_TOKEN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"<(/?)([a-zA-Z][\w:-]*)([^>]*?)(/?)>|([^<]+)").unwrap());
+
+/// Whether a page has formulas
+pub(crate) fn has_math(html: &str) -> bool {
+    html.contains("<math") || html.contains("math/tex") || TEX_ELEMENT.is_match(html)
+}
+
+/// Replace the formulas of a page by Unicode approximations, e.g. `\frac{a}{b^2}` by
+/// `a/b²`, for converting it to text
+pub(crate) fn to_unicode(html: &str) -> String {
+    if !has_math(html) {
+        return html.to_string();
+    }
+
+    // KaTeX draws formulas twice, as MathML and as styled spans: keep the MathML
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!(".katex-html", |el| {
+            el.remove();
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    let html = rewrite_str(html, settings).unwrap_or_else(|_| html.to_string());
+
+    let html = MATHML.replace_all(&html, |caps: &Captures| {
+        let text = render_mathml(&parse_mathml(&caps[1]));
+        escape_html(&text.split_whitespace().collect::<Vec<_>>().join(" "))
+    });
+    let html = TEX_SCRIPT.replace_all(&html, |caps: &Captures| {
+        escape_html(&tex_to_unicode(&decode(&caps[2])))
+    });
+    TEX_ELEMENT
+        .replace_all(&html, |caps: &Captures| {
+            let tex = decode(&caps[3]);
+            format!(
+                "<{0}{1}>{2}</{0}>",
+                &caps[1],
+                &caps[2],
+                escape_html(&tex_to_unicode(strip_delimiters(&tex)))
+            )
+        })
+        .into_owned()
+}
+
+/// Put the TeX of a page in `\(…\)` for inline and `\[…\]` for display formulas, which
+/// MathJax typesets without the scripts it once left in pages. MathML is left to the
+/// browser.
+pub(crate) fn to_mathjax(html: &str) -> String {
+    if !has_math(html) {
+        return html.to_string();
+    }
+
+    let html = TEX_SCRIPT.replace_all(html, |caps: &Captures| {
+        if caps.get(1).is_some() {
+            format!(r#"<div class="math">\[{}\]</div>"#, &caps[2])
+        } else {
+            format!(r#"<span class="math">\({}\)</span>"#, &caps[2])
+        }
+    });
+    TEX_ELEMENT
+        .replace_all(&html, |caps: &Captures| {
+            let tex = caps[3].trim();
+            if tex.is_empty() || strip_delimiters(tex).len() < tex.len() {
+                return caps[0].to_string();
+            }
+            let (open, close) = if &caps[1] == "div" {
+                (r"\[", r"\]")
+            } else {
+                (r"\(", r"\)")
+            };
+            format!(
+                "<{0}{1}>{2}{3}{4}</{0}>",
+                &caps[1], &caps[2], open, tex, close
+            )
+        })
+        .into_owned()
+}
+
+fn strip_delimiters(tex: &str) -> &str {
+    let tex = tex.trim();
+    [(r"\(", r"\)"), (r"\[", r"\]"), ("$$", "$$")]
+        .iter()
+        .find_map(|(open, close)| tex.strip_prefix(open)?.strip_suffix(close))
+        .unwrap_or(tex)
+        .trim()
+}
+
+/// Decode the entities of MathML and TeX, numeric ones included
+fn decode(text: &str) -> String {
+    static NUMERIC: LazyLock<Regex> =
+        LazyLock::new(|| Regex::new(r"&#(?:x([0-9a-fA-F]+)|([0-9]+));").unwrap());
+    let text = NUMERIC.replace_all(text, |caps: &Captures| {
+        let code = match (caps.get(1), caps.get(2)) {
+            (Some(hex), _) => u32::from_str_radix(hex.as_str(), 16).ok(),
+            (_, Some(dec)) => dec.as_str().parse().ok(),
+            _ => None,
+        };
+        code.and_then(char::from_u32)
+            .map_or_else(|| caps[0].to_string(), String::from)
+    });
+    crate::decode_entities(text.into_owned())
+        // Invisible function application and multiplication
+        .replace(['\u{2061}', '\u{2062}', '\u{2063}', '\u{2064}'], "")
+}
+
+enum Node {
+    Element(String, Vec<Node>),
+    Text(String),
+}
+
+/// The elements of a MathML formula, as a tree
+fn parse_mathml(mathml: &str) -> Vec<Node> {
+    // Elements still open, with the children read so far
+    let mut stack: Vec<(String, Vec<Node>)> = vec![(String::new(), Vec::new())];
+    for caps in MATHML_TOKEN.captures_iter(mathml) {
+        if let Some(text) = caps.get(5) {
+            let text = decode(text.as_str());
+            if !text.trim().is_empty() {
+                stack
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push(Node::Text(text.trim().to_string()));
+            }
+        } else if !caps[1].is_empty() {
+            // Close up to the matching element, tolerating unclosed ones
+            if let Some(open) = stack.iter().rposition(|(name, _)| *name == caps[2]) {
+                while stack.len() > open {
+                    let (name, children) = stack.pop().unwrap();
+                    stack
+                        .last_mut()
+                        .unwrap()
+                        .1
+                        .push(Node::Element(name, children));
+                }
+            }
+        } else if !caps[4].is_empty() {
+            stack
+                .last_mut()
+                .unwrap()
+                .1
+                .push(Node::Element(caps[2].to_string(), Vec::new()));
+        } else {
+            stack.push((caps[2].to_string(), Vec::new()));
+        }
+    }
+    while stack.len() > 1 {
+        let (name, children) = stack.pop().unwrap();
+        stack
+            .last_mut()
+            .unwrap()
+            .1
+            .push(Node::Element(name, children));
+    }
+    stack.pop().unwrap().1
+}
+
+fn render_mathml(nodes: &[Node]) -> String {
+    nodes.iter().map(render_node).collect()
+}
+
+fn render_node(node: &Node) -> String {
+    let (name, children) = match node {
+        Node::Text(text) => return text.clone(),
+        Node::Element(name, children) => (name.as_str(), children),
+    };
+    let child = |i: usize| children.get(i).map(render_node).unwrap_or_default();
+    match name {
+        "mo" => {
+            let op = render_mathml(children);
+            if [
+                "=", "<", ">", "≤", "≥", "≠", "≈", "≡", "+", "−", "×", "→", "⇒", "∈",
+            ]
+            .contains(&op.as_str())
+            {
+                format!(" {} ", op)
+            } else {
+                op
+            }
+        }
+        "msup" | "mover" => format!("{}{}", child(0), superscript(&child(1))),
+        "msub" | "munder" => format!("{}{}", child(0), subscript(&child(1))),
+        "msubsup" | "munderover" => format!(
+            "{}{}{}",
+            child(0),
+            subscript(&child(1)),
+            superscript(&child(2))
+        ),
+        "mfrac" => fraction(&child(0), &child(1)),
+        "msqrt" => format!("√{}", group(&render_mathml(children))),
+        "mroot" => root(&child(1), &child(0)),
+        "mfenced" => format!(
+            "({})",
+            children
+                .iter()
+                .map(render_node)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        "mtable" => format!(
+            "[{}]",
+            children
+                .iter()
+                .map(render_node)
+                .collect::<Vec<_>>()
+                .join("; ")
+        ),
+        "mtr" => children
+            .iter()
+            .map(render_node)
+            .collect::<Vec<_>>()
+            .join(", "),
+        // The first child is the formula, the rest are other encodings of it
+        "semantics" => child(0),
+        "annotation" | "annotation-xml" | "mphantom" => String::new(),
+        "mspace" => " ".to_string(),
+        _ => render_mathml(children),
+    }
+}
+
+/// Approximate TeX in Unicode, e.g. `\sum_{i=1}^n x_i^2` as `∑ᵢ₌₁ⁿ xᵢ²`
+pub(crate) fn tex_to_unicode(tex: &str) -> String {
+    let mut parser = Tex {
+        chars: tex.chars().peekable(),
+    };
+    let text = parser.expr(false);
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+struct Tex<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl Tex<'_> {
+    /// Everything up to the end, or the closing brace of the group within `group`
+    fn expr(&mut self, group: bool) -> String {
+        let mut out = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if c == '}' {
+                self.chars.next();
+                if group {
+                    break;
+                }
+                continue;
+            }
+            match c {
+                '^' => {
+                    self.chars.next();
+                    let sup = self.atom();
+                    out.push_str(&superscript(&sup));
+                }
+                '_' => {
+                    self.chars.next();
+                    let sub = self.atom();
+                    out.push_str(&subscript(&sub));
+                }
+                '&' => {
+                    self.chars.next();
+                    out.push_str(", ");
+                }
+                c if c.is_whitespace() => {
+                    self.chars.next();
+                    out.push(' ');
+                }
+                _ => {
+                    let atom = self.atom();
+                    out.push_str(&atom);
+                }
+            }
+        }
+        out
+    }
+
+    /// The next group, command or character
+    fn atom(&mut self) -> String {
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+        }
+        match self.chars.next() {
+            None => String::new(),
+            Some('{') => self.expr(true),
+            Some('\\') => self.command(),
+            Some('~') => " ".to_string(),
+            Some(c) if c.is_whitespace() => " ".to_string(),
+            Some(c) => c.to_string(),
+        }
+    }
+
+    fn command(&mut self) -> String {
+        let mut name = String::new();
+        while let Some(&c) = self.chars.peek() {
+            if !c.is_ascii_alphabetic() {
+                break;
+            }
+            name.push(c);
+            self.chars.next();
+        }
+        if name.is_empty() {
+            return match self.chars.next() {
+                Some(',' | ';' | ':' | ' ') => " ".to_string(),
+                Some('!') | None => String::new(),
+                Some('\\') => "; ".to_string(),
+                Some(c) => c.to_string(),
+            };
+        }
+
+        match name.as_str() {
+            "frac" | "dfrac" | "tfrac" => {
+                let (num, den) = (self.atom(), self.atom());
+                fraction(&num, &den)
+            }
+            "sqrt" => {
+                let mut index = String::new();
+                if self.chars.peek() == Some(&'[') {
+                    self.chars.next();
+                    for c in self.chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        index.push(c);
+                    }
+                }
+                let radicand = self.atom();
+                if index.is_empty() {
+                    format!("√{}", group(&radicand))
+                } else {
+                    root(&radicand, &index)
+                }
+            }
+            "mathbb" => self.atom().chars().map(double_struck).collect(),
+            "text" | "textrm" | "mathrm" | "mathit" | "mathbf" | "mathsf" | "mathtt"
+            | "mathcal" | "boldsymbol" | "operatorname" | "textbf" | "textit" => self.atom(),
+            "hat" | "widehat" => accent(self.atom(), '\u{302}'),
+            "bar" | "overline" => accent(self.atom(), '\u{304}'),
+            "vec" => accent(self.atom(), '\u{20d7}'),
+            "dot" => accent(self.atom(), '\u{307}'),
+            "ddot" => accent(self.atom(), '\u{308}'),
+            "tilde" | "widetilde" => accent(self.atom(), '\u{303}'),
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" | "bigl" | "bigr" | "Bigl"
+            | "Bigr" => {
+                // `\left.` is an invisible delimiter
+                if self.chars.peek() == Some(&'.') {
+                    self.chars.next();
+                }
+                String::new()
+            }
+            "begin" | "end" => {
+                self.atom();
+                String::new()
+            }
+            "displaystyle" | "textstyle" | "limits" | "nolimits" => String::new(),
+            "quad" | "qquad" => " ".to_string(),
+            _ => match symbol(&name) {
+                Some(symbol) => symbol.to_string(),
+                // Functions like `\sin` and `\log` read as their name
+                None => name,
+            },
+        }
+    }
+}
+
+/// `a/b`, with parentheses around compound terms
+fn fraction(num: &str, den: &str) -> String {
+    format!("{}/{}", group(num), group(den))
+}
+
+/// `text` in parentheses unless it's a single term
+fn group(text: &str) -> String {
+    let text = text.trim();
+    let simple = text.chars().count() == 1
+        || text.chars().all(|c| c.is_alphanumeric() || c == '.')
+        || (text.starts_with('(') && text.ends_with(')'));
+    if simple {
+        text.to_string()
+    } else {
+        format!("({})", text)
+    }
+}
+
+fn root(radicand: &str, index: &str) -> String {
+    match index.trim() {
+        "3" => format!("∛{}", group(radicand)),
+        "4" => format!("∜{}", group(radicand)),
+        index => format!("{}√{}", superscript(index), group(radicand)),
+    }
+}
+
+fn accent(base: String, mark: char) -> String {
+    if base.chars().count() == 1 {
+        format!("{}{}", base, mark)
+    } else {
+        base
+    }
+}
+
+/// Text raised with Unicode's superscript letters, or `^(text)` when some have none
+fn superscript(text: &str) -> String {
+    const FROM: &str = "0123456789+-−=()niabcdefghjklmoprstuvwxyzT′";
+    const TO: &str = "⁰¹²³⁴⁵⁶⁷⁸⁹⁺⁻⁻⁼⁽⁾ⁿⁱᵃᵇᶜᵈᵉᶠᵍʰʲᵏˡᵐᵒᵖʳˢᵗᵘᵛʷˣʸᶻᵀ′";
+    shift(text, FROM, TO).unwrap_or_else(|| format!("^{}", script(text)))
+}
+
+/// Text lowered with Unicode's subscript letters, or `_(text)` when some have none
+fn subscript(text: &str) -> String {
+    const FROM: &str = "0123456789+-−=()aehijklmnoprstuvx";
+    const TO: &str = "₀₁₂₃₄₅₆₇₈₉₊₋₋₌₍₎ₐₑₕᵢⱼₖₗₘₙₒₚᵣₛₜᵤᵥₓ";
+    shift(text, FROM, TO).unwrap_or_else(|| format!("_{}", script(text)))
+}
+
+/// A raised or lowered term, in parentheses unless it's a single character
+fn script(text: &str) -> String {
+    let text = text.trim();
+    if text.chars().count() == 1 {
+        text.to_string()
+    } else {
+        format!("({})", text)
+    }
+}
+
+fn shift(text: &str, from: &str, to: &str) -> Option<String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Some(String::new());
+    }
+    text.chars()
+        .map(|c| {
+            let i = from.chars().position(|f| f == c)?;
+            to.chars().nth(i)
+        })
+        .collect()
+}
+
+fn double_struck(c: char) -> char {
+    match c {
+        'R' => 'ℝ',
+        'N' => 'ℕ',
+        'Z' => 'ℤ',
+        'Q' => 'ℚ',
+        'C' => 'ℂ',
+        'P' => 'ℙ',
+        c => c,
+    }
+}
+
+/// The character of a TeX symbol command, without its backslash
+fn symbol(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "alpha" => "α",
+        "beta" => "β",
+        "gamma" => "γ",
+        "delta" => "δ",
+        "epsilon" | "varepsilon" => "ε",
+        "zeta" => "ζ",
+        "eta" => "η",
+        "theta" | "vartheta" => "θ",
+        "iota" => "ι",
+        "kappa" => "κ",
+        "lambda" => "λ",
+        "mu" => "μ",
+        "nu" => "ν",
+        "xi" => "ξ",
+        "pi" => "π",
+        "rho" | "varrho" => "ρ",
+        "sigma" => "σ",
+        "tau" => "τ",
+        "upsilon" => "υ",
+        "phi" | "varphi" => "φ",
+        "chi" => "χ",
+        "psi" => "ψ",
+        "omega" => "ω",
+        "Gamma" => "Γ",
+        "Delta" => "Δ",
+        "Theta" => "Θ",
+        "Lambda" => "Λ",
+        "Xi" => "Ξ",
+        "Pi" => "Π",
+        "Sigma" => "Σ",
+        "Upsilon" => "Υ",
+        "Phi" => "Φ",
+        "Psi" => "Ψ",
+        "Omega" => "Ω",
+        "sum" => "∑",
+        "prod" => "∏",
+        "coprod" => "∐",
+        "int" => "∫",
+        "iint" => "∬",
+        "iiint" => "∭",
+        "oint" => "∮",
+        "infty" => "∞",
+        "partial" => "∂",
+        "nabla" => "∇",
+        "pm" => "±",
+        "mp" => "∓",
+        "times" => "×",
+        "div" => "÷",
+        "cdot" => "·",
+        "circ" => "∘",
+        "bullet" => "•",
+        "ast" => "∗",
+        "star" => "⋆",
+        "cdots" => "⋯",
+        "ldots" | "dots" => "…",
+        "vdots" => "⋮",
+        "ddots" => "⋱",
+        "leq" | "le" => "≤",
+        "geq" | "ge" => "≥",
+        "neq" | "ne" => "≠",
+        "approx" => "≈",
+        "equiv" => "≡",
+        "sim" => "∼",
+        "simeq" => "≃",
+        "cong" => "≅",
+        "propto" => "∝",
+        "ll" => "≪",
+        "gg" => "≫",
+        "in" => "∈",
+        "notin" => "∉",
+        "ni" => "∋",
+        "subset" => "⊂",
+        "subseteq" => "⊆",
+        "supset" => "⊃",
+        "supseteq" => "⊇",
+        "cup" => "∪",
+        "cap" => "∩",
+        "setminus" => "∖",
+        "emptyset" | "varnothing" => "∅",
+        "forall" => "∀",
+        "exists" => "∃",
+        "neg" | "lnot" => "¬",
+        "land" | "wedge" => "∧",
+        "lor" | "vee" => "∨",
+        "oplus" => "⊕",
+        "otimes" => "⊗",
+        "to" | "rightarrow" => "→",
+        "leftarrow" | "gets" => "←",
+        "leftrightarrow" => "↔",
+        "Rightarrow" => "⇒",
+        "Leftarrow" => "⇐",
+        "Leftrightarrow" | "iff" => "⇔",
+        "implies" => "⟹",
+        "mapsto" => "↦",
+        "uparrow" => "↑",
+        "downarrow" => "↓",
+        "langle" => "⟨",
+        "rangle" => "⟩",
+        "lfloor" => "⌊",
+        "rfloor" => "⌋",
+        "lceil" => "⌈",
+        "rceil" => "⌉",
+        "lbrace" => "{",
+        "rbrace" => "}",
+        "vert" | "lvert" | "rvert" | "mid" => "|",
+        "Vert" | "lVert" | "rVert" | "parallel" => "‖",
+        "perp" => "⊥",
+        "angle" => "∠",
+        "prime" => "′",
+        "hbar" => "ℏ",
+        "ell" => "ℓ",
+        "Re" => "ℜ",
+        "Im" => "ℑ",
+        "aleph" => "ℵ",
+        "colon" => ":",
+        "backslash" => "\\",
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Formats, Theme, render_page};
+
+    #[test]
+    fn test_formulas_read_as_unicode_and_typeset_in_browsers() {
+        let page = r#"<p>The mean is <span class="math notranslate">\(\bar{x} = \frac{1}{n}\sum_{i=1}^n x_i\)</span>.</p>
+            <div class="math">\[\sqrt{a^2 + b^2} \leq \alpha\]</div>
+            <p>Area <math><semantics><mrow><mi>&#x3C0;</mi><msup><mi>r</mi><mn>2</mn></msup></mrow><annotation encoding="application/x-tex">\pi r^2</annotation></semantics></math>
+            and <script type="math/tex">e^{i\pi} + 1 = 0</script>.</p>"#;
+
+        let md = render_page("numpy", "mean", page, Formats::MARKDOWN);
+        assert!(md.contains("The mean is x̄ = 1/n∑ᵢ₌₁ⁿ xᵢ."), "{}", md);
+        assert!(md.contains("√(a² + b²) ≤ α"), "{}", md);
+        assert!(md.contains("Area πr²"), "{}", md);
+        assert!(md.contains("e^(iπ) + 1 = 0"), "{}", md);
+
+        let html = render_page("numpy", "mean", page, Formats::HTML);
+        assert!(html.contains(r#"<span class="math notranslate">\(\bar{x}"#));
+        assert!(html.contains(r#"<span class="math">\(e^{i\pi} + 1 = 0\)</span>"#));
+        assert!(html.contains("<math><semantics>"));
+        // MathJax is only loaded from where the user put it
+        let offline = Theme::Auto.apply(&html, "mean", None, None);
+        assert!(!offline.contains("<script"));
+        assert!(offline.contains("e^(iπ) + 1 = 0"), "{}", offline);
+        let typeset = Theme::Auto.apply(&html, "mean", None, Some("/opt/mathjax/tex mml.js"));
+        assert!(typeset.contains(r#"src="file:///opt/mathjax/tex%20mml.js""#));
+        assert!(typeset.contains(r#"\(e^{i\pi} + 1 = 0\)"#));
+        let plain = Theme::Auto.apply("<p>x</p>", "x", None, Some("https://example.com/mj.js"));
+        assert!(!plain.contains("<script"));
+    }
+}
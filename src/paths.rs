@@ -0,0 +1,174 @@
+//! Mapping page paths to file names that Windows takes.
+//!
+//! Page paths may hold characters Windows forbids in file names (`css/:hover`), names
+//! it reserves for devices (`aux`, `con`) or names longer than its 255-character
+//! limit. On Windows each component of a page path is encoded before it's written:
+//! forbidden characters become `~XX` escapes of their code, reserved names get their
+//! first letter escaped, and overlong names are cut short and suffixed with a hash of
+//! the whole name. Escapes are reversed from the name alone; shortened names are
+//! looked up in the names of the doc's pages, as listed by its page hashes.
+//!
+//! Paths longer than `MAX_PATH` need no encoding, the standard library already opens
+//! them as verbatim paths. Elsewhere pages keep their names as they are.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::page_hash;
+
+/// Whether page paths are encoded for the filesystem
+pub(crate) const SAFE_PATHS: bool = cfg!(windows);
+
+/// Longest encoded name, leaving room for format extensions within the 255-character
+/// limit of a file name
+const MAX_NAME: usize = 200;
+
+/// Names of devices, reserved with or without an extension
+const RESERVED: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
+
+/// Where the files of a page are written within its doc dir, before the format extension
+pub(crate) fn disk_page(page: &str) -> Cow<'_, str> {
+    if SAFE_PATHS {
+        Cow::Owned(encode(page))
+    } else {
+        Cow::Borrowed(page)
+    }
+}
+
+/// Encode each component of a page path into a file name Windows takes
+pub(crate) fn encode(page: &str) -> String {
+    page.split('/')
+        .map(encode_name)
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn encode_name(name: &str) -> String {
+    let stem = name.split('.').next().unwrap_or_default();
+    let reserved = RESERVED.iter().any(|r| r.eq_ignore_ascii_case(stem));
+    let last = name.chars().count().saturating_sub(1);
+
+    let mut encoded = String::with_capacity(name.len());
+    for (i, c) in name.chars().enumerate() {
+        let forbidden =
+            c.is_control() || matches!(c, '<' | '>' | ':' | '"' | '\\' | '|' | '?' | '*');
+        // Windows drops trailing dots and spaces
+        let trailing = i == last && matches!(c, '.' | ' ');
+        if forbidden || trailing || c == '~' || (reserved && i == 0) {
+            let _ = write!(encoded, "~{:02X}", c as u32);
+        } else {
+            encoded.push(c);
+        }
+    }
+
+    if encoded.chars().count() <= MAX_NAME {
+        return encoded;
+    }
+    let kept: String = encoded.chars().take(MAX_NAME - 18).collect();
+    // Don't cut an escape in half
+    let kept = match kept.rfind('~') {
+        Some(i) if kept.len() - i < 3 => &kept[..i],
+        _ => &kept,
+    };
+    format!("{}~~{:016x}", kept, page_hash(name))
+}
+
+/// Reverses [`encode`], knowing the pages whose names were shortened
+#[derive(Debug, Default)]
+pub(crate) struct PageNames {
+    /// Original names of shortened names
+    shortened: HashMap<String, String>,
+}
+
+impl PageNames {
+    /// Names of the given pages of a doc
+    pub(crate) fn new<'a>(pages: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut shortened = HashMap::new();
+        for name in pages.into_iter().flat_map(|page| page.split('/')) {
+            let encoded = encode_name(name);
+            // Escapes never double the `~`, only shortening does
+            if encoded.contains("~~") {
+                shortened.insert(encoded, name.to_string());
+            }
+        }
+        Self { shortened }
+    }
+
+    /// The page path of a path written by [`disk_page`]
+    pub(crate) fn page<'a>(&self, disk: &'a str) -> Cow<'a, str> {
+        if !SAFE_PATHS {
+            return Cow::Borrowed(disk);
+        }
+        Cow::Owned(self.decode(disk))
+    }
+
+    /// The page path of a path [`encode`]d on any platform
+    pub(crate) fn decode(&self, disk: &str) -> String {
+        disk.split('/')
+            .map(|name| match self.shortened.get(name) {
+                Some(original) => original.clone(),
+                None => unescape(name),
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+fn unescape(name: &str) -> String {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(i) = rest.find('~') {
+        decoded.push_str(&rest[..i]);
+        let code = rest
+            .get(i + 1..i + 3)
+            .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+            .and_then(char::from_u32);
+        match code {
+            Some(c) => {
+                decoded.push(c);
+                rest = &rest[i + 3..];
+            }
+            None => {
+                decoded.push('~');
+                rest = &rest[i + 1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_paths_encode_to_windows_safe_names() {
+        assert_eq!(encode("css/:hover"), "css/~3Ahover");
+        assert_eq!(encode("c/aux"), "c/~61ux");
+        assert_eq!(encode("c/CON.h"), "c/~43ON.h");
+        assert_eq!(encode("c/console"), "c/console");
+        assert_eq!(encode("a/b?c*d~e."), "a/b~3Fc~2Ad~7Ee~2E");
+
+        let long = format!("std/{}", "very_long_name".repeat(20));
+        let encoded = encode(&long);
+        let name = encoded.rsplit('/').next().unwrap();
+        assert!(name.len() <= 200 && name.contains("~~"), "{}", name);
+        // Another long name with the same start doesn't collide
+        assert_ne!(encode(&format!("{}x", long)), encoded);
+
+        let pages = [long.clone(), "css/:hover".to_string()];
+        let names = PageNames::new(&pages);
+        for page in ["css/:hover", "c/aux", "c/CON.h", "a/b?c*d~e.", &long] {
+            assert_eq!(names.decode(&encode(page)), page);
+        }
+        // Pages keep their names where the filesystem takes them
+        if !SAFE_PATHS {
+            assert_eq!(disk_page("css/:hover"), "css/:hover");
+        }
+    }
+}
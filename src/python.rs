@@ -0,0 +1,209 @@
+//! Python bindings, built as the `devdocs` extension module with maturin:
+//!
+//! ```text
+//! maturin develop --features python
+//! ```
+//!
+//! ```python
+//! import devdocs
+//!
+//! docs = devdocs.DevDocs()
+//! docs.install("rust")
+//! for hit in docs.search("vec push", limit=5):
+//!     print(hit.doc, hit.name, hit.score)
+//! print(docs.get_page("rust", "std/vec/struct.vec", format="markdown"))
+//! ```
+//!
+//! Calls block until they finish, without holding the GIL, so other Python threads
+//! keep running meanwhile.
+
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use crate::{DevDocsError, Formats, SearchOptions, SearchResult, blocking};
+
+/// Turn an error of the manager into the closest Python exception
+fn py_error(error: anyhow::Error) -> PyErr {
+    match error.downcast_ref::<DevDocsError>() {
+        Some(DevDocsError::DocNotFound(_)) => PyKeyError::new_err(error.to_string()),
+        Some(DevDocsError::InvalidSlug(_)) => PyValueError::new_err(error.to_string()),
+        _ => PyRuntimeError::new_err(format!("{:#}", error)),
+    }
+}
+
+/// A format by the name the CLI knows it as
+fn format(name: &str) -> PyResult<Formats> {
+    match name {
+        "html" => Ok(Formats::HTML),
+        "markdown" | "md" => Ok(Formats::MARKDOWN),
+        "text" | "txt" => Ok(Formats::TEXT),
+        _ => Err(PyValueError::new_err(format!(
+            "Unknown format '{}', expected html, markdown or text",
+            name
+        ))),
+    }
+}
+
+/// An entry matching a search
+#[pyclass(frozen, module = "devdocs")]
+pub struct SearchHit {
+    /// Slug of the doc the entry is in
+    #[pyo3(get)]
+    doc: String,
+    #[pyo3(get)]
+    doc_name: String,
+    #[pyo3(get)]
+    name: String,
+    #[pyo3(get, name = "type")]
+    entry_type: String,
+    /// Page path within the doc, with an `#anchor` for sections
+    #[pyo3(get)]
+    path: String,
+    #[pyo3(get)]
+    score: u32,
+}
+
+#[pymethods]
+impl SearchHit {
+    fn __repr__(&self) -> String {
+        format!(
+            "SearchHit(doc={:?}, name={:?}, type={:?}, path={:?}, score={})",
+            self.doc, self.name, self.entry_type, self.path, self.score
+        )
+    }
+}
+
+impl From<SearchResult> for SearchHit {
+    fn from(result: SearchResult) -> Self {
+        let entry = result.entry;
+        SearchHit {
+            doc: entry.doc_slug,
+            doc_name: entry.doc_name,
+            name: entry.entry.name,
+            entry_type: entry.entry.entry_type,
+            path: entry.entry.path.to_string_lossy().into_owned(),
+            score: result.score,
+        }
+    }
+}
+
+/// The docs installed on this machine, as used by the `dev` CLI
+#[pyclass(module = "devdocs")]
+pub struct DevDocs {
+    manager: blocking::DevDocsManager,
+}
+
+#[pymethods]
+impl DevDocs {
+    /// Open the docs in `data_dir`, or in the default data dir of `profile`
+    #[new]
+    #[pyo3(signature = (data_dir=None, profile=None))]
+    fn new(py: Python<'_>, data_dir: Option<String>, profile: Option<String>) -> PyResult<Self> {
+        let mut builder = crate::DevDocsManager::builder();
+        if let Some(data_dir) = data_dir {
+            builder = builder.data_dir(data_dir);
+        }
+        if let Some(profile) = profile {
+            builder = builder.profile(profile);
+        }
+        let manager = py
+            .detach(|| blocking::DevDocsManager::from_builder(builder))
+            .map_err(py_error)?;
+        Ok(DevDocs { manager })
+    }
+
+    /// Slugs of the installed docs
+    fn installed(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        let mut slugs = py
+            .detach(|| self.manager.list_installed_docs())
+            .map_err(py_error)?;
+        slugs.sort();
+        Ok(slugs)
+    }
+
+    /// Download and install a doc by slug or alias, rendering its pages in `formats`
+    #[pyo3(signature = (slug, formats=None))]
+    fn install(&self, py: Python<'_>, slug: &str, formats: Option<Vec<String>>) -> PyResult<()> {
+        let formats = match formats {
+            Some(names) => Some(
+                names
+                    .iter()
+                    .map(|name| format(name))
+                    .collect::<PyResult<Vec<_>>>()?
+                    .into_iter()
+                    .fold(Formats::empty(), |all, format| all | format),
+            ),
+            None => None,
+        };
+        py.detach(|| self.manager.add_doc(slug, formats))
+            .map_err(py_error)
+    }
+
+    /// Update a doc, or every installed doc without one, returning the slugs updated
+    #[pyo3(signature = (slug=None))]
+    fn update(&self, py: Python<'_>, slug: Option<&str>) -> PyResult<Vec<String>> {
+        py.detach(|| match slug {
+            Some(slug) => {
+                let status = self.manager.update_doc(slug)?;
+                Ok(match status {
+                    crate::UpdateStatus::Updated(_) => vec![slug.to_string()],
+                    _ => Vec::new(),
+                })
+            }
+            None => {
+                let report = self.manager.update_all()?;
+                Ok(report.updated.into_iter().map(|(slug, _)| slug).collect())
+            }
+        })
+        .map_err(py_error)
+    }
+
+    /// Remove an installed doc
+    fn remove(&self, py: Python<'_>, slug: &str) -> PyResult<()> {
+        py.detach(|| self.manager.remove_doc(slug))
+            .map_err(py_error)
+    }
+
+    /// Fuzzy search the entries of the installed docs, best first, optionally only
+    /// in some docs or of some entry types
+    #[pyo3(signature = (query, limit=20, docs=None, types=None))]
+    fn search(
+        &self,
+        py: Python<'_>,
+        query: &str,
+        limit: usize,
+        docs: Option<Vec<String>>,
+        types: Option<Vec<String>>,
+    ) -> PyResult<Vec<SearchHit>> {
+        let options = SearchOptions {
+            limit,
+            docs: docs.unwrap_or_default(),
+            types: types.unwrap_or_default(),
+            ..Default::default()
+        };
+        let results = py
+            .detach(|| self.manager.search_with(query, &options))
+            .map_err(py_error)?;
+        Ok(results.into_iter().map(SearchHit::from).collect())
+    }
+
+    /// A page of a doc rendered as `html`, `markdown` or `text`, downloading just that
+    /// page when the doc isn't installed
+    #[pyo3(signature = (slug, path, format="markdown"))]
+    fn get_page(&self, py: Python<'_>, slug: &str, path: &str, format: &str) -> PyResult<String> {
+        let format = self::format(format)?;
+        py.detach(|| self.manager.remote_page(slug, path, format))
+            .map_err(py_error)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("DevDocs({:?})", self.manager.write_dir())
+    }
+}
+
+#[pymodule]
+fn devdocs(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<DevDocs>()?;
+    m.add_class::<SearchHit>()?;
+    Ok(())
+}
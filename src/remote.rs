@@ -0,0 +1,334 @@
+//! Searching and reading docs on devdocs.io that aren't installed
+
+use std::collections::HashMap;
+use std::path::{Component, Path};
+
+use anyhow::{Context, Result, bail};
+use tokio::fs;
+use tracing::debug;
+
+use crate::paths::disk_page;
+use crate::{
+    CachedDoc, DevDocsError, DevDocsManager, FETCHED_DIR, Formats, SearchOptions, SearchResponse,
+    SearchResult, add_ext, current_timestamp, has_type, rank_candidates, render_page,
+    split_fragment,
+};
+
+impl DevDocsManager {
+    /// Search like [`search_response`](Self::search_response), falling back to the
+    /// indexes of docs that aren't installed when nothing installed matches.
+    ///
+    /// Named docs that aren't installed are searched remotely, in place of the installed
+    /// ones; without any named, the `remote` setting lists the docs to fall back to.
+    pub(crate) async fn search_response_remote(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<SearchResponse> {
+        let mut installed = Vec::new();
+        let mut missing = Vec::new();
+        for name in &options.docs {
+            match self.resolve_installed(name).await {
+                Some(slug) => installed.push(slug),
+                None => missing.push(name.clone()),
+            }
+        }
+
+        let mut response = if options.docs.is_empty() || !installed.is_empty() {
+            let local = SearchOptions {
+                docs: installed,
+                remote: false,
+                ..options.clone()
+            };
+            self.local_response(query, &local).await?
+        } else {
+            SearchResponse::default()
+        };
+        // Later pages only page through the installed results
+        if response.results.is_empty() && options.offset == 0 {
+            if options.docs.is_empty() {
+                missing = self.settings.read().await.remote_docs.clone();
+            }
+            response.remote = self.search_remote(query, &missing, options).await?;
+        }
+        Ok(response)
+    }
+
+    /// Search the indexes of docs on devdocs.io, without installing them.
+    ///
+    /// `docs` are slugs or aliases of available docs. Their indexes are downloaded on
+    /// first use and kept for the life of the manager.
+    pub async fn search_remote(
+        &self,
+        query: &str,
+        docs: &[String],
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        if query.trim().is_empty() || docs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let available = self.get_available_docs().await?;
+        let mut scope = Vec::with_capacity(docs.len());
+        for name in docs {
+            let slug = self.resolve_slug(name).await?;
+            let Some(doc) = available.iter().find(|doc| doc.slug == slug) else {
+                return Err(DevDocsError::DocNotFound(name.clone()).into());
+            };
+            if !self.remote_indexes.read().await.contains_key(&slug) {
+                debug!("Searching {} remotely", slug);
+                let index = self.source_of(&slug).await.fetch_index(&slug).await?;
+                let mut cached_doc = CachedDoc {
+                    doc: doc.clone(),
+                    formats: None,
+                    index,
+                    cached_at: current_timestamp(),
+                    haystacks: Vec::new(),
+                    haystacks_generation: None,
+                };
+                cached_doc.ensure_haystacks();
+                self.remote_indexes
+                    .write()
+                    .await
+                    .insert(slug.clone(), cached_doc);
+            }
+            scope.push(slug);
+        }
+
+        let indexes = self.remote_indexes.read().await;
+        let candidates: Vec<_> = indexes
+            .iter()
+            .filter(|(slug, _)| scope.contains(slug))
+            .flat_map(|(slug, cached_doc)| {
+                cached_doc
+                    .index
+                    .entries
+                    .iter()
+                    .zip(&cached_doc.haystacks)
+                    .map(move |(entry, haystack)| (slug, cached_doc, entry, haystack))
+            })
+            .filter(|(_, _, entry, _)| has_type(options, entry))
+            .collect();
+
+        let options = SearchOptions {
+            offset: 0,
+            ..options.clone()
+        };
+        let (results, _) = self.in_search_pool(|| {
+            rank_candidates(
+                &candidates,
+                query,
+                &options,
+                &|_, _| None,
+                &HashMap::new(),
+                &Default::default(),
+            )
+        });
+        Ok(results)
+    }
+
+    /// A single page of a doc rendered in `format`, downloading just that page when
+    /// the doc isn't installed, as [`fetch_page`](Self::fetch_page) does.
+    ///
+    /// `path` is the page path within the doc, without extension; the `#fragment` of
+    /// an entry path is ignored.
+    pub async fn remote_page(&self, slug: &str, path: &str, format: Formats) -> Result<String> {
+        let (path, _) = split_fragment(path);
+        let slug = self.resolve_slug(slug).await?;
+        let html = self.fetch_page(&slug, path).await?;
+        Ok(render_page(&slug, path, &html, format))
+    }
+
+    /// The HTML of a single page, downloading just that page when its doc isn't
+    /// installed.
+    ///
+    /// Downloaded pages are kept with the installed docs, so reading one again doesn't
+    /// touch the network. `path` is the page path within the doc, without extension;
+    /// the `#fragment` of an entry path is ignored.
+    pub async fn fetch_page(&self, slug: &str, path: &str) -> Result<String> {
+        let (path, _) = split_fragment(path);
+        let escapes = Path::new(path)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)));
+        if escapes {
+            bail!("Invalid page path '{}'", path);
+        }
+        if let Some(installed) = self.resolve_installed(slug).await {
+            if let Ok(html) = self.get_page_content(&installed, path).await {
+                return Ok(html);
+            }
+            let rendered = add_ext(Path::new(&installed).join(&*disk_page(path)), "html");
+            if let Some(file) = self.resolve_path(&rendered) {
+                return Ok(fs::read_to_string(file).await?);
+            }
+        }
+
+        let slug = self.resolve_slug(slug).await?;
+        let relative = add_ext(
+            Path::new(FETCHED_DIR).join(&slug).join(&*disk_page(path)),
+            "html",
+        );
+        if let Some(file) = self.resolve_path(&relative) {
+            return Ok(fs::read_to_string(file).await?);
+        }
+
+        let url = format!("{}/{}/{}.html", self.documents_url, slug, path);
+        debug!("Downloading page: {}", url);
+        let html = String::from_utf8(self.transport.get(&url).await?)
+            .with_context(|| format!("{}/{} isn't UTF-8", slug, path))?;
+        let file = self.write_dir().join(relative);
+        if let Some(parent) = file.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&file, &html).await?;
+        Ok(html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_remote_search_falls_back_to_docs_not_installed() {
+        let transport = MockTransport::new()
+            .respond(
+                "/docs.json",
+                r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10},
+                    {"name":"Kubernetes","slug":"kubernetes","type":"simple","mtime":1,"db_size":10}]"#,
+            )
+            .respond(
+                "/documents/rust/index.json",
+                r#"{"entries":[{"name":"Vec","path":"std/vec","type":"struct"}],
+                    "types":[{"name":"struct","count":1,"slug":"struct"}]}"#,
+            )
+            .respond(
+                "/documents/rust/db.json",
+                r#"{"std/vec":"<h1>Vec</h1>"}"#,
+            )
+            .respond(
+                "/documents/kubernetes/index.json",
+                r#"{"entries":[{"name":"Deployment","path":"deployment#spec","type":"workloads"}],
+                    "types":[{"name":"workloads","count":1,"slug":"workloads"}]}"#,
+            )
+            .respond(
+                "/documents/kubernetes/deployment.html",
+                "<h1>Deployment</h1><p>Runs <em>replicas</em>.</p>",
+            );
+        let manager = test_builder(temp_dir("remote-search"))
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        manager
+            .add_doc("rust", Some(Formats::MARKDOWN))
+            .await
+            .unwrap();
+
+        // Opt-in only, and only when nothing installed matches
+        let options = SearchOptions::default();
+        let response = manager
+            .search_response("deployment", &options)
+            .await
+            .unwrap();
+        assert!(response.results.is_empty() && response.remote.is_empty());
+        let options = SearchOptions {
+            remote: true,
+            ..Default::default()
+        };
+        let response = manager.search_response("vec", &options).await.unwrap();
+        assert_eq!(response.results.len(), 1);
+        assert!(response.remote.is_empty());
+
+        manager.set_config("remote", "kubernetes").await.unwrap();
+        let response = manager
+            .search_response("deployment", &options)
+            .await
+            .unwrap();
+        assert!(response.results.is_empty());
+        assert_eq!(response.remote.len(), 1);
+        assert_eq!(response.remote[0].entry.doc_slug, "kubernetes");
+        assert!(!manager.is_doc_installed("kubernetes").await.unwrap());
+
+        // Named docs that aren't installed are searched remotely instead
+        manager.unset_config("remote").await.unwrap();
+        let options = SearchOptions {
+            docs: vec!["kubernetes".to_string()],
+            remote: true,
+            ..Default::default()
+        };
+        let response = manager.search_response("deploy", &options).await.unwrap();
+        assert_eq!(response.remote[0].entry.entry.name, "Deployment");
+
+        let page = manager
+            .remote_page("kubernetes", "deployment", Formats::MARKDOWN)
+            .await
+            .unwrap();
+        assert!(page.contains("Runs *replicas*."), "{}", page);
+        assert!(!manager.is_doc_installed("kubernetes").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_fetch_page_downloads_and_keeps_a_single_page() {
+        let docs = r#"[{"name":"Kubernetes","slug":"kubernetes","type":"simple","mtime":1,"db_size":200000000}]"#;
+        let transport = MockTransport::new().respond("/docs.json", docs).respond(
+            "/documents/kubernetes/workloads/deployment.html",
+            "<h1>Deployment</h1>",
+        );
+        let dir = temp_dir("fetch-page");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(transport)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        let html = manager
+            .fetch_page("kubernetes", "workloads/deployment")
+            .await
+            .unwrap();
+        assert_eq!(html, "<h1>Deployment</h1>");
+        assert!(!manager.is_doc_installed("kubernetes").await.unwrap());
+        assert!(
+            manager
+                .fetch_page("kubernetes", "../../etc/passwd")
+                .await
+                .is_err()
+        );
+        // An absolute path would replace the data dir when joined onto it
+        let outside = temp_dir("fetch-page-outside");
+        std::fs::create_dir_all(&outside).unwrap();
+        std::fs::write(outside.join("page.html"), "<h1>Outside</h1>").unwrap();
+        let absolute = outside.join("page");
+        assert!(
+            manager
+                .fetch_page("kubernetes", &absolute.to_string_lossy())
+                .await
+                .is_err()
+        );
+
+        // Read again without the network
+        let offline = test_builder(&dir)
+            .base_url("http://docs.test")
+            .documents_url("http://docs.test/documents")
+            .transport(MockTransport::new().respond("/docs.json", docs))
+            .build()
+            .unwrap();
+        offline.init().await.unwrap();
+        let markdown = offline
+            .remote_page("kubernetes", "workloads/deployment", Formats::MARKDOWN)
+            .await
+            .unwrap();
+        assert!(markdown.contains("Deployment"), "{}", markdown);
+        assert!(
+            offline
+                .fetch_page("kubernetes", "workloads/service")
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,374 @@
+//! Ranking pages by the meaning of a query rather than its spelling
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+use crate::{
+    DevDocsManager, EMBEDDINGS_DIR, Embedder, Entry, SECTION_TYPE, SearchOptions, SearchResult,
+    cap_per_doc, decode_entities, search_result, write_atomic,
+};
+
+/// Maximum number of characters of a section that get embedded
+const SECTION_CHARS: usize = 1000;
+/// Number of sections handed to the embedder at once
+const EMBED_BATCH: usize = 64;
+/// Smoothing constant of the reciprocal rank fusion used by hybrid search
+const RRF_K: f32 = 60.0;
+
+/// The embedding of a page, or of a section of one
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub(crate) struct SectionEmbedding {
+    /// Entry path of the section: the page path, with an `#anchor` for sub-sections
+    pub path: String,
+    pub title: String,
+    pub vector: Vec<f32>,
+}
+
+impl DevDocsManager {
+    /// Find the pages and sections closest in meaning to `query`.
+    ///
+    /// Needs an [`Embedder`], and only covers docs installed while one was configured.
+    /// Scores are cosine similarities scaled to 0..=1000. Honors the scope, paging,
+    /// score and per-doc settings of `options`.
+    pub async fn search_semantic(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        let Some(embedder) = self.embedder.clone() else {
+            anyhow::bail!(
+                "Semantic search needs an embedder: enable local embeddings or supply one with `embedder`"
+            );
+        };
+        if query.trim().is_empty() || options.limit == 0 {
+            return Ok(vec![]);
+        }
+
+        let slugs = self.resolve_scope(&options.docs).await?;
+
+        let query = query.to_string();
+        let needle = tokio::task::spawn_blocking(move || embedder.embed(&[query]))
+            .await??
+            .pop()
+            .context("Embedder returned no vector for the query")?;
+
+        let mut scored = Vec::new();
+        for slug in slugs {
+            let Some(embeddings) = self.load_embeddings(&slug).await else {
+                continue;
+            };
+            for embedding in embeddings {
+                let score = (cosine(&needle, &embedding.vector).max(0.0) * 1000.0) as u32;
+                if score >= options.min_score {
+                    scored.push((score, slug.clone(), embedding));
+                }
+            }
+        }
+        scored.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| a.1.cmp(&b.1))
+                .then_with(|| a.2.path.cmp(&b.2.path))
+        });
+
+        let cache = self.cache.read().await;
+        let mut under_cap = cap_per_doc(options.max_per_doc);
+        Ok(scored
+            .into_iter()
+            .filter(|(_, slug, _)| under_cap(slug))
+            .filter_map(|(score, slug, embedding)| {
+                let cached_doc = cache.get(&slug)?;
+                let path = Path::new(&embedding.path);
+                // Point at the index entry for the section when there is one
+                let entry = cached_doc
+                    .index
+                    .entries
+                    .iter()
+                    .find(|e| e.path == path)
+                    .cloned()
+                    .unwrap_or_else(|| Entry {
+                        name: embedding.title,
+                        path: PathBuf::from(&embedding.path),
+                        entry_type: SECTION_TYPE.to_string(),
+                    });
+                Some(search_result(&slug, cached_doc, &entry, score))
+            })
+            .skip(options.offset)
+            .take(options.limit)
+            .collect())
+    }
+
+    /// Rank by name and by meaning at once, fusing the fuzzy and semantic rankings.
+    ///
+    /// Results found by both rank highest. Scores are fused reciprocal ranks, only
+    /// meaningful relative to each other.
+    pub async fn search_hybrid(
+        &self,
+        query: &str,
+        options: &SearchOptions,
+    ) -> Result<Vec<SearchResult>> {
+        // Look deeper than the limit so results ranked low by one side can still surface
+        let wide = SearchOptions {
+            limit: options
+                .offset
+                .saturating_add(options.limit)
+                .saturating_mul(2),
+            offset: 0,
+            ..options.clone()
+        };
+        let rankings = [
+            self.search_with(query, &wide).await?,
+            self.search_semantic(query, &wide).await?,
+        ];
+
+        let mut fused: HashMap<(String, PathBuf), (f32, SearchResult)> = HashMap::new();
+        for ranking in rankings {
+            for (rank, result) in ranking.into_iter().enumerate() {
+                let key = (
+                    result.entry.doc_slug.clone(),
+                    result.entry.entry.path.clone(),
+                );
+                let weight = 1.0 / (RRF_K + rank as f32 + 1.0);
+                fused.entry(key).or_insert((0.0, result)).0 += weight;
+            }
+        }
+
+        let mut fused: Vec<(f32, SearchResult)> = fused.into_values().collect();
+        fused.sort_by(|a, b| {
+            b.0.total_cmp(&a.0)
+                .then_with(|| a.1.entry.doc_slug.cmp(&b.1.entry.doc_slug))
+                .then_with(|| a.1.entry.entry.path.cmp(&b.1.entry.entry.path))
+        });
+
+        let mut under_cap = cap_per_doc(options.max_per_doc);
+        Ok(fused
+            .into_iter()
+            .filter(|(_, r)| under_cap(&r.entry.doc_slug))
+            .skip(options.offset)
+            .take(options.limit)
+            .map(|(score, mut result)| {
+                result.score = (score * 100_000.0) as u32;
+                result
+            })
+            .collect())
+    }
+
+    pub(crate) async fn save_embeddings(
+        &self,
+        slug: &str,
+        embeddings: &[SectionEmbedding],
+    ) -> Result<()> {
+        let dir = self.write_dir().join(EMBEDDINGS_DIR);
+        fs::create_dir_all(&dir).await?;
+        let data = bitcode::serialize(embeddings)?;
+        write_atomic(&dir.join(format!("{}.bin", slug)), &data).await?;
+        Ok(())
+    }
+
+    /// Read the embeddings of a doc from the topmost layer that has them
+    async fn load_embeddings(&self, slug: &str) -> Option<Vec<SectionEmbedding>> {
+        let path = self.resolve_path(&Path::new(EMBEDDINGS_DIR).join(format!("{}.bin", slug)))?;
+        let content = fs::read(path).await.ok()?;
+        bitcode::deserialize(&content).ok()
+    }
+}
+
+/// Split a page into the text before its first anchored `h2`/`h3` and the text of each
+/// anchored heading onwards, as `(entry path, title, text)`
+pub(crate) fn page_sections(page: &str, title: &str, html: &str) -> Vec<(String, String, String)> {
+    use lol_html::{RewriteStrSettings, doc_text, element, rewrite_str, text};
+    use std::cell::RefCell;
+
+    // (path, heading, text) in document order, starting with the page itself
+    let sections = RefCell::new(vec![(page.to_string(), String::new(), String::new())]);
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("*", |_| {
+                if let Some((_, _, text)) = sections.borrow_mut().last_mut() {
+                    text.push(' ');
+                }
+                Ok(())
+            }),
+            element!("h2[id], h3[id]", |el| {
+                let id = el.get_attribute("id").unwrap_or_default();
+                let path = format!("{}#{}", page, id);
+                sections
+                    .borrow_mut()
+                    .push((path, String::new(), String::new()));
+                Ok(())
+            }),
+            text!("h2[id], h3[id]", |chunk| {
+                if let Some((_, heading, _)) = sections.borrow_mut().last_mut() {
+                    heading.push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        document_content_handlers: vec![doc_text!(|chunk| {
+            if let Some((_, _, text)) = sections.borrow_mut().last_mut() {
+                text.push_str(chunk.as_str());
+            }
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    if rewrite_str(html, settings).is_err() {
+        return Vec::new();
+    }
+
+    let collapse =
+        |text: &str| decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" "));
+    sections
+        .into_inner()
+        .into_iter()
+        .filter_map(|(path, heading, text)| {
+            let text: String = collapse(&text).chars().take(SECTION_CHARS).collect();
+            let heading = collapse(&heading);
+            let title = if heading.is_empty() {
+                title.to_string()
+            } else {
+                heading
+            };
+            (!text.is_empty()).then_some((path, title, text))
+        })
+        .collect()
+}
+
+/// Embed `(entry path, title, text)` sections in batches
+pub(crate) fn embed_sections(
+    embedder: &dyn Embedder,
+    sections: Vec<(String, String, String)>,
+) -> Result<Vec<SectionEmbedding>> {
+    let mut embeddings = Vec::with_capacity(sections.len());
+    for batch in sections.chunks(EMBED_BATCH) {
+        let texts: Vec<String> = batch
+            .iter()
+            .map(|(_, title, text)| format!("{}: {}", title, text))
+            .collect();
+        let vectors = embedder.embed(&texts)?;
+        if vectors.len() != batch.len() {
+            anyhow::bail!(
+                "Embedder returned {} vectors for {} texts",
+                vectors.len(),
+                batch.len()
+            );
+        }
+        embeddings.extend(batch.iter().zip(vectors).map(|((path, title, _), vector)| {
+            SectionEmbedding {
+                path: path.clone(),
+                title: title.clone(),
+                vector,
+            }
+        }));
+    }
+    Ok(embeddings)
+}
+
+/// Cosine similarity of two vectors, 0 when either is all zeros
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// A small sentence-embedding model run locally through ONNX Runtime, downloaded into
+/// the data dir the first time something is embedded
+#[cfg(feature = "semantic")]
+pub(crate) struct LocalEmbedder {
+    cache_dir: PathBuf,
+    model: std::sync::Mutex<Option<fastembed::TextEmbedding>>,
+}
+
+#[cfg(feature = "semantic")]
+impl LocalEmbedder {
+    pub(crate) fn new(cache_dir: PathBuf) -> Self {
+        Self {
+            cache_dir,
+            model: std::sync::Mutex::new(None),
+        }
+    }
+}
+
+#[cfg(feature = "semantic")]
+impl Embedder for LocalEmbedder {
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        use fastembed::{EmbeddingModel, TextEmbedding, TextInitOptions};
+
+        let mut model = self.model.lock().expect("embedding model poisoned");
+        if model.is_none() {
+            let options = TextInitOptions::new(EmbeddingModel::AllMiniLML6V2)
+                .with_cache_dir(self.cache_dir.clone())
+                .with_show_download_progress(false);
+            *model = Some(TextEmbedding::try_new(options)?);
+        }
+        let model = model.as_mut().expect("embedding model was just loaded");
+        model.embed(texts, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{insert_doc, sample_cached_doc, temp_dir, test_builder};
+
+    /// Embeds texts as the counts of their words, hashed into a few buckets
+    struct WordCounts;
+
+    impl Embedder for WordCounts {
+        fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    let mut vector = vec![0.0; 32];
+                    for word in text.to_lowercase().split(|c: char| !c.is_alphanumeric()) {
+                        if !word.is_empty() {
+                            let bucket = word.bytes().fold(7usize, |h, b| h * 31 + b as usize);
+                            vector[bucket % 32] += 1.0;
+                        }
+                    }
+                    vector
+                })
+                .collect())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_semantic_ranks_sections_by_embedding() {
+        let manager = test_builder(temp_dir("semantic"))
+            .embedder(WordCounts)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        insert_doc(&manager, sample_cached_doc("rust")).await;
+
+        let html = concat!(
+            "<p>Asynchronous values.</p>",
+            r#"<h2 id="cancellation">Cancellation</h2><p>Dropping a future cancels it.</p>"#,
+            r#"<h2 id="pinning">Pinning</h2><p>Futures are pinned before polling.</p>"#,
+        );
+        let sections = page_sections("std/future/index", "std::future", html);
+        assert_eq!(sections.len(), 3);
+        assert_eq!(sections[1].0, "std/future/index#cancellation");
+        assert_eq!(sections[1].1, "Cancellation");
+        let embeddings = embed_sections(&WordCounts, sections).unwrap();
+        manager.save_embeddings("rust", &embeddings).await.unwrap();
+
+        let options = SearchOptions::default();
+        let results = manager
+            .search_semantic("dropping a future", &options)
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0].entry.entry.path,
+            PathBuf::from("std/future/index#cancellation")
+        );
+        assert_eq!(results[0].entry.entry.entry_type, SECTION_TYPE);
+
+        let plain = test_builder(temp_dir("semantic-off")).build().unwrap();
+        assert!(plain.search_semantic("future", &options).await.is_err());
+    }
+}
@@ -0,0 +1,448 @@
+//! A local HTTP server exposing the installed docs as a JSON API
+//!
+//! | Endpoint | Response |
+//! |---|---|
+//! | `GET /` | a self-contained search page over the endpoints below |
+//! | `GET /api/docs` | the installed [`Doc`]s, sorted by slug |
+//! | `GET /api/docs/{slug}/index` | the [`DocIndex`] of an installed doc |
+//! | `GET /api/search?q=&doc=&type=&limit=&offset=` | a [`SearchResponse`]; `doc` and `type` may be repeated |
+//! | `GET /api/page/{slug}/{path}` | a rendered page: HTML, or Markdown when the `Accept` header asks for `text/markdown` |
+//! | `GET /api/search/live?doc=&limit=` | a WebSocket for incremental search, see below |
+//! | `GET /theme.css` | the colors of the configured [`Theme`](crate::Theme), as CSS variables |
+//! | `GET /user.css`, `GET /user.js` | the user's [`USER_CSS`](crate::USER_CSS) and [`USER_JS`](crate::USER_JS), empty when they have none |
+//! | `GET /metrics` | the [`ManagerStats`](crate::ManagerStats) and the requests answered so far, in the Prometheus text format |
+//! | `GET /healthz` | `{"status": "ok", "docs": <installed>}`, or 503 when the data dir is gone |
+//!
+//! The live search socket keeps a [`SearchSession`](crate::SearchSession) for the whole
+//! connection. Each text message the client sends is the full current query; each
+//! answer is a [`LiveResults`] for the latest query. Queries that arrive while one is
+//! being ranked are coalesced, so a fast typist only gets answers for what they typed last.
+//!
+//! Failures are answered with `{"error": "<message>"}`: 404 for unknown docs and pages,
+//! 400 for malformed requests, 500 for everything else.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::path::{Component, Path};
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{MatchedPath, Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::get;
+use axum::{Extension, Json, Router};
+use futures::FutureExt;
+use serde::Serialize;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info};
+
+use crate::{
+    DevDocsError, DevDocsManager, Doc, DocIndex, Formats, SearchOptions, SearchResponse,
+    SearchResult,
+};
+
+/// The web UI: one page with a search box, the results and the selected page
+const INDEX_HTML: &str = include_str!("serve/index.html");
+
+/// An answer of the live search socket
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveResults {
+    /// The query these results are for
+    pub query: String,
+    /// Total number of entries matching the query
+    pub matched: u32,
+    pub results: Vec<SearchResult>,
+}
+
+/// Serve the API of `manager` on `addr` until `cancel` fires, then finish the requests
+/// in flight
+pub async fn serve(
+    manager: Arc<DevDocsManager>,
+    addr: SocketAddr,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    info!("Serving docs on http://{}", listener.local_addr()?);
+    axum::serve(listener, router(manager))
+        .with_graceful_shutdown(cancel.clone().cancelled_owned())
+        .await?;
+    Ok(())
+}
+
+/// The routes of the API, to mount into a larger application
+pub fn router(manager: Arc<DevDocsManager>) -> Router {
+    Router::new()
+        .route("/", get(|| async { Html(INDEX_HTML) }))
+        .route("/api/docs", get(docs))
+        .route("/api/docs/{slug}/index", get(doc_index))
+        .route("/api/search", get(search))
+        .route("/api/search/live", get(live_search))
+        .route("/api/page/{slug}/{*path}", get(page))
+        .route("/theme.css", get(theme))
+        .route("/user.css", get(user_css))
+        .route("/user.js", get(user_js))
+        .route("/metrics", get(metrics))
+        .route("/healthz", get(healthz))
+        .route_layer(middleware::from_fn(count_requests))
+        .layer(Extension(RequestCounts::default()))
+        .with_state(manager)
+}
+
+/// Requests answered by route and status, shared by the clones of a router
+type RequestCounts = Arc<Mutex<BTreeMap<(String, u16), u64>>>;
+
+async fn count_requests(
+    Extension(counts): Extension<RequestCounts>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = match request.extensions().get::<MatchedPath>() {
+        Some(path) => path.as_str().to_string(),
+        None => request.uri().path().to_string(),
+    };
+    let response = next.run(request).await;
+    let key = (route, response.status().as_u16());
+    *counts
+        .lock()
+        .expect("request counts poisoned")
+        .entry(key)
+        .or_default() += 1;
+    response
+}
+
+/// A failed request, answered as JSON
+struct ApiError(StatusCode, String);
+
+impl From<anyhow::Error> for ApiError {
+    fn from(error: anyhow::Error) -> Self {
+        let status = match error.downcast_ref::<DevDocsError>() {
+            Some(DevDocsError::DocNotFound(_)) => StatusCode::NOT_FOUND,
+            Some(DevDocsError::InvalidSlug(_)) => StatusCode::BAD_REQUEST,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        ApiError(status, error.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = serde_json::json!({ "error": self.1 });
+        (self.0, Json(body)).into_response()
+    }
+}
+
+type ApiResult<T> = std::result::Result<T, ApiError>;
+
+async fn docs(State(manager): State<Arc<DevDocsManager>>) -> ApiResult<Json<Vec<Doc>>> {
+    let mut slugs = manager.list_installed_docs().await?;
+    slugs.sort();
+    let mut docs = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        docs.push(manager.get_doc_info(&slug).await?);
+    }
+    Ok(Json(docs))
+}
+
+async fn doc_index(
+    State(manager): State<Arc<DevDocsManager>>,
+    axum::extract::Path(slug): axum::extract::Path<String>,
+) -> ApiResult<Json<DocIndex>> {
+    Ok(Json(manager.get_doc_index(&slug).await?))
+}
+
+async fn search(
+    State(manager): State<Arc<DevDocsManager>>,
+    // A list of pairs, since `doc` and `type` may be repeated
+    Query(params): Query<Vec<(String, String)>>,
+) -> ApiResult<Json<SearchResponse>> {
+    let (query, options) = search_params(params)?;
+    Ok(Json(manager.search_response(&query, &options).await?))
+}
+
+async fn theme(State(manager): State<Arc<DevDocsManager>>) -> Response {
+    let css = manager.theme().await.palette();
+    ([(header::CONTENT_TYPE, "text/css; charset=utf-8")], css).into_response()
+}
+
+async fn user_css(State(manager): State<Arc<DevDocsManager>>) -> Response {
+    let css = manager.user_css().unwrap_or_default().to_string();
+    ([(header::CONTENT_TYPE, "text/css; charset=utf-8")], css).into_response()
+}
+
+async fn user_js(State(manager): State<Arc<DevDocsManager>>) -> Response {
+    let js = manager.user_js().unwrap_or_default().to_string();
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        js,
+    )
+        .into_response()
+}
+
+async fn metrics(
+    State(manager): State<Arc<DevDocsManager>>,
+    Extension(counts): Extension<RequestCounts>,
+) -> ApiResult<Response> {
+    let mut text = manager.stats().await?.to_prometheus();
+    let name = "devdocs_http_requests_total";
+    text.push_str(&format!(
+        "# HELP {name} HTTP requests answered, by route and status\n# TYPE {name} counter\n"
+    ));
+    for ((route, status), count) in counts.lock().expect("request counts poisoned").iter() {
+        text.push_str(&format!(
+            "{name}{{path=\"{route}\",status=\"{status}\"}} {count}\n"
+        ));
+    }
+    Ok(([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], text).into_response())
+}
+
+/// Whether the server can answer searches, for supervisors and load balancers
+async fn healthz(State(manager): State<Arc<DevDocsManager>>) -> Response {
+    if !manager.write_dir().is_dir() {
+        let body = serde_json::json!({ "status": "unavailable", "error": "data dir is missing" });
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response();
+    }
+    match manager.list_installed_docs().await {
+        Ok(docs) => Json(serde_json::json!({ "status": "ok", "docs": docs.len() })).into_response(),
+        Err(e) => {
+            let body = serde_json::json!({ "status": "unavailable", "error": e.to_string() });
+            (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+        }
+    }
+}
+
+async fn live_search(
+    State(manager): State<Arc<DevDocsManager>>,
+    Query(params): Query<Vec<(String, String)>>,
+    upgrade: WebSocketUpgrade,
+) -> ApiResult<Response> {
+    let (_, options) = search_params(params)?;
+    // Load the session before upgrading, so unknown docs still get a plain 404
+    let session = manager.search_session(&options).await?;
+    Ok(upgrade.on_upgrade(move |socket| async move {
+        if let Err(e) = live_session(socket, session, options.limit).await {
+            debug!("Live search connection closed: {}", e);
+        }
+    }))
+}
+
+async fn live_session(
+    mut socket: WebSocket,
+    mut session: crate::SearchSession,
+    limit: usize,
+) -> Result<()> {
+    while let Some(message) = socket.recv().await {
+        let Message::Text(mut query) = message? else {
+            continue;
+        };
+        // Skip to the newest query already waiting
+        while let Some(Some(next)) = socket.recv().now_or_never() {
+            match next? {
+                Message::Text(text) => query = text,
+                Message::Close(_) => return Ok(()),
+                _ => {}
+            }
+        }
+
+        // Ranking waits on nucleo's workers, so keep it off the async threads
+        let (returned, answer) = tokio::task::spawn_blocking(move || {
+            session.set_query(query.as_str());
+            let results = session.results(limit);
+            let answer = LiveResults {
+                query: query.to_string(),
+                matched: session.matched_count(),
+                results,
+            };
+            (session, answer)
+        })
+        .await?;
+        session = returned;
+
+        let json = serde_json::to_string(&answer)?;
+        socket.send(Message::Text(json.into())).await?;
+    }
+    Ok(())
+}
+
+/// Read a query and search options from the parameters of a search request
+fn search_params(params: Vec<(String, String)>) -> ApiResult<(String, SearchOptions)> {
+    let mut query = String::new();
+    let mut options = SearchOptions::default();
+    for (key, value) in params {
+        match key.as_str() {
+            "q" => query = value,
+            "doc" => options.docs.push(value),
+            "type" => options.types.push(value),
+            "limit" | "offset" => {
+                let number = value.parse().map_err(|_| {
+                    ApiError(
+                        StatusCode::BAD_REQUEST,
+                        format!("Invalid {} '{}'", key, value),
+                    )
+                })?;
+                if key == "limit" {
+                    options.limit = number;
+                } else {
+                    options.offset = number;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok((query, options))
+}
+
+async fn page(
+    State(manager): State<Arc<DevDocsManager>>,
+    axum::extract::Path((slug, path)): axum::extract::Path<(String, String)>,
+    headers: HeaderMap,
+) -> ApiResult<Response> {
+    // Pages are addressed without extension, but links copied from files keep theirs
+    let path = path
+        .strip_suffix(".html")
+        .or_else(|| path.strip_suffix(".md"))
+        .unwrap_or(&path);
+    let escapes = Path::new(path)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_)));
+    if path.is_empty() || escapes {
+        return Err(ApiError(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid page path '{}'", path),
+        ));
+    }
+    if !manager.is_doc_installed(&slug).await? {
+        return Err(anyhow::Error::from(DevDocsError::DocNotFound(slug)).into());
+    }
+
+    let markdown = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/markdown"));
+    let (format, content_type) = if markdown {
+        (Formats::MARKDOWN, "text/markdown; charset=utf-8")
+    } else {
+        (Formats::HTML, "text/html; charset=utf-8")
+    };
+
+    let file = manager.page_file(&slug, path, format).await.map_err(|_| {
+        ApiError(
+            StatusCode::NOT_FOUND,
+            format!("No page '{}/{}'", slug, path),
+        )
+    })?;
+    let body = tokio::fs::read_to_string(file)
+        .await
+        .map_err(anyhow::Error::from)?;
+    Ok(([(header::CONTENT_TYPE, content_type)], body).into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{entry, insert_doc, sample_cached_doc, temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_serve_answers_api_requests() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let dir = temp_dir("serve");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("Vec::push", "method")];
+        insert_doc(&manager, doc).await;
+        std::fs::create_dir_all(dir.join("rust")).unwrap();
+        std::fs::write(dir.join("rust/vec.html"), "<h1>Vec</h1>").unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router(Arc::new(manager)))
+                .await
+                .unwrap()
+        });
+        let get = |path: &str| {
+            let request = format!(
+                "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+                path
+            );
+            async move {
+                let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+                response
+            }
+        };
+
+        let response = get("/api/search?q=vec&type=method").await;
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(response.contains(r#""name":"Vec::push""#));
+        assert!(!response.contains(r#""name":"Vec","#));
+        assert!(get("/api/docs").await.contains(r#""slug":"rust""#));
+        assert!(get("/api/page/rust/vec").await.contains("<h1>Vec</h1>"));
+        assert!(
+            get("/api/docs/python/index")
+                .await
+                .starts_with("HTTP/1.1 404")
+        );
+        assert!(get("/api/page/rust/../x").await.starts_with("HTTP/1.1 4"));
+        assert!(get("/").await.contains("api/search/live"));
+        assert!(
+            get("/theme.css")
+                .await
+                .contains("prefers-color-scheme: dark")
+        );
+        let metrics = get("/metrics").await;
+        assert!(metrics.contains("# TYPE devdocs_searches_total counter"));
+        assert!(metrics.contains("\ndevdocs_searches_total 1\n"));
+        assert!(metrics.contains("\ndevdocs_docs_installed 1\n"));
+        assert!(metrics.contains("# TYPE devdocs_search_duration_seconds histogram"));
+        assert!(metrics.contains("devdocs_search_duration_seconds_bucket{le=\"+Inf\"} 1\n"));
+        assert!(metrics.contains(
+            "devdocs_http_requests_total{path=\"/api/docs/{slug}/index\",status=\"404\"} 1\n"
+        ));
+
+        let health = get("/healthz").await;
+        assert!(health.starts_with("HTTP/1.1 200"));
+        assert!(health.contains(r#""docs":1"#));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(get("/healthz").await.starts_with("HTTP/1.1 503"));
+    }
+
+    #[tokio::test]
+    async fn test_live_search_answers_each_query() {
+        use futures::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let dir = temp_dir("live-search");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let mut doc = sample_cached_doc("rust");
+        doc.index.entries = vec![entry("Vec", "struct"), entry("HashMap", "struct")];
+        insert_doc(&manager, doc).await;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router(Arc::new(manager)))
+                .await
+                .unwrap()
+        });
+
+        let url = format!("ws://{}/api/search/live?doc=rust&limit=5", addr);
+        let (mut socket, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+        for (query, expected) in [("hash", "HashMap"), ("vec", "Vec")] {
+            socket.send(Message::text(query)).await.unwrap();
+            let answer = socket.next().await.unwrap().unwrap();
+            let answer: serde_json::Value =
+                serde_json::from_str(answer.to_text().unwrap()).unwrap();
+            assert_eq!(answer["query"], query);
+            assert_eq!(answer["matched"], 1);
+            assert_eq!(answer["results"][0]["entry"]["entry"]["name"], expected);
+        }
+    }
+}
@@ -0,0 +1,284 @@
+//! Running the docs server as a user service, so it starts with the session and is
+//! restarted when it dies.
+//!
+//! [`Service::definition`] is a systemd user unit on Linux and a launchd agent on
+//! macOS, running a given command line, usually the current `dev` binary with
+//! `serve`. Services of different profiles get different names, so they can run side
+//! by side.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+use crate::escape_html;
+
+/// Name of the service of the default profile
+pub const SERVICE_NAME: &str = "devdocs";
+
+/// Prefix of the launchd labels, which are reverse domain names
+const LAUNCHD_DOMAIN: &str = "io.devdocs";
+
+/// The init system the service is installed into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceManager {
+    /// A systemd user unit, in `~/.config/systemd/user`
+    Systemd,
+    /// A launchd agent, in `~/Library/LaunchAgents`
+    Launchd,
+}
+
+impl ServiceManager {
+    /// The service manager of this platform, if it has one that's supported
+    pub fn native() -> Option<Self> {
+        if cfg!(target_os = "macos") {
+            Some(ServiceManager::Launchd)
+        } else if cfg!(target_os = "linux") {
+            Some(ServiceManager::Systemd)
+        } else {
+            None
+        }
+    }
+
+    /// Directory the manager picks up the services of the current user from
+    pub fn user_dir(self) -> Result<PathBuf> {
+        match self {
+            ServiceManager::Systemd => dirs::config_dir()
+                .map(|dir| dir.join("systemd").join("user"))
+                .context("Couldn't find the config directory"),
+            ServiceManager::Launchd => dirs::home_dir()
+                .map(|dir| dir.join("Library").join("LaunchAgents"))
+                .context("Couldn't find the home directory"),
+        }
+    }
+}
+
+/// A command line run as a user service
+#[derive(Debug, Clone)]
+pub struct Service {
+    pub manager: ServiceManager,
+    /// Name of the service, [`SERVICE_NAME`] with the profile appended
+    pub name: String,
+    /// Program and arguments the service runs
+    pub command: Vec<String>,
+}
+
+impl Service {
+    /// The service running `command` for `profile`, or the default profile
+    pub fn new(manager: ServiceManager, profile: Option<&str>, command: Vec<String>) -> Self {
+        let name = match profile {
+            Some(profile) => format!("{}-{}", SERVICE_NAME, profile),
+            None => SERVICE_NAME.to_string(),
+        };
+        Self {
+            manager,
+            name,
+            command,
+        }
+    }
+
+    /// The launchd label, or the systemd unit name
+    pub fn id(&self) -> String {
+        match self.manager {
+            ServiceManager::Systemd => format!("{}.service", self.name),
+            ServiceManager::Launchd => format!("{}.{}", LAUNCHD_DOMAIN, self.name),
+        }
+    }
+
+    /// Name of the file defining the service
+    pub fn file_name(&self) -> String {
+        match self.manager {
+            ServiceManager::Systemd => self.id(),
+            ServiceManager::Launchd => format!("{}.plist", self.id()),
+        }
+    }
+
+    /// Contents of the file defining the service
+    pub fn definition(&self) -> String {
+        match self.manager {
+            ServiceManager::Systemd => self.systemd_unit(),
+            ServiceManager::Launchd => self.launchd_plist(),
+        }
+    }
+
+    fn systemd_unit(&self) -> String {
+        let exec = self
+            .command
+            .iter()
+            .map(|arg| systemd_quote(arg))
+            .collect::<Vec<_>>()
+            .join(" ");
+        format!(
+            "[Unit]\n\
+             Description=DevDocs server ({name})\n\
+             After=network-online.target\n\
+             \n\
+             [Service]\n\
+             ExecStart={exec}\n\
+             Restart=on-failure\n\
+             RestartSec=5\n\
+             \n\
+             [Install]\n\
+             WantedBy=default.target\n",
+            name = self.name,
+        )
+    }
+
+    fn launchd_plist(&self) -> String {
+        let args: String = self
+            .command
+            .iter()
+            .map(|arg| format!("        <string>{}</string>\n", escape_html(arg)))
+            .collect();
+        let log = dirs::home_dir()
+            .unwrap_or_default()
+            .join("Library")
+            .join("Logs")
+            .join(format!("{}.log", self.name));
+        format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+             <plist version=\"1.0\">\n\
+             <dict>\n\
+             \x20   <key>Label</key>\n\
+             \x20   <string>{label}</string>\n\
+             \x20   <key>ProgramArguments</key>\n\
+             \x20   <array>\n\
+             {args}\
+             \x20   </array>\n\
+             \x20   <key>RunAtLoad</key>\n\
+             \x20   <true/>\n\
+             \x20   <key>KeepAlive</key>\n\
+             \x20   <dict>\n\
+             \x20       <key>SuccessfulExit</key>\n\
+             \x20       <false/>\n\
+             \x20   </dict>\n\
+             \x20   <key>StandardErrorPath</key>\n\
+             \x20   <string>{log}</string>\n\
+             </dict>\n\
+             </plist>\n",
+            label = escape_html(&self.id()),
+            log = escape_html(&log.to_string_lossy()),
+        )
+    }
+
+    /// Write the definition of the service into `dir`, replacing an older one, and
+    /// return its path
+    pub fn install(&self, dir: &Path) -> Result<PathBuf> {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Couldn't create {}", dir.display()))?;
+        let path = dir.join(self.file_name());
+        std::fs::write(&path, self.definition())
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Remove the definition of the service from `dir`, returning whether there was one
+    pub fn uninstall(&self, dir: &Path) -> Result<bool> {
+        let path = dir.join(self.file_name());
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e).with_context(|| format!("Couldn't remove {}", path.display())),
+        }
+    }
+
+    /// Commands starting the service installed at `path`, now and on every login
+    pub fn start_commands(&self, path: &Path) -> Vec<Vec<String>> {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        match self.manager {
+            ServiceManager::Systemd => vec![
+                args(&["systemctl", "--user", "daemon-reload"]),
+                args(&["systemctl", "--user", "enable", "--now", &self.id()]),
+            ],
+            ServiceManager::Launchd => {
+                vec![args(&["launchctl", "load", "-w", &path.to_string_lossy()])]
+            }
+        }
+    }
+
+    /// Commands stopping the service installed at `path` and keeping it from starting
+    pub fn stop_commands(&self, path: &Path) -> Vec<Vec<String>> {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect();
+        match self.manager {
+            ServiceManager::Systemd => {
+                vec![args(&[
+                    "systemctl",
+                    "--user",
+                    "disable",
+                    "--now",
+                    &self.id(),
+                ])]
+            }
+            ServiceManager::Launchd => {
+                vec![args(&[
+                    "launchctl",
+                    "unload",
+                    "-w",
+                    &path.to_string_lossy(),
+                ])]
+            }
+        }
+    }
+}
+
+/// Quote an argument of `ExecStart`, where `%` starts a specifier and `$` a variable
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg.replace('%', "%%").replace('$', "$$");
+    if !escaped.is_empty()
+        && !escaped
+            .chars()
+            .any(|c| c.is_whitespace() || matches!(c, '"' | '\'' | '\\' | ';'))
+    {
+        return escaped;
+    }
+    let mut quoted = String::from("\"");
+    for c in escaped.chars() {
+        if matches!(c, '"' | '\\') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::temp_dir;
+
+    #[test]
+    fn test_services_run_the_server_and_can_be_removed() {
+        let command = vec![
+            "/opt/dev tools/dev".to_string(),
+            "--profile=work".to_string(),
+            "serve".to_string(),
+            "--addr=127.0.0.1:8080".to_string(),
+        ];
+        let systemd = Service::new(ServiceManager::Systemd, Some("work"), command.clone());
+        assert_eq!(systemd.file_name(), "devdocs-work.service");
+        let unit = systemd.definition();
+        assert!(unit.contains(
+            "ExecStart=\"/opt/dev tools/dev\" --profile=work serve --addr=127.0.0.1:8080\n"
+        ));
+        assert!(unit.contains("Restart=on-failure"));
+        assert!(unit.contains("WantedBy=default.target"));
+
+        let launchd = Service::new(ServiceManager::Launchd, None, command);
+        assert_eq!(launchd.file_name(), "io.devdocs.devdocs.plist");
+        let plist = launchd.definition();
+        assert!(plist.contains("<string>io.devdocs.devdocs</string>"));
+        assert!(plist.contains(
+            "<string>/opt/dev tools/dev</string>\n        <string>--profile=work</string>"
+        ));
+        assert!(plist.contains("<key>KeepAlive</key>"));
+
+        let dir = temp_dir("service");
+        let path = systemd.install(&dir).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), unit);
+        assert!(systemd.start_commands(&path)[1].contains(&"devdocs-work.service".to_string()));
+        assert!(systemd.uninstall(&dir).unwrap());
+        assert!(!path.exists());
+        assert!(!systemd.uninstall(&dir).unwrap());
+    }
+}
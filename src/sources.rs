@@ -0,0 +1,91 @@
+//! Doc sources besides DevDocs, to register with
+//! [`DevDocsManagerBuilder::source`](crate::DevDocsManagerBuilder::source) or to
+//! [`import`](crate::DevDocsManager::import) from
+
+#[cfg(feature = "dash")]
+mod dash;
+mod html_dir;
+mod man;
+mod rustdoc;
+mod tldr;
+
+#[cfg(feature = "dash")]
+pub use dash::{DASH_FEEDS_URL, DashSource};
+pub use html_dir::HtmlDirSource;
+pub use man::ManPageSource;
+pub use rustdoc::RustdocSource;
+pub use tldr::{TLDR_ARCHIVE_URL, TldrSource};
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::MockTransport;
+    use crate::tests::{entry, sample_cached_doc, temp_dir, test_builder};
+    use crate::{Doc, DocIndex, DocSource, Formats};
+    use anyhow::Result;
+    use futures::future::BoxFuture;
+    use std::collections::HashMap;
+
+    struct WikiSource;
+
+    impl DocSource for WikiSource {
+        fn name(&self) -> &str {
+            "wiki"
+        }
+
+        fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+            let mut doc = sample_cached_doc("wiki").doc;
+            doc.name = "Team Wiki".to_string();
+            // Clashes with DevDocs, which wins
+            let rust = sample_cached_doc("rust").doc;
+            Box::pin(async move { Ok(vec![doc, rust]) })
+        }
+
+        fn fetch_index<'a>(&'a self, _slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+            Box::pin(async move {
+                Ok(DocIndex {
+                    entries: vec![entry("Deploying", "guide")],
+                    types: vec![],
+                })
+            })
+        }
+
+        fn fetch_content<'a>(
+            &'a self,
+            _slug: &'a str,
+        ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+            let page = ("deploying".to_string(), "<h1>Deploying</h1>".to_string());
+            Box::pin(async move { Ok(HashMap::from([page])) })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_additional_sources_are_listed_and_installed() {
+        let transport = MockTransport::new().respond(
+            "/docs.json",
+            r#"[{"name":"Rust","slug":"rust","type":"rustdoc","mtime":1,"db_size":10}]"#,
+        );
+        let dir = temp_dir("sources");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .transport(transport)
+            .source(WikiSource)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let docs = manager.refresh_available_docs().await.unwrap();
+        let names: Vec<&str> = docs.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, ["Rust", "Team Wiki"]);
+
+        manager.add_doc("wiki", Some(Formats::HTML)).await.unwrap();
+        let results = manager.search("deploy", None).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, "wiki");
+        assert!(dir.join("wiki/deploying.html").exists());
+
+        let duplicate = test_builder(&dir)
+            .source(WikiSource)
+            .source(WikiSource)
+            .build();
+        assert!(duplicate.is_err());
+    }
+}
@@ -0,0 +1,447 @@
+//! Docsets from Dash's public feeds
+//!
+//! Each docset has a feed at `<feeds>/<Name>.xml` naming its version and mirrors of a
+//! `.tgz` holding `<Name>.docset`, whose SQLite index (`docSet.dsidx`) lists the entries
+//! of the HTML pages in `Contents/Resources/Documents`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use regex::Regex;
+
+use crate::{Doc, DocIndex, DocSource, Entry, EntryType, HttpTransport, Links, page_hash};
+
+/// Where Kapeli publishes the feeds of its docsets
+pub const DASH_FEEDS_URL: &str = "https://kapeli.com/feeds";
+
+/// Prefix of the slugs of docsets, keeping them apart from DevDocs slugs
+const SLUG_PREFIX: &str = "dash~";
+
+/// Docsets named after their feed (`Python_3`, `Bash`, ...), installed as `dash~<name>`.
+///
+/// The feeds can't be listed, so docsets are only found by name through
+/// [`DocSource::find`], e.g. with `dev add --source dash <name>`.
+#[derive(Debug)]
+pub struct DashSource {
+    feeds_url: String,
+    transport: Option<Arc<dyn HttpTransport>>,
+    /// The docset unpacked last, whose index and pages are each handed out once
+    unpacked: tokio::sync::Mutex<Option<Unpacked>>,
+}
+
+#[derive(Debug)]
+struct Unpacked {
+    slug: String,
+    index: Option<DocIndex>,
+    pages: Option<HashMap<String, String>>,
+}
+
+impl DashSource {
+    pub fn new() -> Self {
+        Self {
+            feeds_url: DASH_FEEDS_URL.to_string(),
+            transport: None,
+            unpacked: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Read feeds from `url` instead of Kapeli's
+    pub fn feeds_url(mut self, url: impl Into<String>) -> Self {
+        self.feeds_url = url.into().trim_end_matches('/').to_string();
+        self
+    }
+
+    fn transport(&self) -> Result<&Arc<dyn HttpTransport>> {
+        self.transport
+            .as_ref()
+            .context("The dash source was used without a transport")
+    }
+
+    /// The version and download mirrors of a docset
+    async fn feed(&self, name: &str) -> Result<(String, Vec<String>)> {
+        let url = format!("{}/{}.xml", self.feeds_url, name);
+        let xml = self.transport()?.get(&url).await?;
+        let xml = String::from_utf8_lossy(&xml);
+
+        let tag = |name: &str| Regex::new(&format!(r"<{0}>\s*(.*?)\s*</{0}>", name)).unwrap();
+        let version = tag("version")
+            .captures(&xml)
+            .map(|caps| caps[1].to_string())
+            .with_context(|| format!("{} is not a docset feed", url))?;
+        let urls: Vec<String> = tag("url")
+            .captures_iter(&xml)
+            .map(|caps| caps[1].to_string())
+            .collect();
+        if urls.is_empty() {
+            anyhow::bail!("{} lists no download", url);
+        }
+        Ok((version, urls))
+    }
+
+    /// Download a docset from the first mirror that answers, and unpack it
+    async fn unpack(&self, slug: &str) -> Result<Unpacked> {
+        let name = docset_name(slug)?;
+        let (_, urls) = self.feed(name).await?;
+        let mut failure = None;
+        for url in &urls {
+            match self.transport()?.get(url).await {
+                Ok(archive) => {
+                    let (index, pages) =
+                        tokio::task::spawn_blocking(move || read_docset(&archive)).await??;
+                    return Ok(Unpacked {
+                        slug: slug.to_string(),
+                        index: Some(index),
+                        pages: Some(pages),
+                    });
+                }
+                Err(e) => failure = Some(e),
+            }
+        }
+        Err(failure.unwrap_or_else(|| anyhow::anyhow!("No mirror of {} answered", name)))
+    }
+
+    /// Take one part of an unpacked docset, unpacking it (again) when that part is gone
+    async fn take<T>(&self, slug: &str, part: impl Fn(&mut Unpacked) -> Option<T>) -> Result<T> {
+        let mut unpacked = self.unpacked.lock().await;
+        if let Some(cached) = unpacked.as_mut().filter(|u| u.slug == slug)
+            && let Some(taken) = part(cached)
+        {
+            return Ok(taken);
+        }
+        let cached = unpacked.insert(self.unpack(slug).await?);
+        Ok(part(cached).expect("freshly unpacked docsets have every part"))
+    }
+}
+
+impl Default for DashSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocSource for DashSource {
+    fn name(&self) -> &str {
+        "dash"
+    }
+
+    fn set_transport(&mut self, transport: Arc<dyn HttpTransport>) {
+        self.transport = Some(transport);
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        Box::pin(async { Ok(Vec::new()) })
+    }
+
+    fn find<'a>(&'a self, name: &'a str) -> BoxFuture<'a, Result<Option<Doc>>> {
+        Box::pin(async move {
+            let name = docset_name(name)?;
+            let (version, _) = self.feed(name).await?;
+            Ok(Some(Doc {
+                name: name.replace('_', " "),
+                slug: format!("{}{}", SLUG_PREFIX, name),
+                doc_type: "dash".to_string(),
+                links: Some(Links {
+                    home: Some("https://kapeli.com/dash".to_string()),
+                    code: None,
+                }),
+                mtime: version_mtime(&version),
+                db_size: 0,
+                attribution: None,
+                alias: None,
+            }))
+        })
+    }
+
+    fn fetch_index<'a>(&'a self, slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        Box::pin(self.take(slug, |unpacked| unpacked.index.take()))
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        Box::pin(self.take(slug, |unpacked| unpacked.pages.take()))
+    }
+}
+
+/// Stand-in modification time of a docset version, which is all feeds tell. Updates
+/// only compare it for equality; it's kept within 32 bits to stay a plausible date.
+fn version_mtime(version: &str) -> u64 {
+    page_hash(version) & u64::from(u32::MAX)
+}
+
+/// Feed name of a docset given by slug or name
+fn docset_name(slug: &str) -> Result<&str> {
+    let name = slug.strip_prefix(SLUG_PREFIX).unwrap_or(slug);
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "_-.".contains(c));
+    if !valid {
+        anyhow::bail!("'{}' is not a docset name", name);
+    }
+    Ok(name)
+}
+
+/// The index and pages of a `.tgz` docset
+fn read_docset(archive: &[u8]) -> Result<(DocIndex, HashMap<String, String>)> {
+    let mut tar = tar::Archive::new(flate2::read::GzDecoder::new(archive));
+    let mut database = None;
+    let mut pages = HashMap::new();
+    for file in tar.entries()? {
+        let mut file = file?;
+        let path = file.path()?.to_string_lossy().into_owned();
+        let Some((_, inner)) = path.split_once(".docset/Contents/Resources/") else {
+            continue;
+        };
+        if inner == "docSet.dsidx" {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            database = Some(bytes);
+        } else if let Some(document) = inner.strip_prefix("Documents/")
+            && let Some(page) = document
+                .strip_suffix(".html")
+                .or_else(|| document.strip_suffix(".htm"))
+        {
+            let mut html = Vec::new();
+            file.read_to_end(&mut html)?;
+            pages.insert(
+                page.to_string(),
+                String::from_utf8_lossy(&html).into_owned(),
+            );
+        }
+    }
+
+    let database = database.context("The docset has no docSet.dsidx index")?;
+    // Docsets annotate some names and paths with `<dash_entry_...>` tags
+    let annotations = Regex::new(r"<dash_entry_[^>]*>").unwrap();
+    let entries = read_entries(&database)?
+        .into_iter()
+        .filter_map(|(name, entry_type, path)| {
+            let path = entry_path(&annotations.replace_all(&path, ""))?;
+            let page = path.split_once('#').map_or(path.as_str(), |(page, _)| page);
+            pages.contains_key(page).then(|| Entry {
+                name: annotations.replace_all(&name, "").into_owned(),
+                path: PathBuf::from(path),
+                entry_type,
+            })
+        })
+        .collect();
+    Ok((index_of(entries), pages))
+}
+
+/// `(name, type, path)` of every entry in a docset index, either of the plain
+/// `searchIndex` table or of the Core Data tables of docsets generated by Apple's tools
+fn read_entries(database: &[u8]) -> Result<Vec<(String, String, String)>> {
+    // SQLite wants a file to open
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let file = std::env::temp_dir().join(format!(
+        "devdocs-dsidx-{}-{}",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::write(&file, database)?;
+    let entries = query_entries(&file);
+    let _ = std::fs::remove_file(&file);
+    entries
+}
+
+fn query_entries(file: &Path) -> Result<Vec<(String, String, String)>> {
+    let connection =
+        rusqlite::Connection::open_with_flags(file, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let plain: i64 = connection.query_row(
+        "SELECT count(*) FROM sqlite_master WHERE type = 'table' AND name = 'searchIndex'",
+        [],
+        |row| row.get(0),
+    )?;
+    let query = if plain > 0 {
+        "SELECT name, type, path FROM searchIndex"
+    } else {
+        "SELECT ZTOKENNAME, ZTYPENAME,
+                ZPATH || CASE WHEN ZANCHOR IS NULL THEN '' ELSE '#' || ZANCHOR END
+         FROM ZTOKEN
+         JOIN ZTOKENMETAINFORMATION ON ZTOKEN.ZMETAINFORMATION = ZTOKENMETAINFORMATION.Z_PK
+         JOIN ZFILEPATH ON ZTOKENMETAINFORMATION.ZFILE = ZFILEPATH.Z_PK
+         JOIN ZTOKENTYPE ON ZTOKEN.ZTOKENTYPE = ZTOKENTYPE.Z_PK"
+    };
+    let mut statement = connection.prepare(query)?;
+    let rows = statement.query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?;
+    Ok(rows.collect::<rusqlite::Result<_>>()?)
+}
+
+/// Entry path of a docset path: without query and page extension. `None` for entries
+/// pointing outside the docset.
+fn entry_path(path: &str) -> Option<String> {
+    if path.contains("://") {
+        return None;
+    }
+    let (page, anchor) = match path.split_once('#') {
+        Some((page, anchor)) => (page, Some(anchor)),
+        None => (path, None),
+    };
+    let page = page.split_once('?').map_or(page, |(page, _)| page);
+    let page = page
+        .strip_suffix(".html")
+        .or_else(|| page.strip_suffix(".htm"))
+        .unwrap_or(page);
+    Some(match anchor {
+        Some(anchor) => format!("{}#{}", page, anchor),
+        None => page.to_string(),
+    })
+}
+
+fn index_of(mut entries: Vec<Entry>) -> DocIndex {
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &entries {
+        *counts.entry(&entry.entry_type).or_default() += 1;
+    }
+    let types = counts
+        .into_iter()
+        .map(|(name, count)| EntryType {
+            name: name.to_string(),
+            count,
+            slug: name.to_lowercase().replace(' ', "-"),
+        })
+        .collect();
+
+    DocIndex { entries, types }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{temp_dir, test_builder};
+    use crate::{Formats, SECTION_TYPE, UpdateStatus};
+    use std::sync::Mutex;
+    use tokio_util::sync::CancellationToken;
+
+    #[tokio::test]
+    async fn test_dash_docsets_are_added_by_name() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        struct FeedTransport {
+            docset: Vec<u8>,
+            version: Arc<Mutex<&'static str>>,
+            downloads: Arc<AtomicUsize>,
+        }
+
+        impl HttpTransport for FeedTransport {
+            fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+                let body = match url {
+                    "http://docs.test/docs.json" => Some(b"[]".to_vec()),
+                    "http://feeds.test/Bash.xml" => Some(
+                        format!(
+                            "<entry><version>{}</version>\
+                             <url>http://down.test/Bash.tgz</url>\
+                             <url>http://mirror.test/Bash.tgz</url></entry>",
+                            self.version.lock().unwrap()
+                        )
+                        .into_bytes(),
+                    ),
+                    "http://mirror.test/Bash.tgz" => {
+                        self.downloads.fetch_add(1, Ordering::Relaxed);
+                        Some(self.docset.clone())
+                    }
+                    _ => None,
+                };
+                Box::pin(async move { body.with_context(|| format!("404 for {}", url)) })
+            }
+        }
+
+        let root = temp_dir("dash");
+        std::fs::create_dir_all(&root).unwrap();
+        let database = root.join("docSet.dsidx");
+        let connection = rusqlite::Connection::open(&database).unwrap();
+        connection
+            .execute_batch(
+                "CREATE TABLE searchIndex(id INTEGER PRIMARY KEY, name TEXT, type TEXT, path TEXT);
+                 INSERT INTO searchIndex(name, type, path) VALUES
+                   ('read', 'Builtin', 'builtins.html#read'),
+                   ('<dash_entry_name=cd>cd', 'Builtin', '<dash_entry_name=cd>builtins.html#cd'),
+                   ('Manual', 'Guide', 'https://www.gnu.org/software/bash/manual/');",
+            )
+            .unwrap();
+        drop(connection);
+
+        let mut tar = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        let resources = "Bash.docset/Contents/Resources";
+        let builtins = br#"<h2 id="cd">cd</h2><p>Change directory.</p><h2 id="read">read</h2>"#;
+        let mut header = tar::Header::new_gnu();
+        header.set_size(builtins.len() as u64);
+        header.set_mode(0o644);
+        tar.append_data(
+            &mut header,
+            format!("{}/Documents/builtins.html", resources),
+            &builtins[..],
+        )
+        .unwrap();
+        tar.append_path_with_name(&database, format!("{}/docSet.dsidx", resources))
+            .unwrap();
+        let docset = tar.into_inner().unwrap().finish().unwrap();
+
+        let version = Arc::new(Mutex::new("5.2"));
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let transport = FeedTransport {
+            docset,
+            version: version.clone(),
+            downloads: downloads.clone(),
+        };
+        let manager = test_builder(root.join("data"))
+            .base_url("http://docs.test")
+            .transport(transport)
+            .source(crate::sources::DashSource::new().feeds_url("http://feeds.test"))
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let cancel = CancellationToken::new();
+        let slug = manager
+            .add_doc_from("dash", "Bash", Some(Formats::HTML), &cancel)
+            .await
+            .unwrap();
+        assert_eq!(slug, "dash~Bash");
+        // The index and the pages come from a single download
+        assert_eq!(downloads.load(Ordering::Relaxed), 1);
+
+        let names: Vec<String> = manager
+            .iter_entries(&slug)
+            .await
+            .unwrap()
+            .filter(|e| e.entry_type != SECTION_TYPE)
+            .map(|e| format!("{} {}", e.name, e.path.display()))
+            .collect();
+        assert_eq!(names, ["cd builtins#cd", "read builtins#read"]);
+        let results = manager.search("cd", None).await.unwrap();
+        assert_eq!(results[0].entry.doc_slug, slug);
+
+        // Refreshes look the docset up again, so new versions are noticed
+        manager.refresh_available_docs().await.unwrap();
+        assert_eq!(
+            manager.update_doc(&slug).await.unwrap(),
+            UpdateStatus::Current
+        );
+        *version.lock().unwrap() = "5.3";
+        manager.refresh_available_docs().await.unwrap();
+        assert!(matches!(
+            manager.update_doc(&slug).await.unwrap(),
+            UpdateStatus::Updated(_)
+        ));
+
+        assert!(
+            manager
+                .add_doc_from("dash", "../etc", None, &cancel)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,342 @@
+//! A local directory of HTML pages, e.g. an exported wiki or vendored documentation
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+
+use crate::{DevDocsError, Doc, DocIndex, DocSource, Entry, EntryType, decode_entities};
+
+/// Pages indexed when no globs are given
+const DEFAULT_GLOBS: &[&str] = &["**/*.html", "**/*.htm"];
+
+/// Entry type of the pages at the top of the directory
+const ROOT_TYPE: &str = "page";
+
+/// The HTML pages below a directory as a single doc, with an entry per page titled
+/// after its `<title>` (or first `<h1>`, or file name)
+#[derive(Debug, Clone)]
+pub struct HtmlDirSource {
+    /// Slug and name of the doc
+    slug: String,
+    /// Name of the source, derived from the slug so several directories can be registered
+    name: String,
+    dir: PathBuf,
+    globs: Vec<String>,
+}
+
+impl HtmlDirSource {
+    pub fn new(slug: impl Into<String>, dir: impl Into<PathBuf>) -> Self {
+        let slug = slug.into();
+        Self {
+            name: format!("html:{}", slug),
+            slug,
+            dir: dir.into(),
+            globs: Vec::new(),
+        }
+    }
+
+    /// Only index the pages whose path relative to the directory matches one of these
+    /// globs (`**/*.html` and `**/*.htm` by default)
+    pub fn entries(mut self, globs: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.globs = globs.into_iter().map(Into::into).collect();
+        self
+    }
+
+    fn glob_set(&self) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        if self.globs.is_empty() {
+            for glob in DEFAULT_GLOBS {
+                builder.add(Glob::new(glob)?);
+            }
+        }
+        for glob in &self.globs {
+            builder.add(Glob::new(glob).with_context(|| format!("Invalid glob '{}'", glob))?);
+        }
+        Ok(builder.build()?)
+    }
+
+    fn check_slug(&self, slug: &str) -> Result<()> {
+        if slug != self.slug {
+            anyhow::bail!(
+                "{} only provides '{}', not '{}'",
+                self.name,
+                self.slug,
+                slug
+            );
+        }
+        Ok(())
+    }
+
+    /// Every matching page as `(page path without extension, HTML)`, sorted by path
+    fn read_pages(&self) -> Result<Vec<(String, String)>> {
+        let globs = self.glob_set()?;
+        let mut pages = Vec::new();
+        for file in walk(&self.dir)? {
+            let relative = file.strip_prefix(&self.dir)?;
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            if !globs.is_match(&relative) {
+                continue;
+            }
+            let html = std::fs::read(&file)?;
+            let page = Path::new(&relative)
+                .with_extension("")
+                .to_string_lossy()
+                .into_owned();
+            pages.push((page, String::from_utf8_lossy(&html).into_owned()));
+        }
+        pages.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(pages)
+    }
+
+    fn describe(&self) -> Result<Doc> {
+        if self.slug.is_empty() || self.slug.contains(['/', '\\']) {
+            return Err(DevDocsError::InvalidSlug(self.slug.clone()).into());
+        }
+        if !self.dir.is_dir() {
+            anyhow::bail!("{} is not a directory", self.dir.display());
+        }
+        let (mut mtime, mut size) = (0, 0);
+        for file in walk(&self.dir)? {
+            let metadata = std::fs::metadata(&file)?;
+            let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+            mtime = mtime.max(modified);
+            size += metadata.len();
+        }
+        Ok(Doc {
+            name: self.slug.clone(),
+            slug: self.slug.clone(),
+            doc_type: "html".to_string(),
+            links: None,
+            mtime,
+            db_size: size as usize,
+            attribution: None,
+            alias: None,
+        })
+    }
+}
+
+impl DocSource for HtmlDirSource {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        let source = self.clone();
+        Box::pin(
+            async move { tokio::task::spawn_blocking(move || Ok(vec![source.describe()?])).await? },
+        )
+    }
+
+    fn fetch_index<'a>(&'a self, slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        let source = self.clone();
+        Box::pin(async move {
+            self.check_slug(slug)?;
+            tokio::task::spawn_blocking(move || {
+                let pages = source.read_pages()?;
+                if pages.is_empty() {
+                    anyhow::bail!("No pages in {} match the globs", source.dir.display());
+                }
+                Ok(pages_index(&pages))
+            })
+            .await?
+        })
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        let source = self.clone();
+        Box::pin(async move {
+            self.check_slug(slug)?;
+            tokio::task::spawn_blocking(move || {
+                Ok(source
+                    .read_pages()?
+                    .into_iter()
+                    .map(|(page, html)| (page, body(&html).to_string()))
+                    .collect())
+            })
+            .await?
+        })
+    }
+}
+
+/// Files below `dir`, skipping hidden files and directories
+fn walk(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            files.extend(walk(&path)?);
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Index a page per entry, typed by the directory holding it
+fn pages_index(pages: &[(String, String)]) -> DocIndex {
+    let entries: Vec<Entry> = pages
+        .iter()
+        .map(|(page, html)| {
+            let (dir, file) = page.rsplit_once('/').unwrap_or((ROOT_TYPE, page));
+            Entry {
+                name: title(html).unwrap_or_else(|| file.to_string()),
+                path: PathBuf::from(page),
+                entry_type: dir.to_string(),
+            }
+        })
+        .collect();
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &entries {
+        *counts.entry(&entry.entry_type).or_default() += 1;
+    }
+    let types = counts
+        .into_iter()
+        .map(|(name, count)| EntryType {
+            name: name.to_string(),
+            count,
+            slug: name.replace(['/', ' '], "-"),
+        })
+        .collect();
+
+    DocIndex { entries, types }
+}
+
+/// The text of a page's `<title>`, else of its first `<h1>`
+fn title(html: &str) -> Option<String> {
+    use lol_html::{RewriteStrSettings, element, rewrite_str, text};
+    use std::cell::{Cell, RefCell};
+
+    let found: RefCell<[String; 2]> = RefCell::default();
+    let headings = Cell::new(0);
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            text!("title", |chunk| {
+                found.borrow_mut()[0].push_str(chunk.as_str());
+                Ok(())
+            }),
+            element!("h1", |_| {
+                headings.set(headings.get() + 1);
+                Ok(())
+            }),
+            text!("h1", |chunk| {
+                if headings.get() == 1 {
+                    found.borrow_mut()[1].push_str(chunk.as_str());
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings).ok()?;
+
+    found
+        .into_inner()
+        .into_iter()
+        .map(|text| decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" ")))
+        .find(|text| !text.is_empty())
+}
+
+/// The inside of a page's `<body>`, or the whole page when it has none
+fn body(html: &str) -> &str {
+    let lower = html.to_ascii_lowercase();
+    let Some(open) = lower.find("<body") else {
+        return html;
+    };
+    let Some(start) = lower[open..].find('>').map(|end| open + end + 1) else {
+        return html;
+    };
+    let end = lower.rfind("</body>").filter(|&end| end >= start);
+    &html[start..end.unwrap_or(html.len())]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+    use crate::{CONFIG_FILE, Formats};
+
+    #[tokio::test]
+    async fn test_html_dirs_are_imported_and_configured() {
+        let root = temp_dir("html-dir");
+        let wiki = root.join("wiki");
+        std::fs::create_dir_all(wiki.join("guides")).unwrap();
+        std::fs::create_dir_all(wiki.join(".git")).unwrap();
+        std::fs::write(
+            wiki.join("index.html"),
+            "<html><head><title>Team &amp; Wiki</title></head><body><p>Welcome</p></body></html>",
+        )
+        .unwrap();
+        std::fs::write(
+            wiki.join("guides/setup.html"),
+            "<h1>Setting <code>up</code></h1><p>Run the installer.</p><h1>Later</h1>",
+        )
+        .unwrap();
+        std::fs::write(wiki.join("guides/draft.html"), "<p>No title</p>").unwrap();
+        std::fs::write(wiki.join("guides/notes.txt"), "not a page").unwrap();
+        std::fs::write(wiki.join(".git/hidden.html"), "<title>Hidden</title>").unwrap();
+
+        let transport = MockTransport::new().respond("/docs.json", "[]");
+        let config = root.join(CONFIG_FILE);
+        std::fs::write(
+            &config,
+            "[[html-dirs]]\nname = \"guides\"\npath = \"wiki\"\nentries = [\"guides/*.html\"]\n",
+        )
+        .unwrap();
+        let manager = test_builder(root.join("data"))
+            .base_url("http://docs.test")
+            .transport(transport)
+            .config_file(&config)
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+
+        let slug = manager.import_html_dir("wiki", &wiki, &[]).await.unwrap();
+        assert_eq!(slug, "wiki");
+        let names: Vec<String> = manager
+            .iter_entries("wiki")
+            .await
+            .unwrap()
+            .map(|e| format!("{} ({})", e.name, e.entry_type))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "draft (guides)",
+                "Setting up (guides)",
+                "Team & Wiki (page)"
+            ]
+        );
+        let file = manager
+            .page_file("wiki", "index", Formats::HTML)
+            .await
+            .unwrap();
+        let page = std::fs::read_to_string(file).unwrap();
+        assert_eq!(page, "<p>Welcome</p>");
+        assert!(manager.is_imported("wiki").await);
+
+        // Directories listed in the config file are offered like any other doc
+        let available = manager.get_available_docs().await.unwrap();
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].slug, "guides");
+        manager.add_doc("guides", None).await.unwrap();
+        let results = manager.search("setting", None).await.unwrap();
+        assert!(results.iter().any(|r| r.entry.doc_slug == "guides"));
+        assert!(!manager.is_imported("guides").await);
+
+        assert!(manager.import_html_dir("a/b", &wiki, &[]).await.is_err());
+    }
+}
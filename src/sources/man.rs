@@ -0,0 +1,629 @@
+//! The system's man pages
+//!
+//! Installing only indexes the manpath directories; each page is converted from roff
+//! to HTML the first time it's opened.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use crate::{Doc, DocIndex, DocSource, Entry, EntryType, escape_html};
+
+/// Slug of the man pages doc
+const SLUG: &str = "man";
+
+/// Where man pages are looked for when neither `MANPATH` nor `manpath` say otherwise
+const DEFAULT_DIRS: &[&str] = &["/usr/local/share/man", "/usr/share/man"];
+
+/// The man pages of the system, as a single doc with one entry type per section
+#[derive(Debug, Clone)]
+pub struct ManPageSource {
+    dirs: Vec<PathBuf>,
+}
+
+impl ManPageSource {
+    /// Man pages from `MANPATH`, else from what `manpath` reports, else from the usual
+    /// directories
+    pub fn new() -> Self {
+        let from_env = std::env::var("MANPATH").ok();
+        let from_command = || {
+            let output = std::process::Command::new("manpath").output().ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        };
+        let dirs = match from_env.or_else(from_command) {
+            Some(path) => path
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .collect(),
+            None => DEFAULT_DIRS.iter().map(PathBuf::from).collect(),
+        };
+        Self { dirs }
+    }
+
+    /// Man pages from these directories only, earlier ones winning on duplicates
+    pub fn dirs(dirs: impl IntoIterator<Item = impl Into<PathBuf>>) -> Self {
+        Self {
+            dirs: dirs.into_iter().map(Into::into).collect(),
+        }
+    }
+
+    /// Every page as `(section, name, file)`, the first directory winning on duplicates
+    fn pages(&self) -> Vec<(String, String, PathBuf)> {
+        let mut seen = HashSet::new();
+        let mut pages = Vec::new();
+        for dir in &self.dirs {
+            let Ok(sections) = std::fs::read_dir(dir) else {
+                continue;
+            };
+            let mut sections: Vec<PathBuf> = sections
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("man"))
+                })
+                .collect();
+            sections.sort();
+
+            for section in sections {
+                let Ok(files) = std::fs::read_dir(&section) else {
+                    continue;
+                };
+                for file in files.filter_map(|entry| entry.ok().map(|e| e.path())) {
+                    let Some((name, section)) = page_name(&file) else {
+                        continue;
+                    };
+                    if seen.insert((section.clone(), name.clone())) {
+                        pages.push((section, name, file));
+                    }
+                }
+            }
+        }
+        pages.sort();
+        pages
+    }
+
+    /// The file of a page, looked up the way [`pages`](Self::pages) finds them
+    fn find_file(&self, section: &str, name: &str) -> Option<PathBuf> {
+        // `3p` pages live in either `man3p` or `man3`
+        let first = section.get(..1)?;
+        self.dirs.iter().find_map(|dir| {
+            [section, first].iter().find_map(|subdir| {
+                let file = dir
+                    .join(format!("man{}", subdir))
+                    .join(format!("{}.{}", name, section));
+                let gz = PathBuf::from(format!("{}.gz", file.display()));
+                [file, gz].into_iter().find(|file| file.is_file())
+            })
+        })
+    }
+
+    /// Read the roff source of a page, following `.so` redirects
+    fn read_page(&self, path: &str) -> Result<Option<String>> {
+        let Some((section, name)) = path.split_once('/') else {
+            return Ok(None);
+        };
+        let Some(file) = self.find_file(section, name) else {
+            return Ok(None);
+        };
+
+        let source = read_roff(&file)?;
+        // Pages like `man3/strncpy.3` are only `.so man3/strcpy.3`
+        if let Some(target) = source.trim().strip_prefix(".so ") {
+            let root = file
+                .parent()
+                .and_then(Path::parent)
+                .unwrap_or(Path::new("."));
+            let target = root.join(target.trim());
+            let target = if target.exists() {
+                target
+            } else {
+                PathBuf::from(format!("{}.gz", target.display()))
+            };
+            return Ok(Some(read_roff(&target)?));
+        }
+        Ok(Some(source))
+    }
+}
+
+impl Default for ManPageSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocSource for ManPageSource {
+    fn name(&self) -> &str {
+        "man"
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        let source = self.clone();
+        Box::pin(async move {
+            let pages = tokio::task::spawn_blocking(move || source.pages()).await?;
+            if pages.is_empty() {
+                return Ok(vec![]);
+            }
+            let mut mtime = 0;
+            let mut size = 0;
+            for (_, _, file) in &pages {
+                if let Ok(metadata) = std::fs::metadata(file) {
+                    size += metadata.len() as usize;
+                    if let Ok(modified) = metadata.modified() {
+                        let secs = modified
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map_or(0, |d| d.as_secs());
+                        mtime = mtime.max(secs);
+                    }
+                }
+            }
+            Ok(vec![Doc {
+                name: "Man Pages".to_string(),
+                slug: SLUG.to_string(),
+                doc_type: "man".to_string(),
+                links: None,
+                mtime,
+                db_size: size,
+                attribution: None,
+                alias: None,
+            }])
+        })
+    }
+
+    fn fetch_index<'a>(&'a self, _slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        let source = self.clone();
+        Box::pin(async move {
+            let pages = tokio::task::spawn_blocking(move || source.pages()).await?;
+            let entries: Vec<Entry> = pages
+                .into_iter()
+                .map(|(section, name, _)| Entry {
+                    name: format!("{}({})", name, section),
+                    path: PathBuf::from(format!("{}/{}", section, name)),
+                    entry_type: section_title(&section),
+                })
+                .collect();
+
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for entry in &entries {
+                *counts.entry(&entry.entry_type).or_default() += 1;
+            }
+            let types = counts
+                .into_iter()
+                .map(|(name, count)| EntryType {
+                    name: name.to_string(),
+                    count,
+                    slug: name.to_lowercase().replace([' ', ':'], "-"),
+                })
+                .collect();
+            Ok(DocIndex { entries, types })
+        })
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        _slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        // Converting thousands of pages up front is wasted work, see `fetch_page`
+        Box::pin(async { Ok(HashMap::new()) })
+    }
+
+    fn fetch_page<'a>(
+        &'a self,
+        _slug: &'a str,
+        path: &'a str,
+    ) -> BoxFuture<'a, Result<Option<String>>> {
+        let source = self.clone();
+        let path = path.to_string();
+        Box::pin(async move {
+            tokio::task::spawn_blocking(move || {
+                Ok(source.read_page(&path)?.map(|roff| roff_to_html(&roff)))
+            })
+            .await?
+        })
+    }
+}
+
+/// Name and section of a man page file, e.g. `("printf", "3p")` for `printf.3p.gz`
+fn page_name(file: &Path) -> Option<(String, String)> {
+    let name = file.file_name()?.to_str()?;
+    let name = name.strip_suffix(".gz").unwrap_or(name);
+    let (name, section) = name.rsplit_once('.')?;
+    let dir = file.parent()?.file_name()?.to_str()?.strip_prefix("man")?;
+    // Other compressions and stray files don't carry their directory's section
+    if name.is_empty() || dir.is_empty() || !section.starts_with(dir) {
+        return None;
+    }
+    Some((name.to_string(), section.to_string()))
+}
+
+fn section_title(section: &str) -> String {
+    let title = match section.chars().next() {
+        Some('1') => "User Commands",
+        Some('2') => "System Calls",
+        Some('3') => "Library Functions",
+        Some('4') => "Special Files",
+        Some('5') => "File Formats",
+        Some('6') => "Games",
+        Some('7') => "Miscellaneous",
+        Some('8') => "System Administration",
+        _ => return format!("Section {}", section),
+    };
+    format!("{}: {}", section, title)
+}
+
+fn read_roff(file: &Path) -> Result<String> {
+    let data = std::fs::read(file)?;
+    if file.extension().is_some_and(|ext| ext == "gz") {
+        let mut text = String::new();
+        flate2::read::GzDecoder::new(&data[..]).read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        Ok(String::from_utf8_lossy(&data).into_owned())
+    }
+}
+
+/// Convert the man(7) macros of a page to HTML.
+///
+/// Covers what pages use for structure and emphasis; other requests are dropped.
+pub(crate) fn roff_to_html(roff: &str) -> String {
+    let mut html = String::new();
+    // Whether a <p>, a <pre> or a list item is open
+    let mut paragraph = false;
+    let mut pre = false;
+    let mut indents = 0;
+    // Set by `.TP`: the next text line is a term
+    let mut term = false;
+
+    let close = |html: &mut String, paragraph: &mut bool| {
+        if *paragraph {
+            html.push_str("</p>\n");
+            *paragraph = false;
+        }
+    };
+
+    for line in roff.lines() {
+        let (request, args) = match line.strip_prefix(['.', '\'']) {
+            Some(rest) => {
+                let rest = rest.trim_start();
+                let (request, args) = rest.split_once([' ', '\t']).unwrap_or((rest, ""));
+                (Some(request), args.trim())
+            }
+            None => (None, line),
+        };
+
+        match request {
+            // Comments
+            Some(r) if r.starts_with("\\\"") || r.starts_with("\\#") => {}
+            Some("TH") => {
+                let args = macro_args(args);
+                if let Some(name) = args.first() {
+                    let section = args.get(1).map_or("", String::as_str);
+                    html.push_str(&format!("<h1>{}({})</h1>\n", inline(name), inline(section)));
+                }
+            }
+            Some(heading @ ("SH" | "SS")) => {
+                close(&mut html, &mut paragraph);
+                let text = inline(&macro_args(args).join(" "));
+                let tag = if heading == "SH" { "h2" } else { "h3" };
+                let id = text.to_lowercase().replace(' ', "-");
+                html.push_str(&format!("<{tag} id=\"{id}\">{text}</{tag}>\n"));
+            }
+            Some("PP" | "LP" | "P" | "sp") => close(&mut html, &mut paragraph),
+            Some("TP") => {
+                close(&mut html, &mut paragraph);
+                term = true;
+            }
+            Some("IP") => {
+                close(&mut html, &mut paragraph);
+                let tag = macro_args(args).into_iter().next().unwrap_or_default();
+                html.push_str("<p>");
+                if !tag.is_empty() {
+                    html.push_str(&format!("{} ", inline(&tag)));
+                }
+                paragraph = true;
+            }
+            Some("RS") => {
+                close(&mut html, &mut paragraph);
+                html.push_str("<blockquote>\n");
+                indents += 1;
+            }
+            Some("RE") if indents > 0 => {
+                close(&mut html, &mut paragraph);
+                html.push_str("</blockquote>\n");
+                indents -= 1;
+            }
+            Some("nf" | "EX") if !pre => {
+                close(&mut html, &mut paragraph);
+                html.push_str("<pre>");
+                pre = true;
+            }
+            Some("fi" | "EE") if pre => {
+                html.push_str("</pre>\n");
+                pre = false;
+            }
+            Some("br") => html.push_str(if pre { "\n" } else { "<br>\n" }),
+            Some(font @ ("B" | "I" | "SM" | "SB")) => {
+                let text = inline(&macro_args(args).join(" "));
+                let text = match font {
+                    "B" | "SB" => format!("<b>{}</b>", text),
+                    "I" => format!("<i>{}</i>", text),
+                    _ => text,
+                };
+                text_line(&mut html, &text, pre, &mut paragraph, &mut term);
+            }
+            Some(fonts @ ("BR" | "RB" | "BI" | "IB" | "IR" | "RI")) => {
+                // Alternate between two fonts, word by word
+                let text: String = macro_args(args)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, arg)| match fonts.as_bytes()[i % 2] {
+                        b'B' => format!("<b>{}</b>", inline(arg)),
+                        b'I' => format!("<i>{}</i>", inline(arg)),
+                        _ => inline(arg),
+                    })
+                    .collect();
+                text_line(&mut html, &text, pre, &mut paragraph, &mut term);
+            }
+            Some(_) => {}
+            None => text_line(&mut html, &inline(line), pre, &mut paragraph, &mut term),
+        }
+    }
+
+    close(&mut html, &mut paragraph);
+    if pre {
+        html.push_str("</pre>\n");
+    }
+    html.push_str(&"</blockquote>\n".repeat(indents));
+    html
+}
+
+/// Append a line of text, as the term of a `.TP` item when one is expected
+fn text_line(html: &mut String, text: &str, pre: bool, paragraph: &mut bool, term: &mut bool) {
+    if pre {
+        html.push_str(text);
+        html.push('\n');
+        return;
+    }
+    if *term {
+        html.push_str(&format!("<dl><dt>{}</dt></dl>\n", text));
+        *term = false;
+        return;
+    }
+    if text.trim().is_empty() {
+        if *paragraph {
+            html.push_str("</p>\n");
+            *paragraph = false;
+        }
+        return;
+    }
+    if !*paragraph {
+        html.push_str("<p>");
+        *paragraph = true;
+    } else {
+        html.push(' ');
+    }
+    html.push_str(text);
+}
+
+/// Split macro arguments on spaces, keeping double-quoted ones together
+fn macro_args(args: &str) -> Vec<String> {
+    let mut parsed = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut chars = args.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ' ' | '\t' if !quoted => {
+                if !current.is_empty() {
+                    parsed.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        parsed.push(current);
+    }
+    parsed
+}
+
+/// Convert the escapes and font changes of a line of text, escaping it for HTML
+fn inline(text: &str) -> String {
+    let mut html = String::new();
+    // Closing tag of the font in effect
+    let mut font: Option<&str> = None;
+    let mut chars = text.chars().peekable();
+
+    let escape = |c: char| escape_html(c.encode_utf8(&mut [0; 4]));
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            html.push_str(&escape(c));
+            continue;
+        }
+        let Some(escape_char) = chars.next() else {
+            break;
+        };
+        match escape_char {
+            'f' => {
+                let mut name = String::new();
+                match chars.next() {
+                    Some('(') => name.extend(chars.by_ref().take(2)),
+                    Some('[') => name.extend(chars.by_ref().take_while(|c| *c != ']')),
+                    Some(c) => name.push(c),
+                    None => {}
+                }
+                if let Some(end) = font.take() {
+                    html.push_str(end);
+                }
+                match name.as_str() {
+                    "B" | "CB" => {
+                        html.push_str("<b>");
+                        font = Some("</b>");
+                    }
+                    "I" | "CI" => {
+                        html.push_str("<i>");
+                        font = Some("</i>");
+                    }
+                    "CW" | "CR" | "C" => {
+                        html.push_str("<code>");
+                        font = Some("</code>");
+                    }
+                    _ => {}
+                }
+            }
+            '(' => {
+                let name: String = chars.by_ref().take(2).collect();
+                html.push_str(special_char(&name));
+            }
+            '[' => {
+                let name: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                html.push_str(special_char(&name));
+            }
+            // Interpolated strings and registers have nothing to show without a roff
+            '*' | 'n' => match chars.next() {
+                Some('(') => {
+                    chars.by_ref().take(2).for_each(drop);
+                }
+                Some('[') => {
+                    chars.by_ref().take_while(|c| *c != ']').for_each(drop);
+                }
+                _ => {}
+            },
+            '-' => html.push('-'),
+            'e' | '\\' => html.push('\\'),
+            ' ' | '~' => html.push(' '),
+            '&' | '|' | '^' | 'c' | ':' | '%' => {}
+            '"' => break,
+            c => html.push_str(&escape(c)),
+        }
+    }
+    if let Some(end) = font {
+        html.push_str(end);
+    }
+    html
+}
+
+fn special_char(name: &str) -> &'static str {
+    match name {
+        "em" => "—",
+        "en" => "–",
+        "hy" | "mi" => "-",
+        "bu" => "•",
+        "co" => "©",
+        "rg" => "®",
+        "lq" | "rq" | "dq" => "\"",
+        "oq" | "cq" | "aq" => "'",
+        "ga" => "`",
+        "ti" => "~",
+        "ha" => "^",
+        "rs" => "\\",
+        "<=" => "≤",
+        ">=" => "≥",
+        "->" => "→",
+        "<-" => "←",
+        "mu" => "×",
+        _ => "",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Formats;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_man_pages_are_converted_when_opened() {
+        use std::io::Write;
+
+        let man = temp_dir("manpath");
+        std::fs::create_dir_all(man.join("man1")).unwrap();
+        std::fs::create_dir_all(man.join("man3")).unwrap();
+        let grep = concat!(
+            ".\\\" Comment\n",
+            ".TH GREP 1 2024\n",
+            ".SH NAME\n",
+            "grep \\- print lines that match patterns\n",
+            ".SH OPTIONS\n",
+            ".TP\n",
+            ".BR \\-i \", \" \\-\\^\\-ignore\\-case\n",
+            "Ignore case distinctions in \\fIpatterns\\fP & data.\n",
+            ".nf\n",
+            "grep -i <x>\n",
+            ".fi\n",
+        );
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(grep.as_bytes()).unwrap();
+        std::fs::write(man.join("man1/grep.1.gz"), gz.finish().unwrap()).unwrap();
+        std::fs::write(man.join("man3/strcpy.3"), ".TH STRCPY 3\nCopy a string.\n").unwrap();
+        std::fs::write(man.join("man3/strncpy.3"), ".so man3/strcpy.3\n").unwrap();
+        std::fs::write(man.join("man3/README"), "not a page").unwrap();
+
+        let transport = MockTransport::new().respond("/docs.json", "[]");
+        let dir = temp_dir("man");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .transport(transport)
+            .source(crate::sources::ManPageSource::dirs([&man]))
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        manager.add_doc("man", Some(Formats::HTML)).await.unwrap();
+
+        let names: Vec<String> = manager
+            .iter_entries("man")
+            .await
+            .unwrap()
+            .map(|e| format!("{} ({})", e.name, e.entry_type))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "grep(1) (1: User Commands)",
+                "strcpy(3) (3: Library Functions)",
+                "strncpy(3) (3: Library Functions)",
+            ]
+        );
+
+        let file = manager
+            .page_file("man", "1/grep", Formats::HTML)
+            .await
+            .unwrap();
+        let html = std::fs::read_to_string(file).unwrap();
+        assert!(html.contains("<h1>GREP(1)</h1>"));
+        assert!(html.contains(r#"<h2 id="options">OPTIONS</h2>"#));
+        assert!(html.contains("<dt><b>-i</b>, <b>--ignore-case</b></dt>"));
+        assert!(html.contains("<p>Ignore case distinctions in <i>patterns</i> &amp; data.</p>"));
+        assert!(html.contains("<pre>grep -i &lt;x&gt;\n</pre>"));
+        assert!(!html.contains("Comment"));
+
+        let file = manager
+            .page_file("man", "3/strncpy", Formats::MARKDOWN)
+            .await
+            .unwrap();
+        assert!(
+            std::fs::read_to_string(file)
+                .unwrap()
+                .contains("Copy a string.")
+        );
+        assert!(
+            manager
+                .page_file("man", "1/nope", Formats::HTML)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,437 @@
+//! Docs generated by `cargo doc`
+//!
+//! Entries come from rustdoc's file layout rather than its search index, whose format
+//! changes between toolchains: every item has a `<kind>.<Name>.html` page in the
+//! directory of its module, and documents its members under `<kind>.<name>` anchors.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+use regex::Regex;
+
+use crate::{Doc, DocIndex, DocSource, Entry, EntryType};
+
+/// File name prefixes of item pages, and the entry type of their items
+const ITEM_KINDS: &[(&str, &str)] = &[
+    ("struct", "struct"),
+    ("enum", "enum"),
+    ("union", "union"),
+    ("trait", "trait"),
+    ("traitalias", "trait alias"),
+    ("fn", "function"),
+    ("macro", "macro"),
+    ("attr", "attribute macro"),
+    ("derive", "derive macro"),
+    ("type", "type alias"),
+    ("constant", "constant"),
+    ("static", "static"),
+    ("primitive", "primitive"),
+    ("keyword", "keyword"),
+];
+
+/// Anchor prefixes of the members documented on item pages, and their entry type
+const MEMBER_KINDS: &[(&str, &str)] = &[
+    ("method", "method"),
+    ("tymethod", "method"),
+    ("variant", "variant"),
+    ("structfield", "field"),
+    ("associatedconstant", "associated constant"),
+    ("associatedtype", "associated type"),
+];
+
+/// Prefix of the slugs of crate docs, keeping them apart from DevDocs slugs
+const SLUG_PREFIX: &str = "crate~";
+
+/// The crates documented in a `cargo doc` output directory (usually `target/doc`),
+/// one doc per crate
+#[derive(Debug, Clone)]
+pub struct RustdocSource {
+    dir: PathBuf,
+    crates: Vec<String>,
+}
+
+impl RustdocSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            crates: Vec::new(),
+        }
+    }
+
+    /// Only provide these crates, instead of every documented one including dependencies
+    pub fn crates(mut self, crates: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.crates = crates.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Directory of the crate documented as `slug`
+    fn crate_dir(&self, slug: &str) -> Result<PathBuf> {
+        let name = slug
+            .strip_prefix(SLUG_PREFIX)
+            .with_context(|| format!("'{}' is not a crate doc", slug))?;
+        let dir = self.dir.join(name);
+        if !dir.join("index.html").is_file() {
+            anyhow::bail!(
+                "{} has no documentation for crate {}",
+                self.dir.display(),
+                name
+            );
+        }
+        Ok(dir)
+    }
+
+    fn list_crates(&self) -> Result<Vec<Doc>> {
+        if !self.dir.is_dir() {
+            anyhow::bail!(
+                "{} does not exist, run `cargo doc` first",
+                self.dir.display()
+            );
+        }
+        let mut names = documented_crates(&self.dir)?;
+        if !self.crates.is_empty() {
+            // Crate dirs use underscores where crate names may have dashes
+            let wanted: HashSet<String> = self.crates.iter().map(|c| c.replace('-', "_")).collect();
+            names.retain(|name| wanted.contains(name));
+            if let Some(missing) = wanted.iter().find(|c| !names.contains(c)) {
+                anyhow::bail!(
+                    "{} has no documentation for crate {}",
+                    self.dir.display(),
+                    missing
+                );
+            }
+        }
+
+        let mut docs = Vec::with_capacity(names.len());
+        for name in names {
+            let (mtime, size) = dir_stats(&self.dir.join(&name))?;
+            docs.push(Doc {
+                name: name.clone(),
+                slug: format!("{}{}", SLUG_PREFIX, name),
+                doc_type: "rust".to_string(),
+                links: None,
+                mtime,
+                db_size: size as usize,
+                attribution: None,
+                alias: None,
+            });
+        }
+        Ok(docs)
+    }
+}
+
+impl DocSource for RustdocSource {
+    fn name(&self) -> &str {
+        "rustdoc"
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        let source = self.clone();
+        Box::pin(async move { tokio::task::spawn_blocking(move || source.list_crates()).await? })
+    }
+
+    fn fetch_index<'a>(&'a self, slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        Box::pin(async move {
+            let dir = self.crate_dir(slug)?;
+            tokio::task::spawn_blocking(move || Ok(crate_index(&dir, &crate_pages(&dir)?))).await?
+        })
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        Box::pin(async move {
+            let dir = self.crate_dir(slug)?;
+            tokio::task::spawn_blocking(move || {
+                Ok(crate_pages(&dir)?
+                    .into_iter()
+                    .map(|(page, html)| (page, anchor_headers(&main_content(&html))))
+                    .collect())
+            })
+            .await?
+        })
+    }
+}
+
+/// Names of the crates documented in `dir`, from `crates.js` or else from which
+/// subdirectories look like crate docs
+fn documented_crates(dir: &Path) -> Result<Vec<String>> {
+    if let Ok(js) = std::fs::read_to_string(dir.join("crates.js"))
+        && let (Some(start), Some(end)) = (js.find('['), js.rfind(']'))
+        && let Ok(names) = serde_json::from_str::<Vec<String>>(&js[start..=end])
+    {
+        return Ok(names);
+    }
+
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.join("all.html").is_file()
+            && let Some(name) = path.file_name()
+        {
+            names.push(name.to_string_lossy().into_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Newest modification time, in seconds, and total size of the files below `dir`
+fn dir_stats(dir: &Path) -> Result<(u64, u64)> {
+    let (mut mtime, mut size) = (0, 0);
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let (dir_mtime, dir_size) = dir_stats(&entry.path())?;
+            mtime = mtime.max(dir_mtime);
+            size += dir_size;
+        } else {
+            let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?.as_secs();
+            mtime = mtime.max(modified);
+            size += metadata.len();
+        }
+    }
+    Ok((mtime, size))
+}
+
+/// Every page of a crate as `(page path without extension, HTML)`, sorted by path
+fn crate_pages(dir: &Path) -> Result<Vec<(String, String)>> {
+    fn walk(root: &Path, dir: &Path, pages: &mut Vec<(String, String)>) -> Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                walk(root, &path, pages)?;
+                continue;
+            }
+            if path.extension().is_none_or(|ext| ext != "html") {
+                continue;
+            }
+            let relative = path.strip_prefix(root)?.with_extension("");
+            let page = relative.to_string_lossy().replace('\\', "/");
+            // The list of every item only repeats what the index holds
+            if page == "all" {
+                continue;
+            }
+            pages.push((page, std::fs::read_to_string(&path)?));
+        }
+        Ok(())
+    }
+
+    let mut pages = Vec::new();
+    walk(dir, dir, &mut pages)?;
+    pages.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(pages)
+}
+
+/// Index the modules, items and members of a crate's pages
+fn crate_index(dir: &Path, pages: &[(String, String)]) -> DocIndex {
+    let crate_name = dir.file_name().unwrap_or_default().to_string_lossy();
+    let members = Regex::new(
+        r#"id="(method|tymethod|variant|structfield|associatedconstant|associatedtype)\.([A-Za-z_][A-Za-z0-9_]*)""#,
+    )
+    .unwrap();
+
+    let mut entries = Vec::new();
+    for (page, html) in pages {
+        let mut parts: Vec<&str> = page.split('/').collect();
+        let file = parts.pop().unwrap_or_default();
+        let mut path = vec![crate_name.as_ref()];
+        path.extend(parts);
+
+        let entry_type = if file == "index" {
+            "module"
+        } else {
+            let Some((kind, item)) = file.split_once('.') else {
+                continue;
+            };
+            let Some((_, entry_type)) = ITEM_KINDS.iter().find(|(k, _)| *k == kind) else {
+                continue;
+            };
+            path.push(item);
+            entry_type
+        };
+        let name = path.join("::");
+
+        // Members of trait implementations belong to the traits, not this item
+        let content = main_content(html);
+        let own = content
+            .find(r#"id="trait-implementations""#)
+            .map_or(&*content, |end| &content[..end]);
+        let mut seen = HashSet::new();
+        for caps in members.captures_iter(own) {
+            let anchor = format!("{}.{}", &caps[1], &caps[2]);
+            if !seen.insert(anchor.clone()) {
+                continue;
+            }
+            let member_type = MEMBER_KINDS
+                .iter()
+                .find(|(k, _)| *k == &caps[1])
+                .map_or("member", |(_, t)| t);
+            entries.push(Entry {
+                name: format!("{}::{}", name, &caps[2]),
+                path: PathBuf::from(format!("{}#{}", page, anchor)),
+                entry_type: member_type.to_string(),
+            });
+        }
+
+        entries.push(Entry {
+            name,
+            path: PathBuf::from(page),
+            entry_type: entry_type.to_string(),
+        });
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for entry in &entries {
+        *counts.entry(&entry.entry_type).or_default() += 1;
+    }
+    let types = counts
+        .into_iter()
+        .map(|(name, count)| EntryType {
+            name: name.to_string(),
+            count,
+            slug: name.replace(' ', "-"),
+        })
+        .collect();
+
+    DocIndex { entries, types }
+}
+
+/// The documentation of a rustdoc page without its navigation, and without the auto
+/// trait and blanket implementations every type has
+fn main_content(html: &str) -> std::borrow::Cow<'_, str> {
+    let Some(start) = html.find(r#"<section id="main-content""#) else {
+        return html.into();
+    };
+    let html = &html[start..];
+    let generic = [
+        r#"<h2 id="synthetic-implementations""#,
+        r#"<h2 id="blanket-implementations""#,
+    ];
+    match generic.iter().filter_map(|marker| html.find(marker)).min() {
+        Some(end) => format!("{}</section>", &html[..end]).into(),
+        None => {
+            let end = html.find("</main>").unwrap_or(html.len());
+            // Drop the closing tags of the wrappers around the section
+            let section = html[..end].trim_end();
+            let section = section.strip_suffix("</div>").unwrap_or(section);
+            section.into()
+        }
+    }
+}
+
+/// Move the anchors of methods from their `<section>` onto its signature header, where
+/// signatures are looked up by anchor
+fn anchor_headers(html: &str) -> String {
+    use lol_html::{RewriteStrSettings, element, rewrite_str};
+    use std::cell::RefCell;
+
+    let pending: RefCell<Option<String>> = RefCell::new(None);
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("section.method[id]", |el| {
+                *pending.borrow_mut() = el.get_attribute("id");
+                el.remove_attribute("id");
+                Ok(())
+            }),
+            element!("h4.code-header", |el| {
+                if let Some(id) = pending.borrow_mut().take() {
+                    el.set_attribute("id", &id)?;
+                }
+                Ok(())
+            }),
+        ],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings).unwrap_or_else(|_| html.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::{temp_dir, test_builder};
+    use crate::{Formats, SECTION_TYPE, UpdateStatus};
+
+    #[tokio::test]
+    async fn test_cargo_doc_output_is_imported() {
+        let target = temp_dir("cargo-doc-target");
+        let krate = target.join("geometry");
+        std::fs::create_dir_all(krate.join("shapes")).unwrap();
+        std::fs::write(
+            target.join("crates.js"),
+            r#"window.ALL_CRATES = ["geometry"];"#,
+        )
+        .unwrap();
+        let page = |body: &str| {
+            format!(
+                r#"<html><body><nav>sidebar</nav><main><div class="width-limiter"><section id="main-content" class="content">{}</section></div></main></body></html>"#,
+                body
+            )
+        };
+        std::fs::write(krate.join("index.html"), page("<h1>Crate geometry</h1>")).unwrap();
+        std::fs::write(krate.join("all.html"), page("<h1>All items</h1>")).unwrap();
+        std::fs::write(
+            krate.join("shapes/struct.Circle.html"),
+            page(concat!(
+                r#"<h1>Struct Circle</h1><span id="structfield.radius">radius</span>"#,
+                r#"<section id="method.area" class="method"><h4 class="code-header">pub fn area(&amp;self) -&gt; f64</h4></section>"#,
+                r#"<h2 id="trait-implementations">Trait Implementations</h2><section id="method.fmt"></section>"#,
+                r#"<h2 id="blanket-implementations">Blanket Implementations</h2><section id="method.into"></section>"#,
+            )),
+        )
+        .unwrap();
+
+        let dir = temp_dir("cargo-doc");
+        let manager = test_builder(&dir).build().unwrap();
+        manager.init().await.unwrap();
+        let source = crate::sources::RustdocSource::new(&target);
+        let slugs = manager.import(&source, Some(Formats::HTML)).await.unwrap();
+        assert_eq!(slugs, ["crate~geometry"]);
+
+        let names: Vec<String> = manager
+            .iter_entries("crate~geometry")
+            .await
+            .unwrap()
+            .filter(|e| e.entry_type != SECTION_TYPE)
+            .map(|e| format!("{} ({})", e.name, e.entry_type))
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "geometry (module)",
+                "geometry::shapes::Circle (struct)",
+                "geometry::shapes::Circle::area (method)",
+                "geometry::shapes::Circle::radius (field)",
+            ]
+        );
+
+        let circle =
+            std::fs::read_to_string(dir.join("crate~geometry/shapes/struct.Circle.html")).unwrap();
+        assert!(circle.contains("Trait Implementations"));
+        assert!(!circle.contains("sidebar") && !circle.contains("Blanket"));
+        let signatures = manager
+            .signatures("crate~geometry", "geometry::shapes::Circle::area")
+            .await
+            .unwrap();
+        assert_eq!(signatures[0].text, "pub fn area(&self) -> f64");
+
+        // Imported docs have no upstream to update from
+        assert!(matches!(
+            manager.update_doc("crate~geometry").await.unwrap(),
+            UpdateStatus::Current
+        ));
+        assert!(
+            manager
+                .import(
+                    &crate::sources::RustdocSource::new(&target).crates(["nope"]),
+                    None
+                )
+                .await
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,361 @@
+//! tldr-pages: short, example-driven pages for command-line tools
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Cursor, Read};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+
+use crate::{Doc, DocIndex, DocSource, Entry, EntryType, HttpTransport, Links, escape_html};
+
+/// Archive of every page in every language, as published by tldr-pages
+pub const TLDR_ARCHIVE_URL: &str =
+    "https://github.com/tldr-pages/tldr/releases/latest/download/tldr.zip";
+
+/// Slug of the tldr doc
+const SLUG: &str = "tldr";
+
+/// The tldr pages of one language, as a single doc with one entry type per platform
+#[derive(Debug)]
+pub struct TldrSource {
+    url: String,
+    language: String,
+    transport: Option<Arc<dyn HttpTransport>>,
+    /// The archive downloaded last, reused by the fetches following a listing
+    archive: Mutex<Option<Arc<Vec<u8>>>>,
+}
+
+impl TldrSource {
+    /// The English pages of the latest release
+    pub fn new() -> Self {
+        Self {
+            url: TLDR_ARCHIVE_URL.to_string(),
+            language: "en".to_string(),
+            transport: None,
+            archive: Mutex::new(None),
+        }
+    }
+
+    /// Use the pages translated to `language` (e.g. `de`, `pt_BR`) instead of English
+    pub fn language(mut self, language: impl Into<String>) -> Self {
+        self.language = language.into();
+        self
+    }
+
+    /// Download the archive from `url` instead of the latest release
+    pub fn url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Directory of the pages of the chosen language in the archive
+    fn pages_dir(&self) -> String {
+        match self.language.as_str() {
+            "en" => "pages/".to_string(),
+            language => format!("pages.{}/", language),
+        }
+    }
+
+    async fn download(&self) -> Result<Arc<Vec<u8>>> {
+        let transport = self
+            .transport
+            .as_ref()
+            .context("The tldr source was used without a transport")?;
+        let archive = Arc::new(transport.get(&self.url).await?);
+        *self.archive.lock().expect("tldr archive poisoned") = Some(archive.clone());
+        Ok(archive)
+    }
+
+    /// The archive downloaded last, or a fresh one
+    async fn archive(&self) -> Result<Arc<Vec<u8>>> {
+        let cached = self.archive.lock().expect("tldr archive poisoned").clone();
+        match cached {
+            Some(archive) => Ok(archive),
+            None => self.download().await,
+        }
+    }
+
+    /// Pages of the chosen language as `(platform, command, markdown)`, plus the time the
+    /// newest of them was changed
+    async fn pages(&self, archive: Arc<Vec<u8>>) -> Result<(Vec<(String, String, String)>, u64)> {
+        let dir = self.pages_dir();
+        tokio::task::spawn_blocking(move || {
+            let mut zip = zip::ZipArchive::new(Cursor::new(&archive[..]))?;
+            let mut pages = Vec::new();
+            let mut newest = 0;
+            for i in 0..zip.len() {
+                let mut file = zip.by_index(i)?;
+                let Some(path) = file.name().strip_prefix(&dir) else {
+                    continue;
+                };
+                let Some((platform, command)) = path
+                    .strip_suffix(".md")
+                    .and_then(|path| path.split_once('/'))
+                else {
+                    continue;
+                };
+                let (platform, command) = (platform.to_string(), command.to_string());
+                if let Some(modified) = file.last_modified() {
+                    newest = newest.max(unix_time(&modified));
+                }
+                let mut markdown = String::new();
+                file.read_to_string(&mut markdown)?;
+                pages.push((platform, command, markdown));
+            }
+            pages.sort();
+            Ok((pages, newest))
+        })
+        .await?
+    }
+}
+
+impl Default for TldrSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocSource for TldrSource {
+    fn name(&self) -> &str {
+        "tldr"
+    }
+
+    fn set_transport(&mut self, transport: Arc<dyn HttpTransport>) {
+        self.transport = Some(transport);
+    }
+
+    fn list(&self) -> BoxFuture<'_, Result<Vec<Doc>>> {
+        Box::pin(async move {
+            // Always fetch a fresh archive, it's how updates are noticed
+            let archive = self.download().await?;
+            let size = archive.len();
+            let (pages, mtime) = self.pages(archive).await?;
+            if pages.is_empty() {
+                anyhow::bail!("The tldr archive has no pages in '{}'", self.language);
+            }
+            Ok(vec![Doc {
+                name: "tldr pages".to_string(),
+                slug: SLUG.to_string(),
+                doc_type: "tldr".to_string(),
+                links: Some(Links {
+                    home: Some("https://tldr.sh".to_string()),
+                    code: Some("https://github.com/tldr-pages/tldr".to_string()),
+                }),
+                mtime,
+                db_size: size,
+                attribution: Some(
+                    "© the tldr-pages contributors, licensed under CC BY 4.0".to_string(),
+                ),
+                alias: None,
+            }])
+        })
+    }
+
+    fn fetch_index<'a>(&'a self, _slug: &'a str) -> BoxFuture<'a, Result<DocIndex>> {
+        Box::pin(async move {
+            let (pages, _) = self.pages(self.archive().await?).await?;
+            let entries: Vec<Entry> = pages
+                .into_iter()
+                .map(|(platform, command, _)| Entry {
+                    path: PathBuf::from(format!("{}/{}", platform, command)),
+                    name: command,
+                    entry_type: platform,
+                })
+                .collect();
+
+            let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+            for entry in &entries {
+                *counts.entry(&entry.entry_type).or_default() += 1;
+            }
+            let types = counts
+                .into_iter()
+                .map(|(name, count)| EntryType {
+                    name: name.to_string(),
+                    count,
+                    slug: name.to_string(),
+                })
+                .collect();
+            Ok(DocIndex { entries, types })
+        })
+    }
+
+    fn fetch_content<'a>(
+        &'a self,
+        _slug: &'a str,
+    ) -> BoxFuture<'a, Result<HashMap<String, String>>> {
+        Box::pin(async move {
+            let (pages, _) = self.pages(self.archive().await?).await?;
+            Ok(pages
+                .into_iter()
+                .map(|(platform, command, markdown)| {
+                    (format!("{}/{}", platform, command), page_to_html(&markdown))
+                })
+                .collect())
+        })
+    }
+}
+
+/// Convert a tldr page to HTML.
+///
+/// The format is fixed: a `#` title, a `>` description, then `-` descriptions each
+/// followed by a backticked command.
+pub(crate) fn page_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    let mut description = Vec::new();
+    for line in markdown.lines().map(str::trim) {
+        if let Some(text) = line.strip_prefix('>') {
+            description.push(inline(text.trim()));
+            continue;
+        }
+        if !description.is_empty() {
+            html.push_str(&format!("<p>{}</p>\n", description.join("<br>\n")));
+            description.clear();
+        }
+        if let Some(title) = line.strip_prefix("# ") {
+            html.push_str(&format!("<h1>{}</h1>\n", escape_html(title)));
+        } else if let Some(example) = line.strip_prefix("- ") {
+            html.push_str(&format!("<p>{}</p>\n", inline(example)));
+        } else if let Some(command) = line
+            .strip_prefix('`')
+            .and_then(|line| line.strip_suffix('`'))
+        {
+            html.push_str(&format!(
+                "<pre><code>{}</code></pre>\n",
+                escape_html(command)
+            ));
+        }
+    }
+    if !description.is_empty() {
+        html.push_str(&format!("<p>{}</p>\n", description.join("<br>\n")));
+    }
+    html
+}
+
+/// Escape text for HTML, turning `` `code` `` and `<https://links>` into elements
+fn inline(text: &str) -> String {
+    let mut html = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(['`', '<']) {
+        html.push_str(&escape_html(&rest[..start]));
+        let (open, close) = if rest[start..].starts_with('`') {
+            ('`', '`')
+        } else {
+            ('<', '>')
+        };
+        let inner = &rest[start + 1..];
+        match inner.find(close) {
+            Some(end) if open == '`' => {
+                html.push_str(&format!("<code>{}</code>", escape_html(&inner[..end])));
+                rest = &inner[end + 1..];
+            }
+            Some(end) if inner.starts_with("http") => {
+                let url = escape_html(&inner[..end]);
+                html.push_str(&format!("<a href=\"{url}\">{url}</a>"));
+                rest = &inner[end + 1..];
+            }
+            _ => {
+                html.push_str(&escape_html(&rest[start..=start]));
+                rest = inner;
+            }
+        }
+    }
+    html.push_str(&escape_html(rest));
+    html
+}
+
+/// Seconds since the epoch of a zip timestamp, taken as UTC
+fn unix_time(time: &zip::DateTime) -> u64 {
+    // Days from the civil date, after Howard Hinnant's `days_from_civil`
+    let (year, month, day) = (
+        i64::from(time.year()),
+        i64::from(time.month()),
+        i64::from(time.day()),
+    );
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    let seconds =
+        i64::from(time.hour()) * 3600 + i64::from(time.minute()) * 60 + i64::from(time.second());
+    (days * 86_400 + seconds).max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Formats;
+    use crate::testing::MockTransport;
+    use crate::tests::{temp_dir, test_builder};
+
+    #[tokio::test]
+    async fn test_tldr_pages_are_listed_and_searchable() {
+        use std::io::Write;
+
+        let tar = concat!(
+            "# tar\n\n",
+            "> Archiving utility.\n",
+            "> More information: <https://www.gnu.org/software/tar>.\n\n",
+            "- Extract a (compressed) archive file into the current directory:\n\n",
+            "`tar xf {{path/to/source.tar[.gz|.bz2|.xz]}}`\n",
+        );
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default()
+            .last_modified_time(zip::DateTime::from_date_and_time(2024, 3, 1, 12, 0, 0).unwrap());
+        for (path, page) in [
+            ("pages/common/tar.md", tar),
+            ("pages/osx/brew.md", "# brew\n"),
+            ("pages.de/common/tar.md", "# tar\n"),
+            ("LICENSE.md", "CC BY 4.0"),
+        ] {
+            zip.start_file(path, options).unwrap();
+            zip.write_all(page.as_bytes()).unwrap();
+        }
+        let archive = zip.finish().unwrap().into_inner();
+
+        let dir = temp_dir("tldr");
+        let manager = test_builder(&dir)
+            .base_url("http://docs.test")
+            .transport(
+                MockTransport::new()
+                    .respond("/docs.json", "[]")
+                    .respond("/tldr.zip", archive),
+            )
+            .source(crate::sources::TldrSource::new().url("http://tldr.test/tldr.zip"))
+            .build()
+            .unwrap();
+        manager.init().await.unwrap();
+        let available = manager.get_available_docs().await.unwrap();
+        assert_eq!(available.len(), 1);
+        assert_eq!(available[0].mtime, 1_709_294_400);
+        manager.add_doc("tldr", Some(Formats::HTML)).await.unwrap();
+
+        let names: Vec<String> = manager
+            .iter_entries("tldr")
+            .await
+            .unwrap()
+            .map(|e| format!("{} ({})", e.name, e.entry_type))
+            .collect();
+        assert_eq!(names, ["tar (common)", "brew (osx)"]);
+
+        let results = manager.search("tar", None).await.unwrap();
+        assert_eq!(results[0].entry.entry.path, PathBuf::from("common/tar"));
+
+        let file = manager
+            .page_file("tldr", "common/tar", Formats::HTML)
+            .await
+            .unwrap();
+        let html = std::fs::read_to_string(file).unwrap();
+        assert!(html.contains("<h1>tar</h1>"));
+        assert!(html.contains(
+            r#"More information: <a href="https://www.gnu.org/software/tar">https://www.gnu.org/software/tar</a>."#
+        ));
+        assert!(
+            html.contains("<pre><code>tar xf {{path/to/source.tar[.gz|.bz2|.xz]}}</code></pre>")
+        );
+    }
+}
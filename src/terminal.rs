@@ -0,0 +1,391 @@
+//! Laying out the tables and definition lists of pages for reading in a terminal,
+//! which the markdown converter would otherwise flatten
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use lol_html::errors::RewritingError;
+use lol_html::html_content::ContentType;
+use lol_html::{EndTagHandler, RewriteStrSettings, doc_text, element, rewrite_str};
+use regex::Regex;
+
+/// Width that laid out blocks are wrapped to
+const TEXT_WIDTH: usize = 100;
+
+/// Columns are never squeezed narrower than this, unless all their text is
+const MIN_COLUMN: usize = 10;
+
+/// Stands in for a laid out block while the rest of the page is converted
+const PLACEHOLDER: &str = "DEVDOCSBLOCK";
+
+/// A top-level table or definition list of a page
+enum Block {
+    Table(Vec<Vec<Cell>>),
+    List(Vec<Item>),
+}
+
+struct Cell {
+    header: bool,
+    text: String,
+}
+
+struct Item {
+    term: bool,
+    text: String,
+}
+
+impl Block {
+    /// The text that content currently being read belongs to
+    fn target(&mut self) -> Option<&mut String> {
+        match self {
+            Block::Table(rows) => rows.last_mut()?.last_mut().map(|cell| &mut cell.text),
+            Block::List(items) => items.last_mut().map(|item| &mut item.text),
+        }
+    }
+}
+
+/// Convert a page to markdown, laying out its tables and definition lists as aligned,
+/// wrapped text and leaving the rest to `convert`. Pages that fail to parse are
+/// converted as they are.
+pub(crate) fn render(html: &str, convert: impl Fn(&str) -> String) -> String {
+    let blocks = match survey(html) {
+        Ok(blocks) if !blocks.is_empty() => blocks,
+        _ => return convert(html),
+    };
+    let Ok(marked) = mark(html) else {
+        return convert(html);
+    };
+
+    let mut markdown = convert(&marked);
+    // Replace the last blocks first so `DEVDOCSBLOCK1` doesn't match `DEVDOCSBLOCK10`
+    for (i, block) in blocks.iter().enumerate().rev() {
+        let text = match block {
+            Block::Table(rows) => layout_table(rows, TEXT_WIDTH),
+            Block::List(items) => layout_list(items, TEXT_WIDTH),
+        };
+        markdown = markdown.replace(&format!("{}{}", PLACEHOLDER, i), &text);
+    }
+    markdown
+}
+
+/// Read the cells of the top-level tables and the items of the top-level definition
+/// lists of a page. Nested blocks are read as the text of the cell or item they're in.
+fn survey(html: &str) -> Result<Vec<Block>, RewritingError> {
+    let blocks: RefCell<Vec<Block>> = RefCell::default();
+    let depth = Rc::new(RefCell::new(0usize));
+    let top = || *depth.borrow() == 1;
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![
+            element!("table, dl", |el| {
+                if *depth.borrow() == 0 {
+                    blocks.borrow_mut().push(match el.tag_name().as_str() {
+                        "table" => Block::Table(Vec::new()),
+                        _ => Block::List(Vec::new()),
+                    });
+                }
+                *depth.borrow_mut() += 1;
+                let depth = depth.clone();
+                let leave: EndTagHandler<'static> = Box::new(move |_| {
+                    *depth.borrow_mut() -= 1;
+                    Ok(())
+                });
+                el.on_end_tag(leave)?;
+                Ok(())
+            }),
+            element!("tr", |_| {
+                if top()
+                    && let Some(Block::Table(rows)) = blocks.borrow_mut().last_mut()
+                {
+                    rows.push(Vec::new());
+                }
+                Ok(())
+            }),
+            element!("td, th", |el| {
+                if top()
+                    && let Some(Block::Table(rows)) = blocks.borrow_mut().last_mut()
+                    && let Some(row) = rows.last_mut()
+                {
+                    row.push(Cell {
+                        header: el.tag_name() == "th",
+                        text: String::new(),
+                    });
+                }
+                Ok(())
+            }),
+            element!("dt, dd", |el| {
+                if top()
+                    && let Some(Block::List(items)) = blocks.borrow_mut().last_mut()
+                {
+                    items.push(Item {
+                        term: el.tag_name() == "dt",
+                        text: String::new(),
+                    });
+                }
+                Ok(())
+            }),
+            element!("p, br, div, li, pre", |_| {
+                // Keep the paragraphs of a cell or definition apart
+                if *depth.borrow() > 0
+                    && let Some(text) = blocks.borrow_mut().last_mut().and_then(Block::target)
+                {
+                    text.push('\n');
+                }
+                Ok(())
+            }),
+        ],
+        document_content_handlers: vec![doc_text!(|chunk| {
+            if *depth.borrow() > 0
+                && let Some(text) = blocks.borrow_mut().last_mut().and_then(Block::target)
+            {
+                text.push_str(chunk.as_str());
+            }
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings)?;
+    Ok(blocks.into_inner())
+}
+
+/// Replace the top-level tables and definition lists of a page with numbered placeholders
+fn mark(html: &str) -> Result<String, RewritingError> {
+    let depth = Rc::new(RefCell::new(0usize));
+    let count = RefCell::new(0usize);
+
+    let settings = RewriteStrSettings {
+        element_content_handlers: vec![element!("table, dl", |el| {
+            if *depth.borrow() == 0 {
+                let mut count = count.borrow_mut();
+                el.replace(
+                    &format!("<p>{}{}</p>", PLACEHOLDER, count),
+                    ContentType::Html,
+                );
+                *count += 1;
+            }
+            *depth.borrow_mut() += 1;
+            let depth = depth.clone();
+            let leave: EndTagHandler<'static> = Box::new(move |_| {
+                *depth.borrow_mut() -= 1;
+                Ok(())
+            });
+            el.on_end_tag(leave)?;
+            Ok(())
+        })],
+        ..RewriteStrSettings::new()
+    };
+    rewrite_str(html, settings)
+}
+
+/// The paragraphs of a cell or item, with entities decoded and whitespace collapsed
+fn paragraphs(text: &str) -> Vec<String> {
+    crate::decode_entities(text.to_string())
+        .split('\n')
+        .map(|line| line.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Lay a table out as a markdown table whose columns line up, wrapping the text of
+/// cells that don't fit in `width`
+fn layout_table(rows: &[Vec<Cell>], width: usize) -> String {
+    let rows: Vec<&Vec<Cell>> = rows.iter().filter(|row| !row.is_empty()).collect();
+    let columns = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    if columns == 0 {
+        return String::new();
+    }
+
+    // Text of every cell, escaped for markdown, with missing cells left blank
+    let cells: Vec<Vec<Vec<String>>> = rows
+        .iter()
+        .map(|row| {
+            (0..columns)
+                .map(|c| {
+                    row.get(c).map_or_else(Vec::new, |cell| {
+                        paragraphs(&cell.text)
+                            .into_iter()
+                            .map(|p| p.replace('|', "\\|"))
+                            .collect()
+                    })
+                })
+                .collect()
+        })
+        .collect();
+    let header = rows[0].iter().all(|cell| cell.header);
+
+    let natural: Vec<usize> = (0..columns)
+        .map(|c| {
+            cells
+                .iter()
+                .flat_map(|row| &row[c])
+                .map(|p| p.chars().count())
+                .max()
+                .unwrap_or(0)
+                .max(3)
+        })
+        .collect();
+    let numeric: Vec<bool> = (0..columns)
+        .map(|c| {
+            let mut body = cells
+                .iter()
+                .skip(header as usize)
+                .map(|row| row[c].join(" "));
+            let mut any = false;
+            let all = body.all(|text| {
+                any |= !text.is_empty();
+                text.is_empty()
+                    || text
+                        .replace(',', "")
+                        .trim_end_matches('%')
+                        .parse::<f64>()
+                        .is_ok()
+            });
+            all && any
+        })
+        .collect();
+
+    // Squeeze the widest column until the table fits, or every column is at its minimum
+    let mut widths = natural.clone();
+    let available = width.saturating_sub(3 * columns + 1);
+    while widths.iter().sum::<usize>() > available {
+        let widest = (0..columns)
+            .filter(|&c| widths[c] > natural[c].min(MIN_COLUMN))
+            .max_by_key(|&c| widths[c]);
+        match widest {
+            Some(c) => widths[c] -= 1,
+            None => break,
+        }
+    }
+
+    let mut out = Vec::new();
+    for (i, row) in cells.iter().enumerate() {
+        let wrapped: Vec<Vec<String>> = row
+            .iter()
+            .zip(&widths)
+            .map(|(text, &width)| text.iter().flat_map(|p| wrap(p, width)).collect())
+            .collect();
+        let height = wrapped.iter().map(Vec::len).max().unwrap_or(0).max(1);
+        for line in 0..height {
+            let cells: Vec<String> = (0..columns)
+                .map(|c| {
+                    let text = wrapped[c].get(line).map_or("", String::as_str);
+                    if numeric[c] {
+                        format!("{:>1$}", text, widths[c])
+                    } else {
+                        format!("{:<1$}", text, widths[c])
+                    }
+                })
+                .collect();
+            out.push(format!("| {} |", cells.join(" | ")));
+        }
+        if i == 0 && header {
+            let rules: Vec<String> = (0..columns)
+                .map(|c| {
+                    if numeric[c] {
+                        format!("{}:", "-".repeat(widths[c] - 1))
+                    } else {
+                        "-".repeat(widths[c])
+                    }
+                })
+                .collect();
+            out.push(format!("| {} |", rules.join(" | ")));
+        }
+    }
+    out.join("\n")
+}
+
+/// Lay a definition list out with its terms in bold and their definitions indented
+/// below them
+fn layout_list(items: &[Item], width: usize) -> String {
+    let mut out: Vec<String> = Vec::new();
+    let mut after_definition = false;
+    for item in items {
+        let paragraphs = paragraphs(&item.text);
+        if item.term {
+            if after_definition {
+                out.push(String::new());
+            }
+            out.push(format!("**{}**", paragraphs.join(" ")));
+            after_definition = false;
+        } else {
+            for (i, paragraph) in paragraphs.iter().enumerate() {
+                if i > 0 {
+                    out.push(String::new());
+                }
+                for (j, line) in wrap(paragraph, width.saturating_sub(4)).iter().enumerate() {
+                    let indent = if i == 0 && j == 0 { ":   " } else { "    " };
+                    out.push(format!("{}{}", indent, line));
+                }
+            }
+            after_definition = true;
+        }
+    }
+    out.join("\n")
+}
+
+/// The plain text of a page rendered as markdown, without its markup: links and
+/// emphasis are reduced to their text, images to their alt text and code blocks to
+/// their indented code
+pub(crate) fn plain_text(markdown: &str) -> String {
+    let heading = Regex::new(r"^#{1,6}\s+").unwrap();
+    let image = Regex::new(r"!\[([^\]]*)\]\([^)]*\)").unwrap();
+    let link = Regex::new(r"\[([^\]]*)\]\([^)]*\)").unwrap();
+    let strong = Regex::new(r"\*\*([^*]+)\*\*|__([^_]+)__").unwrap();
+    let emphasis = Regex::new(r"\*([^*\s][^*]*)\*").unwrap();
+    let code = Regex::new(r"`([^`]+)`").unwrap();
+    let escape = Regex::new(r"\\([\\`*_{}\[\]()#+\-.!|<>])").unwrap();
+
+    let mut out = Vec::new();
+    let mut in_code = false;
+    for line in markdown.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            out.push(format!("    {}", line));
+            continue;
+        }
+
+        let line = heading.replace(line, "");
+        let line = image.replace_all(&line, "[image: $1]");
+        let line = link.replace_all(&line, "$1");
+        let line = strong.replace_all(&line, "$1$2");
+        let line = emphasis.replace_all(&line, "$1");
+        let line = code.replace_all(&line, "$1");
+        out.push(escape.replace_all(&line, "$1").into_owned());
+    }
+    let mut text = out.join("\n");
+    text.push('\n');
+    text
+}
+
+/// Wrap text at word boundaries into lines of at most `width` characters, breaking
+/// words that are longer than that
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let width = width.max(1);
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    let mut len = 0;
+    for word in text.split_whitespace() {
+        let mut word: Vec<char> = word.chars().collect();
+        if len > 0 && len + 1 + word.len() <= width {
+            line.push(' ');
+            len += 1;
+        } else if len > 0 {
+            lines.push(std::mem::take(&mut line));
+            len = 0;
+        }
+        while word.len() > width {
+            let rest = word.split_off(width);
+            lines.push(word.into_iter().collect());
+            word = rest;
+        }
+        len += word.len();
+        line.extend(word);
+    }
+    if len > 0 {
+        lines.push(line);
+    }
+    lines
+}
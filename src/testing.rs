@@ -0,0 +1,155 @@
+//! Canned DevDocs responses for testing code built on the manager without the network.
+//!
+//! [`MockTransport`] answers requests by URL path, so it serves the same fixtures
+//! whatever the base URLs are set to. [`MockTransport::fixtures`] knows the list of
+//! docs in [`DOCS_JSON`] and the index and pages of each of them:
+//!
+//! ```no_run
+//! # async fn run() -> anyhow::Result<()> {
+//! use dev::DevDocsManager;
+//! use dev::testing::MockTransport;
+//!
+//! let transport = MockTransport::fixtures();
+//! let manager = DevDocsManager::with_transport("/tmp/devdocs-test", transport.clone())?;
+//! manager.init().await?;
+//! manager.add_doc("kubernetes", None).await?;
+//! assert_eq!(transport.requests().len(), 3);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context, Result};
+use futures::future::BoxFuture;
+
+use crate::HttpTransport;
+
+/// The docs listed by the fixtures, as upstream's `docs.json`
+pub const DOCS_JSON: &str = r#"[
+  {"name":"Kubernetes","slug":"kubernetes","type":"kubernetes","mtime":1700000000,"db_size":2048,
+   "links":{"home":"https://kubernetes.io/","code":"https://github.com/kubernetes/kubernetes"}},
+  {"name":"Rust","slug":"rust","type":"rustdoc","mtime":1700000000,"db_size":4096,
+   "links":{"home":"https://www.rust-lang.org/","code":"https://github.com/rust-lang/rust"}}
+]"#;
+
+/// The `index.json` of the Kubernetes fixture
+pub const KUBERNETES_INDEX: &str = r#"{
+  "entries":[
+    {"name":"Pod","path":"concepts/workloads/pods/pod","type":"Workloads"},
+    {"name":"Deployment","path":"concepts/workloads/controllers/deployment","type":"Workloads"},
+    {"name":"Deployment: rolling back","path":"concepts/workloads/controllers/deployment#rolling-back","type":"Workloads"},
+    {"name":"Service","path":"concepts/services-networking/service","type":"Networking"}
+  ],
+  "types":[
+    {"name":"Networking","count":1,"slug":"networking"},
+    {"name":"Workloads","count":3,"slug":"workloads"}
+  ]
+}"#;
+
+/// The `db.json` of the Kubernetes fixture
+pub const KUBERNETES_DB: &str = r#"{
+  "concepts/workloads/pods/pod":"<h1>Pod</h1><p>The smallest deployable unit of computing.</p>",
+  "concepts/workloads/controllers/deployment":"<h1>Deployment</h1><p>Declarative updates for Pods.</p><h2 id=\"rolling-back\">Rolling back</h2><pre>kubectl rollout undo deployment/nginx</pre>",
+  "concepts/services-networking/service":"<h1>Service</h1><p>Expose an application running as Pods.</p>"
+}"#;
+
+/// The `index.json` of the Rust fixture
+pub const RUST_INDEX: &str = r#"{
+  "entries":[
+    {"name":"std::vec::Vec","path":"std/vec/struct.vec","type":"Structs"},
+    {"name":"Vec::push","path":"std/vec/struct.vec#method.push","type":"Structs"},
+    {"name":"vec!","path":"std/macro.vec","type":"Macros"}
+  ],
+  "types":[
+    {"name":"Macros","count":1,"slug":"macros"},
+    {"name":"Structs","count":2,"slug":"structs"}
+  ]
+}"#;
+
+/// The `db.json` of the Rust fixture
+pub const RUST_DB: &str = r#"{
+  "std/vec/struct.vec":"<h1>Struct std::vec::Vec</h1><p>A contiguous growable array type.</p><h3 id=\"method.push\">push</h3><pre>pub fn push(&amp;mut self, value: T)</pre>",
+  "std/macro.vec":"<h1>Macro std::vec</h1><p>Creates a Vec containing the arguments.</p>"
+}"#;
+
+/// Serves canned responses by URL path and records the requests it gets.
+///
+/// Clones share their responses and recorded requests, so a clone kept by the test
+/// sees what the manager asked for. Unknown paths fail like a 404.
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    responses: Arc<Mutex<HashMap<String, Vec<u8>>>>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockTransport {
+    /// A transport without any responses
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A transport serving [`DOCS_JSON`] and the docs it lists
+    pub fn fixtures() -> Self {
+        Self::new()
+            .respond("/docs.json", DOCS_JSON)
+            .respond("/kubernetes/index.json", KUBERNETES_INDEX)
+            .respond("/kubernetes/db.json", KUBERNETES_DB)
+            .respond("/rust/index.json", RUST_INDEX)
+            .respond("/rust/db.json", RUST_DB)
+    }
+
+    /// Answer requests for `path`, like `/rust/index.json`, with `body`
+    pub fn respond(self, path: &str, body: impl Into<Vec<u8>>) -> Self {
+        self.set(path, body);
+        self
+    }
+
+    /// Answer requests for `path` with `body` from now on, e.g. to publish a new
+    /// version of a doc while its manager is in use
+    pub fn set(&self, path: &str, body: impl Into<Vec<u8>>) {
+        self.responses
+            .lock()
+            .expect("mock transport poisoned")
+            .insert(path.to_string(), body.into());
+    }
+
+    /// Fail requests for `path` from now on
+    pub fn remove(&self, path: &str) {
+        self.responses
+            .lock()
+            .expect("mock transport poisoned")
+            .remove(path);
+    }
+
+    /// URLs requested so far, in order
+    pub fn requests(&self) -> Vec<String> {
+        self.requests
+            .lock()
+            .expect("mock transport poisoned")
+            .clone()
+    }
+}
+
+impl HttpTransport for MockTransport {
+    fn get<'a>(&'a self, url: &'a str) -> BoxFuture<'a, Result<Vec<u8>>> {
+        self.requests
+            .lock()
+            .expect("mock transport poisoned")
+            .push(url.to_string());
+        let body = self
+            .responses
+            .lock()
+            .expect("mock transport poisoned")
+            .get(path_of(url))
+            .cloned();
+        Box::pin(async move { body.with_context(|| format!("404 for {}", url)) })
+    }
+}
+
+/// Path of a URL, from the slash after its host
+fn path_of(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.find('/').map_or("/", |i| &rest[i..])
+}
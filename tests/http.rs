@@ -0,0 +1,199 @@
+//! The manager against a local HTTP server serving the fixtures, through the built-in
+//! reqwest transport.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use dev::testing::{DOCS_JSON, KUBERNETES_DB, KUBERNETES_INDEX, RUST_DB, RUST_INDEX};
+use dev::{DevDocsManager, Formats, UpdateStatus};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+type Routes = Arc<Mutex<HashMap<String, Vec<u8>>>>;
+
+/// Answers GETs by path like devdocs.io and documents.devdocs.io, and records them
+struct Server {
+    url: String,
+    routes: Routes,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl Server {
+    /// Serve the fixtures on a free local port
+    async fn start() -> Server {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = Server {
+            url,
+            routes: Arc::default(),
+            requests: Arc::default(),
+        };
+        for (path, body) in [
+            ("/docs.json", DOCS_JSON),
+            ("/kubernetes/index.json", KUBERNETES_INDEX),
+            ("/kubernetes/db.json", KUBERNETES_DB),
+            ("/rust/index.json", RUST_INDEX),
+            ("/rust/db.json", RUST_DB),
+        ] {
+            server.set(path, body);
+        }
+
+        let routes = server.routes.clone();
+        let requests = server.requests.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(respond(stream, routes.clone(), requests.clone()));
+            }
+        });
+        server
+    }
+
+    fn set(&self, path: &str, body: &str) {
+        self.routes
+            .lock()
+            .unwrap()
+            .insert(path.to_string(), body.as_bytes().to_vec());
+    }
+
+    fn remove(&self, path: &str) {
+        self.routes.lock().unwrap().remove(path);
+    }
+
+    fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// A manager downloading from this server, keeping everything in `name`'s temp dir
+    fn manager(&self, name: &str) -> DevDocsManager {
+        let dir = temp_dir(name);
+        DevDocsManager::builder()
+            .isolated()
+            .config_file(dir.join("config.toml"))
+            .data_dir(dir)
+            .base_url(&self.url)
+            .documents_url(&self.url)
+            .build()
+            .unwrap()
+    }
+}
+
+/// Answer one request, closing the connection after it
+async fn respond(mut stream: TcpStream, routes: Routes, requests: Arc<Mutex<Vec<String>>>) {
+    let mut head = Vec::new();
+    let mut buf = [0; 1024];
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => head.extend_from_slice(&buf[..n]),
+        }
+    }
+    let head = String::from_utf8_lossy(&head);
+    let target = head.split_whitespace().nth(1).unwrap_or("/");
+    let path = target.split('?').next().unwrap_or(target);
+    requests.lock().unwrap().push(path.to_string());
+
+    let body = routes.lock().unwrap().get(path).cloned();
+    let (status, body) = match body {
+        Some(body) => ("200 OK", body),
+        None => ("404 Not Found", b"not found".to_vec()),
+    };
+    let head = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+    let _ = stream.write_all(head.as_bytes()).await;
+    let _ = stream.write_all(&body).await;
+    let _ = stream.shutdown().await;
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("devdocs-http-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[tokio::test]
+async fn test_docs_install_and_search_over_http() {
+    let server = Server::start().await;
+    let manager = server.manager("install");
+    manager.init().await.unwrap();
+
+    let available = manager.get_available_docs().await.unwrap();
+    assert_eq!(available.len(), 2);
+    manager
+        .add_doc("kubernetes", Some(Formats::MARKDOWN))
+        .await
+        .unwrap();
+    assert!(manager.is_doc_installed("kubernetes").await.unwrap());
+    assert_eq!(
+        server.requests(),
+        [
+            "/docs.json",
+            "/kubernetes/index.json",
+            "/kubernetes/db.json"
+        ]
+    );
+
+    let results = manager.search("deployment", None).await.unwrap();
+    assert_eq!(results[0].entry.entry.name, "Deployment");
+    let page = manager
+        .page_file(
+            "kubernetes",
+            "concepts/workloads/pods/pod",
+            Formats::MARKDOWN,
+        )
+        .await
+        .unwrap();
+    let page = std::fs::read_to_string(page).unwrap();
+    assert!(page.contains("The smallest deployable unit"), "{}", page);
+}
+
+#[tokio::test]
+async fn test_missing_documents_fail_without_installing() {
+    let server = Server::start().await;
+    server.remove("/rust/db.json");
+    let manager = server.manager("missing");
+    manager.init().await.unwrap();
+
+    let err = manager.add_doc("rust", None).await.unwrap_err();
+    assert!(format!("{:#}", err).contains("404"), "{:#}", err);
+    assert!(!manager.is_doc_installed("rust").await.unwrap());
+    assert!(manager.add_doc("python", None).await.is_err());
+}
+
+#[tokio::test]
+async fn test_updates_download_newly_published_docs() {
+    let server = Server::start().await;
+    let manager = server.manager("update");
+    manager.init().await.unwrap();
+    manager
+        .add_doc("rust", Some(Formats::MARKDOWN))
+        .await
+        .unwrap();
+    assert_eq!(
+        manager.update_doc("rust").await.unwrap(),
+        UpdateStatus::Current
+    );
+
+    server.set(
+        "/docs.json",
+        &DOCS_JSON.replace("1700000000,\"db_size\":4096", "1800000000,\"db_size\":4096"),
+    );
+    server.set(
+        "/rust/db.json",
+        &RUST_DB.replace("growable array", "growable, heap-allocated array"),
+    );
+    manager.refresh_available_docs().await.unwrap();
+    assert!(matches!(
+        manager.update_doc("rust").await.unwrap(),
+        UpdateStatus::Updated(_)
+    ));
+    let page = manager
+        .page_file("rust", "std/vec/struct.vec", Formats::MARKDOWN)
+        .await
+        .unwrap();
+    let page = std::fs::read_to_string(page).unwrap();
+    assert!(page.contains("heap-allocated"), "{}", page);
+}